@@ -0,0 +1,488 @@
+//! `serumcv`: list, inspect, configure, and capture from video capture
+//! devices using SerumCV's own types.
+
+use core::net::SocketAddr;
+use core::time::Duration;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context as _};
+use clap::{Args, Parser, Subcommand};
+use serumcv_video_capture::backends::v4l::{
+    V4LBackend, V4LSource, V4LVideoCaptureDescriptor, V4LVideoCaptureDevice,
+};
+use serumcv_video_capture::config::find_profile;
+use serumcv_video_capture::prelude::*;
+
+/// Inspect and capture from video capture devices.
+#[derive(Debug, Parser)]
+#[command(name = "serumcv", version, about)]
+struct Cli {
+    /// The device to use, like `/dev/video0` or `/dev/media0`.
+    ///
+    /// Defaults to the first connected device.
+    #[arg(short, long, global = true)]
+    device: Option<PathBuf>,
+
+    /// A TOML file of device profiles, as a `[[profiles]]` list.
+    ///
+    /// Devices use whichever profile is for them when they connect.
+    #[arg(short, long, global = true)]
+    profiles: Option<PathBuf>,
+
+    /// Log what the library is doing. Repeat for more detail.
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Lists connected devices, along with their models and serials.
+    List,
+    /// Shows where a device lives and what it is.
+    Info,
+    /// Lists every image configuration that a device supports.
+    Formats,
+    /// Prints a device's image configuration and properties.
+    Get {
+        /// Only print these properties.
+        keys: Vec<String>,
+    },
+    /// Changes a device's image configuration and properties.
+    Set {
+        /// The image configuration to use, like `1920x1080@30 MJPG`.
+        #[arg(short, long)]
+        config: Option<VideoCaptureImageConfiguration>,
+
+        /// Properties to set, like `brightness=128`. They're set in order.
+        properties: Vec<VideoCaptureProperty>,
+    },
+    /// Writes frames from a device to disk, exactly as the device sent them.
+    Capture(CaptureArgs),
+    /// Serves live previews over HTTP, so you can watch in a browser.
+    ///
+    /// Serves every connected device unless `--device` is given.
+    Preview {
+        /// The address to serve on.
+        #[arg(short, long, default_value = "127.0.0.1:8080")]
+        address: SocketAddr,
+    },
+}
+
+#[derive(Debug, Args)]
+struct CaptureArgs {
+    /// Writes a single frame to this file.
+    #[arg(
+        short,
+        long,
+        conflicts_with = "output_dir",
+        required_unless_present = "output_dir"
+    )]
+    snapshot: Option<PathBuf>,
+
+    /// Writes frames into this directory, one file each.
+    #[arg(short, long)]
+    output_dir: Option<PathBuf>,
+
+    /// How many frames to write into the output directory.
+    #[arg(short = 'n', long, default_value_t = 1)]
+    frames: u32,
+
+    /// Gives up if the device takes longer than this many seconds to send a
+    /// frame.
+    #[arg(short, long, default_value = "5", value_parser = seconds)]
+    timeout: Duration,
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    let level = match cli.verbose {
+        0 => tracing::Level::WARN,
+        1 => tracing::Level::INFO,
+        2 => tracing::Level::DEBUG,
+        _ => tracing::Level::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(io::stderr)
+        .init();
+
+    let profiles = cli
+        .profiles
+        .as_deref()
+        .map(load_profiles)
+        .transpose()?
+        .unwrap_or_default();
+
+    let mut out = io::stdout().lock();
+    match cli.command {
+        Command::List => list(&mut out),
+        Command::Info => info(&mut out, &device_path(cli.device)?, &profiles),
+        Command::Formats => formats(&mut out, &connect(cli.device, profiles)?),
+        Command::Get { keys } => get(&mut out, &connect(cli.device, profiles)?, &keys),
+        Command::Set { config, properties } => set(
+            &mut out,
+            &mut connect(cli.device, profiles)?,
+            config.as_ref(),
+            &properties,
+        ),
+        Command::Capture(args) => capture(&mut out, &mut connect(cli.device, profiles)?, &args),
+        Command::Preview { address } => preview(&mut out, cli.device, &profiles, address),
+    }
+}
+
+/// Reads the `[[profiles]]` list out of a TOML file.
+fn load_profiles(path: &Path) -> anyhow::Result<Vec<VideoCaptureProfile>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read profiles from `{}`.", path.display()))?;
+    parse_profiles(&text).with_context(|| format!("`{}` has bad profiles.", path.display()))
+}
+
+fn parse_profiles(text: &str) -> anyhow::Result<Vec<VideoCaptureProfile>> {
+    let mut table: toml::Table = text.parse()?;
+    let Some(profiles) = table.remove("profiles") else {
+        return Ok(Vec::new());
+    };
+
+    Ok(profiles.try_into()?)
+}
+
+/// Uses the given device path, or finds the first connected device.
+fn device_path(given: Option<PathBuf>) -> anyhow::Result<PathBuf> {
+    given.map_or_else(
+        || {
+            V4LBackend::list_connected_devices()
+                .into_iter()
+                .next()
+                .context("No capture devices are connected.")
+        },
+        Ok,
+    )
+}
+
+fn connect(
+    given: Option<PathBuf>,
+    profiles: Vec<VideoCaptureProfile>,
+) -> anyhow::Result<V4LVideoCaptureDevice<'static, 'static>> {
+    let path = device_path(given)?;
+    V4LVideoCaptureDevice::new_with_profiles(path.clone(), profiles)
+        .with_context(|| format!("Failed to connect to `{}`.", path.display()))
+}
+
+fn list<Out: Write>(out: &mut Out) -> anyhow::Result<()> {
+    for path in V4LBackend::list_connected_devices() {
+        // some video nodes (like metadata ones) don't have a media device
+        let descriptor = V4LSource::new(&path)
+            .and_then(|source| V4LVideoCaptureDescriptor::from_source(&source));
+
+        match descriptor {
+            Ok(found) => writeln!(
+                out,
+                "{}\t{}\t{}",
+                path.display(),
+                found.device_model,
+                found.device_identifier
+            )?,
+            Err(e) => {
+                tracing::info!("Skipping `{}`: {e}", path.display());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn info<Out: Write>(
+    out: &mut Out,
+    path: &Path,
+    profiles: &[VideoCaptureProfile],
+) -> anyhow::Result<()> {
+    let source = V4LSource::new(path)?;
+    let descriptor = V4LVideoCaptureDescriptor::from_source(&source)?;
+
+    writeln!(out, "media:  {}", source.media.display())?;
+    writeln!(out, "video:  {}", source.video.display())?;
+    writeln!(out, "model:  {}", descriptor.device_model)?;
+    writeln!(out, "serial: {}", descriptor.device_identifier)?;
+
+    let caps = &descriptor.capabilities;
+    writeln!(out, "driver: {} ({})", caps.driver, caps.version)?;
+    writeln!(out, "card:   {}", caps.card)?;
+    writeln!(out, "bus:    {}", caps.bus_info)?;
+    writeln!(out, "device capabilities: {}", caps.device_capabilities)?;
+    writeln!(out, "all capabilities:    {}", caps.capabilities)?;
+
+    let media = &descriptor.media_info;
+    writeln!(
+        out,
+        "media driver: {} ({}), hardware revision {:#x}",
+        media.driver, media.driver_version, media.hw_revision
+    )?;
+
+    writeln!(out, "nodes:")?;
+    for node in source.nodes()? {
+        writeln!(
+            out,
+            "  {} ({}){}",
+            node.path.display(),
+            node.kind,
+            node.entity_name
+                .map(|name| format!(": {name}"))
+                .unwrap_or_default()
+        )?;
+    }
+
+    let found = find_profile(
+        profiles,
+        &descriptor.device_identifier,
+        &descriptor.device_model,
+    );
+    if let Some(profile) = found {
+        writeln!(out, "profile: {}", profile.notes)?;
+    }
+
+    Ok(())
+}
+
+fn formats<Out: Write>(
+    out: &mut Out,
+    device: &V4LVideoCaptureDevice<'_, '_>,
+) -> anyhow::Result<()> {
+    for conf in device.supported_image_configurations()? {
+        writeln!(out, "{conf}")?;
+    }
+
+    Ok(())
+}
+
+fn get<Out: Write>(
+    out: &mut Out,
+    device: &V4LVideoCaptureDevice<'_, '_>,
+    keys: &[String],
+) -> anyhow::Result<()> {
+    if keys.is_empty() {
+        writeln!(out, "config={}", device.image_configuration()?)?;
+        for property in device.properties() {
+            writeln!(out, "{property}")?;
+        }
+        return Ok(());
+    }
+
+    for key in keys {
+        if key == "config" {
+            writeln!(out, "config={}", device.image_configuration()?)?;
+            continue;
+        }
+
+        let Some(property) = device.property(key.clone()) else {
+            bail!("The device has no property called `{key}`.");
+        };
+        writeln!(out, "{property}")?;
+    }
+
+    Ok(())
+}
+
+fn set<Out: Write>(
+    out: &mut Out,
+    device: &mut V4LVideoCaptureDevice<'_, '_>,
+    config: Option<&VideoCaptureImageConfiguration>,
+    properties: &[VideoCaptureProperty],
+) -> anyhow::Result<()> {
+    if let Some(conf) = config {
+        let actual = device.set_image_configuration(conf)?;
+        writeln!(out, "config={actual}")?;
+    }
+
+    for property in properties {
+        device.set_property(property)?;
+
+        // read it back, since devices can round or clamp values
+        match device.property(property.key()) {
+            Some(actual) => writeln!(out, "{actual}")?,
+            None => writeln!(out, "{property}")?,
+        }
+    }
+
+    Ok(())
+}
+
+fn capture<Out: Write>(
+    out: &mut Out,
+    device: &mut V4LVideoCaptureDevice<'_, '_>,
+    args: &CaptureArgs,
+) -> anyhow::Result<()> {
+    if let Some(ref path) = args.snapshot {
+        let frame = device.read_frame_timeout(args.timeout)?;
+        fs::write(path, frame.data)
+            .with_context(|| format!("Failed to write `{}`.", path.display()))?;
+        writeln!(out, "{}", path.display())?;
+        return Ok(());
+    }
+
+    let Some(ref dir) = args.output_dir else {
+        bail!("Give either `--snapshot` or `--output-dir`.");
+    };
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create `{}`.", dir.display()))?;
+
+    for _ in 0..args.frames {
+        let frame = device.read_frame_timeout(args.timeout)?;
+        let path = dir.join(format!(
+            "frame-{:06}.{}",
+            frame.metadata.sequence,
+            extension(frame.format)
+        ));
+
+        fs::write(&path, frame.data)
+            .with_context(|| format!("Failed to write `{}`.", path.display()))?;
+        writeln!(out, "{}", path.display())?;
+    }
+
+    Ok(())
+}
+
+fn preview<Out: Write>(
+    out: &mut Out,
+    given: Option<PathBuf>,
+    profiles: &[VideoCaptureProfile],
+    address: SocketAddr,
+) -> anyhow::Result<()> {
+    let paths = given.map_or_else(V4LBackend::list_connected_devices, |path| vec![path]);
+    if paths.is_empty() {
+        bail!("No capture devices are connected.");
+    }
+
+    let server = PreviewServer::bind(address)?;
+    let mut threads = Vec::new();
+    for path in paths {
+        let name = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |name| name.to_string_lossy().into(),
+        );
+        let device = connect(Some(path), profiles.to_vec())?;
+
+        writeln!(out, "http://{}/{name}", server.address())?;
+        threads.push(server.spawn_device(&name, device));
+    }
+    out.flush()?;
+
+    // keep serving until every device stops
+    for thread in threads {
+        if let Ok(Err(e)) = thread.join() {
+            writeln!(out, "{e}")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Picks a file extension for a frame's format.
+///
+/// JPEG-based formats get `.jpg`, so they open in anything. Everything else
+/// is raw, so it's named after its FourCC.
+fn extension(format: Format) -> String {
+    if format == Format::MJPEG || format == Format::JPEG {
+        return String::from("jpg");
+    }
+
+    format.to_string().to_lowercase()
+}
+
+/// Parses a number of seconds, like `2.5`.
+fn seconds(input: &str) -> Result<Duration, String> {
+    input
+        .parse()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .ok_or_else(|| format!("`{input}` isn't a number of seconds."))
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory as _;
+
+    use super::*;
+
+    #[test]
+    fn arguments_are_consistent() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn set_parses_library_types() {
+        let cli = Cli::try_parse_from([
+            "serumcv",
+            "set",
+            "--config",
+            "640x480@30 YUYV",
+            "brightness=128",
+            "exposure_auto=1",
+        ])
+        .unwrap();
+
+        let Command::Set { config, properties } = cli.command else {
+            panic!("expected the `set` command");
+        };
+        assert_eq!(
+            config.map(|conf| conf.resolution),
+            Some(SpecificResolution::RES_4X3_480P),
+            "config parsed"
+        );
+        assert_eq!(properties.len(), 2, "both properties parsed");
+        assert_eq!(
+            extension(Format::YUYV),
+            "yuyv",
+            "raw frames use their fourcc"
+        );
+    }
+
+    #[test]
+    fn profiles_load_from_toml() {
+        let profiles = parse_profiles(
+            r#"
+            [[profiles]]
+            device_model = "C922 Pro Stream Webcam"
+            notes = "every unit"
+
+            [[profiles]]
+            device_identifier = "ABCD1234"
+            device_model = "C922 Pro Stream Webcam"
+            notes = "the one on the arm"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(profiles.len(), 2, "both profiles");
+
+        let notes = |serial: &str| {
+            find_profile(&profiles, serial, "C922 Pro Stream Webcam")
+                .map(|profile| profile.notes.as_str())
+        };
+        assert_eq!(notes("ABCD1234"), Some("the one on the arm"), "exact");
+        assert_eq!(notes("EFGH5678"), Some("every unit"), "model");
+
+        assert!(parse_profiles("").unwrap().is_empty(), "no profiles");
+        assert!(
+            parse_profiles("[[profiles]]\nnotes = 3").is_err(),
+            "bad fields"
+        );
+    }
+
+    #[test]
+    fn capture_timeouts_are_seconds() {
+        let cli = Cli::try_parse_from(["serumcv", "capture", "-s", "a.jpg", "-t", "0.25"]).unwrap();
+        let Command::Capture(args) = cli.command else {
+            panic!("expected the `capture` command");
+        };
+        assert_eq!(args.timeout, Duration::from_millis(250));
+
+        assert!(
+            Cli::try_parse_from(["serumcv", "capture", "-s", "a.jpg", "-t", "-1"]).is_err(),
+            "negative timeouts"
+        );
+    }
+}
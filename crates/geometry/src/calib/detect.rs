@@ -0,0 +1,712 @@
+//! Finding a checkerboard's inner corners in a grayscale image.
+//!
+//! Corners where four squares meet are saddle points of the image's
+//! brightness, so candidates come from the Hessian's determinant. Each one
+//! has to look like an X up close, with dark and bright squares alternating
+//! around it. Then a lattice is grown outward from the middle of the board,
+//! predicting where each next corner should be from the ones already found.
+
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
+
+use serumcv_image::ImageView;
+
+use super::Checkerboard;
+
+/// The blur applied before looking for saddle points.
+const BLUR_SIGMA: f64 = 1.5;
+
+/// How far around a candidate we look for alternating squares, in pixels.
+/// Squares have to be at least twice this big.
+const RING_RADIUS: f64 = 4.0;
+
+/// How many points on that ring are sampled.
+const RING_SAMPLES: usize = 32;
+
+/// The weakest candidate we'll keep, relative to the strongest.
+const MIN_RESPONSE: f32 = 0.05;
+
+/// How far a corner may be from where the lattice predicts it, relative to
+/// the distance between neighboring corners.
+const MATCH_TOLERANCE: f64 = 0.3;
+
+/// A grayscale image, as floats, that can be sampled between pixels.
+pub(super) struct Gray {
+    width: usize,
+    height: usize,
+    data: Vec<f32>,
+}
+
+impl Gray {
+    pub(super) fn new(image: &ImageView<'_, u8>) -> Self {
+        let data = image
+            .rows()
+            .flat_map(|row| row.iter().map(|&value| f32::from(value)))
+            .collect();
+
+        Self {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            data,
+        }
+    }
+
+    /// A Gaussian-blurred copy.
+    fn blurred(&self, sigma: f64) -> Self {
+        let (kernel, radius) = gaussian_kernel(sigma);
+
+        let horizontal = Self::from_fn(self.width, self.height, |x, y| {
+            kernel
+                .iter()
+                .enumerate()
+                .map(|(k, weight)| weight * self.at_clamped((x + k).saturating_sub(radius), y))
+                .sum()
+        });
+        Self::from_fn(self.width, self.height, |x, y| {
+            kernel
+                .iter()
+                .enumerate()
+                .map(|(k, weight)| {
+                    weight * horizontal.at_clamped(x, (y + k).saturating_sub(radius))
+                })
+                .sum()
+        })
+    }
+
+    fn from_fn<F: FnMut(usize, usize) -> f32>(width: usize, height: usize, mut f: F) -> Self {
+        let mut data = Vec::with_capacity(width.saturating_mul(height));
+        for y in 0..height {
+            for x in 0..width {
+                data.push(f(x, y));
+            }
+        }
+
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// The pixel at `(x, y)`, or the nearest edge pixel if that's past the
+    /// right or bottom edge.
+    fn at_clamped(&self, x: usize, y: usize) -> f32 {
+        let index =
+            y.min(self.height.saturating_sub(1)) * self.width + x.min(self.width.saturating_sub(1));
+        self.data.get(index).copied().unwrap_or_default()
+    }
+
+    /// Bilinearly samples the image between pixels.
+    pub(super) fn sample(&self, x: f64, y: f64) -> f64 {
+        let (floor_x, floor_y) = (x.floor(), y.floor());
+        let (fx, fy) = (x - floor_x, y - floor_y);
+        let (left, top) = (to_index(floor_x), to_index(floor_y));
+        let pixel = |dx: usize, dy: usize| f64::from(self.at_clamped(left + dx, top + dy));
+
+        let upper = fx.mul_add(pixel(1, 0) - pixel(0, 0), pixel(0, 0));
+        let lower = fx.mul_add(pixel(1, 1) - pixel(0, 1), pixel(0, 1));
+        fy.mul_add(lower - upper, upper)
+    }
+
+    /// The image's gradient, by central differences between samples.
+    fn gradient(&self, x: f64, y: f64) -> [f64; 2] {
+        [
+            (self.sample(x + 1.0, y) - self.sample(x - 1.0, y)) / 2.0,
+            (self.sample(x, y + 1.0) - self.sample(x, y - 1.0)) / 2.0,
+        ]
+    }
+
+    /// How much each pixel looks like a saddle point: `fxy² - fxx * fyy`,
+    /// the Hessian's determinant with its sign flipped.
+    fn saddle_response(&self) -> Self {
+        Self::from_fn(self.width, self.height, |x, y| {
+            if x == 0 || y == 0 || x + 1 >= self.width || y + 1 >= self.height {
+                return 0.0;
+            }
+
+            let at = |dx: usize, dy: usize| self.at_clamped(x + dx - 1, y + dy - 1);
+            let center = at(1, 1);
+            let fxx = 2.0_f32.mul_add(-center, at(2, 1)) + at(0, 1);
+            let fyy = 2.0_f32.mul_add(-center, at(1, 2)) + at(1, 0);
+            let fxy = (at(2, 2) - at(2, 0) - at(0, 2) + at(0, 0)) / 4.0;
+            fxy.mul_add(fxy, -(fxx * fyy)).max(0.0)
+        })
+    }
+
+    /// Local maxima of the saddle response.
+    fn saddle_points(&self) -> Vec<[f64; 2]> {
+        let response = self.saddle_response();
+        let strongest = response.data.iter().copied().fold(0.0, f32::max);
+        if strongest <= 0.0 {
+            return Vec::new();
+        }
+
+        let radius = 3;
+        let mut points = Vec::new();
+        for y in radius..self.height.saturating_sub(radius) {
+            for x in radius..self.width.saturating_sub(radius) {
+                let value = response.at_clamped(x, y);
+                if value < strongest * MIN_RESPONSE {
+                    continue;
+                }
+
+                // ties go to the first pixel, so flat peaks give one point
+                let is_peak = (y - radius..=y + radius).all(|ny| {
+                    (x - radius..=x + radius).all(|nx| {
+                        let other = response.at_clamped(nx, ny);
+                        let later = (ny, nx) >= (y, x);
+                        other < value || (other <= value && later)
+                    })
+                });
+                if is_peak {
+                    points.push([x as f64, y as f64]);
+                }
+            }
+        }
+
+        points
+    }
+
+    /// Checks that dark and bright squares alternate around a point, like
+    /// they do at a checkerboard's inner corners.
+    ///
+    /// The board's outer corners and edges only have one or two squares
+    /// around them, so they don't pass.
+    fn looks_like_corner(&self, point: [f64; 2]) -> bool {
+        let ring: Vec<f64> = (0..RING_SAMPLES)
+            .map(|index| {
+                let angle = core::f64::consts::TAU * index as f64 / RING_SAMPLES as f64;
+                let (sin, cos) = angle.sin_cos();
+                self.sample(
+                    RING_RADIUS.mul_add(cos, point[0]),
+                    RING_RADIUS.mul_add(sin, point[1]),
+                )
+            })
+            .collect();
+
+        let (darkest, brightest) = ring
+            .iter()
+            .fold((f64::MAX, f64::MIN), |(low, high), &value| {
+                (low.min(value), high.max(value))
+            });
+        if brightest - darkest < 20.0 {
+            return false;
+        }
+
+        let middle = f64::midpoint(darkest, brightest);
+        let bright: Vec<bool> = ring.iter().map(|&value| value > middle).collect();
+        let around = bright.iter().zip(bright.iter().cycle().skip(1));
+        let changes = around.clone().filter(|&(now, next)| now != next).count();
+        // opposite squares are the same color
+        let opposite = bright
+            .iter()
+            .zip(bright.iter().cycle().skip(RING_SAMPLES.div_ceil(2)))
+            .filter(|&(here, there)| here == there)
+            .count();
+
+        changes == 4 && opposite * 5 >= RING_SAMPLES * 4
+    }
+
+    /// Moves a corner to the point where the gradients around it are
+    /// perpendicular to their offsets from it, like OpenCV's
+    /// `cornerSubPix`.
+    pub(super) fn refine(&self, corner: [f64; 2], window: u32) -> [f64; 2] {
+        let half = f64::from(window.max(1));
+        let sigma = half / 2.0;
+        let mut refined = corner;
+
+        for _ in 0..20 {
+            let (mut gxx, mut gxy, mut gyy) = (0.0, 0.0, 0.0);
+            let (mut bx, mut by) = (0.0, 0.0);
+
+            for dy in -i64::from(window)..=i64::from(window) {
+                for dx in -i64::from(window)..=i64::from(window) {
+                    let (ox, oy) = (dx as f64, dy as f64);
+                    let weight = (-ox.mul_add(ox, oy * oy) / (2.0 * sigma * sigma)).exp();
+                    let (px, py) = (refined[0] + ox, refined[1] + oy);
+                    let [gx, gy] = self.gradient(px, py);
+
+                    let (wxx, wxy, wyy) = (weight * gx * gx, weight * gx * gy, weight * gy * gy);
+                    gxx += wxx;
+                    gxy += wxy;
+                    gyy += wyy;
+                    bx += wxx.mul_add(px, wxy * py);
+                    by += wxy.mul_add(px, wyy * py);
+                }
+            }
+
+            let det = gxx.mul_add(gyy, -(gxy * gxy));
+            if det.abs() < 1e-9 {
+                break;
+            }
+            let next = [
+                gyy.mul_add(bx, -(gxy * by)) / det,
+                gxx.mul_add(by, -(gxy * bx)) / det,
+            ];
+            let moved = (next[0] - refined[0]).hypot(next[1] - refined[1]);
+
+            // a corner that wanders off its window wasn't a corner
+            if (next[0] - corner[0]).hypot(next[1] - corner[1]) > half {
+                return corner;
+            }
+            refined = next;
+            if moved < 0.005 {
+                break;
+            }
+        }
+
+        refined
+    }
+}
+
+/// Finds a checkerboard's inner corners.
+///
+/// The corners come back row by row, starting from the one nearest the
+/// image's top-left. Within each row, they go left to right as the board
+/// is seen, so a board held upright gives the usual reading order.
+///
+/// This returns `None` unless every corner was found. Squares need to be at
+/// least ten pixels across, and the board needs a bit of quiet space around
+/// it.
+#[inline]
+pub fn find_checkerboard(image: &ImageView<'_, u8>, board: &Checkerboard) -> Option<Vec<[f64; 2]>> {
+    let (columns, rows) = (board.columns as usize, board.rows as usize);
+    if columns < 2 || rows < 2 {
+        return None;
+    }
+
+    let gray = Gray::new(image).blurred(BLUR_SIGMA);
+    let candidates: Vec<[f64; 2]> = gray
+        .saddle_points()
+        .into_iter()
+        .filter(|&point| gray.looks_like_corner(point))
+        .map(|point| gray.refine(point, 3))
+        .collect();
+    if candidates.len() < columns * rows {
+        return None;
+    }
+
+    let lattice = grow_lattice(&candidates)?;
+    let grid = lattice.into_grid(&candidates, columns, rows)?;
+    let ordered = orient(grid, columns, rows);
+
+    // refine again, with a window sized to the squares
+    let spacing = ordered
+        .iter()
+        .zip(ordered.iter().skip(1))
+        .map(|(&a, &b)| distance(a, b))
+        .fold(f64::MAX, f64::min);
+    let window = u32::try_from(to_index((spacing / 3.0).clamp(2.0, 10.0))).unwrap_or(2);
+    Some(
+        ordered
+            .into_iter()
+            .map(|corner| gray.refine(corner, window))
+            .collect(),
+    )
+}
+
+/// Moves corners to sub-pixel accuracy.
+///
+/// `window` is half the size of the square looked at around each corner. It
+/// should cover a good part of the squares, without reaching the next
+/// corner over. [`find_checkerboard`] already does this for you.
+#[inline]
+pub fn refine_corners(image: &ImageView<'_, u8>, corners: &mut [[f64; 2]], window: u32) {
+    let gray = Gray::new(image);
+    for corner in corners {
+        *corner = gray.refine(*corner, window);
+    }
+}
+
+/// Candidates that fit on a lattice, by their lattice coordinates.
+struct Lattice {
+    cells: BTreeMap<(i64, i64), usize>,
+}
+
+impl Lattice {
+    fn point(&self, candidates: &[[f64; 2]], cell: (i64, i64)) -> Option<[f64; 2]> {
+        self.cells
+            .get(&cell)
+            .and_then(|&index| candidates.get(index).copied())
+    }
+
+    /// Puts the lattice's corners into rows, if it has exactly the board's
+    /// shape (either way around).
+    fn into_grid(
+        self,
+        candidates: &[[f64; 2]],
+        columns: usize,
+        rows: usize,
+    ) -> Option<Vec<Vec<[f64; 2]>>> {
+        let min_i = self.cells.keys().map(|&(i, _)| i).min()?;
+        let max_i = self.cells.keys().map(|&(i, _)| i).max()?;
+        let min_j = self.cells.keys().map(|&(_, j)| j).min()?;
+        let max_j = self.cells.keys().map(|&(_, j)| j).max()?;
+        let width = usize::try_from(max_i - min_i + 1).ok()?;
+        let height = usize::try_from(max_j - min_j + 1).ok()?;
+        if self.cells.len() != columns * rows || width * height != self.cells.len() {
+            return None;
+        }
+
+        let transposed = if (width, height) == (columns, rows) {
+            false
+        } else if (width, height) == (rows, columns) {
+            true
+        } else {
+            return None;
+        };
+
+        (0..rows)
+            .map(|row| {
+                (0..columns)
+                    .map(|column| {
+                        let (along, down) = if transposed {
+                            (row, column)
+                        } else {
+                            (column, row)
+                        };
+                        let cell = (
+                            min_i + i64::try_from(along).ok()?,
+                            min_j + i64::try_from(down).ok()?,
+                        );
+                        self.point(candidates, cell)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Grows a lattice of corners outward from the one nearest the middle.
+fn grow_lattice(candidates: &[[f64; 2]]) -> Option<Lattice> {
+    let count = candidates.len() as f64;
+    let centroid = candidates.iter().fold([0.0, 0.0], |sum, point| {
+        [sum[0] + point[0] / count, sum[1] + point[1] / count]
+    });
+    let seed = nearest(candidates, centroid, &[])?;
+    let seed_point = *candidates.get(seed)?;
+
+    // the two lattice directions, from the seed's nearest neighbors
+    let first = nearest(candidates, seed_point, &[seed])?;
+    let first_point = *candidates.get(first)?;
+    let step = sub(first_point, seed_point);
+    let second = candidates
+        .iter()
+        .enumerate()
+        .filter(|&(index, _)| index != seed && index != first)
+        .filter(|&(_, &point)| {
+            let offset = sub(point, seed_point);
+            let cos = dot(offset, step) / (norm(offset) * norm(step));
+            cos.abs() < 0.5
+        })
+        .min_by(|&(_, &a), &(_, &b)| distance(a, seed_point).total_cmp(&distance(b, seed_point)))
+        .map(|(index, _)| index)?;
+
+    let mut lattice = Lattice {
+        cells: BTreeMap::from([((0, 0), seed), ((1, 0), first), ((0, 1), second)]),
+    };
+    let mut used = vec![seed, first, second];
+
+    loop {
+        let mut grew = false;
+        let filled: Vec<(i64, i64)> = lattice.cells.keys().copied().collect();
+        for (i, j) in filled {
+            for (di, dj) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                let target = (i + di, j + dj);
+                if lattice.cells.contains_key(&target) {
+                    continue;
+                }
+                let Some((predicted, spacing)) = predict(&lattice, candidates, target) else {
+                    continue;
+                };
+                let Some(found) = nearest(candidates, predicted, &used) else {
+                    continue;
+                };
+                let found_point = candidates.get(found).copied()?;
+                if distance(found_point, predicted) <= spacing * MATCH_TOLERANCE {
+                    lattice.cells.insert(target, found);
+                    used.push(found);
+                    grew = true;
+                }
+            }
+        }
+
+        if !grew {
+            return Some(lattice);
+        }
+    }
+}
+
+/// Predicts where a lattice cell's corner should be, along with the local
+/// distance between corners.
+fn predict(
+    lattice: &Lattice,
+    candidates: &[[f64; 2]],
+    (i, j): (i64, i64),
+) -> Option<([f64; 2], f64)> {
+    let at = |cell: (i64, i64)| lattice.point(candidates, cell);
+
+    // continue a straight line of two corners
+    for (di, dj) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+        if let (Some(near), Some(far)) = (at((i + di, j + dj)), at((i + 2 * di, j + 2 * dj))) {
+            return Some((sub(scale(near, 2.0), far), distance(near, far)));
+        }
+    }
+
+    // or complete a parallelogram of three
+    for (di, dj) in [(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+        if let (Some(across), Some(down), Some(diagonal)) =
+            (at((i + di, j)), at((i, j + dj)), at((i + di, j + dj)))
+        {
+            let predicted = sub([across[0] + down[0], across[1] + down[1]], diagonal);
+            return Some((
+                predicted,
+                distance(across, diagonal).min(distance(down, diagonal)),
+            ));
+        }
+    }
+
+    None
+}
+
+/// Puts the grid in its canonical order: counterclockwise from the
+/// columns to the rows (which is clockwise on screen, where y points down),
+/// starting with the corner nearest the image's top-left.
+fn orient(mut grid: Vec<Vec<[f64; 2]>>, columns: usize, rows: usize) -> Vec<[f64; 2]> {
+    let corner = |grid: &Vec<Vec<[f64; 2]>>, row: usize, column: usize| {
+        grid.get(row)
+            .and_then(|cells| cells.get(column))
+            .copied()
+            .unwrap_or_default()
+    };
+
+    let origin = corner(&grid, 0, 0);
+    let along = sub(corner(&grid, 0, 1), origin);
+    let down = sub(corner(&grid, 1, 0), origin);
+    if along[0].mul_add(down[1], -(along[1] * down[0])) < 0.0 {
+        grid.iter_mut().for_each(|cells| cells.reverse());
+    }
+
+    // a half turn always keeps the board's shape, and square boards can
+    // take quarter turns, too
+    let mut options = vec![grid.clone()];
+    let mut half_turn = grid.clone();
+    half_turn.reverse();
+    half_turn.iter_mut().for_each(|cells| cells.reverse());
+    options.push(half_turn);
+    if columns == rows {
+        let quarter = |grid: &Vec<Vec<[f64; 2]>>| -> Vec<Vec<[f64; 2]>> {
+            (0..rows)
+                .map(|row| {
+                    (0..columns)
+                        .map(|column| corner(grid, columns - 1 - column, row))
+                        .collect()
+                })
+                .collect()
+        };
+        let quarter_turn = quarter(&grid);
+        let three_quarters = quarter(&options.get(1).cloned().unwrap_or_default());
+        options.push(quarter_turn);
+        options.push(three_quarters);
+    }
+
+    options
+        .into_iter()
+        .min_by(|a, b| {
+            let score = |grid: &Vec<Vec<[f64; 2]>>| {
+                let first = corner(grid, 0, 0);
+                first[0] + first[1]
+            };
+            score(a).total_cmp(&score(b))
+        })
+        .unwrap_or(grid)
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// The closest candidate to a point, skipping some.
+fn nearest(candidates: &[[f64; 2]], point: [f64; 2], skip: &[usize]) -> Option<usize> {
+    candidates
+        .iter()
+        .enumerate()
+        .filter(|&(index, _)| !skip.contains(&index))
+        .min_by(|&(_, &a), &(_, &b)| distance(a, point).total_cmp(&distance(b, point)))
+        .map(|(index, _)| index)
+}
+
+/// A normalized Gaussian kernel, three sigmas wide on each side, and how
+/// wide each side is.
+fn gaussian_kernel(sigma: f64) -> (Vec<f32>, usize) {
+    let radius = to_index((sigma * 3.0).ceil()).max(1);
+    let weights: Vec<f64> = (0..=2 * radius)
+        .map(|k| {
+            let offset = k as f64 - radius as f64;
+            (-offset * offset / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+    let total: f64 = weights.iter().sum();
+
+    let kernel = weights
+        .iter()
+        .map(|weight| to_f32(weight / total))
+        .collect();
+    (kernel, radius)
+}
+
+#[expect(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    reason = "coordinates are clamped to zero first, and images are much smaller than `usize::MAX`"
+)]
+const fn to_index(value: f64) -> usize {
+    value.max(0.0) as usize
+}
+
+#[expect(
+    clippy::cast_possible_truncation,
+    reason = "kernel weights are between zero and one"
+)]
+const fn to_f32(value: f64) -> f32 {
+    value as f32
+}
+
+fn sub(a: [f64; 2], b: [f64; 2]) -> [f64; 2] {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn scale(a: [f64; 2], factor: f64) -> [f64; 2] {
+    [a[0] * factor, a[1] * factor]
+}
+
+fn dot(a: [f64; 2], b: [f64; 2]) -> f64 {
+    a[0].mul_add(b[0], a[1] * b[1])
+}
+
+fn norm(a: [f64; 2]) -> f64 {
+    a[0].hypot(a[1])
+}
+
+fn distance(a: [f64; 2], b: [f64; 2]) -> f64 {
+    norm(sub(a, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use serumcv_image::Image;
+
+    use super::super::render::render_board;
+    use super::*;
+
+    const BOARD: Checkerboard = Checkerboard::new(5, 4, 1.0);
+    /// How big each square is, in pixels.
+    const SQUARE: f64 = 16.0;
+
+    /// Renders the board turned by `angle` radians around `center`, and
+    /// gives back where its corners are, row by row as the board reads.
+    fn render(angle: f64, center: [f64; 2]) -> (Image<u8>, Vec<[f64; 2]>) {
+        let (sin, cos) = angle.sin_cos();
+        let (columns, rows) = (f64::from(BOARD.columns), f64::from(BOARD.rows));
+        // the middle of the board, in squares
+        let middle = [(columns + 1.0) / 2.0, (rows + 1.0) / 2.0];
+
+        // the board's first corner is at [1, 1] here, but [0, 0] when rendered
+        let image = render_board(&BOARD, 240, 200, |u, v| {
+            let (dx, dy) = (u - center[0], v - center[1]);
+            [
+                cos.mul_add(dx, sin * dy) / SQUARE + middle[0] - 1.0,
+                (-sin).mul_add(dx, cos * dy) / SQUARE + middle[1] - 1.0,
+            ]
+        });
+
+        let corners = (1..=BOARD.rows)
+            .flat_map(|row| {
+                (1..=BOARD.columns).map(move |column| (f64::from(column), f64::from(row)))
+            })
+            .map(|(board_x, board_y)| {
+                let (dx, dy) = (
+                    (board_x - middle[0]) * SQUARE,
+                    (board_y - middle[1]) * SQUARE,
+                );
+                [
+                    cos.mul_add(dx, -(sin * dy)) + center[0],
+                    sin.mul_add(dx, cos * dy) + center[1],
+                ]
+            })
+            .collect();
+        (image, corners)
+    }
+
+    #[test]
+    fn upright_boards_come_back_row_by_row() {
+        // edges between pixels, so they blur evenly
+        let (image, truth) = render(0.0, [120.5, 100.5]);
+        let found = find_checkerboard(&image.view(), &BOARD).expect("the board is in view");
+
+        assert_eq!(found.len(), BOARD.corner_count());
+        for (index, (corner, wanted)) in found.iter().zip(&truth).enumerate() {
+            assert!(
+                distance(*corner, *wanted) < 0.25,
+                "corner {index} is at {corner:?}, not {wanted:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn turned_boards_start_at_the_top_left() {
+        // upside down, and a bit more
+        let (image, truth) = render(3.4, [120.0, 100.0]);
+        let found = find_checkerboard(&image.view(), &BOARD).expect("the board is in view");
+
+        for corner in &found {
+            assert!(
+                truth.iter().any(|&wanted| distance(*corner, wanted) < 0.25),
+                "{corner:?} isn't a corner"
+            );
+        }
+        let top_left = truth
+            .iter()
+            .copied()
+            .min_by(|a, b| (a[0] + a[1]).total_cmp(&(b[0] + b[1])))
+            .unwrap();
+        let first = *found.first().unwrap();
+        assert!(
+            distance(first, top_left) < 0.25,
+            "{first:?} isn't the top left"
+        );
+
+        // rows go clockwise from columns, on screen
+        let columns = BOARD.columns as usize;
+        let along = sub(*found.get(1).unwrap(), first);
+        let down = sub(*found.get(columns).unwrap(), first);
+        assert!(
+            along[0].mul_add(down[1], -(along[1] * down[0])) > 0.0,
+            "along {along:?}, then down {down:?}"
+        );
+        assert!(
+            norm(along) < SQUARE * 1.1 && norm(down) < SQUARE * 1.1,
+            "neighbors are next to each other"
+        );
+    }
+
+    #[test]
+    fn partial_boards_arent_found() {
+        let (cut_off, _) = render(0.0, [30.0, 100.0]);
+        assert_eq!(
+            find_checkerboard(&cut_off.view(), &BOARD),
+            None,
+            "the left columns are outside the image"
+        );
+
+        let (whole, _) = render(0.0, [120.0, 100.0]);
+        let bigger = Checkerboard::new(6, 4, 1.0);
+        assert_eq!(
+            find_checkerboard(&whole.view(), &bigger),
+            None,
+            "a column is missing"
+        );
+        let line = Checkerboard::new(1, 4, 1.0);
+        assert_eq!(find_checkerboard(&whole.view(), &line), None, "not a board");
+    }
+}
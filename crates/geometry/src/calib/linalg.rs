@@ -0,0 +1,444 @@
+//! Just enough dense linear algebra for calibration.
+//!
+//! The matrices here are tiny (at most a few hundred rows), so everything is
+//! written for clarity instead of speed.
+
+use core::ops::{Index, IndexMut, Mul};
+
+/// A dense, row-major matrix of `f64`s.
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct Matrix {
+    rows: usize,
+    cols: usize,
+    data: Vec<f64>,
+}
+
+impl Matrix {
+    /// A matrix full of zeros.
+    pub(super) fn zeros(rows: usize, cols: usize) -> Self {
+        Self {
+            rows,
+            cols,
+            data: vec![0.0; rows.saturating_mul(cols)],
+        }
+    }
+
+    /// The identity matrix.
+    pub(super) fn identity(size: usize) -> Self {
+        Self::from_fn(size, size, |row, col| if row == col { 1.0 } else { 0.0 })
+    }
+
+    /// Makes a matrix by calling `f` with each `(row, column)`.
+    pub(super) fn from_fn<F: FnMut(usize, usize) -> f64>(
+        rows: usize,
+        cols: usize,
+        mut f: F,
+    ) -> Self {
+        let mut data = Vec::with_capacity(rows.saturating_mul(cols));
+        for row in 0..rows {
+            for col in 0..cols {
+                data.push(f(row, col));
+            }
+        }
+
+        Self { rows, cols, data }
+    }
+
+    /// A 3x3 matrix from its rows.
+    pub(super) fn from_rows3(rows: [[f64; 3]; 3]) -> Self {
+        Self {
+            rows: 3,
+            cols: 3,
+            data: rows.as_flattened().to_vec(),
+        }
+    }
+
+    /// A 3x3 matrix from its columns.
+    pub(super) fn from_columns3(columns: [[f64; 3]; 3]) -> Self {
+        let [a, b, c] = columns;
+        Self::from_rows3([[a[0], b[0], c[0]], [a[1], b[1], c[1]], [a[2], b[2], c[2]]])
+    }
+
+    /// One of the matrix's columns.
+    pub(super) fn column(&self, col: usize) -> Vec<f64> {
+        (0..self.rows).map(|row| self[(row, col)]).collect()
+    }
+
+    /// A 3x3 matrix's columns, as arrays.
+    pub(super) fn column3(&self, col: usize) -> [f64; 3] {
+        [self[(0, col)], self[(1, col)], self[(2, col)]]
+    }
+
+    /// A 3x3 matrix's rows, as arrays.
+    pub(super) fn rows3(&self) -> [[f64; 3]; 3] {
+        [0, 1, 2].map(|row| [self[(row, 0)], self[(row, 1)], self[(row, 2)]])
+    }
+
+    pub(super) fn transpose(&self) -> Self {
+        Self::from_fn(self.cols, self.rows, |row, col| self[(col, row)])
+    }
+
+    /// Multiplies a 3x3 matrix by a 3D vector.
+    pub(super) fn apply3(&self, vector: [f64; 3]) -> [f64; 3] {
+        let row = |r: usize| {
+            self[(r, 0)].mul_add(
+                vector[0],
+                self[(r, 1)].mul_add(vector[1], self[(r, 2)] * vector[2]),
+            )
+        };
+        [row(0), row(1), row(2)]
+    }
+
+    /// Inverts a 3x3 matrix, unless it's singular.
+    pub(super) fn inverse3(&self) -> Option<Self> {
+        let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| {
+            self[(r0, c0)].mul_add(self[(r1, c1)], -(self[(r0, c1)] * self[(r1, c0)]))
+        };
+        let adjugate = [
+            [
+                cofactor(1, 2, 1, 2),
+                -cofactor(0, 2, 1, 2),
+                cofactor(0, 1, 1, 2),
+            ],
+            [
+                -cofactor(1, 2, 0, 2),
+                cofactor(0, 2, 0, 2),
+                -cofactor(0, 1, 0, 2),
+            ],
+            [
+                cofactor(1, 2, 0, 1),
+                -cofactor(0, 2, 0, 1),
+                cofactor(0, 1, 0, 1),
+            ],
+        ];
+        let det = self[(0, 0)].mul_add(
+            adjugate[0][0],
+            self[(0, 1)].mul_add(adjugate[1][0], self[(0, 2)] * adjugate[2][0]),
+        );
+        if det.abs() < f64::EPSILON {
+            return None;
+        }
+
+        let mut inverse = Self::from_rows3(adjugate);
+        inverse.data.iter_mut().for_each(|value| *value /= det);
+        Some(inverse)
+    }
+
+    /// The eigenvalues and eigenvectors of a symmetric matrix, using Jacobi
+    /// rotations.
+    ///
+    /// Eigenvalues come back smallest first. Each eigenvector is the
+    /// matching column of the returned matrix.
+    pub(super) fn symmetric_eigen(&self) -> (Vec<f64>, Self) {
+        let size = self.rows;
+        let mut a = self.clone();
+        let mut vectors = Self::identity(size);
+
+        for _sweep in 0..100 {
+            let off_diagonal: f64 = (0..size)
+                .flat_map(|row| (0..size).map(move |col| (row, col)))
+                .filter(|&(row, col)| row != col)
+                .map(|(row, col)| a[(row, col)].powi(2))
+                .sum();
+            if off_diagonal < 1e-30 {
+                break;
+            }
+
+            for p in 0..size {
+                for q in p.saturating_add(1)..size {
+                    a.rotate(&mut vectors, p, q);
+                }
+            }
+        }
+
+        let mut order: Vec<usize> = (0..size).collect();
+        order.sort_by(|&x, &y| a[(x, x)].total_cmp(&a[(y, y)]));
+        let values = order.iter().map(|&index| a[(index, index)]).collect();
+        let sorted = Self::from_fn(size, size, |row, col| {
+            order.get(col).map_or(0.0, |&from| vectors[(row, from)])
+        });
+
+        (values, sorted)
+    }
+
+    /// One Jacobi rotation that zeroes `self[(first, second)]`.
+    fn rotate(&mut self, vectors: &mut Self, first: usize, second: usize) {
+        let (p, q) = (first, second);
+        let off = self[(p, q)];
+        if off.abs() < 1e-300 {
+            return;
+        }
+
+        let theta = (self[(q, q)] - self[(p, p)]) / (2.0 * off);
+        let tan = theta.signum() / (theta.abs() + theta.hypot(1.0));
+        let cos = tan.hypot(1.0).recip();
+        let sin = tan * cos;
+
+        for k in 0..self.rows {
+            let (kp, kq) = (self[(k, p)], self[(k, q)]);
+            self[(k, p)] = cos.mul_add(kp, -(sin * kq));
+            self[(k, q)] = sin.mul_add(kp, cos * kq);
+        }
+        for k in 0..self.rows {
+            let (pk, qk) = (self[(p, k)], self[(q, k)]);
+            self[(p, k)] = cos.mul_add(pk, -(sin * qk));
+            self[(q, k)] = sin.mul_add(pk, cos * qk);
+        }
+        for k in 0..vectors.rows {
+            let (kp, kq) = (vectors[(k, p)], vectors[(k, q)]);
+            vectors[(k, p)] = cos.mul_add(kp, -(sin * kq));
+            vectors[(k, q)] = sin.mul_add(kp, cos * kq);
+        }
+    }
+
+    /// The unit vector `x` that makes `|self * x|` smallest.
+    ///
+    /// This is how homogeneous systems like `A x = 0` are solved.
+    pub(super) fn null_vector(&self) -> Vec<f64> {
+        let (_, vectors) = (self.transpose() * self).symmetric_eigen();
+        vectors.column(0)
+    }
+
+    /// Solves `self * x = rhs` for a symmetric, positive-definite matrix.
+    ///
+    /// Returns `None` if the matrix isn't positive-definite.
+    pub(super) fn solve_cholesky(&self, rhs: &[f64]) -> Option<Vec<f64>> {
+        let size = self.rows;
+        let mut lower = Self::zeros(size, size);
+        for row in 0..size {
+            for col in 0..=row {
+                let dot: f64 = (0..col).map(|k| lower[(row, k)] * lower[(col, k)]).sum();
+                let value = self[(row, col)] - dot;
+                if row == col {
+                    if value <= 0.0 {
+                        return None;
+                    }
+                    lower[(row, col)] = value.sqrt();
+                } else {
+                    lower[(row, col)] = value / lower[(col, col)];
+                }
+            }
+        }
+
+        // forward, then back substitution
+        let mut y = vec![0.0; size];
+        for row in 0..size {
+            let dot: f64 = (0..row)
+                .map(|k| lower[(row, k)] * y.get(k).copied().unwrap_or_default())
+                .sum();
+            let value = (rhs.get(row).copied().unwrap_or_default() - dot) / lower[(row, row)];
+            if let Some(slot) = y.get_mut(row) {
+                *slot = value;
+            }
+        }
+        let mut x = vec![0.0; size];
+        for row in (0..size).rev() {
+            let dot: f64 = (row.saturating_add(1)..size)
+                .map(|k| lower[(k, row)] * x.get(k).copied().unwrap_or_default())
+                .sum();
+            let value = (y.get(row).copied().unwrap_or_default() - dot) / lower[(row, row)];
+            if let Some(slot) = x.get_mut(row) {
+                *slot = value;
+            }
+        }
+
+        Some(x)
+    }
+}
+
+impl Index<(usize, usize)> for Matrix {
+    type Output = f64;
+
+    #[expect(
+        clippy::indexing_slicing,
+        reason = "going out of bounds is a bug in the calibration code, so it should panic"
+    )]
+    fn index(&self, (row, col): (usize, usize)) -> &f64 {
+        debug_assert!(row < self.rows && col < self.cols, "index out of bounds");
+        &self.data[row * self.cols + col]
+    }
+}
+
+impl IndexMut<(usize, usize)> for Matrix {
+    #[expect(
+        clippy::indexing_slicing,
+        reason = "going out of bounds is a bug in the calibration code, so it should panic"
+    )]
+    fn index_mut(&mut self, (row, col): (usize, usize)) -> &mut f64 {
+        debug_assert!(row < self.rows && col < self.cols, "index out of bounds");
+        &mut self.data[row * self.cols + col]
+    }
+}
+
+impl Mul<&Self> for Matrix {
+    type Output = Self;
+
+    fn mul(self, rhs: &Self) -> Self {
+        Self::from_fn(self.rows, rhs.cols, |row, col| {
+            (0..self.cols).map(|k| self[(row, k)] * rhs[(k, col)]).sum()
+        })
+    }
+}
+
+/// The cross product of two 3D vectors.
+pub(super) fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1].mul_add(b[2], -(a[2] * b[1])),
+        a[2].mul_add(b[0], -(a[0] * b[2])),
+        a[0].mul_add(b[1], -(a[1] * b[0])),
+    ]
+}
+
+/// The length of a 3D vector.
+pub(super) fn norm(a: [f64; 3]) -> f64 {
+    a[0].hypot(a[1]).hypot(a[2])
+}
+
+/// Turns a rotation vector (axis times angle, in radians) into a matrix.
+pub(super) fn rotation_from_vector(vector: [f64; 3]) -> Matrix {
+    let angle = norm(vector);
+    if angle < 1e-12 {
+        let [x, y, z] = vector;
+        return Matrix::from_rows3([[1.0, -z, y], [z, 1.0, -x], [-y, x, 1.0]]);
+    }
+
+    let [x, y, z] = vector.map(|component| component / angle);
+    let (sin, cos) = angle.sin_cos();
+    let rest = 1.0 - cos;
+    Matrix::from_rows3([
+        [
+            (rest * x).mul_add(x, cos),
+            (rest * x).mul_add(y, -(sin * z)),
+            (rest * x).mul_add(z, sin * y),
+        ],
+        [
+            (rest * y).mul_add(x, sin * z),
+            (rest * y).mul_add(y, cos),
+            (rest * y).mul_add(z, -(sin * x)),
+        ],
+        [
+            (rest * z).mul_add(x, -(sin * y)),
+            (rest * z).mul_add(y, sin * x),
+            (rest * z).mul_add(z, cos),
+        ],
+    ])
+}
+
+/// Turns a rotation matrix into a rotation vector.
+pub(super) fn vector_from_rotation(rotation: &Matrix) -> [f64; 3] {
+    let r = |row: usize, col: usize| rotation[(row, col)];
+    let trace = r(0, 0) + r(1, 1) + r(2, 2);
+    let angle = ((trace - 1.0) / 2.0).clamp(-1.0, 1.0).acos();
+    let skew = [r(2, 1) - r(1, 2), r(0, 2) - r(2, 0), r(1, 0) - r(0, 1)];
+
+    if angle < 1e-9 {
+        return skew.map(|component| component / 2.0);
+    }
+
+    if core::f64::consts::PI - angle < 1e-6 {
+        // near a half turn, the skew part vanishes, so use the diagonal
+        let axis =
+            [r(0, 0), r(1, 1), r(2, 2)].map(|diagonal| ((diagonal + 1.0) / 2.0).max(0.0).sqrt());
+        let signs = [
+            1.0,
+            (r(0, 1) + r(1, 0)).signum(),
+            (r(0, 2) + r(2, 0)).signum(),
+        ];
+        return [axis[0] * signs[0], axis[1] * signs[1], axis[2] * signs[2]]
+            .map(|component| component * angle);
+    }
+
+    let scale = angle / (2.0 * angle.sin());
+    skew.map(|component| component * scale)
+}
+
+/// The nearest rotation to a 3x3 matrix, by polar decomposition.
+pub(super) fn nearest_rotation(matrix: &Matrix) -> Matrix {
+    // R = M (MᵀM)^(-1/2)
+    let (values, vectors) = (matrix.transpose() * matrix).symmetric_eigen();
+    let inverse_root = Matrix::from_fn(3, 3, |row, col| {
+        (0..3)
+            .map(|k| {
+                let value = values.get(k).copied().unwrap_or(1.0).max(f64::MIN_POSITIVE);
+                vectors[(row, k)] * vectors[(col, k)] / value.sqrt()
+            })
+            .sum()
+    });
+
+    matrix.clone() * &inverse_root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotations_round_trip_through_vectors() {
+        for vector in [
+            [0.1, -0.2, 0.3],
+            [0.0, 0.0, 0.0],
+            [3.0, 0.1, 0.0],
+            [0.0, 1e-13, 0.0],
+        ] {
+            let back = vector_from_rotation(&rotation_from_vector(vector));
+            let error = norm([
+                back[0] - vector[0],
+                back[1] - vector[1],
+                back[2] - vector[2],
+            ]);
+            assert!(error < 1e-9, "{vector:?} came back as {back:?}");
+        }
+
+        // a symmetric matrix's eigenvectors really are eigenvectors
+        let matrix = Matrix::from_rows3([[4.0, 1.0, 0.5], [1.0, 3.0, 0.2], [0.5, 0.2, 1.0]]);
+        let (values, vectors) = matrix.symmetric_eigen();
+        assert!(values.is_sorted(), "eigenvalues come back smallest first");
+        for (index, value) in values.iter().enumerate() {
+            let vector = vectors.column3(index);
+            let applied = matrix.apply3(vector);
+            let [ax, ay, az] = applied;
+            let [vx, vy, vz] = vector;
+            let error = norm([ax - value * vx, ay - value * vy, az - value * vz]);
+            assert!(error < 1e-9, "eigenpair {index} is off by {error}");
+        }
+
+        let solved = matrix.solve_cholesky(&[1.0, 2.0, 3.0]).unwrap();
+        let [x, y, z] = matrix.apply3(solved.try_into().unwrap());
+        assert!(
+            norm([x - 1.0, y - 2.0, z - 3.0]) < 1e-9,
+            "the solution solves it"
+        );
+    }
+
+    #[test]
+    fn singular_matrices_arent_solved() {
+        let singular = Matrix::from_rows3([[1.0, 2.0, 3.0], [2.0, 4.0, 6.0], [0.0, 1.0, 1.0]]);
+        assert_eq!(singular.inverse3(), None, "rows are multiples");
+        assert_eq!(Matrix::zeros(3, 3).inverse3(), None, "all zeros");
+
+        let flat = Matrix::from_rows3([[1.0, 1.0, 0.0], [1.0, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+        assert_eq!(
+            flat.solve_cholesky(&[1.0, 1.0, 1.0]),
+            None,
+            "rank deficient"
+        );
+        let indefinite = Matrix::from_rows3([[1.0, 0.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, 1.0]]);
+        assert_eq!(
+            indefinite.solve_cholesky(&[1.0, 1.0, 1.0]),
+            None,
+            "not positive-definite"
+        );
+
+        // but invertible ones still invert
+        let fine = Matrix::from_rows3([[2.0, 0.0, 1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 1.0]]);
+        let product = fine.inverse3().unwrap() * &fine;
+        assert!(
+            product
+                .data
+                .iter()
+                .zip(&Matrix::identity(3).data)
+                .all(|(found, expected)| (found - expected).abs() < 1e-12),
+            "inverse times matrix is {product:?}"
+        );
+    }
+}
@@ -0,0 +1,314 @@
+//! Camera calibration from pictures of a checkerboard.
+//!
+//! Hold a printed checkerboard in front of the camera at a bunch of
+//! different angles, find its corners in each frame, then hand them all to
+//! [`calibrate`]. You'll get the camera's intrinsics and lens distortion,
+//! along with how far off they are (the reprojection error, in pixels).
+//!
+//! Calibrations belong to a specific device, so capture devices can keep
+//! them in their profiles.
+//!
+//! For a stereo pair, calibrate each camera, then hand views that both
+//! cameras saw at once to [`calibrate_stereo`].
+//!
+//! ```no_run
+//! use serumcv_geometry::calib::{self, CalibrationOptions, Checkerboard};
+//! use serumcv_geometry::Resolution;
+//! use serumcv_image::Image;
+//!
+//! fn calibrate(frames: &[Image<u8>]) {
+//!     // 9x6 inner corners, with 25 mm squares
+//!     let board = Checkerboard::new(9, 6, 0.025);
+//!     let views: Vec<_> = frames
+//!         .iter()
+//!         .filter_map(|frame| calib::find_checkerboard(&frame.view(), &board))
+//!         .collect();
+//!
+//!     // every frame is the same size
+//!     let resolution = frames
+//!         .first()
+//!         .map_or_else(Resolution::default, |frame| Resolution::new(frame.width(), frame.height()));
+//!     let report = calib::calibrate(&board, &views, resolution, CalibrationOptions::default()).unwrap();
+//!     println!("{:?}, off by {:.2} px", report.calibration.camera, report.calibration.rms_error);
+//! }
+//! ```
+
+mod detect;
+mod linalg;
+#[cfg(test)]
+mod render;
+mod stereo;
+mod zhang;
+
+pub use detect::{find_checkerboard, refine_corners};
+pub use stereo::{calibrate_stereo, StereoCalibration, StereoRectification};
+pub use zhang::calibrate;
+
+use crate::camera::Camera;
+
+/// A checkerboard calibration target.
+///
+/// Boards are described by their inner corners, where four squares meet. A
+/// board with 10x7 squares has 9x6 inner corners. Boards with an odd number
+/// of corners one way and an even number the other look different when
+/// they're turned around, so they're the least likely to confuse
+/// [`find_checkerboard`].
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Checkerboard {
+    /// Inner corners across each row.
+    pub columns: u32,
+    /// Inner corners down each column.
+    pub rows: u32,
+    /// The length of one square's side. Poses come back in the same units.
+    pub square_size: f64,
+}
+
+impl Checkerboard {
+    /// Describes a board by its inner corners and square size.
+    #[inline]
+    #[must_use]
+    pub const fn new(columns: u32, rows: u32, square_size: f64) -> Self {
+        Self {
+            columns,
+            rows,
+            square_size,
+        }
+    }
+
+    /// The number of inner corners.
+    #[inline]
+    pub const fn corner_count(&self) -> usize {
+        self.columns as usize * self.rows as usize
+    }
+
+    /// Where each inner corner is on the board itself, in the same order
+    /// that [`find_checkerboard`] finds them.
+    ///
+    /// The board lies flat, so `z` is always zero.
+    #[inline]
+    pub fn object_points(&self) -> Vec<[f64; 3]> {
+        (0..self.rows)
+            .flat_map(|row| {
+                (0..self.columns).map(move |column| {
+                    [
+                        f64::from(column) * self.square_size,
+                        f64::from(row) * self.square_size,
+                        0.0,
+                    ]
+                })
+            })
+            .collect()
+    }
+}
+
+/// Settings for [`calibrate`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CalibrationOptions {
+    /// How many radial distortion coefficients to estimate, from zero to
+    /// three. The rest stay at zero.
+    ///
+    /// Two is plenty for most lenses. The third mostly helps wide-angle
+    /// lenses, and it overfits easily without views near the frame's edges.
+    pub radial_coefficients: u8,
+    /// Whether to estimate tangential distortion, which comes from a lens
+    /// that isn't quite parallel to the sensor.
+    pub tangential: bool,
+    /// Keeps the principal point in the middle of the frame, instead of
+    /// estimating it.
+    pub fix_principal_point: bool,
+    /// The most refinement steps to take.
+    pub max_iterations: u32,
+}
+
+impl Default for CalibrationOptions {
+    /// Two radial and two tangential coefficients, with up to 100 steps.
+    #[inline]
+    fn default() -> Self {
+        Self {
+            radial_coefficients: 2,
+            tangential: true,
+            fix_principal_point: false,
+            max_iterations: 100,
+        }
+    }
+}
+
+/// A calibrated camera, and how well the calibration fits.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CameraCalibration {
+    /// The camera's model, at the resolution it was calibrated at.
+    pub camera: Camera,
+    /// The root-mean-square reprojection error over every corner, in
+    /// pixels. Good calibrations are usually under half a pixel.
+    pub rms_error: f64,
+}
+
+/// Where the board was in one view, relative to the camera.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct BoardPose {
+    /// The board's rotation, as an axis scaled by the angle in radians.
+    pub rotation: [f64; 3],
+    /// The board's first corner, in the camera's coordinates and the
+    /// board's units.
+    pub translation: [f64; 3],
+    /// The root-mean-square reprojection error in this view, in pixels.
+    /// Views that are much worse than the rest probably had a bad detection.
+    pub rms_error: f64,
+}
+
+/// Everything [`calibrate`] found.
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+pub struct CalibrationReport {
+    /// The camera's calibration.
+    pub calibration: CameraCalibration,
+    /// The board's pose in each view, in the order they were given.
+    pub poses: Vec<BoardPose>,
+}
+
+#[cfg(test)]
+mod tests {
+    use serumcv_image::Image;
+
+    use crate::camera::{BrownConrady, CameraIntrinsics, CameraModel, PinholeCamera};
+    use crate::error::CalibrationError;
+    use crate::Resolution;
+
+    use super::linalg::{rotation_from_vector, Matrix};
+    use super::render::render_board;
+    use super::*;
+
+    const BOARD: Checkerboard = Checkerboard::new(7, 5, 0.03);
+    const RESOLUTION: Resolution = Resolution::new(320, 240);
+
+    fn camera() -> PinholeCamera {
+        PinholeCamera::new(
+            RESOLUTION,
+            CameraIntrinsics::new(300.0, 295.0, 163.0, 118.0),
+            BrownConrady::new(-0.2, 0.08, 0.001, -0.001, 0.0),
+        )
+    }
+
+    /// Board poses that tilt every which way, each as a rotation vector and
+    /// a translation that puts the board's middle near the optical axis.
+    fn poses() -> Vec<([f64; 3], [f64; 3])> {
+        vec![
+            ([0.3, 0.1, 0.05], [-0.09, -0.06, 0.45]),
+            ([-0.3, 0.2, -0.1], [-0.08, -0.07, 0.5]),
+            ([0.1, -0.4, 0.2], [-0.1, -0.05, 0.48]),
+            ([-0.2, -0.3, 0.0], [-0.07, -0.06, 0.42]),
+            ([0.45, 0.0, -0.2], [-0.11, -0.04, 0.55]),
+            ([0.0, 0.45, 0.1], [-0.06, -0.08, 0.5]),
+        ]
+    }
+
+    /// Renders a board by casting a ray through each point in the picture.
+    fn render(pose: ([f64; 3], [f64; 3])) -> Image<u8> {
+        let camera = camera();
+        let rotation = rotation_from_vector(pose.0);
+        let [tx, ty, tz] = pose.1;
+        // the board's plane, seen from the camera: undo the pose
+        let inverse = rotation.transpose();
+        let origin = inverse.apply3([-tx, -ty, -tz]);
+
+        render_board(&BOARD, RESOLUTION.width, RESOLUTION.height, |u, v| {
+            let ray = inverse.apply3(camera.unproject([u, v]).unwrap());
+            let along = -origin[2] / ray[2];
+            [
+                along.mul_add(ray[0], origin[0]) / BOARD.square_size,
+                along.mul_add(ray[1], origin[1]) / BOARD.square_size,
+            ]
+        })
+    }
+
+    /// Where the board's corners really are in a pose's picture.
+    fn true_corners(pose: ([f64; 3], [f64; 3])) -> Vec<[f64; 2]> {
+        let camera = camera();
+        let rotation: Matrix = rotation_from_vector(pose.0);
+        BOARD
+            .object_points()
+            .into_iter()
+            .map(|point| {
+                let [x, y, z] = rotation.apply3(point);
+                let [tx, ty, tz] = pose.1;
+                camera.project([x + tx, y + ty, z + tz]).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn calibrates_from_rendered_checkerboards() {
+        let mut views = Vec::new();
+        for pose in poses() {
+            let image = render(pose);
+            let found = find_checkerboard(&image.view(), &BOARD).expect("the board is in view");
+            let worst = found
+                .iter()
+                .zip(true_corners(pose))
+                .map(|(a, b)| (a[0] - b[0]).hypot(a[1] - b[1]))
+                .fold(0.0, f64::max);
+            assert!(
+                worst < 0.25,
+                "corners should be within a quarter pixel, but one was {worst} px off"
+            );
+            views.push(found);
+        }
+        let blank = Image::from_pixel(64, 48, 200_u8);
+        assert_eq!(
+            find_checkerboard(&blank.view(), &BOARD),
+            None,
+            "there's no board to find"
+        );
+
+        let report = calibrate(&BOARD, &views, RESOLUTION, CalibrationOptions::default()).unwrap();
+        let truth = camera();
+        let Camera::Pinhole(found) = report.calibration.camera else {
+            panic!("calibrations are pinhole cameras");
+        };
+        let rms_error = report.calibration.rms_error;
+
+        assert!(rms_error < 0.1, "reprojection error is {rms_error} px");
+        assert!(
+            (found.intrinsics.fx - truth.intrinsics.fx).abs() < 3.0,
+            "fx is {}",
+            found.intrinsics.fx
+        );
+        assert!(
+            (found.intrinsics.fy - truth.intrinsics.fy).abs() < 3.0,
+            "fy is {}",
+            found.intrinsics.fy
+        );
+        assert!(
+            (found.intrinsics.cx - truth.intrinsics.cx).abs() < 3.0,
+            "cx is {}",
+            found.intrinsics.cx
+        );
+        assert!(
+            (found.intrinsics.cy - truth.intrinsics.cy).abs() < 3.0,
+            "cy is {}",
+            found.intrinsics.cy
+        );
+        assert!(
+            (found.distortion.k1 - truth.distortion.k1).abs() < 0.03,
+            "k1 is {}",
+            found.distortion.k1
+        );
+        assert_eq!(report.poses.len(), views.len(), "one pose per view");
+        let first = report.poses.first().unwrap();
+        assert!(
+            (first.translation[2] - 0.45).abs() < 0.01,
+            "the first board was 45 cm away"
+        );
+
+        assert_eq!(
+            calibrate(
+                &BOARD,
+                views.get(..2).unwrap(),
+                RESOLUTION,
+                CalibrationOptions::default()
+            ),
+            Err(CalibrationError::NotEnoughViews { needed: 3, got: 2 })
+        );
+    }
+}
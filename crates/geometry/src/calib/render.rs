@@ -0,0 +1,56 @@
+//! Pictures of checkerboards, for testing calibration without a camera.
+
+use serumcv_image::Image;
+
+use super::Checkerboard;
+
+/// Renders a picture of a board by sampling a 3x3 grid in each pixel, so
+/// square edges are anti-aliased like in a real picture.
+///
+/// `to_board` says where a point in the picture lands on the board, in
+/// squares, with the first inner corner at `[0, 0]`. The board gets one
+/// square of white border, and everything past that is gray.
+pub(super) fn render_board<F: Fn(f64, f64) -> [f64; 2]>(
+    board: &Checkerboard,
+    width: u32,
+    height: u32,
+    to_board: F,
+) -> Image<u8> {
+    let (columns, rows) = (f64::from(board.columns), f64::from(board.rows));
+    let shade = |u: f64, v: f64| {
+        let [board_x, board_y] = to_board(u, v);
+        if board_x < -2.0 || board_y < -2.0 || board_x > columns + 1.0 || board_y > rows + 1.0 {
+            return 120.0;
+        }
+        if board_x < -1.0 || board_y < -1.0 || board_x > columns || board_y > rows {
+            return 235.0;
+        }
+        if (board_x.floor() + board_y.floor()).rem_euclid(2.0) < 1.0 {
+            25.0
+        } else {
+            235.0
+        }
+    };
+
+    Image::from_fn(width, height, |px, py| {
+        let total: f64 = (0..3_u32)
+            .flat_map(|sy| (0..3_u32).map(move |sx| (f64::from(sx), f64::from(sy))))
+            .map(|(sx, sy)| {
+                shade(
+                    f64::from(px) + (sx - 1.0) / 3.0,
+                    f64::from(py) + (sy - 1.0) / 3.0,
+                )
+            })
+            .sum();
+        to_byte(total / 9.0)
+    })
+}
+
+#[expect(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    reason = "the shade is between the board's colors"
+)]
+fn to_byte(shade: f64) -> u8 {
+    shade.round() as u8
+}
@@ -0,0 +1,730 @@
+//! Stereo calibration and rectification.
+//!
+//! Calibrating a stereo pair finds where the right camera sits relative to
+//! the left one. Each camera should already be calibrated on its own, so
+//! only the board's poses and the cameras' relative pose get refined.
+//!
+//! Rectifying the pair turns both cameras (virtually) until they face the
+//! same way, with matching rows. Then a point shows up on the same row in
+//! both images, just further left in the right one. That shift is its
+//! disparity, which gives its depth.
+
+use serumcv_image::{Image, ImageView, RemapMap};
+
+use crate::camera::{Camera, CameraIntrinsics, CameraModel};
+use crate::error::CalibrationError;
+use crate::Resolution;
+
+use super::linalg::{self, Matrix};
+use super::zhang::{central_difference, dot, homography, initial_pose};
+use super::Checkerboard;
+
+/// The most refinement steps to take.
+const MAX_ITERATIONS: u32 = 100;
+
+/// Stands in for a pixel that a camera can't see, so the refinement steers
+/// away from it.
+const UNSEEN: [f64; 2] = [1e6, 1e6];
+
+/// Two calibrated cameras, and how they sit relative to each other.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StereoCalibration {
+    /// The left camera.
+    pub left: Camera,
+    /// The right camera.
+    pub right: Camera,
+    /// Turns the left camera's coordinates into the right's, as an axis
+    /// scaled by the angle in radians.
+    pub rotation: [f64; 3],
+    /// Where the left camera is, in the right camera's coordinates and the
+    /// board's units. Points go from left to right coordinates by rotating,
+    /// then adding this.
+    pub translation: [f64; 3],
+    /// The root-mean-square reprojection error over every corner in both
+    /// cameras, in pixels.
+    pub rms_error: f64,
+}
+
+impl StereoCalibration {
+    /// The distance between the cameras, in the board's units.
+    #[inline]
+    pub fn baseline(&self) -> f64 {
+        linalg::norm(self.translation)
+    }
+
+    /// Works out how to rectify the pair.
+    ///
+    /// The rectified images are the left camera's resolution, and share a
+    /// focal length and principal point (the averages of the two cameras').
+    ///
+    /// # Errors
+    ///
+    /// This fails if the cameras aren't side by side, like when one is in
+    /// front of the other.
+    #[inline]
+    pub fn rectify(&self) -> Result<StereoRectification, CalibrationError> {
+        // split the rotation between the cameras, so they each turn half as
+        // much. then the only difference between them is `between`.
+        let half = linalg::rotation_from_vector(self.rotation.map(|angle| angle / 2.0));
+        let between = half.transpose().apply3(self.translation);
+        let baseline = linalg::norm(between);
+
+        // then turn both until the baseline is their x axis. keeping it
+        // pointing mostly the same way as before stops the images flipping.
+        let sign = if between[0] < 0.0 { -1.0 } else { 1.0 };
+        let x_axis = between.map(|component| component * sign / baseline);
+        let up = linalg::cross([0.0, 0.0, 1.0], x_axis);
+        let up_length = linalg::norm(up);
+        if !up_length.is_finite() || up_length <= 0.1 {
+            return Err(CalibrationError::NotSideBySide);
+        }
+        let y_axis = up.map(|component| component / up_length);
+        let turn = Matrix::from_rows3([x_axis, y_axis, linalg::cross(x_axis, y_axis)]);
+
+        let (left, right) = (self.left.intrinsics(), self.right.intrinsics());
+        let focal = (left.fx + left.fy + right.fx + right.fy) / 4.0;
+        let intrinsics = CameraIntrinsics::new(
+            focal,
+            focal,
+            f64::midpoint(left.cx, right.cx),
+            f64::midpoint(left.cy, right.cy),
+        );
+
+        Ok(StereoRectification {
+            left: self.left,
+            right: self.right,
+            left_rotation: (turn.clone() * &half).rows3(),
+            right_rotation: (turn * &half.transpose()).rows3(),
+            intrinsics,
+            resolution: self.left.resolution(),
+            baseline,
+        })
+    }
+}
+
+/// How to rectify a stereo pair, from [`StereoCalibration::rectify`].
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StereoRectification {
+    /// The left camera, as calibrated.
+    pub left: Camera,
+    /// The right camera, as calibrated.
+    pub right: Camera,
+    /// Turns the left camera's coordinates into its rectified ones. This is
+    /// a rotation matrix, row by row.
+    pub left_rotation: [[f64; 3]; 3],
+    /// Turns the right camera's coordinates into its rectified ones.
+    pub right_rotation: [[f64; 3]; 3],
+    /// The focal lengths and principal point of both rectified images.
+    /// They don't have any distortion.
+    pub intrinsics: CameraIntrinsics,
+    /// The size of both rectified images.
+    pub resolution: Resolution,
+    /// The distance between the cameras, in the board's units.
+    pub baseline: f64,
+}
+
+impl StereoRectification {
+    /// Builds the map that rectifies the left camera's frames.
+    #[inline]
+    pub fn left_map(&self) -> RemapMap {
+        self.map(&self.left, &self.left_rotation)
+    }
+
+    /// Builds the map that rectifies the right camera's frames.
+    #[inline]
+    pub fn right_map(&self) -> RemapMap {
+        self.map(&self.right, &self.right_rotation)
+    }
+
+    /// The depth of a point with some disparity, in the board's units.
+    ///
+    /// Returns `None` unless the disparity is positive.
+    #[inline]
+    pub fn depth(&self, disparity: f64) -> Option<f64> {
+        (disparity > 0.0).then(|| self.intrinsics.fx * self.baseline / disparity)
+    }
+
+    /// Turns a disparity image into a depth image, in the board's units.
+    ///
+    /// Pixels without a depth (including ones without a disparity) are
+    /// `NaN`.
+    #[inline]
+    #[expect(
+        clippy::cast_possible_truncation,
+        reason = "depths are nowhere near the limits of an `f32`"
+    )]
+    pub fn depth_image(&self, disparity: &ImageView<'_, f32>) -> Image<f32> {
+        Image::from_fn(disparity.width(), disparity.height(), |x, y| {
+            disparity
+                .get(x, y)
+                .and_then(|&found| self.depth(f64::from(found)))
+                .map_or(f32::NAN, |depth| depth as f32)
+        })
+    }
+
+    /// Finds where a pixel of the rectified left image is, given its
+    /// disparity, in the rectified left camera's coordinates.
+    #[inline]
+    pub fn point(&self, pixel: [f64; 2], disparity: f64) -> Option<[f64; 3]> {
+        let depth = self.depth(disparity)?;
+        let [x, y] = self.intrinsics.to_normalized(pixel);
+        Some([x * depth, y * depth, depth])
+    }
+
+    /// Builds a map that reads each rectified pixel from the camera.
+    fn map(&self, camera: &Camera, rotation: &[[f64; 3]; 3]) -> RemapMap {
+        let unturn = Matrix::from_rows3(*rotation).transpose();
+        RemapMap::from_fn(self.resolution.width, self.resolution.height, |x, y| {
+            let [ray_x, ray_y] = self.intrinsics.to_normalized([f64::from(x), f64::from(y)]);
+            camera
+                .project(unturn.apply3([ray_x, ray_y, 1.0]))
+                .map_or([f32::NAN; 2], |pixel| pixel.map(to_f32))
+        })
+    }
+}
+
+/// Calibrates a stereo pair from views of a checkerboard that both cameras
+/// saw at once.
+///
+/// Each pair holds the left camera's corners, then the right's, in the
+/// order that [`find_checkerboard`](super::find_checkerboard) gives them.
+/// The cameras' own calibrations stay as they are, so calibrate each one
+/// first.
+///
+/// # Errors
+///
+/// This fails if there aren't any pairs, any view has the wrong number of
+/// corners, or the board's pose can't be found in some view.
+#[inline]
+pub fn calibrate_stereo<V: AsRef<[[f64; 2]]>>(
+    board: &Checkerboard,
+    pairs: &[(V, V)],
+    left: Camera,
+    right: Camera,
+) -> Result<StereoCalibration, CalibrationError> {
+    if board.columns < 2 || board.rows < 2 {
+        return Err(CalibrationError::BoardTooSmall {
+            columns: board.columns,
+            rows: board.rows,
+        });
+    }
+    if pairs.is_empty() {
+        return Err(CalibrationError::NotEnoughViews { needed: 1, got: 0 });
+    }
+
+    let object: Vec<[f64; 2]> = board
+        .object_points()
+        .into_iter()
+        .map(|[x, y, _]| [x, y])
+        .collect();
+    let views: Vec<[&[[f64; 2]]; 2]> = pairs
+        .iter()
+        .map(|pair| [pair.0.as_ref(), pair.1.as_ref()])
+        .collect();
+    for (view, corners) in views.iter().enumerate() {
+        for seen in corners {
+            if seen.len() != object.len() {
+                return Err(CalibrationError::WrongCornerCount {
+                    view,
+                    expected: object.len(),
+                    got: seen.len(),
+                });
+            }
+        }
+    }
+
+    let problem = Problem {
+        cameras: [left, right],
+        object,
+        views,
+    };
+    let mut params = problem.initial_params()?;
+    problem.refine(&mut params);
+
+    let points = 2 * problem.object.len() * problem.views.len();
+    let rms_error = (problem.cost(&params) / points as f64).sqrt();
+    let [rx, ry, rz, tx, ty, tz] = params.extrinsics;
+    Ok(StereoCalibration {
+        left,
+        right,
+        rotation: [rx, ry, rz],
+        translation: [tx, ty, tz],
+        rms_error,
+    })
+}
+
+/// Everything being refined: the right camera's pose relative to the left,
+/// then the board's pose relative to the left camera in each view. Each is
+/// a rotation vector, then a translation.
+#[derive(Clone, Debug)]
+struct Params {
+    extrinsics: [f64; 6],
+    poses: Vec<[f64; 6]>,
+}
+
+/// What's being fit: the board, and where both cameras saw it.
+struct Problem<'views> {
+    cameras: [Camera; 2],
+    object: Vec<[f64; 2]>,
+    views: Vec<[&'views [[f64; 2]]; 2]>,
+}
+
+impl Problem<'_> {
+    /// Finds the board in each view on its own, then averages out where the
+    /// right camera is.
+    fn initial_params(&self) -> Result<Params, CalibrationError> {
+        let [ref left, ref right] = self.cameras;
+        let mut rotations = Matrix::zeros(3, 3);
+        let mut translations = [0.0; 3];
+        let mut poses = Vec::with_capacity(self.views.len());
+
+        for &[left_view, right_view] in &self.views {
+            let left_pose = board_pose(left, &self.object, left_view)?;
+            let right_pose = board_pose(right, &self.object, right_view)?;
+            let (left_rotation, left_translation) = split(&left_pose);
+            let (right_rotation, right_translation) = split(&right_pose);
+
+            // right = R (left) + t, for the board's origin in both
+            let rotation = right_rotation * &left_rotation.transpose();
+            let moved = rotation.apply3(left_translation);
+            for (sum, (to, from)) in translations
+                .iter_mut()
+                .zip(right_translation.iter().zip(moved))
+            {
+                *sum += to - from;
+            }
+            rotations = Matrix::from_fn(3, 3, |row, col| {
+                rotations[(row, col)] + rotation[(row, col)]
+            });
+            poses.push(left_pose);
+        }
+
+        let count = self.views.len() as f64;
+        let [rx, ry, rz] = linalg::vector_from_rotation(&linalg::nearest_rotation(&rotations));
+        let [tx, ty, tz] = translations.map(|sum| sum / count);
+        Ok(Params {
+            extrinsics: [rx, ry, rz, tx, ty, tz],
+            poses,
+        })
+    }
+
+    /// The differences between where a view's points land and where they
+    /// were seen, as `[dx, dy, dx, dy, ...]` for the left camera, then the
+    /// right.
+    fn residuals(
+        &self,
+        extrinsics: &[f64; 6],
+        pose: &[f64; 6],
+        seen: [&[[f64; 2]]; 2],
+    ) -> Vec<f64> {
+        let (board_rotation, board_translation) = split(pose);
+        let (rotation, translation) = split(extrinsics);
+        let in_left: Vec<[f64; 3]> = self
+            .object
+            .iter()
+            .map(|&[x, y]| add(board_rotation.apply3([x, y, 0.0]), board_translation))
+            .collect();
+        let in_right = in_left
+            .iter()
+            .map(|&point| add(rotation.apply3(point), translation));
+
+        let [ref left, ref right] = self.cameras;
+        let [left_seen, right_seen] = seen;
+        let left_residuals = in_left
+            .iter()
+            .zip(left_seen)
+            .flat_map(|(&point, observed)| difference(left, point, *observed));
+        let right_residuals = in_right
+            .zip(right_seen)
+            .flat_map(|(point, observed)| difference(right, point, *observed));
+
+        left_residuals.chain(right_residuals).collect()
+    }
+
+    /// The sum of squared residuals over every view.
+    fn cost(&self, params: &Params) -> f64 {
+        params
+            .poses
+            .iter()
+            .zip(&self.views)
+            .flat_map(|(pose, &seen)| self.residuals(&params.extrinsics, pose, seen))
+            .map(|residual| residual * residual)
+            .sum()
+    }
+
+    /// Refines every parameter at once with Levenberg-Marquardt, just like
+    /// the single-camera calibration.
+    fn refine(&self, params: &mut Params) {
+        let unknowns = 6 + 6 * params.poses.len();
+        let mut cost = self.cost(params);
+        let mut damping: f64 = 1e-3;
+
+        for _ in 0..MAX_ITERATIONS {
+            let (normal, gradient) = self.normal_equations(params);
+
+            let mut improved = None;
+            for _attempt in 0..24 {
+                let damped = Matrix::from_fn(unknowns, unknowns, |row, col| {
+                    let value = normal[(row, col)];
+                    if row == col {
+                        damping.mul_add(value.max(1e-12), value)
+                    } else {
+                        value
+                    }
+                });
+                let rhs: Vec<f64> = gradient.iter().map(|value| -value).collect();
+                if let Some(step) = damped.solve_cholesky(&rhs) {
+                    let candidate = apply_step(params, &step);
+                    let candidate_cost = self.cost(&candidate);
+                    if candidate_cost.is_finite() && candidate_cost < cost {
+                        improved = Some((candidate, candidate_cost));
+                        damping = (damping / 10.0).max(1e-15);
+                        break;
+                    }
+                }
+                damping *= 10.0;
+            }
+
+            let Some((candidate, candidate_cost)) = improved else {
+                break;
+            };
+            *params = candidate;
+            let change = cost - candidate_cost;
+            cost = candidate_cost;
+            if change <= cost * 1e-12 {
+                break;
+            }
+        }
+    }
+
+    /// `JᵀJ` and `Jᵀr`, assembled a view at a time. Each view's residuals
+    /// only depend on the extrinsics and its own pose.
+    fn normal_equations(&self, params: &Params) -> (Matrix, Vec<f64>) {
+        let unknowns = 6 + 6 * params.poses.len();
+        let mut normal = Matrix::zeros(unknowns, unknowns);
+        let mut gradient = vec![0.0; unknowns];
+
+        for (view, (pose, &seen)) in params.poses.iter().zip(&self.views).enumerate() {
+            let residuals = self.residuals(&params.extrinsics, pose, seen);
+
+            // six columns for the extrinsics, then six for this view's pose
+            let mut columns: Vec<(usize, Vec<f64>)> = Vec::with_capacity(12);
+            for parameter in 0..6 {
+                let value = params
+                    .extrinsics
+                    .get(parameter)
+                    .copied()
+                    .unwrap_or_default();
+                let derivative = central_difference(value, |moved_value| {
+                    let mut moved = params.extrinsics;
+                    if let Some(target) = moved.get_mut(parameter) {
+                        *target = moved_value;
+                    }
+                    self.residuals(&moved, pose, seen)
+                });
+                columns.push((parameter, derivative));
+            }
+            for parameter in 0..6 {
+                let value = pose.get(parameter).copied().unwrap_or_default();
+                let derivative = central_difference(value, |moved_value| {
+                    let mut moved = *pose;
+                    if let Some(target) = moved.get_mut(parameter) {
+                        *target = moved_value;
+                    }
+                    self.residuals(&params.extrinsics, &moved, seen)
+                });
+                columns.push((6 + 6 * view + parameter, derivative));
+            }
+
+            for &(row, ref row_column) in &columns {
+                for &(col, ref col_column) in &columns {
+                    normal[(row, col)] += dot(row_column, col_column);
+                }
+                if let Some(slot) = gradient.get_mut(row) {
+                    *slot += dot(row_column, &residuals);
+                }
+            }
+        }
+
+        (normal, gradient)
+    }
+}
+
+/// Finds the board's pose relative to a calibrated camera.
+fn board_pose(
+    camera: &Camera,
+    object: &[[f64; 2]],
+    corners: &[[f64; 2]],
+) -> Result<[f64; 6], CalibrationError> {
+    // undistorted, normalized corners act like they came from a camera
+    // with unit intrinsics
+    let normalized = corners
+        .iter()
+        .map(|&corner| {
+            let [x, y, z] = camera.unproject(corner)?;
+            (z > 0.0).then(|| [x / z, y / z])
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or(CalibrationError::Degenerate)?;
+    let found = homography(object, &normalized).ok_or(CalibrationError::Degenerate)?;
+
+    initial_pose(&CameraIntrinsics::new(1.0, 1.0, 0.0, 0.0), &found)
+        .ok_or(CalibrationError::Degenerate)
+}
+
+#[expect(
+    clippy::cast_possible_truncation,
+    reason = "pixel coordinates fit in an `f32` with room to spare"
+)]
+const fn to_f32(value: f64) -> f32 {
+    value as f32
+}
+
+/// Splits a pose into its rotation matrix and translation.
+fn split(pose: &[f64; 6]) -> (Matrix, [f64; 3]) {
+    let [rx, ry, rz, tx, ty, tz] = *pose;
+    (linalg::rotation_from_vector([rx, ry, rz]), [tx, ty, tz])
+}
+
+fn add(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+/// How far a point lands from where it was seen, in pixels.
+fn difference(camera: &Camera, point: [f64; 3], seen: [f64; 2]) -> [f64; 2] {
+    let [x, y] = camera.project(point).unwrap_or(UNSEEN);
+    [x - seen[0], y - seen[1]]
+}
+
+/// Moves every parameter by a step from the normal equations.
+fn apply_step(params: &Params, step: &[f64]) -> Params {
+    let mut moved = params.clone();
+    moved
+        .extrinsics
+        .iter_mut()
+        .zip(step)
+        .for_each(|(value, delta)| *value += delta);
+    let pose_steps = step.get(6..).unwrap_or_default();
+    for (pose, deltas) in moved.poses.iter_mut().zip(pose_steps.chunks_exact(6)) {
+        pose.iter_mut()
+            .zip(deltas)
+            .for_each(|(value, delta)| *value += delta);
+    }
+
+    moved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::{BrownConrady, PinholeCamera};
+
+    const BOARD: Checkerboard = Checkerboard::new(7, 5, 0.03);
+    const RESOLUTION: Resolution = Resolution::new(320, 240);
+
+    /// Right from left: a slight turn, and 8 cm to the right.
+    const ROTATION: [f64; 3] = [0.01, -0.03, 0.005];
+    const TRANSLATION: [f64; 3] = [-0.08, 0.002, 0.001];
+
+    fn cameras() -> [Camera; 2] {
+        [
+            PinholeCamera::new(
+                RESOLUTION,
+                CameraIntrinsics::new(300.0, 298.0, 161.0, 121.0),
+                BrownConrady::new(-0.2, 0.08, 0.0, 0.0, 0.0),
+            )
+            .into(),
+            PinholeCamera::new(
+                RESOLUTION,
+                CameraIntrinsics::new(305.0, 303.0, 157.0, 118.0),
+                BrownConrady::new(-0.15, 0.05, 0.001, 0.0, 0.0),
+            )
+            .into(),
+        ]
+    }
+
+    /// Where both cameras see the board's corners, for a board pose
+    /// relative to the left camera.
+    fn pair(pose: [f64; 6]) -> (Vec<[f64; 2]>, Vec<[f64; 2]>) {
+        let [left, right] = cameras();
+        let (board_rotation, board_translation) = split(&pose);
+        let rotation = linalg::rotation_from_vector(ROTATION);
+
+        BOARD
+            .object_points()
+            .into_iter()
+            .map(|point| {
+                let in_left = add(board_rotation.apply3(point), board_translation);
+                let in_right = add(rotation.apply3(in_left), TRANSLATION);
+                (
+                    left.project(in_left).unwrap(),
+                    right.project(in_right).unwrap(),
+                )
+            })
+            .unzip()
+    }
+
+    #[test]
+    fn stereo_pairs_calibrate_and_rectify() {
+        let pairs: Vec<_> = [
+            [0.2, -0.3, 0.05, -0.06, -0.05, 0.5],
+            [-0.25, 0.2, -0.1, -0.1, -0.04, 0.45],
+            [0.1, 0.35, 0.2, -0.03, -0.06, 0.55],
+            [-0.3, -0.1, 0.0, -0.08, -0.02, 0.4],
+        ]
+        .into_iter()
+        .map(pair)
+        .collect();
+        let [left, right] = cameras();
+
+        let stereo = calibrate_stereo(&BOARD, &pairs, left, right).unwrap();
+        assert!(stereo.rms_error < 1e-4, "rms error is {}", stereo.rms_error);
+        for (found, truth) in stereo
+            .rotation
+            .iter()
+            .chain(&stereo.translation)
+            .zip(ROTATION.iter().chain(&TRANSLATION))
+        {
+            assert!(
+                (found - truth).abs() < 1e-5,
+                "found {found}, expected {truth}"
+            );
+        }
+        assert!((stereo.baseline() - 0.080_031).abs() < 1e-5, "baseline");
+
+        // after rectifying, a point lands on the same row of both images,
+        // and its disparity gives back its depth
+        let rectification = stereo.rectify().unwrap();
+        let rotation = linalg::rotation_from_vector(ROTATION);
+        let left_turn = Matrix::from_rows3(rectification.left_rotation);
+        let right_turn = Matrix::from_rows3(rectification.right_rotation);
+        for in_left in [[0.1, -0.05, 0.7], [-0.2, 0.1, 1.5], [0.0, 0.0, 3.0]] {
+            let in_right = add(rotation.apply3(in_left), TRANSLATION);
+            let rectified = left_turn.apply3(in_left);
+            let [left_x, left_y] = rectification
+                .intrinsics
+                .to_pixel([rectified[0] / rectified[2], rectified[1] / rectified[2]]);
+            let other = right_turn.apply3(in_right);
+            let [right_x, right_y] = rectification
+                .intrinsics
+                .to_pixel([other[0] / other[2], other[1] / other[2]]);
+
+            assert!(
+                (left_y - right_y).abs() < 1e-6,
+                "rows differ for {in_left:?}"
+            );
+            let depth = rectification.depth(left_x - right_x).unwrap();
+            assert!(
+                (depth - rectified[2]).abs() < 1e-6,
+                "depth of {in_left:?} is {depth}"
+            );
+        }
+
+        // and the map reads each rectified pixel from where the camera
+        // sees it
+        let map = rectification.left_map();
+        let [source_x, source_y] = map.get(100, 80).unwrap();
+        let ray = left
+            .unproject([f64::from(source_x), f64::from(source_y)])
+            .unwrap();
+        let turned = left_turn.apply3(ray);
+        let [x, y] = rectification
+            .intrinsics
+            .to_pixel([turned[0] / turned[2], turned[1] / turned[2]]);
+        assert!(
+            (x - 100.0).abs() < 1e-3 && (y - 80.0).abs() < 1e-3,
+            "landed on {x}, {y}"
+        );
+    }
+
+    #[test]
+    fn known_extrinsics_rectify_onto_matching_rows() {
+        let [left, right] = cameras();
+        let stereo = StereoCalibration {
+            left,
+            right,
+            rotation: ROTATION,
+            translation: TRANSLATION,
+            rms_error: 0.0,
+        };
+        let rectification = stereo.rectify().unwrap();
+        let rotation = linalg::rotation_from_vector(ROTATION);
+        let left_turn = Matrix::from_rows3(rectification.left_rotation);
+        let right_turn = Matrix::from_rows3(rectification.right_rotation);
+
+        // both rectified cameras face the same way, so the baseline lies
+        // along their rows
+        let rectified_baseline = right_turn.apply3(TRANSLATION);
+        assert!(
+            rectified_baseline[1].abs() < 1e-12 && rectified_baseline[2].abs() < 1e-12,
+            "baseline is {rectified_baseline:?}"
+        );
+
+        for in_left in [[0.3, -0.2, 1.0], [-0.4, 0.25, 2.0], [0.05, 0.3, 0.6]] {
+            let in_right = add(rotation.apply3(in_left), TRANSLATION);
+            let [left_x, left_y, left_z] = left_turn.apply3(in_left);
+            let [right_x, right_y, right_z] = right_turn.apply3(in_right);
+            let [_, left_row] = rectification
+                .intrinsics
+                .to_pixel([left_x / left_z, left_y / left_z]);
+            let [_, right_row] = rectification
+                .intrinsics
+                .to_pixel([right_x / right_z, right_y / right_z]);
+            assert!(
+                (left_row - right_row).abs() < 1e-9,
+                "{in_left:?} lands on rows {left_row} and {right_row}"
+            );
+        }
+        assert!(
+            (rectification.baseline - stereo.baseline()).abs() < 1e-12,
+            "rectifying keeps the baseline"
+        );
+
+        // one camera in front of the other can't be rectified
+        let stacked = StereoCalibration {
+            translation: [0.0, 0.0, -0.1],
+            ..stereo
+        };
+        assert_eq!(
+            stacked.rectify(),
+            Err(CalibrationError::NotSideBySide),
+            "cameras aren't side by side"
+        );
+    }
+
+    #[test]
+    fn unusable_pairs_fail() {
+        let [left, right] = cameras();
+        assert_eq!(
+            calibrate_stereo::<Vec<[f64; 2]>>(&BOARD, &[], left, right),
+            Err(CalibrationError::NotEnoughViews { needed: 1, got: 0 }),
+            "no pairs"
+        );
+
+        let (seen_left, mut seen_right) = pair([0.1, -0.1, 0.0, -0.06, -0.05, 0.5]);
+        seen_right.pop();
+        assert_eq!(
+            calibrate_stereo(&BOARD, &[(seen_left.clone(), seen_right)], left, right),
+            Err(CalibrationError::WrongCornerCount {
+                view: 0,
+                expected: 35,
+                got: 34
+            }),
+            "the right camera missed a corner"
+        );
+
+        // the lens can't bend light from that far outside the frame, so
+        // there's no pose that explains these
+        let offscreen: Vec<[f64; 2]> = seen_left
+            .iter()
+            .map(|&[x, y]| [x.mul_add(1e4, 1e6), y.mul_add(1e4, 1e6)])
+            .collect();
+        assert_eq!(
+            calibrate_stereo(&BOARD, &[(seen_left, offscreen)], left, right),
+            Err(CalibrationError::Degenerate),
+            "corners far outside the frame"
+        );
+    }
+}
@@ -0,0 +1,651 @@
+//! Zhang's calibration method.
+//!
+//! Each view of the (flat) board gives a homography from the board to the
+//! image. A few homographies pin down the intrinsics in closed form, which
+//! then give each view's pose. Finally, everything (distortion included) is
+//! refined together with Levenberg-Marquardt, minimizing reprojection
+//! error.
+
+use crate::camera::{BrownConrady, CameraIntrinsics, PinholeCamera};
+use crate::error::CalibrationError;
+use crate::Resolution;
+
+use super::linalg::{self, Matrix};
+use super::{BoardPose, CalibrationOptions, CalibrationReport, CameraCalibration, Checkerboard};
+
+/// The fewest views we'll calibrate from.
+const MIN_VIEWS: usize = 3;
+
+/// Where each parameter lives in `Params::intrinsics`.
+const FX: usize = 0;
+const FY: usize = 1;
+const CX: usize = 2;
+const CY: usize = 3;
+const K1: usize = 4;
+const K2: usize = 5;
+const P1: usize = 6;
+const P2: usize = 7;
+const K3: usize = 8;
+
+/// Everything being refined: `[fx, fy, cx, cy, k1, k2, p1, p2, k3]`, then a
+/// rotation vector and translation for each view.
+#[derive(Clone, Debug)]
+struct Params {
+    intrinsics: [f64; 9],
+    poses: Vec<[f64; 6]>,
+}
+
+impl Params {
+    const fn camera(&self) -> (CameraIntrinsics, BrownConrady) {
+        let [fx, fy, cx, cy, k1, k2, p1, p2, k3] = self.intrinsics;
+        (
+            CameraIntrinsics::new(fx, fy, cx, cy),
+            BrownConrady::new(k1, k2, p1, p2, k3),
+        )
+    }
+
+    /// Where a view's board points land in the image.
+    fn project(&self, pose: &[f64; 6], object: &[[f64; 2]]) -> Vec<[f64; 2]> {
+        let (intrinsics, distortion) = self.camera();
+        let [rx, ry, rz, tx, ty, tz] = *pose;
+        let rotation = linalg::rotation_from_vector([rx, ry, rz]);
+
+        object
+            .iter()
+            .map(|&[x, y]| {
+                let [cam_x, cam_y, cam_z] = rotation.apply3([x, y, 0.0]);
+                let depth = (cam_z + tz).max(1e-9);
+                let normalized = [(cam_x + tx) / depth, (cam_y + ty) / depth];
+                intrinsics.to_pixel(distortion.distort(normalized))
+            })
+            .collect()
+    }
+
+    /// The differences between where a view's points land and where they
+    /// were seen, as `[dx, dy, dx, dy, ...]`.
+    fn residuals(&self, pose: &[f64; 6], object: &[[f64; 2]], seen: &[[f64; 2]]) -> Vec<f64> {
+        self.project(pose, object)
+            .iter()
+            .zip(seen)
+            .flat_map(|(projected, observed)| {
+                [projected[0] - observed[0], projected[1] - observed[1]]
+            })
+            .collect()
+    }
+
+    /// The sum of squared residuals over every view.
+    fn cost(&self, object: &[[f64; 2]], views: &[&[[f64; 2]]]) -> f64 {
+        self.poses
+            .iter()
+            .zip(views)
+            .flat_map(|(pose, seen)| self.residuals(pose, object, seen))
+            .map(|residual| residual * residual)
+            .sum()
+    }
+}
+
+/// Calibrates a camera from views of a checkerboard.
+///
+/// Each view holds the board's corners, in the order that
+/// [`find_checkerboard`](super::find_checkerboard) gives them. Views should
+/// tilt the board in different directions, and cover the whole frame
+/// between them. Fifteen or so is plenty.
+///
+/// # Errors
+///
+/// This fails if there are fewer than three views, any view has the wrong
+/// number of corners, or the views don't pin down the camera (like when the
+/// board is never tilted).
+#[inline]
+pub fn calibrate<V: AsRef<[[f64; 2]]>>(
+    board: &Checkerboard,
+    views: &[V],
+    resolution: Resolution,
+    options: CalibrationOptions,
+) -> Result<CalibrationReport, CalibrationError> {
+    if board.columns < 2 || board.rows < 2 {
+        return Err(CalibrationError::BoardTooSmall {
+            columns: board.columns,
+            rows: board.rows,
+        });
+    }
+    if views.len() < MIN_VIEWS {
+        return Err(CalibrationError::NotEnoughViews {
+            needed: MIN_VIEWS,
+            got: views.len(),
+        });
+    }
+
+    let object: Vec<[f64; 2]> = board
+        .object_points()
+        .into_iter()
+        .map(|[x, y, _]| [x, y])
+        .collect();
+    let seen: Vec<&[[f64; 2]]> = views.iter().map(AsRef::as_ref).collect();
+    for (view, corners) in seen.iter().enumerate() {
+        if corners.len() != object.len() {
+            return Err(CalibrationError::WrongCornerCount {
+                view,
+                expected: object.len(),
+                got: corners.len(),
+            });
+        }
+    }
+
+    let homographies = seen
+        .iter()
+        .map(|corners| homography(&object, corners).ok_or(CalibrationError::Degenerate))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut intrinsics = initial_intrinsics(&homographies, resolution)?;
+    if options.fix_principal_point {
+        intrinsics.cx = f64::from(resolution.width) / 2.0;
+        intrinsics.cy = f64::from(resolution.height) / 2.0;
+    }
+    let poses = homographies
+        .iter()
+        .map(|found| initial_pose(&intrinsics, found).ok_or(CalibrationError::Degenerate))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut params = Params {
+        intrinsics: [
+            intrinsics.fx,
+            intrinsics.fy,
+            intrinsics.cx,
+            intrinsics.cy,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+        ],
+        poses,
+    };
+    refine(
+        &mut params,
+        &free_parameters(options),
+        &object,
+        &seen,
+        options.max_iterations,
+    );
+
+    let (intrinsics, distortion) = params.camera();
+    if !intrinsics.fx.is_finite() || !intrinsics.fy.is_finite() || intrinsics.fx <= 0.0 {
+        return Err(CalibrationError::Degenerate);
+    }
+
+    let mut total = 0.0;
+    let poses = params
+        .poses
+        .iter()
+        .zip(&seen)
+        .map(|(pose, corners)| {
+            let squared: f64 = params
+                .residuals(pose, &object, corners)
+                .iter()
+                .map(|residual| residual * residual)
+                .sum();
+            total += squared;
+            let [rx, ry, rz, tx, ty, tz] = *pose;
+            BoardPose {
+                rotation: [rx, ry, rz],
+                translation: [tx, ty, tz],
+                rms_error: (squared / object.len() as f64).sqrt(),
+            }
+        })
+        .collect();
+    let rms_error = (total / (object.len() * seen.len()) as f64).sqrt();
+
+    Ok(CalibrationReport {
+        calibration: CameraCalibration {
+            camera: PinholeCamera::new(resolution, intrinsics, distortion).into(),
+            rms_error,
+        },
+        poses,
+    })
+}
+
+/// The intrinsic parameters that the options let us change.
+fn free_parameters(options: CalibrationOptions) -> Vec<usize> {
+    let mut free = vec![FX, FY];
+    if !options.fix_principal_point {
+        free.extend([CX, CY]);
+    }
+    let radial = [K1, K2, K3];
+    free.extend(radial.iter().take(usize::from(options.radial_coefficients)));
+    if options.tangential {
+        free.extend([P1, P2]);
+    }
+
+    free
+}
+
+/// Normalizes points so their centroid is at the origin and their average
+/// distance from it is √2, which keeps the DLT well-conditioned.
+///
+/// Returns the normalized points and the matrix that normalizes them.
+fn normalize(points: &[[f64; 2]]) -> (Vec<[f64; 2]>, Matrix) {
+    let count = points.len().max(1) as f64;
+    let center = points.iter().fold([0.0, 0.0], |sum, point| {
+        [sum[0] + point[0] / count, sum[1] + point[1] / count]
+    });
+    let spread = points
+        .iter()
+        .map(|point| (point[0] - center[0]).hypot(point[1] - center[1]))
+        .sum::<f64>()
+        / count;
+    let scale = if spread > 0.0 {
+        core::f64::consts::SQRT_2 / spread
+    } else {
+        1.0
+    };
+
+    let normalized = points
+        .iter()
+        .map(|point| {
+            [
+                (point[0] - center[0]) * scale,
+                (point[1] - center[1]) * scale,
+            ]
+        })
+        .collect();
+    let matrix = Matrix::from_rows3([
+        [scale, 0.0, -center[0] * scale],
+        [0.0, scale, -center[1] * scale],
+        [0.0, 0.0, 1.0],
+    ]);
+
+    (normalized, matrix)
+}
+
+/// The homography taking board points to image points, by the normalized
+/// DLT.
+pub(super) fn homography(object: &[[f64; 2]], image: &[[f64; 2]]) -> Option<Matrix> {
+    let (object_n, object_t) = normalize(object);
+    let (image_n, image_t) = normalize(image);
+
+    let mut system = Matrix::zeros(2 * object.len(), 9);
+    for (index, (from, to)) in object_n.iter().zip(&image_n).enumerate() {
+        let [x, y] = *from;
+        let [u, v] = *to;
+        let rows = [
+            [-x, -y, -1.0, 0.0, 0.0, 0.0, u * x, u * y, u],
+            [0.0, 0.0, 0.0, -x, -y, -1.0, v * x, v * y, v],
+        ];
+        for (offset, row) in rows.iter().enumerate() {
+            for (col, &value) in row.iter().enumerate() {
+                system[(2 * index + offset, col)] = value;
+            }
+        }
+    }
+
+    let h = system.null_vector();
+    let normalized = Matrix::from_fn(3, 3, |row, col| {
+        h.get(row * 3 + col).copied().unwrap_or_default()
+    });
+    let found = image_t.inverse3()? * &normalized * &object_t;
+
+    let last = found[(2, 2)];
+    (last.abs() > f64::EPSILON).then(|| Matrix::from_fn(3, 3, |row, col| found[(row, col)] / last))
+}
+
+/// Zhang's closed-form intrinsics, assuming square pixels without skew.
+///
+/// The homographies are first moved into a rough camera's normalized
+/// coordinates, which keeps the numbers in the linear system similar.
+fn initial_intrinsics(
+    homographies: &[Matrix],
+    resolution: Resolution,
+) -> Result<CameraIntrinsics, CalibrationError> {
+    let (width, height) = (f64::from(resolution.width), f64::from(resolution.height));
+    let rough = Matrix::from_rows3([
+        [width, 0.0, width / 2.0],
+        [0.0, width, height / 2.0],
+        [0.0, 0.0, 1.0],
+    ]);
+    let rough_inverse = rough.inverse3().ok_or(CalibrationError::Degenerate)?;
+
+    // two rows per view, and one more for zero skew
+    let mut system = Matrix::zeros(2 * homographies.len() + 1, 6);
+    for (index, found) in homographies.iter().enumerate() {
+        let h = rough_inverse.clone() * found;
+        let size = (0..3)
+            .flat_map(|row| (0..3).map(move |col| (row, col)))
+            .map(|cell| h[cell].powi(2))
+            .sum::<f64>()
+            .sqrt();
+        let v = |i: usize, j: usize| {
+            let (a, b) = (
+                h.column3(i).map(|x| x / size),
+                h.column3(j).map(|x| x / size),
+            );
+            [
+                a[0] * b[0],
+                a[0].mul_add(b[1], a[1] * b[0]),
+                a[1] * b[1],
+                a[2].mul_add(b[0], a[0] * b[2]),
+                a[2].mul_add(b[1], a[1] * b[2]),
+                a[2] * b[2],
+            ]
+        };
+        let (v12, v11, v22) = (v(0, 1), v(0, 0), v(1, 1));
+        for col in 0..6 {
+            system[(2 * index, col)] = v12.get(col).copied().unwrap_or_default();
+            system[(2 * index + 1, col)] = v11.get(col).copied().unwrap_or_default()
+                - v22.get(col).copied().unwrap_or_default();
+        }
+    }
+    system[(2 * homographies.len(), 1)] = 1.0;
+
+    let b = system.null_vector();
+    // `b` only matters up to scale, so make `B11` positive
+    let sign = b.first().copied().unwrap_or_default().signum();
+    let [b11, b12, b22, b13, b23, b33] =
+        [0, 1, 2, 3, 4, 5].map(|index| b.get(index).copied().unwrap_or_default() * sign);
+
+    let denominator = b11.mul_add(b22, -(b12 * b12));
+    if b11 <= 0.0 || denominator <= 0.0 {
+        return Err(CalibrationError::Degenerate);
+    }
+    let v0 = b12.mul_add(b13, -(b11 * b23)) / denominator;
+    let lambda = b33 - b13.mul_add(b13, v0 * b12.mul_add(b13, -(b11 * b23))) / b11;
+    if lambda / b11 <= 0.0 {
+        return Err(CalibrationError::Degenerate);
+    }
+    let alpha = (lambda / b11).sqrt();
+    let beta = (lambda * b11 / denominator).sqrt();
+    let u0 = -b13 * alpha * alpha / lambda;
+
+    // then undo the rough camera
+    Ok(CameraIntrinsics::new(
+        alpha * width,
+        beta * width,
+        u0.mul_add(width, width / 2.0),
+        v0.mul_add(width, height / 2.0),
+    ))
+}
+
+/// A view's pose, from its homography: `[rotation vector, translation]`.
+pub(super) fn initial_pose(intrinsics: &CameraIntrinsics, found: &Matrix) -> Option<[f64; 6]> {
+    let inverse = Matrix::from_rows3(intrinsics.matrix()).inverse3()?;
+    let columns = [0, 1, 2].map(|col| inverse.apply3(found.column3(col)));
+    let [first, second, last] = columns;
+
+    let mut scale = 2.0 / (linalg::norm(first) + linalg::norm(second));
+    // the board has to be in front of the camera
+    if last[2] < 0.0 {
+        scale = -scale;
+    }
+    let [r1, r2, t] = columns.map(|vector| vector.map(|x| x * scale));
+
+    let rotation =
+        linalg::nearest_rotation(&Matrix::from_columns3([r1, r2, linalg::cross(r1, r2)]));
+    let [rx, ry, rz] = linalg::vector_from_rotation(&rotation);
+    Some([rx, ry, rz, t[0], t[1], t[2]])
+}
+
+/// Refines every parameter at once with Levenberg-Marquardt.
+///
+/// The Jacobian is found numerically, one view at a time. Each view's
+/// residuals only depend on the intrinsics and its own pose, so the normal
+/// equations are assembled from those blocks.
+fn refine(
+    params: &mut Params,
+    free: &[usize],
+    object: &[[f64; 2]],
+    views: &[&[[f64; 2]]],
+    max_iterations: u32,
+) {
+    let unknowns = free.len() + 6 * params.poses.len();
+    let mut cost = params.cost(object, views);
+    let mut damping: f64 = 1e-3;
+
+    for _ in 0..max_iterations {
+        let (normal, gradient) = normal_equations(params, free, object, views);
+
+        let mut improved = None;
+        for _attempt in 0..24 {
+            let damped = Matrix::from_fn(unknowns, unknowns, |row, col| {
+                let value = normal[(row, col)];
+                if row == col {
+                    damping.mul_add(value.max(1e-12), value)
+                } else {
+                    value
+                }
+            });
+            let rhs: Vec<f64> = gradient.iter().map(|value| -value).collect();
+            if let Some(step) = damped.solve_cholesky(&rhs) {
+                let candidate = apply_step(params, free, &step);
+                let candidate_cost = candidate.cost(object, views);
+                if candidate_cost.is_finite() && candidate_cost < cost {
+                    improved = Some((candidate, candidate_cost));
+                    damping = (damping / 10.0).max(1e-15);
+                    break;
+                }
+            }
+            damping *= 10.0;
+        }
+
+        let Some((candidate, candidate_cost)) = improved else {
+            break;
+        };
+        *params = candidate;
+        let change = cost - candidate_cost;
+        cost = candidate_cost;
+        if change <= cost * 1e-12 {
+            break;
+        }
+    }
+}
+
+/// `JᵀJ` and `Jᵀr` for the free parameters.
+fn normal_equations(
+    params: &Params,
+    free: &[usize],
+    object: &[[f64; 2]],
+    views: &[&[[f64; 2]]],
+) -> (Matrix, Vec<f64>) {
+    let unknowns = free.len() + 6 * params.poses.len();
+    let mut normal = Matrix::zeros(unknowns, unknowns);
+    let mut gradient = vec![0.0; unknowns];
+
+    for (view, (pose, seen)) in params.poses.iter().zip(views).enumerate() {
+        let residuals = params.residuals(pose, object, seen);
+
+        // one column per free intrinsic, then six for this view's pose
+        let mut columns: Vec<(usize, Vec<f64>)> = Vec::with_capacity(free.len() + 6);
+        for (slot, &parameter) in free.iter().enumerate() {
+            let derivative = central_difference(
+                params
+                    .intrinsics
+                    .get(parameter)
+                    .copied()
+                    .unwrap_or_default(),
+                |value| {
+                    let mut moved = params.clone();
+                    if let Some(target) = moved.intrinsics.get_mut(parameter) {
+                        *target = value;
+                    }
+                    moved.residuals(pose, object, seen)
+                },
+            );
+            columns.push((slot, derivative));
+        }
+        for parameter in 0..6 {
+            let derivative =
+                central_difference(pose.get(parameter).copied().unwrap_or_default(), |value| {
+                    let mut moved = *pose;
+                    if let Some(target) = moved.get_mut(parameter) {
+                        *target = value;
+                    }
+                    params.residuals(&moved, object, seen)
+                });
+            columns.push((free.len() + 6 * view + parameter, derivative));
+        }
+
+        for &(row, ref row_column) in &columns {
+            for &(col, ref col_column) in &columns {
+                normal[(row, col)] += dot(row_column, col_column);
+            }
+            if let Some(slot) = gradient.get_mut(row) {
+                *slot += dot(row_column, &residuals);
+            }
+        }
+    }
+
+    (normal, gradient)
+}
+
+/// Moves the free parameters by a step from the normal equations.
+fn apply_step(params: &Params, free: &[usize], step: &[f64]) -> Params {
+    let mut moved = params.clone();
+    for (&parameter, delta) in free.iter().zip(step) {
+        if let Some(value) = moved.intrinsics.get_mut(parameter) {
+            *value += delta;
+        }
+    }
+    let pose_steps = step.get(free.len()..).unwrap_or_default();
+    for (pose, deltas) in moved.poses.iter_mut().zip(pose_steps.chunks_exact(6)) {
+        pose.iter_mut()
+            .zip(deltas)
+            .for_each(|(value, delta)| *value += delta);
+    }
+
+    moved
+}
+
+/// The derivative of `f` at `value`, by central differences.
+pub(super) fn central_difference<F: Fn(f64) -> Vec<f64>>(value: f64, f: F) -> Vec<f64> {
+    let step = 1e-6 * value.abs().max(1e-2);
+    let (ahead, behind) = (f(value + step), f(value - step));
+
+    ahead
+        .iter()
+        .zip(&behind)
+        .map(|(a, b)| (a - b) / (2.0 * step))
+        .collect()
+}
+
+/// The dot product of two vectors.
+pub(super) fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOARD: Checkerboard = Checkerboard::new(6, 4, 0.03);
+    const RESOLUTION: Resolution = Resolution::new(320, 240);
+
+    fn object() -> Vec<[f64; 2]> {
+        BOARD
+            .object_points()
+            .into_iter()
+            .map(|[x, y, _]| [x, y])
+            .collect()
+    }
+
+    /// Where the board's corners land in a distortion-free camera.
+    fn view(axis: [f64; 3], translation: [f64; 3]) -> Vec<[f64; 2]> {
+        let intrinsics = CameraIntrinsics::new(300.0, 300.0, 160.0, 120.0);
+        let rotation = linalg::rotation_from_vector(axis);
+        let [tx, ty, tz] = translation;
+        object()
+            .into_iter()
+            .map(|[x, y]| {
+                let [cam_x, cam_y, cam_z] = rotation.apply3([x, y, 0.0]);
+                let depth = cam_z + tz;
+                intrinsics.to_pixel([(cam_x + tx) / depth, (cam_y + ty) / depth])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn homographies_map_known_points() {
+        let truth = Matrix::from_rows3([[2.0, 0.1, 30.0], [0.05, 1.8, 20.0], [0.001, 0.0005, 1.0]]);
+        let apply = |[x, y]: [f64; 2]| {
+            let [u, v, w] = truth.apply3([x, y, 1.0]);
+            [u / w, v / w]
+        };
+        let board: Vec<[f64; 2]> = object()
+            .iter()
+            .map(|&[x, y]| [x * 100.0, y * 100.0])
+            .collect();
+        let image: Vec<[f64; 2]> = board.iter().copied().map(apply).collect();
+
+        let found = homography(&board, &image).unwrap();
+        for row in 0..3 {
+            for col in 0..3 {
+                let (got, wanted) = (found[(row, col)], truth[(row, col)]);
+                assert!(
+                    (got - wanted).abs() < 1e-9 * wanted.abs().max(1.0),
+                    "({row}, {col}) is {got}, not {wanted}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn degenerate_views_fail() {
+        let options = CalibrationOptions::default();
+        let tilted = [
+            view([0.3, 0.1, 0.0], [-0.07, -0.05, 0.4]),
+            view([-0.2, 0.3, 0.1], [-0.08, -0.04, 0.45]),
+            view([0.1, -0.35, -0.1], [-0.06, -0.05, 0.5]),
+        ];
+        assert!(
+            calibrate(&BOARD, &tilted, RESOLUTION, options).is_ok(),
+            "these are fine"
+        );
+
+        assert_eq!(
+            calibrate(&BOARD, &tilted[..0], RESOLUTION, options),
+            Err(CalibrationError::NotEnoughViews { needed: 3, got: 0 })
+        );
+        assert_eq!(
+            calibrate(&Checkerboard::new(1, 4, 0.03), &tilted, RESOLUTION, options),
+            Err(CalibrationError::BoardTooSmall {
+                columns: 1,
+                rows: 4
+            })
+        );
+        let mut short = tilted.clone();
+        if let Some(last) = short.last_mut() {
+            last.pop();
+        }
+        assert_eq!(
+            calibrate(&BOARD, &short, RESOLUTION, options),
+            Err(CalibrationError::WrongCornerCount {
+                view: 2,
+                expected: 24,
+                got: 23
+            })
+        );
+
+        // boards that are never tilted can't tell focal length from distance
+        let parallel = [
+            view([0.0; 3], [-0.07, -0.05, 0.4]),
+            view([0.0; 3], [-0.02, -0.03, 0.5]),
+            view([0.0; 3], [-0.1, -0.06, 0.45]),
+        ];
+        assert_eq!(
+            calibrate(&BOARD, &parallel, RESOLUTION, options),
+            Err(CalibrationError::Degenerate),
+            "parallel views"
+        );
+
+        // every corner on one line doesn't make a plane
+        let mut collinear = tilted;
+        if let Some(first) = collinear.first_mut() {
+            for (index, corner) in first.iter_mut().enumerate() {
+                let step = index as f64;
+                *corner = [10.0 + step, 2.0_f64.mul_add(step, 20.0)];
+            }
+        }
+        assert_eq!(
+            calibrate(&BOARD, &collinear, RESOLUTION, options),
+            Err(CalibrationError::Degenerate),
+            "collinear corners"
+        );
+    }
+}
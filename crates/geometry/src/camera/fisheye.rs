@@ -0,0 +1,254 @@
+//! The fisheye camera model, with Kannala-Brandt distortion.
+
+use core::f64::consts::{FRAC_PI_2, PI};
+
+use crate::Resolution;
+
+use super::{CameraIntrinsics, CameraModel};
+
+/// Kannala-Brandt fisheye distortion.
+///
+/// Instead of bending a pinhole projection, this maps each ray's angle from
+/// the optical axis, `θ`, to a distance from the principal point:
+///
+/// ```text
+/// θd = θ (1 + k1 θ² + k2 θ⁴ + k3 θ⁶ + k4 θ⁸)
+/// ```
+///
+/// This is the same model (and coefficient order) as OpenCV's `fisheye`
+/// module.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KannalaBrandt {
+    /// The coefficient on `θ³`.
+    pub k1: f64,
+    /// The coefficient on `θ⁵`.
+    pub k2: f64,
+    /// The coefficient on `θ⁷`.
+    pub k3: f64,
+    /// The coefficient on `θ⁹`.
+    pub k4: f64,
+}
+
+impl KannalaBrandt {
+    /// An equidistant fisheye lens, where distance from the principal point
+    /// grows evenly with the angle.
+    pub const EQUIDISTANT: Self = Self::new(0.0, 0.0, 0.0, 0.0);
+
+    /// Makes distortion from its coefficients.
+    #[inline]
+    #[must_use]
+    pub const fn new(k1: f64, k2: f64, k3: f64, k4: f64) -> Self {
+        Self { k1, k2, k3, k4 }
+    }
+
+    /// Maps a ray's angle from the optical axis to its distance from the
+    /// principal point, in normalized units.
+    #[inline]
+    pub fn distort_angle(&self, theta: f64) -> f64 {
+        let t2 = theta * theta;
+        let poly = t2.mul_add(
+            t2.mul_add(t2.mul_add(t2.mul_add(self.k4, self.k3), self.k2), self.k1),
+            1.0,
+        );
+        theta * poly
+    }
+
+    /// Undoes [`KannalaBrandt::distort_angle`], using Newton's method.
+    ///
+    /// Returns `None` if no angle between zero and 180 degrees gives that
+    /// distance.
+    #[inline]
+    pub fn undistort_angle(&self, distance: f64) -> Option<f64> {
+        let mut theta = distance.min(PI);
+        for _ in 0..32 {
+            let error = self.distort_angle(theta) - distance;
+            if error.abs() < 1e-12 {
+                return (0.0..=PI).contains(&theta).then_some(theta);
+            }
+
+            let t2 = theta * theta;
+            let slope = t2.mul_add(
+                t2.mul_add(
+                    t2.mul_add(t2.mul_add(9.0 * self.k4, 7.0 * self.k3), 5.0 * self.k2),
+                    3.0 * self.k1,
+                ),
+                1.0,
+            );
+            if slope <= 0.0 {
+                return None;
+            }
+            theta -= error / slope;
+        }
+
+        None
+    }
+
+    /// Moves a normalized point from where a pinhole camera would see it to
+    /// where the fisheye lens puts it.
+    #[inline]
+    pub fn distort(&self, point: [f64; 2]) -> [f64; 2] {
+        let [x, y] = point;
+        let radius = x.hypot(y);
+        if radius < 1e-12 {
+            return point;
+        }
+
+        let scale = self.distort_angle(radius.atan()) / radius;
+        [x * scale, y * scale]
+    }
+
+    /// Finds the pinhole point that the lens moved to `point`.
+    ///
+    /// Returns `None` for points more than 90 degrees off-axis, since a
+    /// pinhole camera can't see them.
+    #[inline]
+    pub fn undistort(&self, point: [f64; 2]) -> Option<[f64; 2]> {
+        let [x, y] = point;
+        let distance = x.hypot(y);
+        if distance < 1e-12 {
+            return Some(point);
+        }
+
+        let theta = self.undistort_angle(distance)?;
+        if theta >= FRAC_PI_2 {
+            return None;
+        }
+        let scale = theta.tan() / distance;
+        Some([x * scale, y * scale])
+    }
+
+    /// The coefficients: `[k1, k2, k3, k4]`.
+    #[inline]
+    pub const fn coefficients(&self) -> [f64; 4] {
+        [self.k1, self.k2, self.k3, self.k4]
+    }
+}
+
+/// A fisheye camera, with Kannala-Brandt distortion.
+///
+/// This fits wide lenses, including ones that see more than 180 degrees
+/// across.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FisheyeCamera {
+    /// The resolution the intrinsics are for.
+    pub resolution: Resolution,
+    /// The focal lengths and principal point.
+    pub intrinsics: CameraIntrinsics,
+    /// The lens distortion.
+    pub distortion: KannalaBrandt,
+}
+
+impl FisheyeCamera {
+    /// Makes a fisheye camera.
+    #[inline]
+    #[must_use]
+    pub const fn new(
+        resolution: Resolution,
+        intrinsics: CameraIntrinsics,
+        distortion: KannalaBrandt,
+    ) -> Self {
+        Self {
+            resolution,
+            intrinsics,
+            distortion,
+        }
+    }
+}
+
+impl CameraModel for FisheyeCamera {
+    #[inline]
+    fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    #[inline]
+    fn intrinsics(&self) -> CameraIntrinsics {
+        self.intrinsics
+    }
+
+    #[inline]
+    fn distort(&self, point: [f64; 2]) -> [f64; 2] {
+        self.distortion.distort(point)
+    }
+
+    #[inline]
+    fn undistort(&self, point: [f64; 2]) -> Option<[f64; 2]> {
+        self.distortion.undistort(point)
+    }
+
+    #[inline]
+    fn project(&self, point: [f64; 3]) -> Option<[f64; 2]> {
+        let [x, y, z] = point;
+        let radius = x.hypot(y);
+        if radius < 1e-12 {
+            // straight ahead works, but straight behind doesn't
+            return (z > 0.0).then(|| self.intrinsics.to_pixel([0.0, 0.0]));
+        }
+
+        let scale = self.distortion.distort_angle(radius.atan2(z)) / radius;
+        Some(self.intrinsics.to_pixel([x * scale, y * scale]))
+    }
+
+    #[inline]
+    fn unproject(&self, pixel: [f64; 2]) -> Option<[f64; 3]> {
+        let [x, y] = self.intrinsics.to_normalized(pixel);
+        let distance = x.hypot(y);
+        if distance < 1e-12 {
+            return Some([0.0, 0.0, 1.0]);
+        }
+
+        let theta = self.distortion.undistort_angle(distance)?;
+        let (sin, cos) = theta.sin_cos();
+        Some([x / distance * sin, y / distance * sin, cos])
+    }
+
+    #[inline]
+    fn scaled(&self, resolution: Resolution) -> Self {
+        Self {
+            resolution,
+            intrinsics: self.intrinsics.scaled(self.resolution, resolution),
+            distortion: self.distortion,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fisheyes_see_past_ninety_degrees() {
+        let camera = FisheyeCamera::new(
+            Resolution::new(800, 800),
+            CameraIntrinsics::new(230.0, 230.0, 399.5, 399.5),
+            KannalaBrandt::new(-0.01, 0.003, -0.001, 0.0),
+        );
+
+        // 100 degrees off-axis still lands in the frame
+        let angle = 100.0_f64.to_radians();
+        let ray = [angle.sin(), 0.0, angle.cos()];
+        let pixel = camera.project(ray).unwrap();
+        assert!(pixel[0] > 399.5 && pixel[0] < 800.0, "landed at {pixel:?}");
+
+        let back = camera.unproject(pixel).unwrap();
+        let error = back
+            .iter()
+            .zip(ray)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f64::max);
+        assert!(error < 1e-9, "{ray:?} came back as {back:?}");
+        assert_eq!(
+            camera.undistort_pixel(pixel),
+            None,
+            "a pinhole can't see it"
+        );
+
+        // points a pinhole can see round trip through it
+        let inside = [500.0, 300.0];
+        let round_trip = camera.distort_pixel(camera.undistort_pixel(inside).unwrap());
+        let error = (round_trip[0] - inside[0]).hypot(round_trip[1] - inside[1]);
+        assert!(error < 1e-6, "{inside:?} came back as {round_trip:?}");
+    }
+}
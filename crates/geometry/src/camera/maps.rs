@@ -0,0 +1,100 @@
+//! Remap tables built from camera models.
+
+use serumcv_image::RemapMap;
+
+use crate::Resolution;
+
+use super::{BrownConrady, CameraModel, PinholeCamera};
+
+/// Builds a map that shows what `source` sees as `target` would see it.
+///
+/// Each of the target's pixels looks along its ray, then reads wherever
+/// that ray lands in the source's frames. Pixels whose rays the source
+/// can't see have no source, so they get the border.
+///
+/// The map is the target's size. Build it once, then apply it to each
+/// frame.
+#[inline]
+pub fn reprojection_map<S, T>(source: &S, target: &T) -> RemapMap
+where
+    S: CameraModel + ?Sized,
+    T: CameraModel + ?Sized,
+{
+    let resolution = target.resolution();
+    RemapMap::from_fn(resolution.width, resolution.height, |x, y| {
+        target
+            .unproject([f64::from(x), f64::from(y)])
+            .and_then(|ray| source.project(ray))
+            .map_or([f32::NAN; 2], |pixel| pixel.map(to_f32))
+    })
+}
+
+/// Builds a map that removes a camera's lens distortion.
+///
+/// The output is a distortion-free pinhole camera with the same focal
+/// lengths and principal point, scaled to `output`. Straight lines come out
+/// straight, but the corners of a wide lens get cropped away.
+#[inline]
+pub fn undistortion_map<C>(camera: &C, output: Resolution) -> RemapMap
+where
+    C: CameraModel + ?Sized,
+{
+    let intrinsics = camera.intrinsics().scaled(camera.resolution(), output);
+    let ideal = PinholeCamera::new(output, intrinsics, BrownConrady::NONE);
+    reprojection_map(camera, &ideal)
+}
+
+#[expect(
+    clippy::cast_possible_truncation,
+    reason = "pixel coordinates fit in an `f32` with room to spare"
+)]
+const fn to_f32(value: f64) -> f32 {
+    value as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use serumcv_image::{Border, Image, Interpolation};
+
+    use super::*;
+    use crate::camera::CameraIntrinsics;
+
+    #[test]
+    fn undistortion_straightens_lines() {
+        let resolution = Resolution::new(160, 120);
+        let camera = PinholeCamera::new(
+            resolution,
+            CameraIntrinsics::new(120.0, 120.0, 79.5, 59.5),
+            BrownConrady::new(-0.3, 0.1, 0.0, 0.0, 0.0),
+        );
+
+        // a horizontal line, as the distorted camera sees it
+        let ideal_row = 20.0;
+        let frame = Image::from_fn(160, 120, |x, y| {
+            let [_, ideal_y] = camera
+                .undistort_pixel([f64::from(x), f64::from(y)])
+                .unwrap();
+            if (ideal_y - ideal_row).abs() < 1.0 {
+                255_u8
+            } else {
+                0
+            }
+        });
+
+        let map = undistortion_map(&camera, resolution);
+        let straight = map.apply(&frame.view(), Interpolation::Nearest, Border::Constant(0));
+
+        // the whole line lands back on one row
+        for x in 20..140 {
+            assert_eq!(
+                straight.get(x, 20),
+                Some(&255),
+                "column {x} is off the line"
+            );
+            assert_eq!(straight.get(x, 23), Some(&0), "column {x} is too thick");
+        }
+
+        // without correction, the line bends toward the middle
+        assert_eq!(frame.get(20, 20), Some(&0), "the distorted line bends");
+    }
+}
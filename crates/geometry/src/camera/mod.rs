@@ -0,0 +1,300 @@
+//! Camera geometry: where things in front of a camera show up in its frames.
+//!
+//! Points on the image plane come in two flavors. Pixel coordinates are what
+//! you see in a frame. Normalized coordinates are what an ideal camera with a
+//! focal length of one would see, so `[x / z, y / z]` for a point `[x, y, z]`
+//! in front of the camera.
+//!
+//! Points in front of the camera use its own coordinates: `x` goes right,
+//! `y` goes down, and `z` goes out through the lens.
+//!
+//! There are two models. [`PinholeCamera`] fits most lenses, and
+//! [`FisheyeCamera`] fits wide ones, up to (and past) 180 degrees. Both
+//! implement [`CameraModel`], and [`Camera`] holds either one.
+//!
+//! To correct whole frames, build a map with [`undistortion_map`] (or
+//! [`reprojection_map`]) once, then apply it to each frame.
+
+mod fisheye;
+mod maps;
+mod pinhole;
+
+pub use fisheye::{FisheyeCamera, KannalaBrandt};
+pub use maps::{reprojection_map, undistortion_map};
+pub use pinhole::{BrownConrady, PinholeCamera};
+
+use crate::Resolution;
+
+/// A camera's focal lengths and principal point, in pixels.
+///
+/// These fill in the usual intrinsic matrix:
+///
+/// ```text
+/// | fx  skew  cx |
+/// |  0   fy   cy |
+/// |  0    0    1 |
+/// ```
+///
+/// Pixel coordinates put the middle of the top-left pixel at `[0, 0]`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CameraIntrinsics {
+    /// The horizontal focal length.
+    pub fx: f64,
+    /// The vertical focal length.
+    pub fy: f64,
+    /// The principal point's horizontal position.
+    pub cx: f64,
+    /// The principal point's vertical position.
+    pub cy: f64,
+    /// How far the pixel grid leans. This is zero for nearly every camera.
+    pub skew: f64,
+}
+
+impl CameraIntrinsics {
+    /// Makes intrinsics without any skew.
+    #[inline]
+    #[must_use]
+    pub const fn new(fx: f64, fy: f64, cx: f64, cy: f64) -> Self {
+        Self {
+            fx,
+            fy,
+            cx,
+            cy,
+            skew: 0.0,
+        }
+    }
+
+    /// The intrinsic matrix, row by row.
+    #[inline]
+    pub const fn matrix(&self) -> [[f64; 3]; 3] {
+        [
+            [self.fx, self.skew, self.cx],
+            [0.0, self.fy, self.cy],
+            [0.0, 0.0, 1.0],
+        ]
+    }
+
+    /// Turns a normalized point into pixel coordinates.
+    #[inline]
+    pub const fn to_pixel(&self, point: [f64; 2]) -> [f64; 2] {
+        let [x, y] = point;
+        [
+            self.fx.mul_add(x, self.skew.mul_add(y, self.cx)),
+            self.fy.mul_add(y, self.cy),
+        ]
+    }
+
+    /// Turns pixel coordinates into a normalized point.
+    #[inline]
+    pub fn to_normalized(&self, pixel: [f64; 2]) -> [f64; 2] {
+        let y = (pixel[1] - self.cy) / self.fy;
+        let x = self.skew.mul_add(-y, pixel[0] - self.cx) / self.fx;
+        [x, y]
+    }
+
+    /// The same intrinsics for the same sensor, after switching from one
+    /// resolution to another.
+    ///
+    /// This assumes the device scales its whole sensor to each resolution.
+    /// Many devices crop instead (especially when the aspect ratio changes),
+    /// and those need calibrating at each resolution.
+    #[inline]
+    #[must_use]
+    pub fn scaled(&self, from: Resolution, to: Resolution) -> Self {
+        let x_scale = f64::from(to.width) / f64::from(from.width);
+        let y_scale = f64::from(to.height) / f64::from(from.height);
+
+        // pixel centers sit half a pixel in from the sensor's edge
+        Self {
+            fx: self.fx * x_scale,
+            fy: self.fy * y_scale,
+            cx: (self.cx + 0.5).mul_add(x_scale, -0.5),
+            cy: (self.cy + 0.5).mul_add(y_scale, -0.5),
+            skew: self.skew * x_scale,
+        }
+    }
+}
+
+/// Projection between points in front of a camera and pixels in its
+/// frames.
+pub trait CameraModel {
+    /// The resolution this model is for.
+    fn resolution(&self) -> Resolution;
+
+    /// The model's focal lengths and principal point.
+    fn intrinsics(&self) -> CameraIntrinsics;
+
+    /// Moves a normalized point from where a perfect pinhole camera would
+    /// see it to where this camera's lens actually puts it.
+    ///
+    /// The result is still normalized, so [`CameraIntrinsics::to_pixel`]
+    /// turns it into a pixel.
+    fn distort(&self, point: [f64; 2]) -> [f64; 2];
+
+    /// Undoes [`CameraModel::distort`].
+    ///
+    /// Returns `None` if no pinhole point lands there, like for fisheye
+    /// points more than 90 degrees off-axis.
+    fn undistort(&self, point: [f64; 2]) -> Option<[f64; 2]>;
+
+    /// Finds the pixel that a point (or a ray's direction) lands on.
+    ///
+    /// Returns `None` if the camera can't see in that direction. The pixel
+    /// may still be outside of the frame.
+    fn project(&self, point: [f64; 3]) -> Option<[f64; 2]>;
+
+    /// Finds the direction that a pixel looks in, as a unit vector.
+    ///
+    /// Returns `None` if the pixel is outside of what the model can
+    /// describe.
+    fn unproject(&self, pixel: [f64; 2]) -> Option<[f64; 3]>;
+
+    /// The same camera at another resolution. See
+    /// [`CameraIntrinsics::scaled`] for the caveats.
+    #[must_use]
+    fn scaled(&self, resolution: Resolution) -> Self
+    where
+        Self: Sized;
+
+    /// Finds the point at a certain depth (its `z`) along a pixel's ray.
+    #[inline]
+    fn unproject_at_depth(&self, pixel: [f64; 2], depth: f64) -> Option<[f64; 3]> {
+        let [x, y, z] = self.unproject(pixel)?;
+        (z > 0.0).then(|| [x / z * depth, y / z * depth, depth])
+    }
+
+    /// Moves a pixel from where a distortion-free camera (with the same
+    /// intrinsics) would see it to where this camera does.
+    #[inline]
+    fn distort_pixel(&self, pixel: [f64; 2]) -> [f64; 2] {
+        let intrinsics = self.intrinsics();
+        intrinsics.to_pixel(self.distort(intrinsics.to_normalized(pixel)))
+    }
+
+    /// Undoes [`CameraModel::distort_pixel`].
+    #[inline]
+    fn undistort_pixel(&self, pixel: [f64; 2]) -> Option<[f64; 2]> {
+        let intrinsics = self.intrinsics();
+        Some(intrinsics.to_pixel(self.undistort(intrinsics.to_normalized(pixel))?))
+    }
+}
+
+/// Any of the camera models.
+///
+/// This is what calibrations are stored as, so each device can use
+/// whichever model fits its lens.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Camera {
+    /// A pinhole camera, for most lenses.
+    Pinhole(PinholeCamera),
+    /// A fisheye camera, for wide lenses.
+    Fisheye(FisheyeCamera),
+}
+
+impl From<PinholeCamera> for Camera {
+    #[inline]
+    fn from(camera: PinholeCamera) -> Self {
+        Self::Pinhole(camera)
+    }
+}
+
+impl From<FisheyeCamera> for Camera {
+    #[inline]
+    fn from(camera: FisheyeCamera) -> Self {
+        Self::Fisheye(camera)
+    }
+}
+
+impl CameraModel for Camera {
+    #[inline]
+    fn resolution(&self) -> Resolution {
+        match *self {
+            Self::Pinhole(ref camera) => camera.resolution(),
+            Self::Fisheye(ref camera) => camera.resolution(),
+        }
+    }
+
+    #[inline]
+    fn intrinsics(&self) -> CameraIntrinsics {
+        match *self {
+            Self::Pinhole(ref camera) => camera.intrinsics(),
+            Self::Fisheye(ref camera) => camera.intrinsics(),
+        }
+    }
+
+    #[inline]
+    fn distort(&self, point: [f64; 2]) -> [f64; 2] {
+        match *self {
+            Self::Pinhole(ref camera) => camera.distort(point),
+            Self::Fisheye(ref camera) => camera.distort(point),
+        }
+    }
+
+    #[inline]
+    fn undistort(&self, point: [f64; 2]) -> Option<[f64; 2]> {
+        match *self {
+            Self::Pinhole(ref camera) => camera.undistort(point),
+            Self::Fisheye(ref camera) => camera.undistort(point),
+        }
+    }
+
+    #[inline]
+    fn project(&self, point: [f64; 3]) -> Option<[f64; 2]> {
+        match *self {
+            Self::Pinhole(ref camera) => camera.project(point),
+            Self::Fisheye(ref camera) => camera.project(point),
+        }
+    }
+
+    #[inline]
+    fn unproject(&self, pixel: [f64; 2]) -> Option<[f64; 3]> {
+        match *self {
+            Self::Pinhole(ref camera) => camera.unproject(pixel),
+            Self::Fisheye(ref camera) => camera.unproject(pixel),
+        }
+    }
+
+    #[inline]
+    fn scaled(&self, resolution: Resolution) -> Self {
+        match *self {
+            Self::Pinhole(ref camera) => Self::Pinhole(camera.scaled(resolution)),
+            Self::Fisheye(ref camera) => Self::Fisheye(camera.scaled(resolution)),
+        }
+    }
+}
+
+/// Scales a vector to unit length.
+fn unit(vector: [f64; 3]) -> [f64; 3] {
+    let [x, y, z] = vector;
+    let length = x.hypot(y).hypot(z);
+    [x / length, y / length, z / length]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intrinsics_scale_with_the_resolution() {
+        let intrinsics = CameraIntrinsics::new(600.0, 600.0, 319.5, 239.5);
+        let half = intrinsics.scaled(Resolution::new(640, 480), Resolution::new(320, 240));
+        assert_eq!(half, CameraIntrinsics::new(300.0, 300.0, 159.5, 119.5));
+
+        // the same ray lands on the same spot of the sensor
+        let camera = PinholeCamera::new(
+            Resolution::new(640, 480),
+            intrinsics,
+            BrownConrady::new(-0.1, 0.01, 0.0, 0.0, 0.0),
+        );
+        let small = camera.scaled(Resolution::new(320, 240));
+        let ray = camera.unproject([100.0, 50.0]).unwrap();
+        let [x, y] = small.project(ray).unwrap();
+        assert!(
+            (x - 49.75).abs() < 1e-9 && (y - 24.75).abs() < 1e-9,
+            "landed on {x}, {y}"
+        );
+    }
+}
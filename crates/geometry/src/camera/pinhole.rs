@@ -0,0 +1,199 @@
+//! The pinhole camera model, with Brown-Conrady lens distortion.
+
+use crate::Resolution;
+
+use super::{CameraIntrinsics, CameraModel};
+
+/// Brown-Conrady lens distortion, with three radial and two tangential
+/// coefficients.
+///
+/// This is the same model (and coefficient order) that OpenCV uses, so
+/// coefficients can be copied between the two.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BrownConrady {
+    /// The first radial coefficient. Negative values are barrel distortion.
+    pub k1: f64,
+    /// The second radial coefficient.
+    pub k2: f64,
+    /// The first tangential coefficient.
+    pub p1: f64,
+    /// The second tangential coefficient.
+    pub p2: f64,
+    /// The third radial coefficient.
+    pub k3: f64,
+}
+
+impl BrownConrady {
+    /// A perfect lens.
+    pub const NONE: Self = Self::new(0.0, 0.0, 0.0, 0.0, 0.0);
+
+    /// Makes distortion from its coefficients, in OpenCV's order.
+    #[inline]
+    #[must_use]
+    pub const fn new(k1: f64, k2: f64, p1: f64, p2: f64, k3: f64) -> Self {
+        Self { k1, k2, p1, p2, k3 }
+    }
+
+    /// Moves a normalized point to where the lens actually puts it.
+    #[inline]
+    pub fn distort(&self, point: [f64; 2]) -> [f64; 2] {
+        let [x, y] = point;
+        let r2 = x.mul_add(x, y * y);
+        let radial = r2.mul_add(r2.mul_add(r2.mul_add(self.k3, self.k2), self.k1), 1.0);
+        let xy = 2.0 * x * y;
+
+        [
+            x.mul_add(
+                radial,
+                self.p1.mul_add(xy, self.p2 * 2.0_f64.mul_add(x * x, r2)),
+            ),
+            y.mul_add(
+                radial,
+                self.p2.mul_add(xy, self.p1 * 2.0_f64.mul_add(y * y, r2)),
+            ),
+        ]
+    }
+
+    /// Finds the normalized point that the lens moved to `point`.
+    ///
+    /// There's no closed form for this, so it's found by fixed-point
+    /// iteration. That's accurate to well under a pixel for the distortion
+    /// real lenses have, but it may not converge for wild coefficients far
+    /// outside of the image.
+    #[inline]
+    pub fn undistort(&self, point: [f64; 2]) -> [f64; 2] {
+        let mut guess = point;
+        for _ in 0..32 {
+            let distorted = self.distort(guess);
+            let error = [distorted[0] - point[0], distorted[1] - point[1]];
+            guess = [guess[0] - error[0], guess[1] - error[1]];
+            if error[0].hypot(error[1]) < 1e-12 {
+                break;
+            }
+        }
+
+        guess
+    }
+
+    /// The coefficients, in OpenCV's order: `[k1, k2, p1, p2, k3]`.
+    #[inline]
+    pub const fn coefficients(&self) -> [f64; 5] {
+        [self.k1, self.k2, self.p1, self.p2, self.k3]
+    }
+}
+
+/// A pinhole camera, with Brown-Conrady distortion.
+///
+/// This fits most lenses up to about 120 degrees across. It can't see
+/// anything behind the camera, or exactly beside it.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PinholeCamera {
+    /// The resolution the intrinsics are for.
+    pub resolution: Resolution,
+    /// The focal lengths and principal point.
+    pub intrinsics: CameraIntrinsics,
+    /// The lens distortion.
+    pub distortion: BrownConrady,
+}
+
+impl PinholeCamera {
+    /// Makes a pinhole camera.
+    #[inline]
+    #[must_use]
+    pub const fn new(
+        resolution: Resolution,
+        intrinsics: CameraIntrinsics,
+        distortion: BrownConrady,
+    ) -> Self {
+        Self {
+            resolution,
+            intrinsics,
+            distortion,
+        }
+    }
+}
+
+impl CameraModel for PinholeCamera {
+    #[inline]
+    fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    #[inline]
+    fn intrinsics(&self) -> CameraIntrinsics {
+        self.intrinsics
+    }
+
+    #[inline]
+    fn distort(&self, point: [f64; 2]) -> [f64; 2] {
+        self.distortion.distort(point)
+    }
+
+    #[inline]
+    fn undistort(&self, point: [f64; 2]) -> Option<[f64; 2]> {
+        let undistorted = self.distortion.undistort(point);
+        undistorted
+            .iter()
+            .all(|value| value.is_finite())
+            .then_some(undistorted)
+    }
+
+    #[inline]
+    fn project(&self, point: [f64; 3]) -> Option<[f64; 2]> {
+        let [x, y, z] = point;
+        (z > 0.0).then(|| {
+            self.intrinsics
+                .to_pixel(self.distortion.distort([x / z, y / z]))
+        })
+    }
+
+    #[inline]
+    fn unproject(&self, pixel: [f64; 2]) -> Option<[f64; 3]> {
+        let [x, y] = self.undistort(self.intrinsics.to_normalized(pixel))?;
+        Some(super::unit([x, y, 1.0]))
+    }
+
+    #[inline]
+    fn scaled(&self, resolution: Resolution) -> Self {
+        Self {
+            resolution,
+            intrinsics: self.intrinsics.scaled(self.resolution, resolution),
+            distortion: self.distortion,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distortion_round_trips() {
+        let camera = PinholeCamera::new(
+            Resolution::new(640, 480),
+            CameraIntrinsics::new(500.0, 510.0, 320.0, 240.0),
+            BrownConrady::new(-0.28, 0.09, 0.001, -0.0005, -0.01),
+        );
+
+        for pixel in [[10.0, 12.0], [320.0, 240.0], [600.0, 100.0], [400.0, 470.0]] {
+            let back = camera.distort_pixel(camera.undistort_pixel(pixel).unwrap());
+            let error = (back[0] - pixel[0]).hypot(back[1] - pixel[1]);
+            assert!(error < 1e-6, "{pixel:?} came back as {back:?}");
+
+            let ray = camera.unproject(pixel).unwrap();
+            let projected = camera
+                .project(ray.map(|component| component * 3.0))
+                .unwrap();
+            let error = (projected[0] - pixel[0]).hypot(projected[1] - pixel[1]);
+            assert!(error < 1e-6, "{pixel:?} was projected to {projected:?}");
+        }
+
+        assert_eq!(
+            camera.project([0.0, 0.0, -1.0]),
+            None,
+            "it can't see behind itself"
+        );
+    }
+}
@@ -0,0 +1,422 @@
+//! Disparity from rectified stereo pairs, by block matching.
+//!
+//! After [rectification](crate::calib::StereoRectification), each point
+//! shows up on the same row of both images, just further left in the right
+//! one. How much further is its disparity. The block matcher finds it by
+//! sliding a small block of the left image along the right image's row,
+//! looking for the best match.
+//!
+//! Disparity is inversely proportional to depth, so
+//! [`StereoRectification::depth_image`](crate::calib::StereoRectification::depth_image)
+//! turns one into the other.
+//!
+//! ```no_run
+//! use serumcv_image::{Border, Image, Interpolation};
+//! use serumcv_geometry::calib::StereoCalibration;
+//! use serumcv_geometry::disparity::{BlockMatcher, BlockMatchingOptions};
+//!
+//! fn depth(stereo: &StereoCalibration, frames: &[(Image<u8>, Image<u8>)]) {
+//!     let rectification = stereo.rectify().unwrap();
+//!     // build the maps once, then use them for every frame
+//!     let (left_map, right_map) = (rectification.left_map(), rectification.right_map());
+//!     let mut matcher = BlockMatcher::new(BlockMatchingOptions::default());
+//!
+//!     for (left, right) in frames {
+//!         let left = left_map.apply(&left.view(), Interpolation::Bilinear, Border::Constant(0));
+//!         let right = right_map.apply(&right.view(), Interpolation::Bilinear, Border::Constant(0));
+//!         let disparity = matcher.compute(&left.view(), &right.view()).unwrap();
+//!         let depth = rectification.depth_image(&disparity.view());
+//!         println!("the middle is {} away", depth.get(depth.width() / 2, depth.height() / 2).unwrap());
+//!     }
+//! }
+//! ```
+
+use serumcv_image::{Image, ImageView};
+
+use crate::error::DisparityError;
+
+/// Settings for a [`BlockMatcher`].
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlockMatchingOptions {
+    /// The smallest disparity to look for, in pixels.
+    pub min_disparity: u32,
+    /// How many disparities to try, starting at `min_disparity`. More can
+    /// find closer things, but take longer.
+    pub disparities: u32,
+    /// The width and height of the blocks being compared, which must be
+    /// odd. Bigger blocks match more reliably, but blur edges.
+    pub block_size: u32,
+    /// How much better the best match has to be than any other (apart from
+    /// its neighbors), as a fraction. Pixels without a clear winner, like in
+    /// flat or repetitive areas, get left out.
+    pub uniqueness: f32,
+    /// How far apart, in pixels, matching from the left and matching from
+    /// the right can land before a pixel gets left out. This catches most
+    /// pixels that only one camera can see. `None` skips the check.
+    pub left_right_tolerance: Option<u32>,
+    /// Whether to find disparities between whole pixels, by fitting a
+    /// parabola through the best match and its neighbors.
+    pub subpixel: bool,
+}
+
+impl Default for BlockMatchingOptions {
+    /// 64 disparities from zero with 9x9 blocks, checked both ways.
+    #[inline]
+    fn default() -> Self {
+        Self {
+            min_disparity: 0,
+            disparities: 64,
+            block_size: 9,
+            uniqueness: 0.15,
+            left_right_tolerance: Some(1),
+            subpixel: true,
+        }
+    }
+}
+
+/// Finds disparities between rectified images by matching blocks, using
+/// the sum of absolute differences.
+///
+/// The matcher keeps its working memory between pairs, so reuse one for
+/// each pair of frames.
+#[derive(Clone, Debug)]
+pub struct BlockMatcher {
+    options: BlockMatchingOptions,
+    /// For each disparity, then each column: the block's column of
+    /// differences, around the current row.
+    column_sums: Vec<u32>,
+    /// For each disparity, then each column: the whole block's cost on the
+    /// current row.
+    costs: Vec<u32>,
+    /// For each column of the right image: its best disparity and cost on
+    /// the current row.
+    right_best: Vec<Option<(usize, u32)>>,
+}
+
+impl BlockMatcher {
+    /// Makes a block matcher.
+    #[inline]
+    pub const fn new(options: BlockMatchingOptions) -> Self {
+        Self {
+            options,
+            column_sums: Vec::new(),
+            costs: Vec::new(),
+            right_best: Vec::new(),
+        }
+    }
+
+    /// The matcher's settings.
+    #[inline]
+    pub const fn options(&self) -> BlockMatchingOptions {
+        self.options
+    }
+
+    /// Finds the disparity of each pixel in the left image.
+    ///
+    /// Pixels without a disparity are `NaN`. That includes the ones too
+    /// close to the edges for a whole block, the ones too far left to have
+    /// a match, and the ones that fail the uniqueness or left-right checks.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the images are different sizes, or the settings don't
+    /// make sense.
+    #[inline]
+    pub fn compute(
+        &mut self,
+        left: &ImageView<'_, u8>,
+        right: &ImageView<'_, u8>,
+    ) -> Result<Image<f32>, DisparityError> {
+        if left.dimensions() != right.dimensions() {
+            return Err(DisparityError::SizeMismatch {
+                left_width: left.width(),
+                left_height: left.height(),
+                right_width: right.width(),
+                right_height: right.height(),
+            });
+        }
+        let options = self.options;
+        if options.block_size.is_multiple_of(2) {
+            return Err(DisparityError::EvenBlockSize {
+                block_size: options.block_size,
+            });
+        }
+        if options.disparities == 0 {
+            return Err(DisparityError::NoDisparities);
+        }
+
+        let (width, height) = left.dimensions();
+        let mut disparity = Image::from_pixel(width, height, f32::NAN);
+        if width < options.block_size || height < options.block_size {
+            return Ok(disparity);
+        }
+
+        let columns = width as usize;
+        let count = options.disparities as usize;
+        self.column_sums.clear();
+        self.column_sums.resize(count * columns, 0);
+        self.costs.resize(count * columns, u32::MAX);
+        self.right_best.resize(columns, None);
+
+        // the block's first rows, minus the last one, which gets added like
+        // any other row below
+        let radius = options.block_size >> 1;
+        for y in 0..options.block_size - 1 {
+            self.add_row(left.row(y), right.row(y), true);
+        }
+
+        for y in radius..height - radius {
+            self.add_row(left.row(y + radius), right.row(y + radius), true);
+            self.block_costs();
+            self.match_row(y, &mut disparity);
+            self.add_row(left.row(y - radius), right.row(y - radius), false);
+        }
+
+        Ok(disparity)
+    }
+
+    /// The disparity of each row of costs.
+    fn shifts(&self) -> impl Iterator<Item = usize> {
+        let first = self.options.min_disparity as usize;
+        first..first + self.options.disparities as usize
+    }
+
+    /// Adds (or removes) one row's differences to the column sums.
+    fn add_row(&mut self, left_row: Option<&[u8]>, right_row: Option<&[u8]>, adding: bool) {
+        let (Some(left), Some(right)) = (left_row, right_row) else {
+            return;
+        };
+        let columns = left.len().max(1);
+        let shifts = self.shifts();
+
+        for (sums, shift) in self.column_sums.chunks_exact_mut(columns).zip(shifts) {
+            let pairs = left.iter().skip(shift).zip(right);
+            for (sum, (&seen, &other)) in sums.iter_mut().skip(shift).zip(pairs) {
+                let difference = u32::from(seen.abs_diff(other));
+                if adding {
+                    *sum += difference;
+                } else {
+                    *sum -= difference;
+                }
+            }
+        }
+    }
+
+    /// Sums the column sums across each block, for every disparity.
+    ///
+    /// Blocks that would hang off either image get `u32::MAX`.
+    fn block_costs(&mut self) {
+        let columns = self.right_best.len().max(1);
+        let block = self.options.block_size as usize;
+        let radius = block >> 1;
+        let shifts = self.shifts();
+
+        let rows = self
+            .costs
+            .chunks_exact_mut(columns)
+            .zip(self.column_sums.chunks_exact(columns));
+        for ((costs, sums), shift) in rows.zip(shifts) {
+            costs.fill(u32::MAX);
+            let Some(first) = sums.get(shift..shift + block) else {
+                continue;
+            };
+
+            // slide the block along, adding the column coming in and taking
+            // away the one going out
+            let mut window: u32 = first.iter().sum();
+            let mut targets = costs.iter_mut().skip(shift + radius);
+            if let Some(target) = targets.next() {
+                *target = window;
+            }
+            let moves = sums.iter().skip(shift + block).zip(sums.iter().skip(shift));
+            for (target, (&entering, &leaving)) in targets.zip(moves) {
+                window = window + entering - leaving;
+                *target = window;
+            }
+        }
+    }
+
+    /// Picks each pixel's disparity on one row.
+    #[expect(
+        clippy::cast_possible_truncation,
+        reason = "disparities are tiny compared to an `f32`'s range"
+    )]
+    fn match_row(&mut self, y: u32, disparity: &mut Image<f32>) {
+        let options = self.options;
+        let columns = self.right_best.len();
+        let count = options.disparities as usize;
+        let first = options.min_disparity as usize;
+
+        if options.left_right_tolerance.is_some() {
+            self.find_right_best();
+        }
+
+        for (x, px) in (0..columns).zip(0_u32..) {
+            let candidate = |index: usize| {
+                self.costs
+                    .get(index * columns + x)
+                    .copied()
+                    .filter(|&cost| cost != u32::MAX)
+            };
+
+            let mut best: Option<(usize, u32)> = None;
+            for index in 0..count {
+                if let Some(cost) = candidate(index) {
+                    if best.is_none_or(|(_, best_cost)| cost < best_cost) {
+                        best = Some((index, cost));
+                    }
+                }
+            }
+            let Some((index, cost)) = best else {
+                continue;
+            };
+
+            // a close second (that isn't just a neighbor) means there's no
+            // clear match
+            let rival = (0..count)
+                .filter(|other| other.abs_diff(index) > 1)
+                .filter_map(candidate)
+                .min();
+            let margin = f64::from(cost) * (1.0 + f64::from(options.uniqueness));
+            if rival.is_some_and(|rival_cost| f64::from(rival_cost) < margin) {
+                continue;
+            }
+
+            if let Some(tolerance) = options.left_right_tolerance {
+                let from_right = x
+                    .checked_sub(first + index)
+                    .and_then(|right_x| self.right_best.get(right_x).copied().flatten());
+                if from_right.is_none_or(|(other, _)| other.abs_diff(index) > tolerance as usize) {
+                    continue;
+                }
+            }
+
+            let offset = match (
+                options.subpixel,
+                index.checked_sub(1).and_then(candidate),
+                candidate(index + 1),
+            ) {
+                (true, Some(below), Some(above)) => {
+                    let (before, after) = (f64::from(below), f64::from(above));
+                    let curve = 2.0_f64.mul_add(-f64::from(cost), before + after);
+                    if curve > 0.0 {
+                        (before - after) / (2.0 * curve)
+                    } else {
+                        0.0
+                    }
+                }
+                _ => 0.0,
+            };
+            if let Some(slot) = disparity.get_mut(px, y) {
+                *slot = ((first + index) as f64 + offset) as f32;
+            }
+        }
+    }
+
+    /// Finds the best disparity for each column of the right image, by
+    /// matching the other way.
+    fn find_right_best(&mut self) {
+        let columns = self.right_best.len().max(1);
+        self.right_best.fill(None);
+
+        let rows = self.costs.chunks_exact(columns).zip(self.shifts());
+        for (index, (costs, shift)) in rows.enumerate() {
+            for (&cost, slot) in costs.iter().skip(shift).zip(self.right_best.iter_mut()) {
+                if cost != u32::MAX && slot.is_none_or(|(_, best_cost)| cost < best_cost) {
+                    *slot = Some((index, cost));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Noise, so every block looks different.
+    fn texture(x: u32, y: u32) -> u8 {
+        let hash = x.wrapping_mul(374_761_393) ^ y.wrapping_mul(668_265_263);
+        hash.wrapping_mul(1_274_126_177).to_be_bytes()[0]
+    }
+
+    /// The true disparity: the top is further away than the bottom.
+    const fn truth(y: u32) -> u32 {
+        if y < 30 {
+            5
+        } else {
+            12
+        }
+    }
+
+    #[test]
+    fn block_matching_finds_shifted_rows() {
+        let left = Image::from_fn(96, 64, texture);
+        let right = Image::from_fn(96, 64, |x, y| texture(x + truth(y), y));
+
+        let mut matcher = BlockMatcher::new(BlockMatchingOptions {
+            disparities: 32,
+            ..BlockMatchingOptions::default()
+        });
+        let disparity = matcher.compute(&left.view(), &right.view()).unwrap();
+
+        // away from the edges (and the step), nearly every pixel matches
+        let mut checked = 0;
+        let mut right_ones = 0;
+        for y in (6..26).chain(34..58) {
+            for x in 20..90 {
+                checked += 1;
+                let found = *disparity.get(x, y).unwrap();
+                if (found - truth(y) as f32).abs() < 0.5 {
+                    right_ones += 1;
+                }
+            }
+        }
+        assert!(
+            right_ones * 100 >= checked * 98,
+            "only {right_ones} of {checked} matched"
+        );
+
+        // the left edge has nothing to match against
+        assert!(
+            disparity.get(3, 40).unwrap().is_nan(),
+            "too close to the edge"
+        );
+        assert!(
+            disparity.get(8, 40).unwrap().is_nan(),
+            "no match in the right image"
+        );
+
+        // the matcher can be reused
+        let again = matcher.compute(&left.view(), &right.view()).unwrap();
+        assert_eq!(again.as_raw().len(), disparity.as_raw().len(), "same size");
+    }
+
+    #[test]
+    fn block_matching_checks_its_inputs() {
+        let small = Image::<u8>::new(10, 10);
+        let large = Image::<u8>::new(12, 10);
+        let mut matcher = BlockMatcher::new(BlockMatchingOptions::default());
+        assert_eq!(
+            matcher.compute(&small.view(), &large.view()).err(),
+            Some(DisparityError::SizeMismatch {
+                left_width: 10,
+                left_height: 10,
+                right_width: 12,
+                right_height: 10,
+            }),
+            "different sizes"
+        );
+
+        let mut even = BlockMatcher::new(BlockMatchingOptions {
+            block_size: 8,
+            ..BlockMatchingOptions::default()
+        });
+        assert_eq!(
+            even.compute(&small.view(), &small.view()).err(),
+            Some(DisparityError::EvenBlockSize { block_size: 8 }),
+            "blocks need a middle"
+        );
+    }
+}
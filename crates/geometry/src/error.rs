@@ -0,0 +1,52 @@
+//! Errors for calibrating cameras and matching stereo images.
+
+use core::error::Error;
+use pisserror::Error;
+
+/// An error that occurs when calibrating a camera.
+#[derive(Clone, Debug, Error, PartialEq, PartialOrd)]
+#[non_exhaustive]
+#[rustfmt::skip]
+pub enum CalibrationError {
+    #[error("Calibration needs at least {needed} views of the board, but only got {got}.")]
+    NotEnoughViews { needed: usize, got: usize },
+
+    #[error("View {view} has {got} corners, but the board has {expected}.")]
+    WrongCornerCount {
+        view: usize,
+        expected: usize,
+        got: usize,
+    },
+
+    #[error("A checkerboard needs at least 2x2 inner corners, but this one has {columns}x{rows}.")]
+    BoardTooSmall { columns: u32, rows: u32 },
+
+    /// Usually, the board was never tilted, or the corners came from a bad
+    /// detection.
+    #[error("The views don't have enough variety to pin down the camera. Try tilting the board more.")]
+    Degenerate,
+
+    #[error("The cameras aren't side by side, so their images can't be rectified.")]
+    NotSideBySide,
+}
+
+/// An error that occurs when finding disparities between stereo images.
+#[derive(Clone, Debug, Error, PartialEq, PartialOrd)]
+#[non_exhaustive]
+#[rustfmt::skip]
+#[expect(variant_size_differences, reason = "the biggest variant is only four `u32`s")]
+pub enum DisparityError {
+    #[error("The left image is `{left_width}x{left_height}`, but the right image is `{right_width}x{right_height}`.")]
+    SizeMismatch {
+        left_width: u32,
+        left_height: u32,
+        right_width: u32,
+        right_height: u32,
+    },
+
+    #[error("Blocks need a middle pixel, so their size must be odd, but it's {block_size}.")]
+    EvenBlockSize { block_size: u32 },
+
+    #[error("The block matcher has to try at least one disparity.")]
+    NoDisparities,
+}
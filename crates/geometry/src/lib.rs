@@ -0,0 +1,20 @@
+//! Camera geometry for SerumCV.
+//!
+//! - [`camera`]: pinhole and fisheye camera models, and maps that undistort
+//!   their frames.
+//! - [`calib`]: finding those models (and stereo pairs) from pictures of a
+//!   checkerboard.
+//! - [`disparity`]: depth from rectified stereo pairs.
+//!
+//! None of this needs a capture device. Frames can come from anywhere, as
+//! long as they fit in an [`ImageView`](serumcv_image::ImageView).
+
+pub mod calib;
+pub mod camera;
+pub mod disparity;
+pub mod error;
+pub mod prelude;
+
+mod resolution;
+
+pub use resolution::Resolution;
@@ -0,0 +1,13 @@
+//! The useful traits and types from the `serumcv_geometry` crate.
+
+pub use super::calib::{
+    calibrate, calibrate_stereo, find_checkerboard, refine_corners, BoardPose, CalibrationOptions,
+    CalibrationReport, CameraCalibration, Checkerboard, StereoCalibration, StereoRectification,
+};
+pub use super::camera::{
+    reprojection_map, undistortion_map, BrownConrady, Camera, CameraIntrinsics, CameraModel,
+    FisheyeCamera, KannalaBrandt, PinholeCamera,
+};
+pub use super::disparity::{BlockMatcher, BlockMatchingOptions};
+pub use super::error::{CalibrationError, DisparityError};
+pub use super::Resolution;
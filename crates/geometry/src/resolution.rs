@@ -0,0 +1,19 @@
+/// The size of a camera's frames, in pixels.
+///
+/// Cameras are calibrated at one resolution. Their models can be
+/// [scaled](crate::camera::CameraModel::scaled) to others.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Resolution {
+    /// Creates a resolution from its width and height.
+    #[inline]
+    #[must_use]
+    pub const fn new(width: u32, height: u32) -> Self {
+        Self { width, height }
+    }
+}
@@ -0,0 +1,113 @@
+/// What to use for pixels past the edge of an image.
+///
+/// The examples show a row `abcd`, with three pixels past each edge.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[non_exhaustive]
+pub enum Border<P> {
+    /// Pretends everything outside is one pixel: `xxx|abcd|xxx`.
+    Constant(P),
+    /// Repeats the edge pixels: `aaa|abcd|ddd`.
+    Replicate,
+    /// Mirrors the image around its edge pixels: `dcb|abcd|cba`.
+    Reflect,
+    /// Tiles the image: `bcd|abcd|abc`.
+    Wrap,
+}
+
+impl<P> Border<P> {
+    /// Finds the row or column that stands in for `index`, along an edge
+    /// that's `len` pixels long.
+    ///
+    /// Returns `None` when the constant should be used instead.
+    #[inline]
+    pub(crate) fn resolve(&self, index: i64, len: u32) -> Option<u32> {
+        if let Ok(inside) = u32::try_from(index) {
+            if inside < len {
+                return Some(inside);
+            }
+        }
+        if len == 0 {
+            return None;
+        }
+
+        let last = i64::from(len) - 1;
+        let resolved = match *self {
+            Self::Constant(_) => return None,
+            Self::Replicate => index.clamp(0, last),
+            Self::Reflect if last == 0 => 0,
+            Self::Reflect => {
+                let wrapped = index.rem_euclid(2 * last);
+                if wrapped > last {
+                    2 * last - wrapped
+                } else {
+                    wrapped
+                }
+            }
+            Self::Wrap => index.rem_euclid(i64::from(len)),
+        };
+
+        u32::try_from(resolved).ok()
+    }
+}
+
+impl<P: Copy + Default> Border<P> {
+    /// The pixel to use when there's nothing else to use, like when
+    /// `resolve` gives `None`.
+    #[inline]
+    pub(crate) fn outside(self) -> P {
+        match self {
+            Self::Constant(value) => value,
+            Self::Replicate | Self::Reflect | Self::Wrap => P::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn borders_extend_a_row() {
+        let row = |border: Border<u8>| {
+            (-3..7)
+                .map(|index| border.resolve(index, 4))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            row(Border::Constant(0)),
+            [
+                None,
+                None,
+                None,
+                Some(0),
+                Some(1),
+                Some(2),
+                Some(3),
+                None,
+                None,
+                None
+            ]
+        );
+        assert_eq!(
+            row(Border::Replicate),
+            [0, 0, 0, 0, 1, 2, 3, 3, 3, 3].map(Some)
+        );
+        assert_eq!(
+            row(Border::Reflect),
+            [3, 2, 1, 0, 1, 2, 3, 2, 1, 0].map(Some)
+        );
+        assert_eq!(row(Border::Wrap), [1, 2, 3, 0, 1, 2, 3, 0, 1, 2].map(Some));
+
+        assert_eq!(
+            Border::<u8>::Reflect.resolve(-5, 1),
+            Some(0),
+            "one pixel reflects onto itself"
+        );
+        assert_eq!(
+            Border::<u8>::Replicate.resolve(0, 0),
+            None,
+            "nothing to repeat"
+        );
+    }
+}
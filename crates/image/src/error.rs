@@ -0,0 +1,65 @@
+//! Errors for creating and slicing images.
+
+use core::error::Error;
+use pisserror::Error;
+
+use crate::Rect;
+
+/// An error that occurs when building an image or view over some memory.
+#[derive(Clone, Debug, Error, PartialEq, PartialOrd)]
+#[non_exhaustive]
+#[rustfmt::skip]
+pub enum ImageError {
+    #[error("The buffer holds `{got}` channels, but an image of this size needs at least `{needed}`.")]
+    BufferTooSmall { needed: usize, got: usize },
+
+    #[error("A row stride of `{stride}` channels is too short for rows that are `{row_len}` channels long.")]
+    StrideTooShort { stride: usize, row_len: usize },
+
+    #[error("A row stride of `{stride_bytes}` bytes doesn't divide evenly into `{channel_size}`-byte channels.")]
+    StrideNotChannelAligned { stride_bytes: usize, channel_size: usize },
+
+    #[error("The given bytes aren't aligned for `{channel_size}`-byte channels.")]
+    MisalignedBuffer { channel_size: usize },
+
+    #[error("The region `{roi}` doesn't fit inside a `{width}x{height}` image.")]
+    RoiOutOfBounds { roi: Rect, width: u32, height: u32 },
+
+    #[error("The region `{roi}` doesn't line up with the chroma subsampling of this planar layout.")]
+    RoiNotSubsampleAligned { roi: Rect },
+
+    #[error("The planar layout expects `{expected}` planes, but got `{got}`.")]
+    WrongPlaneCount { expected: usize, got: usize },
+
+    #[error("Plane `{plane}` is `{got_width}x{got_height}`, but the layout expects `{expected_width}x{expected_height}`.")]
+    WrongPlaneDimensions {
+        plane: usize,
+        expected_width: u32,
+        expected_height: u32,
+        got_width: u32,
+        got_height: u32,
+    },
+
+    #[error("The map holds `{got}` coordinates, but a map of this size needs `{needed}`.")]
+    MapSizeMismatch { needed: usize, got: usize },
+
+    #[error("The source is `{src_width}x{src_height}`, but the destination is `{dst_width}x{dst_height}`.")]
+    DimensionMismatch {
+        src_width: u32,
+        src_height: u32,
+        dst_width: u32,
+        dst_height: u32,
+    },
+
+    #[error("The source has `{src_channels}` channels per pixel, but the destination has `{dst_channels}`.")]
+    ChannelCountMismatch { src_channels: usize, dst_channels: usize },
+
+    #[error("The kernel holds `{got}` weights, but a kernel of this size needs `{needed}`.")]
+    KernelSizeMismatch { needed: usize, got: usize },
+
+    #[error("Kernels need a middle, so their sides must be odd, but this one is `{width}x{height}`.")]
+    EvenKernel { width: usize, height: usize },
+
+    #[error("There's no `{size}`-wide derivative kernel of order `({dx}, {dy})`.")]
+    UnsupportedDerivative { dx: u32, dy: u32, size: u32 },
+}
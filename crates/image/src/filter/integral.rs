@@ -0,0 +1,166 @@
+//! Summed-area tables.
+
+use crate::pixel::{Channel as _, Pixel};
+use crate::{ImageView, Rect};
+
+/// A table of running sums over an image, also known as a summed-area
+/// table.
+///
+/// Once it's built, the sum of any rectangle takes four lookups, no matter
+/// how big the rectangle is. Sums are kept in `f64`, so they stay exact for
+/// any `u8` or `u16` image that fits in memory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IntegralImage {
+    /// The sums of everything above and left of each corner, channel by
+    /// channel. There's one more row and column of corners than pixels.
+    sums: Vec<f64>,
+    width: u32,
+    height: u32,
+    channels: usize,
+}
+
+impl IntegralImage {
+    /// Adds up an image.
+    #[inline]
+    pub fn new<P: Pixel>(src: &ImageView<'_, P>) -> Self {
+        let channels = P::CHANNELS;
+        let stride = (src.width() as usize + 1) * channels;
+        let mut sums = vec![0.0; stride * (src.height() as usize + 1)];
+
+        let mut corners = sums.chunks_exact_mut(stride.max(1));
+        let mut above = corners.next().map(|row| &*row);
+        let mut running = vec![0.0_f64; channels];
+        for (row, sum_row) in src.rows().zip(corners) {
+            let Some(above_row) = above else {
+                break;
+            };
+
+            // the first corner in each row stays zero
+            running.fill(0.0);
+            let pixel_sums = sum_row
+                .chunks_exact_mut(channels)
+                .zip(above_row.chunks_exact(channels))
+                .skip(1);
+            for (px, (sums_here, sums_above)) in row.iter().zip(pixel_sums) {
+                let totals = px.channels().iter().zip(&mut running);
+                for ((&channel, total), (sum, &up)) in
+                    totals.zip(sums_here.iter_mut().zip(sums_above))
+                {
+                    *total += f64::from(channel.to_f32());
+                    *sum = *total + up;
+                }
+            }
+
+            above = Some(&*sum_row);
+        }
+
+        Self {
+            sums,
+            width: src.width(),
+            height: src.height(),
+            channels,
+        }
+    }
+
+    /// The width of the image that was added up.
+    #[inline]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height of the image that was added up.
+    #[inline]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The number of channels in each of the image's pixels.
+    #[inline]
+    pub const fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Adds up one channel over a rectangle of the image.
+    ///
+    /// Returns `None` if the rectangle doesn't fit in the image, or the
+    /// channel doesn't exist.
+    #[inline]
+    pub fn sum(&self, rect: Rect, channel: usize) -> Option<f64> {
+        if !rect.fits_within(self.width, self.height) || channel >= self.channels {
+            return None;
+        }
+
+        let (right, bottom) = (rect.x + rect.width, rect.y + rect.height);
+        Some(
+            self.corner(right, bottom, channel)
+                - self.corner(rect.x, bottom, channel)
+                - self.corner(right, rect.y, channel)
+                + self.corner(rect.x, rect.y, channel),
+        )
+    }
+
+    /// The sum of one channel above and left of a corner between pixels.
+    fn corner(&self, x: u32, y: u32, channel: usize) -> f64 {
+        let stride = (self.width as usize + 1) * self.channels;
+        self.sums
+            .get(y as usize * stride + x as usize * self.channels + channel)
+            .copied()
+            .unwrap_or(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Image, Rgb};
+
+    /// A 4x3 image whose red channel is `x + 10 * y`, and whose green
+    /// channel is all ones.
+    fn table() -> IntegralImage {
+        let image = Image::from_fn(4, 3, |x, y| {
+            Rgb([f32::from(u8::try_from(x + 10 * y).unwrap()), 1.0, 0.0])
+        });
+        IntegralImage::new(&image.view())
+    }
+
+    #[test]
+    fn sums_cover_whole_and_empty_rects() {
+        let integral = table();
+        assert_eq!(integral.sum(Rect::new(0, 0, 4, 3), 0), Some(138.0), "full");
+        assert_eq!(integral.sum(Rect::new(0, 0, 4, 3), 1), Some(12.0), "area");
+        assert_eq!(integral.sum(Rect::new(0, 0, 4, 3), 2), Some(0.0));
+        assert_eq!(integral.sum(Rect::new(0, 0, 4, 3), 3), None, "no channel");
+
+        assert_eq!(integral.sum(Rect::new(2, 1, 0, 0), 0), Some(0.0), "empty");
+        assert_eq!(integral.sum(Rect::new(1, 1, 2, 0), 0), Some(0.0), "no rows");
+        assert_eq!(
+            integral.sum(Rect::new(1, 1, 0, 2), 0),
+            Some(0.0),
+            "no columns"
+        );
+    }
+
+    #[test]
+    fn sums_stop_at_the_edges() {
+        let integral = table();
+
+        // touching the right and bottom edges is fine
+        assert_eq!(integral.sum(Rect::new(2, 1, 2, 2), 0), Some(70.0));
+        assert_eq!(integral.sum(Rect::new(3, 2, 1, 1), 0), Some(23.0), "corner");
+        assert_eq!(
+            integral.sum(Rect::new(4, 3, 0, 0), 0),
+            Some(0.0),
+            "empty corner"
+        );
+
+        // but going past them isn't
+        assert_eq!(integral.sum(Rect::new(3, 0, 2, 1), 0), None, "too wide");
+        assert_eq!(integral.sum(Rect::new(0, 2, 1, 2), 0), None, "too tall");
+        assert_eq!(integral.sum(Rect::new(5, 0, 0, 1), 0), None, "starts past");
+        assert_eq!(
+            integral.sum(Rect::new(u32::MAX, u32::MAX, u32::MAX, u32::MAX), 0),
+            None,
+            "doesn't overflow"
+        );
+    }
+}
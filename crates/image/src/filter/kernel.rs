@@ -0,0 +1,353 @@
+//! Filter kernels.
+
+use crate::error::ImageError;
+
+/// A grid of weights to slide over an image.
+///
+/// Both sides are odd, so the kernel has a middle weight that lines up with
+/// each output pixel. Weights are stored row by row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Kernel {
+    weights: Vec<f32>,
+    width: u32,
+    height: u32,
+}
+
+impl Kernel {
+    /// Makes a kernel from its weights, row by row.
+    ///
+    /// # Errors
+    ///
+    /// This fails if there isn't exactly one weight per cell, or if either
+    /// side is even.
+    #[inline]
+    pub fn new(weights: Vec<f32>, width: u32, height: u32) -> Result<Self, ImageError> {
+        check_odd(width as usize, height as usize)?;
+
+        let needed = width as usize * height as usize;
+        if weights.len() != needed {
+            return Err(ImageError::KernelSizeMismatch {
+                needed,
+                got: weights.len(),
+            });
+        }
+
+        Ok(Self {
+            weights,
+            width,
+            height,
+        })
+    }
+
+    /// The 3x3 Laplacian, which adds up the second derivatives in `x` and
+    /// `y`.
+    ///
+    /// It's zero on flat areas and gradients, and largest around thin lines
+    /// and corners.
+    #[inline]
+    #[must_use]
+    pub fn laplacian() -> Self {
+        Self {
+            weights: vec![0.0, 1.0, 0.0, 1.0, -4.0, 1.0, 0.0, 1.0, 0.0],
+            width: 3,
+            height: 3,
+        }
+    }
+
+    /// The width of this kernel, in weights.
+    #[inline]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height of this kernel, in weights.
+    #[inline]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the weights, row by row.
+    #[inline]
+    pub fn weights(&self) -> &[f32] {
+        &self.weights
+    }
+
+    /// Returns one weight, where `[0, 0]` is the top-left corner.
+    #[inline]
+    pub fn get(&self, x: u32, y: u32) -> Option<f32> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        self.weights
+            .get(y as usize * self.width as usize + x as usize)
+            .copied()
+    }
+
+    /// The number of weights on each side of the middle, across and down.
+    #[expect(
+        clippy::integer_division,
+        reason = "the sides are odd, so this rounds off the middle weight"
+    )]
+    pub(super) const fn radii(&self) -> (u32, u32) {
+        (self.width / 2, self.height / 2)
+    }
+
+    /// Returns the rows of weights, from the top.
+    pub(super) fn rows(&self) -> impl Iterator<Item = &[f32]> {
+        self.weights.chunks_exact(self.width.max(1) as usize)
+    }
+}
+
+/// A kernel made of a row of weights and a column of weights.
+///
+/// Sliding the row over the image and then the column over the result is
+/// the same as sliding their product over the image, but it takes
+/// `width + height` steps per pixel instead of `width * height`. Most
+/// useful kernels, like blurs and derivatives, can be split up like this.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SeparableKernel {
+    horizontal: Vec<f32>,
+    vertical: Vec<f32>,
+}
+
+impl SeparableKernel {
+    /// Makes a kernel from the row of weights to slide across, and the
+    /// column of weights to slide down.
+    ///
+    /// # Errors
+    ///
+    /// This fails if either one has an even length (including zero).
+    #[inline]
+    pub fn new(horizontal: Vec<f32>, vertical: Vec<f32>) -> Result<Self, ImageError> {
+        check_odd(horizontal.len(), vertical.len())?;
+        Ok(Self {
+            horizontal,
+            vertical,
+        })
+    }
+
+    /// A Gaussian blur with the given standard deviation, in pixels.
+    ///
+    /// The kernel reaches three deviations out from the middle, and its
+    /// weights add up to one. A deviation that isn't positive leaves images
+    /// as they are.
+    #[inline]
+    #[must_use]
+    #[expect(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        reason = "the radius is positive, and a kernel billions of pixels wide wouldn't fit anyway"
+    )]
+    #[expect(
+        clippy::cast_precision_loss,
+        reason = "kernel offsets are nowhere near 2^24"
+    )]
+    pub fn gaussian(sigma: f32) -> Self {
+        if !sigma.is_finite() || sigma <= 0.0 {
+            return Self::identity();
+        }
+
+        let radius = (3.0 * sigma).ceil() as u32;
+        let spread = 2.0 * sigma * sigma;
+        let mut weights: Vec<f32> = (0..=2 * radius)
+            .map(|index| {
+                let offset = index.abs_diff(radius) as f32;
+                (-offset * offset / spread).exp()
+            })
+            .collect();
+
+        let total: f32 = weights.iter().sum();
+        for weight in &mut weights {
+            *weight /= total;
+        }
+
+        Self {
+            horizontal: weights.clone(),
+            vertical: weights,
+        }
+    }
+
+    /// A Sobel derivative kernel, which finds the `dx`-th derivative across
+    /// and the `dy`-th derivative down, smoothing in the other direction.
+    ///
+    /// `size` is the length of both sides, and must be odd, at least 3, and
+    /// at most 31. The weights aren't normalized: a 3x3 first derivative of
+    /// a ramp that climbs by one per pixel gives 8.
+    ///
+    /// # Errors
+    ///
+    /// This fails if `size` isn't allowed, if both orders are zero, or if
+    /// either order is too high for `size`.
+    #[inline]
+    pub fn sobel(dx: u32, dy: u32, size: u32) -> Result<Self, ImageError> {
+        let unsupported = ImageError::UnsupportedDerivative { dx, dy, size };
+        let orders_fit = dx < size && dy < size && dx + dy > 0;
+        if !(3..=31).contains(&size) || size.is_multiple_of(2) || !orders_fit {
+            return Err(unsupported);
+        }
+
+        Ok(Self {
+            horizontal: derivative(dx, size),
+            vertical: derivative(dy, size),
+        })
+    }
+
+    /// A 3x3 Scharr first derivative kernel, across when `dx` is 1 or down
+    /// when `dy` is 1.
+    ///
+    /// It's like a 3x3 Sobel kernel, but gives the same answer for edges at
+    /// any angle much more closely. The weights aren't normalized: a ramp
+    /// that climbs by one per pixel gives 32.
+    ///
+    /// # Errors
+    ///
+    /// This fails unless exactly one of `dx` and `dy` is 1 and the other is
+    /// zero.
+    #[inline]
+    pub fn scharr(dx: u32, dy: u32) -> Result<Self, ImageError> {
+        let (difference, smoothing) = (vec![-1.0, 0.0, 1.0], vec![3.0, 10.0, 3.0]);
+        match (dx, dy) {
+            (1, 0) => Ok(Self {
+                horizontal: difference,
+                vertical: smoothing,
+            }),
+            (0, 1) => Ok(Self {
+                horizontal: smoothing,
+                vertical: difference,
+            }),
+            _ => Err(ImageError::UnsupportedDerivative { dx, dy, size: 3 }),
+        }
+    }
+
+    /// Returns the row of weights, from the left.
+    #[inline]
+    pub fn horizontal(&self) -> &[f32] {
+        &self.horizontal
+    }
+
+    /// Returns the column of weights, from the top.
+    #[inline]
+    pub fn vertical(&self) -> &[f32] {
+        &self.vertical
+    }
+
+    /// Multiplies the row and column out into a full kernel.
+    #[inline]
+    #[must_use]
+    #[expect(
+        clippy::cast_possible_truncation,
+        reason = "the lengths came from a kernel that was small enough to build"
+    )]
+    pub fn to_kernel(&self) -> Kernel {
+        let weights = self
+            .vertical
+            .iter()
+            .flat_map(|&down| self.horizontal.iter().map(move |&across| down * across))
+            .collect();
+
+        Kernel {
+            weights,
+            width: self.horizontal.len() as u32,
+            height: self.vertical.len() as u32,
+        }
+    }
+
+    /// The number of weights on each side of the middle, across and down.
+    #[expect(
+        clippy::integer_division,
+        reason = "the lengths are odd, so this rounds off the middle weight"
+    )]
+    #[expect(
+        clippy::cast_possible_truncation,
+        reason = "images are at most `u32::MAX` wide, so longer kernels are useless anyway"
+    )]
+    pub(super) const fn radii(&self) -> (u32, u32) {
+        (
+            (self.horizontal.len() / 2) as u32,
+            (self.vertical.len() / 2) as u32,
+        )
+    }
+
+    /// A kernel that leaves images as they are.
+    fn identity() -> Self {
+        Self {
+            horizontal: vec![1.0],
+            vertical: vec![1.0],
+        }
+    }
+}
+
+/// Makes sure a kernel has a middle.
+const fn check_odd(width: usize, height: usize) -> Result<(), ImageError> {
+    if width.is_multiple_of(2) || height.is_multiple_of(2) {
+        return Err(ImageError::EvenKernel { width, height });
+    }
+
+    Ok(())
+}
+
+/// One side of a Sobel kernel: `order` differences, padded out to `size`
+/// with binomial smoothing.
+fn derivative(order: u32, size: u32) -> Vec<f32> {
+    let mut weights = vec![1.0_f32];
+    for step in 1..size {
+        let sign = if step + order >= size { -1.0 } else { 1.0 };
+        weights = (0..=weights.len())
+            .map(|index| {
+                let before = index.checked_sub(1).and_then(|i| weights.get(i));
+                before.copied().unwrap_or(0.0) + sign * weights.get(index).copied().unwrap_or(0.0)
+            })
+            .collect();
+    }
+
+    weights
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernels_have_the_usual_weights() {
+        let sobel = SeparableKernel::sobel(1, 0, 3).unwrap();
+        assert_eq!(sobel.horizontal(), [-1.0, 0.0, 1.0], "differences across");
+        assert_eq!(sobel.vertical(), [1.0, 2.0, 1.0], "smooths down");
+
+        let second = SeparableKernel::sobel(0, 2, 5).unwrap();
+        assert_eq!(second.horizontal(), [1.0, 4.0, 6.0, 4.0, 1.0]);
+        assert_eq!(second.vertical(), [1.0, 0.0, -2.0, 0.0, 1.0]);
+
+        let gaussian = SeparableKernel::gaussian(1.0);
+        assert_eq!(gaussian.horizontal().len(), 7, "three deviations each way");
+        let total: f32 = gaussian.horizontal().iter().sum();
+        assert!((total - 1.0).abs() < 1e-6, "weights add up to {total}");
+        assert_eq!(
+            SeparableKernel::gaussian(0.0).to_kernel().weights(),
+            [1.0],
+            "no blur at all"
+        );
+
+        assert_eq!(
+            SeparableKernel::scharr(1, 1),
+            Err(ImageError::UnsupportedDerivative {
+                dx: 1,
+                dy: 1,
+                size: 3
+            })
+        );
+        assert!(SeparableKernel::sobel(3, 0, 3).is_err(), "order too high");
+        assert!(SeparableKernel::sobel(1, 0, 4).is_err(), "even size");
+        assert_eq!(
+            Kernel::new(vec![0.0; 6], 3, 2),
+            Err(ImageError::EvenKernel {
+                width: 3,
+                height: 2
+            })
+        );
+        assert_eq!(
+            Kernel::new(vec![0.0; 8], 3, 3),
+            Err(ImageError::KernelSizeMismatch { needed: 9, got: 8 })
+        );
+    }
+}
@@ -0,0 +1,439 @@
+//! Linear filters, like blurs and derivatives.
+//!
+//! Each filter slides a kernel over the image and takes a weighted sum of
+//! the pixels under it. Like most image libraries, kernels are lined up
+//! with the image as they are, without flipping them first, so this is
+//! really correlation rather than convolution. For symmetric kernels, those
+//! are the same thing.
+//!
+//! Pixels past the edge come from a [`Border`]. Sums are done in floating
+//! point, then rounded and saturated into the output's channels, so
+//! blurring a `u8` image can't wrap around.
+//!
+//! Derivatives can be negative, so they usually want a floating-point
+//! output. The `_into` functions can write into another channel type:
+//!
+//! ```
+//! use serumcv_image::filter::{self, SeparableKernel};
+//! use serumcv_image::{Border, Image};
+//!
+//! let image = Image::<u8>::from_fn(64, 48, |x, _| (x * 4) as u8);
+//! let mut slope = Image::<f32>::new(64, 48);
+//! filter::filter_separable_into(
+//!     &image.view(),
+//!     &mut slope.view_mut(),
+//!     &SeparableKernel::sobel(1, 0, 3)?,
+//!     Border::Replicate,
+//! )?;
+//!
+//! // a 3x3 Sobel kernel scales slopes by 8
+//! assert_eq!(slope.get(20, 20), Some(&32.0));
+//! # Ok::<(), serumcv_image::error::ImageError>(())
+//! ```
+
+mod integral;
+mod kernel;
+
+pub use integral::IntegralImage;
+pub use kernel::{Kernel, SeparableKernel};
+
+use crate::error::ImageError;
+use crate::pixel::{row_len, Channel as _, Pixel};
+use crate::{Border, Image, ImageView, ImageViewMut, Rect};
+
+/// Slides a kernel over an image.
+#[inline]
+pub fn filter_2d<P: Pixel>(src: &ImageView<'_, P>, kernel: &Kernel, border: Border<P>) -> Image<P> {
+    let mut image = Image::new(src.width(), src.height());
+    correlate(src, image.view_mut(), kernel, border);
+    image
+}
+
+/// Slides a kernel over an image, writing into one you already have.
+///
+/// The destination can have a different channel type, like `f32` for
+/// kernels with negative weights.
+///
+/// # Errors
+///
+/// This fails if the destination's size or number of channels doesn't
+/// match the source.
+#[inline]
+pub fn filter_2d_into<P: Pixel, Q: Pixel>(
+    src: &ImageView<'_, P>,
+    dst: &mut ImageViewMut<'_, Q>,
+    kernel: &Kernel,
+    border: Border<P>,
+) -> Result<(), ImageError> {
+    check_destination(src, dst)?;
+    correlate(src, dst.reborrow(), kernel, border);
+    Ok(())
+}
+
+/// Slides a separable kernel over an image.
+#[inline]
+pub fn filter_separable<P: Pixel>(
+    src: &ImageView<'_, P>,
+    kernel: &SeparableKernel,
+    border: Border<P>,
+) -> Image<P> {
+    let mut image = Image::new(src.width(), src.height());
+    correlate_separable(src, image.view_mut(), kernel, border);
+    image
+}
+
+/// Slides a separable kernel over an image, writing into one you already
+/// have.
+///
+/// The destination can have a different channel type, like `f32` for
+/// derivatives.
+///
+/// # Errors
+///
+/// This fails if the destination's size or number of channels doesn't
+/// match the source.
+#[inline]
+pub fn filter_separable_into<P: Pixel, Q: Pixel>(
+    src: &ImageView<'_, P>,
+    dst: &mut ImageViewMut<'_, Q>,
+    kernel: &SeparableKernel,
+    border: Border<P>,
+) -> Result<(), ImageError> {
+    check_destination(src, dst)?;
+    correlate_separable(src, dst.reborrow(), kernel, border);
+    Ok(())
+}
+
+/// Blurs an image with a Gaussian of the given standard deviation, in
+/// pixels.
+///
+/// See [`SeparableKernel::gaussian`] for how the kernel is built.
+#[inline]
+pub fn gaussian_blur<P: Pixel>(src: &ImageView<'_, P>, sigma: f32, border: Border<P>) -> Image<P> {
+    filter_separable(src, &SeparableKernel::gaussian(sigma), border)
+}
+
+/// Averages each pixel with its neighbors, out to `radius` pixels in every
+/// direction.
+///
+/// This goes through an [`IntegralImage`], so it takes the same time for
+/// any radius.
+#[inline]
+pub fn box_filter<P: Pixel>(src: &ImageView<'_, P>, radius: u32, border: Border<P>) -> Image<P> {
+    let mut image = Image::new(src.width(), src.height());
+    average_boxes(src, image.view_mut(), radius, border);
+    image
+}
+
+/// Averages each pixel with its neighbors, writing into an image you already
+/// have.
+///
+/// # Errors
+///
+/// This fails if the destination's size or number of channels doesn't
+/// match the source.
+#[inline]
+pub fn box_filter_into<P: Pixel, Q: Pixel>(
+    src: &ImageView<'_, P>,
+    dst: &mut ImageViewMut<'_, Q>,
+    radius: u32,
+    border: Border<P>,
+) -> Result<(), ImageError> {
+    check_destination(src, dst)?;
+    average_boxes(src, dst.reborrow(), radius, border);
+    Ok(())
+}
+
+/// Makes sure a destination can hold the filtered source.
+fn check_destination<P: Pixel, Q: Pixel>(
+    src: &ImageView<'_, P>,
+    dst: &ImageViewMut<'_, Q>,
+) -> Result<(), ImageError> {
+    if P::CHANNELS != Q::CHANNELS {
+        return Err(ImageError::ChannelCountMismatch {
+            src_channels: P::CHANNELS,
+            dst_channels: Q::CHANNELS,
+        });
+    }
+
+    if src.dimensions() != dst.dimensions() {
+        return Err(ImageError::DimensionMismatch {
+            src_width: src.width(),
+            src_height: src.height(),
+            dst_width: dst.width(),
+            dst_height: dst.height(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Visits each pixel of an image with `pad_x` extra columns on the left and
+/// right, and `pad_y` extra rows on the top and bottom, filled in from the
+/// border. Pixels go row by row.
+fn for_each_padded<P: Pixel, F: FnMut(&P)>(
+    src: &ImageView<'_, P>,
+    pad_x: u32,
+    pad_y: u32,
+    border: Border<P>,
+    mut visit: F,
+) {
+    let lookup = |pad: u32, len: u32| -> Vec<Option<u32>> {
+        (-i64::from(pad)..i64::from(len) + i64::from(pad))
+            .map(|index| border.resolve(index, len))
+            .collect()
+    };
+    let (columns, rows) = (lookup(pad_x, src.width()), lookup(pad_y, src.height()));
+
+    let outside = border.outside();
+    for row in rows.iter().copied() {
+        let pixels = row.and_then(|inside_y| src.row(inside_y));
+        for column in columns.iter().copied() {
+            let px = pixels
+                .zip(column)
+                .and_then(|(inside, inside_x)| inside.get(inside_x as usize));
+            visit(px.unwrap_or(&outside));
+        }
+    }
+}
+
+/// Copies an image with extra pixels around the edges, filled in from the
+/// border.
+fn pad<P: Pixel>(src: &ImageView<'_, P>, pad_x: u32, pad_y: u32, border: Border<P>) -> Image<P> {
+    let width = src.width().saturating_add(pad_x.saturating_mul(2));
+    let height = src.height().saturating_add(pad_y.saturating_mul(2));
+
+    let mut data = Vec::with_capacity(row_len::<P>(width) * height as usize);
+    for_each_padded(src, pad_x, pad_y, border, |px| {
+        data.extend_from_slice(px.channels());
+    });
+    Image::from_parts(data, width, height)
+}
+
+/// Pads an image for a kernel with the given radii, converting all of its
+/// channels to `f32`.
+///
+/// Returns the channels with the length of each row.
+fn pad_to_f32<P: Pixel>(
+    src: &ImageView<'_, P>,
+    radii: (u32, u32),
+    border: Border<P>,
+) -> (Vec<f32>, usize) {
+    let (pad_x, pad_y) = radii;
+    let width = src.width().saturating_add(pad_x.saturating_mul(2));
+    let height = src.height().saturating_add(pad_y.saturating_mul(2));
+    let padded_len = row_len::<P>(width);
+
+    let mut values = Vec::with_capacity(padded_len * height as usize);
+    for_each_padded(src, pad_x, pad_y, border, |px| {
+        values.extend(px.channels().iter().map(|channel| channel.to_f32()));
+    });
+    (values, padded_len)
+}
+
+/// Fills the destination with a kernel slid over the source.
+fn correlate<P: Pixel, Q: Pixel>(
+    src: &ImageView<'_, P>,
+    mut dst: ImageViewMut<'_, Q>,
+    kernel: &Kernel,
+    border: Border<P>,
+) {
+    let (padded, padded_len) = pad_to_f32(src, kernel.radii(), border);
+    let sums_len = row_len::<P>(src.width());
+
+    let mut sums = vec![0.0; sums_len];
+    for (y, row) in dst.rows_mut().enumerate() {
+        sums.fill(0.0);
+
+        let below = padded.chunks_exact(padded_len.max(1)).skip(y);
+        for (weights, values) in kernel.rows().zip(below) {
+            accumulate_shifts(&mut sums, values, weights, P::CHANNELS);
+        }
+        store(row, &sums);
+    }
+}
+
+/// Fills the destination with a separable kernel slid over the source.
+fn correlate_separable<P: Pixel, Q: Pixel>(
+    src: &ImageView<'_, P>,
+    mut dst: ImageViewMut<'_, Q>,
+    kernel: &SeparableKernel,
+    border: Border<P>,
+) {
+    let (padded, padded_len) = pad_to_f32(src, kernel.radii(), border);
+    let sums_len = row_len::<P>(src.width());
+
+    // going across first means each padded row only gets filtered once
+    let padded_rows = padded.chunks_exact(padded_len.max(1));
+    let mut across = vec![0.0; sums_len * padded_rows.len()];
+    for (sums, values) in across.chunks_exact_mut(sums_len.max(1)).zip(padded_rows) {
+        accumulate_shifts(sums, values, kernel.horizontal(), P::CHANNELS);
+    }
+
+    let mut sums = vec![0.0; sums_len];
+    for (y, row) in dst.rows_mut().enumerate() {
+        sums.fill(0.0);
+
+        let below = across.chunks_exact(sums_len.max(1)).skip(y);
+        for (&weight, values) in kernel.vertical().iter().zip(below) {
+            accumulate(&mut sums, values, weight);
+        }
+        store(row, &sums);
+    }
+}
+
+/// Fills the destination with the average of the box around each source
+/// pixel.
+#[expect(
+    clippy::cast_possible_truncation,
+    reason = "averages of `f32` channels fit back in an `f32`"
+)]
+fn average_boxes<P: Pixel, Q: Pixel>(
+    src: &ImageView<'_, P>,
+    mut dst: ImageViewMut<'_, Q>,
+    radius: u32,
+    border: Border<P>,
+) {
+    let padded = pad(src, radius, radius, border);
+    let integral = IntegralImage::new(&padded.view());
+
+    let size = radius.saturating_mul(2).saturating_add(1);
+    let area = f64::from(size) * f64::from(size);
+    for (y, row) in (0..).zip(dst.rows_mut()) {
+        for (x, px) in (0..).zip(row.iter_mut()) {
+            let square = Rect::new(x, y, size, size);
+            for (index, channel) in px.channels_mut().iter_mut().enumerate() {
+                let sum = integral.sum(square, index).unwrap_or(0.0);
+                *channel = Q::Channel::from_f32((sum / area) as f32);
+            }
+        }
+    }
+}
+
+/// Adds one row of a kernel to a row of sums, shifting the padded row of
+/// values along for each weight.
+fn accumulate_shifts(sums: &mut [f32], values: &[f32], weights: &[f32], channels: usize) {
+    let len = sums.len();
+    for (shift, &weight) in weights.iter().enumerate() {
+        let start = shift * channels;
+        if let Some(shifted) = values.get(start..start + len) {
+            accumulate(sums, shifted, weight);
+        }
+    }
+}
+
+/// Adds `weight` times each value to its sum.
+fn accumulate(sums: &mut [f32], values: &[f32], weight: f32) {
+    for (sum, &value) in sums.iter_mut().zip(values) {
+        *sum += weight * value;
+    }
+}
+
+/// Writes a row of sums into a row of pixels.
+fn store<Q: Pixel>(row: &mut [Q], sums: &[f32]) {
+    for (px, values) in row.iter_mut().zip(sums.chunks_exact(Q::CHANNELS)) {
+        for (channel, &value) in px.channels_mut().iter_mut().zip(values) {
+            *channel = Q::Channel::from_f32(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Rgb;
+
+    /// Noise, so filters have something to work on.
+    fn noise(x: u32, y: u32) -> Rgb<f32> {
+        let hash = |seed: u32| {
+            let mixed = (x.wrapping_mul(374_761_393) ^ y.wrapping_mul(668_265_263))
+                .wrapping_add(seed)
+                .wrapping_mul(1_274_126_177);
+            f32::from(mixed.to_be_bytes()[0])
+        };
+        Rgb([hash(0), hash(1), hash(2)])
+    }
+
+    fn assert_close(got: &Image<Rgb<f32>>, want: &Image<Rgb<f32>>, what: &str) {
+        for (got_px, want_px) in got.pixels().iter().zip(want.pixels()) {
+            for (a, b) in got_px.0.iter().zip(want_px.0) {
+                assert!((a - b).abs() < 1e-3, "{what}: got {a}, expected {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn shortcuts_match_full_kernels() {
+        let image = Image::from_fn(23, 17, noise);
+
+        let sobel = SeparableKernel::sobel(1, 1, 5).unwrap();
+        assert_close(
+            &filter_separable(&image.view(), &sobel, Border::Reflect),
+            &filter_2d(&image.view(), &sobel.to_kernel(), Border::Reflect),
+            "separable",
+        );
+
+        let border = Border::Constant(Rgb([1.0, 2.0, 3.0]));
+        let average = Kernel::new(vec![1.0 / 49.0; 49], 7, 7).unwrap();
+        assert_close(
+            &box_filter(&image.view(), 3, border),
+            &filter_2d(&image.view(), &average, border),
+            "box",
+        );
+    }
+
+    #[test]
+    fn derivatives_find_slopes() {
+        let bowl = Image::<u16>::from_fn(16, 16, |x, y| u16::try_from(x * x + 3 * y).unwrap());
+        let mut curve = Image::<f32>::new(16, 16);
+        filter_2d_into(
+            &bowl.view(),
+            &mut curve.view_mut(),
+            &Kernel::laplacian(),
+            Border::Wrap,
+        )
+        .unwrap();
+        assert_eq!(curve.get(8, 8), Some(&2.0), "x² curves by two");
+
+        let mut slope = Image::<f32>::new(16, 16);
+        let scharr = SeparableKernel::scharr(0, 1).unwrap();
+        filter_separable_into(&bowl.view(), &mut slope.view_mut(), &scharr, Border::Wrap).unwrap();
+        assert_eq!(slope.get(8, 8), Some(&96.0), "three per row, times 32");
+        assert_eq!(
+            slope.get(8, 0),
+            Some(&(-16.0 * (45.0 - 3.0))),
+            "wrapping around the top edge sees a cliff"
+        );
+
+        // blurs can't go past the channel's range
+        let bright = Image::<u8>::from_pixel(8, 8, 250);
+        let blurred = gaussian_blur(&bright.view(), 2.0, Border::Constant(255));
+        assert!(blurred.pixels().iter().all(|&px| px >= 250), "no wrapping");
+    }
+
+    #[test]
+    fn destinations_must_match_the_source() {
+        let image = Image::<Rgb<u8>>::new(4, 3);
+        let mut gray = Image::<f32>::new(4, 3);
+        let mut short = Image::<Rgb<f32>>::new(4, 2);
+
+        assert_eq!(
+            box_filter_into(&image.view(), &mut gray.view_mut(), 1, Border::Replicate),
+            Err(ImageError::ChannelCountMismatch {
+                src_channels: 3,
+                dst_channels: 1
+            }),
+            "three channels into one"
+        );
+        assert_eq!(
+            box_filter_into(&image.view(), &mut short.view_mut(), 1, Border::Replicate),
+            Err(ImageError::DimensionMismatch {
+                src_width: 4,
+                src_height: 3,
+                dst_width: 4,
+                dst_height: 2,
+            }),
+            "one row short"
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use crate::error::ImageError;
+use crate::pixel::{self, required_len, row_len, Pixel};
+use crate::{ImageView, ImageViewMut};
+
+/// An image that owns its pixels.
+///
+/// Owned images are always contiguous: each row sits right after the last.
+/// Use [`Image::view`] to get a cheap, strided view you can slice into
+/// regions of interest.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image<P: Pixel> {
+    data: Vec<P::Channel>,
+    width: u32,
+    height: u32,
+}
+
+impl<P: Pixel> Image<P> {
+    /// Creates a new image where every pixel has its default value (usually
+    /// zero).
+    #[inline]
+    pub fn new(width: u32, height: u32) -> Self {
+        Self::from_pixel(width, height, P::default())
+    }
+
+    /// Creates a new image filled with the given pixel.
+    #[inline]
+    pub fn from_pixel(width: u32, height: u32, value: P) -> Self {
+        let mut data = Vec::with_capacity(row_len::<P>(width) * height as usize);
+        for _ in 0..(u64::from(width) * u64::from(height)) {
+            data.extend_from_slice(value.channels());
+        }
+
+        Self::from_parts(data, width, height)
+    }
+
+    /// Creates a new image by calling a function with the coordinates of
+    /// each pixel.
+    #[inline]
+    pub fn from_fn<F: FnMut(u32, u32) -> P>(width: u32, height: u32, mut f: F) -> Self {
+        let mut data = Vec::with_capacity(row_len::<P>(width) * height as usize);
+        for y in 0..height {
+            for x in 0..width {
+                data.extend_from_slice(f(x, y).channels());
+            }
+        }
+
+        Self::from_parts(data, width, height)
+    }
+
+    /// Creates an image from a contiguous buffer of channels.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the buffer's length doesn't exactly match the given
+    /// dimensions.
+    #[inline]
+    pub fn from_raw(data: Vec<P::Channel>, width: u32, height: u32) -> Result<Self, ImageError> {
+        let needed = required_len(row_len::<P>(width), height, row_len::<P>(width));
+        if data.len() != needed {
+            return Err(ImageError::BufferTooSmall {
+                needed,
+                got: data.len(),
+            });
+        }
+
+        Ok(Self::from_parts(data, width, height))
+    }
+
+    /// Creates an image without checking the buffer. Only use this when the
+    /// length is known to be right!
+    #[inline]
+    pub(crate) const fn from_parts(data: Vec<P::Channel>, width: u32, height: u32) -> Self {
+        Self {
+            data,
+            width,
+            height,
+        }
+    }
+
+    /// The width of this image, in pixels.
+    #[inline]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height of this image, in pixels.
+    #[inline]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns `(width, height)`.
+    #[inline]
+    pub const fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Returns a read-only view of the whole image.
+    #[inline]
+    pub fn view(&self) -> ImageView<'_, P> {
+        // this can't fail, since we always keep the buffer at exactly the
+        // right length
+        ImageView::from_contiguous(&self.data, self.width, self.height)
+            .unwrap_or_else(|_| unreachable!("owned images always hold every row"))
+    }
+
+    /// Returns a mutable view of the whole image.
+    #[inline]
+    pub fn view_mut(&mut self) -> ImageViewMut<'_, P> {
+        let stride = row_len::<P>(self.width);
+        ImageViewMut::from_slice(&mut self.data, self.width, self.height, stride)
+            .unwrap_or_else(|_| unreachable!("owned images always hold every row"))
+    }
+
+    /// Returns the pixel at the given coordinates.
+    #[inline]
+    pub fn get(&self, x: u32, y: u32) -> Option<&P> {
+        self.pixel_index(x, y)
+            .and_then(|index| self.pixels().get(index))
+    }
+
+    /// Returns the pixel at the given coordinates mutably.
+    #[inline]
+    pub fn get_mut(&mut self, x: u32, y: u32) -> Option<&mut P> {
+        self.pixel_index(x, y)
+            .and_then(|index| self.pixels_mut().get_mut(index))
+    }
+
+    /// Returns every pixel in the image, row by row.
+    #[inline]
+    pub fn pixels(&self) -> &[P] {
+        pixel::cast_channels::<P>(&self.data)
+    }
+
+    /// Returns every pixel in the image mutably, row by row.
+    #[inline]
+    pub fn pixels_mut(&mut self) -> &mut [P] {
+        pixel::cast_channels_mut::<P>(&mut self.data)
+    }
+
+    /// Returns the underlying channels.
+    #[inline]
+    pub fn as_raw(&self) -> &[P::Channel] {
+        &self.data
+    }
+
+    /// Gives back the underlying channels.
+    #[inline]
+    pub fn into_raw(self) -> Vec<P::Channel> {
+        self.data
+    }
+
+    /// Finds the index of a pixel in the pixel slice.
+    fn pixel_index(&self, x: u32, y: u32) -> Option<usize> {
+        (x < self.width && y < self.height).then(|| y as usize * self.width as usize + x as usize)
+    }
+}
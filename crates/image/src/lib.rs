@@ -0,0 +1,36 @@
+//! Image containers for SerumCV.
+//!
+//! There are three main types here:
+//!
+//! - [`Image`]: an image that owns its pixels.
+//! - [`ImageView`]: a borrowed, strided view of some pixels. These are cheap
+//!   to copy and slice into regions of interest.
+//! - [`ImageViewMut`]: like `ImageView`, but you can write through it.
+//!
+//! All of them are generic over a [`Pixel`] type, like `Rgb<u8>` or
+//! `Luma<f32>`. For planar data, see [`PlanarView`] and [`PlanarImage`].
+//!
+//! To move pixels around, like when undistorting a camera's frames, see
+//! [`RemapMap`]. To blur them or find their edges, see [`filter`].
+
+pub mod error;
+pub mod filter;
+pub mod pixel;
+pub mod planar;
+pub mod prelude;
+pub mod remap;
+
+mod border;
+mod image;
+mod rect;
+mod view;
+
+// re-exports
+pub use border::Border;
+pub use filter::{IntegralImage, Kernel, SeparableKernel};
+pub use image::Image;
+pub use pixel::{Bgr, Bgra, Channel, Luma, LumaA, Pixel, Rgb, Rgba, Yuv};
+pub use planar::{PlanarImage, PlanarLayout, PlanarView};
+pub use rect::Rect;
+pub use remap::{Interpolation, RemapMap};
+pub use view::{ImageView, ImageViewMut};
@@ -0,0 +1,313 @@
+//! Pixel and channel types.
+
+use core::fmt::Debug;
+
+use crate::error::ImageError;
+
+mod private {
+    /// Keeps outside crates from implementing `Channel`.
+    ///
+    /// Views reinterpret raw bytes as channels, so every channel type must
+    /// accept any bit pattern. We only trust the primitives listed here.
+    #[expect(
+        unnameable_types,
+        reason = "this is a sealed trait, so nobody should name it"
+    )]
+    pub trait Sealed {}
+
+    impl Sealed for u8 {}
+    impl Sealed for u16 {}
+    impl Sealed for f32 {}
+}
+
+/// A single numeric sample within a pixel, like the red value of an RGB
+/// pixel.
+///
+/// This is implemented for `u8`, `u16`, and `f32`. It's sealed, so no other
+/// types can implement it.
+pub trait Channel:
+    private::Sealed + Copy + Debug + Default + PartialEq + PartialOrd + Send + Sync + 'static
+{
+    /// The value of a fully saturated channel.
+    ///
+    /// For floating-point channels, this is `1.0`.
+    const MAX: Self;
+
+    /// The value of an empty channel.
+    const ZERO: Self;
+
+    /// Converts this channel into a floating-point value in its native range.
+    ///
+    /// Note that this doesn't normalize the value. A `u8` of 255 becomes
+    /// `255.0`.
+    fn to_f32(self) -> f32;
+
+    /// Converts a floating-point value back into this channel type.
+    ///
+    /// Integer channels round to the nearest value and saturate at their
+    /// bounds. `NaN` becomes zero.
+    fn from_f32(value: f32) -> Self;
+}
+
+impl Channel for u8 {
+    const MAX: Self = Self::MAX;
+    const ZERO: Self = 0;
+
+    #[inline]
+    fn to_f32(self) -> f32 {
+        f32::from(self)
+    }
+
+    #[inline]
+    #[expect(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        reason = "float to int casts saturate, which is exactly what we want here"
+    )]
+    fn from_f32(value: f32) -> Self {
+        value.round() as Self
+    }
+}
+
+impl Channel for u16 {
+    const MAX: Self = Self::MAX;
+    const ZERO: Self = 0;
+
+    #[inline]
+    fn to_f32(self) -> f32 {
+        f32::from(self)
+    }
+
+    #[inline]
+    #[expect(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        reason = "float to int casts saturate, which is exactly what we want here"
+    )]
+    fn from_f32(value: f32) -> Self {
+        value.round() as Self
+    }
+}
+
+impl Channel for f32 {
+    const MAX: Self = 1.0;
+    const ZERO: Self = 0.0;
+
+    #[inline]
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    #[inline]
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+}
+
+/// A pixel made of one or more channels.
+///
+/// You probably want one of the provided pixel types, like [`Rgb`] or
+/// [`Luma`]. The channel primitives (`u8`, `u16`, `f32`) are also pixels,
+/// which is handy for single planes of a planar image.
+///
+/// # Safety
+///
+/// An implementor must have the exact layout of
+/// `[Self::Channel; Self::CHANNELS]`: no padding, and no alignment stricter
+/// than the channel's. Views reinterpret rows of channels as slices of
+/// pixels, so breaking this is undefined behavior.
+pub unsafe trait Pixel: Copy + Debug + Default + PartialEq + Send + Sync + 'static {
+    /// The type of each channel in this pixel.
+    type Channel: Channel;
+
+    /// The number of channels in this pixel.
+    const CHANNELS: usize;
+
+    /// Returns this pixel's channels.
+    fn channels(&self) -> &[Self::Channel];
+
+    /// Returns this pixel's channels mutably.
+    fn channels_mut(&mut self) -> &mut [Self::Channel];
+}
+
+macro_rules! primitive_pixel {
+    ($($ty:ty),*) => {$(
+        // SAFETY: a primitive has the same layout as a one-element array of
+        // itself.
+        unsafe impl Pixel for $ty {
+            type Channel = Self;
+            const CHANNELS: usize = 1;
+
+            #[inline]
+            fn channels(&self) -> &[Self::Channel] {
+                core::slice::from_ref(self)
+            }
+
+            #[inline]
+            fn channels_mut(&mut self) -> &mut [Self::Channel] {
+                core::slice::from_mut(self)
+            }
+        }
+    )*};
+}
+
+primitive_pixel!(u8, u16, f32);
+
+macro_rules! array_pixel {
+    ($($count:literal),*) => {$(
+        // SAFETY: this is literally the array we promise to look like.
+        unsafe impl<C: Channel> Pixel for [C; $count] {
+            type Channel = C;
+            const CHANNELS: usize = $count;
+
+            #[inline]
+            fn channels(&self) -> &[Self::Channel] {
+                self
+            }
+
+            #[inline]
+            fn channels_mut(&mut self) -> &mut [Self::Channel] {
+                self
+            }
+        }
+    )*};
+}
+
+// `Default` is only implemented for arrays of certain lengths, so these get
+// spelled out
+array_pixel!(1, 2, 3, 4);
+
+macro_rules! named_pixel {
+    ($($(#[$meta:meta])* $name:ident = $count:literal;)*) => {$(
+        $(#[$meta])*
+        #[repr(transparent)]
+        #[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+        pub struct $name<C>(pub [C; $count]);
+
+        // SAFETY: `repr(transparent)` over `[C; N]` keeps the array's layout.
+        unsafe impl<C: Channel> Pixel for $name<C> {
+            type Channel = C;
+            const CHANNELS: usize = $count;
+
+            #[inline]
+            fn channels(&self) -> &[Self::Channel] {
+                &self.0
+            }
+
+            #[inline]
+            fn channels_mut(&mut self) -> &mut [Self::Channel] {
+                &mut self.0
+            }
+        }
+
+        impl<C> From<[C; $count]> for $name<C> {
+            #[inline]
+            fn from(value: [C; $count]) -> Self {
+                Self(value)
+            }
+        }
+    )*};
+}
+
+named_pixel! {
+    /// A grayscale pixel.
+    Luma = 1;
+    /// A grayscale pixel with an alpha channel.
+    LumaA = 2;
+    /// A red, green, and blue pixel.
+    Rgb = 3;
+    /// A red, green, blue, and alpha pixel.
+    Rgba = 4;
+    /// A blue, green, and red pixel. Many capture devices and file formats
+    /// like this order.
+    Bgr = 3;
+    /// A blue, green, red, and alpha pixel.
+    Bgra = 4;
+    /// A luma, blue-difference, and red-difference pixel, also known as
+    /// YCbCr.
+    Yuv = 3;
+}
+
+/// Reinterprets a slice of channels as a slice of pixels.
+///
+/// Any trailing channels that don't make up a whole pixel are left out.
+#[inline]
+pub(crate) const fn cast_channels<P: Pixel>(channels: &[P::Channel]) -> &[P] {
+    let len = pixel_count::<P>(channels.len());
+
+    // SAFETY: `Pixel` guarantees that `P` looks exactly like
+    // `[P::Channel; P::CHANNELS]` and has the same alignment as a channel, so
+    // `len` pixels fit inside the given channels.
+    unsafe { core::slice::from_raw_parts(channels.as_ptr().cast::<P>(), len) }
+}
+
+/// Reinterprets a mutable slice of channels as a mutable slice of pixels.
+#[inline]
+pub(crate) const fn cast_channels_mut<P: Pixel>(channels: &mut [P::Channel]) -> &mut [P] {
+    let len = pixel_count::<P>(channels.len());
+
+    // SAFETY: see `cast_channels`. we hold the only reference to these
+    // channels, so handing out a mutable one is fine.
+    unsafe { core::slice::from_raw_parts_mut(channels.as_mut_ptr().cast::<P>(), len) }
+}
+
+/// Reinterprets raw bytes as channels.
+///
+/// This returns `None` when the bytes aren't aligned for the channel type.
+/// Trailing bytes that don't make up a whole channel are left out.
+#[inline]
+pub(crate) fn channels_from_bytes<C: Channel>(bytes: &[u8]) -> Option<&[C]> {
+    // SAFETY: `Channel` is sealed to primitives that accept any bit pattern.
+    let (prefix, channels, _) = unsafe { bytes.align_to::<C>() };
+    prefix.is_empty().then_some(channels)
+}
+
+/// Reinterprets raw bytes as channels, mutably.
+#[inline]
+pub(crate) fn channels_from_bytes_mut<C: Channel>(bytes: &mut [u8]) -> Option<&mut [C]> {
+    // SAFETY: `Channel` is sealed to primitives that accept any bit pattern,
+    // and every byte pattern is a valid `u8` on the way back out.
+    let (prefix, channels, _) = unsafe { bytes.align_to_mut::<C>() };
+    prefix.is_empty().then_some(channels)
+}
+
+/// How many whole pixels fit in the given number of channels.
+#[inline]
+const fn pixel_count<P: Pixel>(channels: usize) -> usize {
+    match channels.checked_div(P::CHANNELS) {
+        Some(count) => count,
+        None => 0,
+    }
+}
+
+/// The number of channels in one row of pixels.
+#[inline]
+pub(crate) const fn row_len<P: Pixel>(width: u32) -> usize {
+    width as usize * P::CHANNELS
+}
+
+/// The number of channels needed to hold `height` rows with the given stride.
+///
+/// The last row doesn't need any padding after it.
+#[inline]
+pub(crate) const fn required_len(row_len: usize, height: u32, stride: usize) -> usize {
+    match height {
+        0 => 0,
+        _ => (height as usize - 1) * stride + row_len,
+    }
+}
+
+/// Turns a stride in bytes into a stride in channels.
+#[inline]
+pub(crate) const fn bytes_to_channels<C: Channel>(
+    stride_bytes: usize,
+) -> Result<usize, ImageError> {
+    let channel_size = size_of::<C>();
+    match stride_bytes.checked_div(channel_size) {
+        Some(stride) if stride_bytes.is_multiple_of(channel_size) => Ok(stride),
+        _ => Err(ImageError::StrideNotChannelAligned {
+            stride_bytes,
+            channel_size,
+        }),
+    }
+}
@@ -0,0 +1,474 @@
+//! Planar images, where each channel (or pair of channels) lives in its own
+//! plane.
+//!
+//! Many capture devices hand out YUV frames like this, often with
+//! subsampled chroma planes. Each plane is just an [`ImageView`] of single
+//! samples, so everything that works on views works on planes too.
+
+use crate::error::ImageError;
+use crate::pixel::{bytes_to_channels, channels_from_bytes, required_len, Channel, Pixel};
+use crate::{Image, ImageView, Rect};
+
+/// How an image is split into planes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum PlanarLayout {
+    /// Y, then U, then V. Chroma is halved in both directions. Also known
+    /// as YUV 4:2:0 or `YU12`.
+    I420,
+    /// Like `I420`, but V comes before U.
+    Yv12,
+    /// Y, then a plane of interleaved U and V samples. Chroma is halved in
+    /// both directions.
+    Nv12,
+    /// Like `Nv12`, but each chroma pair is V then U.
+    Nv21,
+    /// Y, then U, then V. Chroma is halved horizontally.
+    Yuv422p,
+    /// Y, then U, then V, all at full resolution.
+    Yuv444p,
+    /// Red, then green, then blue, all at full resolution.
+    Rgbp,
+}
+
+/// What a single plane holds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PlaneKind {
+    /// Samples at the image's full resolution.
+    Full,
+    /// One subsampled chroma channel.
+    Chroma,
+    /// Two subsampled chroma channels, interleaved.
+    InterleavedChroma,
+}
+
+impl PlanarLayout {
+    /// The number of planes an image with this layout has.
+    #[inline]
+    pub const fn plane_count(self) -> usize {
+        match self {
+            Self::Nv12 | Self::Nv21 => 2,
+            Self::I420 | Self::Yv12 | Self::Yuv422p | Self::Yuv444p | Self::Rgbp => 3,
+        }
+    }
+
+    /// How much the chroma planes are shrunk, as `(horizontal, vertical)`
+    /// factors.
+    #[inline]
+    pub const fn subsampling(self) -> (u32, u32) {
+        match self {
+            Self::I420 | Self::Yv12 | Self::Nv12 | Self::Nv21 => (2, 2),
+            Self::Yuv422p => (2, 1),
+            Self::Yuv444p | Self::Rgbp => (1, 1),
+        }
+    }
+
+    /// Returns the size of one plane, in samples, for an image with the
+    /// given dimensions.
+    ///
+    /// Interleaved chroma planes have two samples for each chroma pixel.
+    #[inline]
+    pub fn plane_dimensions(self, plane: usize, width: u32, height: u32) -> Option<(u32, u32)> {
+        let (h_sub, v_sub) = self.subsampling();
+        Some(match self.plane_kind(plane)? {
+            PlaneKind::Full => (width, height),
+            PlaneKind::Chroma => (width.div_ceil(h_sub), height.div_ceil(v_sub)),
+            PlaneKind::InterleavedChroma => (width.div_ceil(h_sub) * 2, height.div_ceil(v_sub)),
+        })
+    }
+
+    /// The stride of a plane, given the stride of the first plane.
+    ///
+    /// This follows the usual rule for single-buffer planar formats: chroma
+    /// planes shrink their stride by the horizontal subsampling factor, while
+    /// interleaved chroma planes keep the luma stride.
+    #[inline]
+    pub fn plane_stride(self, plane: usize, first_stride: usize) -> Option<usize> {
+        let (h_sub, _) = self.subsampling();
+        Some(match self.plane_kind(plane)? {
+            PlaneKind::Full | PlaneKind::InterleavedChroma => first_stride,
+            PlaneKind::Chroma => first_stride.div_ceil(h_sub as usize),
+        })
+    }
+
+    /// Maps a region of the full image onto one of its planes.
+    ///
+    /// This returns `None` if the region would split a chroma sample in
+    /// half.
+    #[inline]
+    #[expect(
+        clippy::integer_division,
+        reason = "we've already checked that the region lines up with the subsampling"
+    )]
+    pub fn plane_rect(self, plane: usize, roi: Rect, width: u32, height: u32) -> Option<Rect> {
+        let (h_sub, v_sub) = self.subsampling();
+        let kind = self.plane_kind(plane)?;
+        if kind == PlaneKind::Full {
+            return Some(roi);
+        }
+
+        // a region can only end partway through a chroma sample at the very
+        // edge of the image
+        let aligned = |start: u32, len: u32, full: u32, sub: u32| {
+            start.is_multiple_of(sub) && (len.is_multiple_of(sub) || start + len == full)
+        };
+        if !aligned(roi.x, roi.width, width, h_sub) || !aligned(roi.y, roi.height, height, v_sub) {
+            return None;
+        }
+
+        let (x, w) = match kind {
+            PlaneKind::InterleavedChroma => (roi.x / h_sub * 2, roi.width.div_ceil(h_sub) * 2),
+            PlaneKind::Full | PlaneKind::Chroma => (roi.x / h_sub, roi.width.div_ceil(h_sub)),
+        };
+        Some(Rect::new(x, roi.y / v_sub, w, roi.height.div_ceil(v_sub)))
+    }
+
+    const fn plane_kind(self, plane: usize) -> Option<PlaneKind> {
+        match (self, plane) {
+            (_, 0) | (Self::Yuv444p | Self::Rgbp, 1 | 2) => Some(PlaneKind::Full),
+            (Self::Nv12 | Self::Nv21, 1) => Some(PlaneKind::InterleavedChroma),
+            (Self::I420 | Self::Yv12 | Self::Yuv422p, 1 | 2) => Some(PlaneKind::Chroma),
+            _ => None,
+        }
+    }
+}
+
+/// A borrowed planar image.
+///
+/// Each plane is a view of single samples with its own stride, so planes may
+/// come from one buffer or several.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlanarView<'img, C: Channel + Pixel<Channel = C>> {
+    layout: PlanarLayout,
+    width: u32,
+    height: u32,
+    planes: Vec<ImageView<'img, C>>,
+}
+
+impl<'img, C: Channel + Pixel<Channel = C>> PlanarView<'img, C> {
+    /// Creates a planar view from one view per plane.
+    ///
+    /// # Errors
+    ///
+    /// This fails if there's the wrong number of planes or any plane has the
+    /// wrong size for the layout.
+    #[inline]
+    pub fn from_planes(
+        layout: PlanarLayout,
+        width: u32,
+        height: u32,
+        planes: Vec<ImageView<'img, C>>,
+    ) -> Result<Self, ImageError> {
+        if planes.len() != layout.plane_count() {
+            return Err(ImageError::WrongPlaneCount {
+                expected: layout.plane_count(),
+                got: planes.len(),
+            });
+        }
+
+        for (index, plane) in planes.iter().enumerate() {
+            let (expected_width, expected_height) = layout
+                .plane_dimensions(index, width, height)
+                .unwrap_or_default();
+
+            if plane.dimensions() != (expected_width, expected_height) {
+                return Err(ImageError::WrongPlaneDimensions {
+                    plane: index,
+                    expected_width,
+                    expected_height,
+                    got_width: plane.width(),
+                    got_height: plane.height(),
+                });
+            }
+        }
+
+        Ok(Self {
+            layout,
+            width,
+            height,
+            planes,
+        })
+    }
+
+    /// Creates a planar view over one buffer that holds every plane back to
+    /// back.
+    ///
+    /// `stride` is the stride of the first plane, in samples. The other
+    /// strides come from [`PlanarLayout::plane_stride`].
+    ///
+    /// # Errors
+    ///
+    /// This fails if the buffer is too short to hold every plane.
+    #[inline]
+    pub fn from_contiguous(
+        layout: PlanarLayout,
+        data: &'img [C],
+        width: u32,
+        height: u32,
+        stride: usize,
+    ) -> Result<Self, ImageError> {
+        let mut planes = Vec::with_capacity(layout.plane_count());
+        let mut offset = 0_usize;
+
+        for index in 0..layout.plane_count() {
+            let (plane_width, plane_height) = layout
+                .plane_dimensions(index, width, height)
+                .unwrap_or_default();
+            let plane_stride = layout.plane_stride(index, stride).unwrap_or_default();
+
+            let rest = data.get(offset..).unwrap_or_default();
+            planes.push(
+                ImageView::from_slice(rest, plane_width, plane_height, plane_stride).map_err(
+                    |err| match err {
+                        // report the size of the whole buffer, not just what's left
+                        ImageError::BufferTooSmall { needed, .. } => ImageError::BufferTooSmall {
+                            needed: offset + needed,
+                            got: data.len(),
+                        },
+                        other => other,
+                    },
+                )?,
+            );
+
+            offset += plane_stride * plane_height as usize;
+        }
+
+        Ok(Self {
+            layout,
+            width,
+            height,
+            planes,
+        })
+    }
+
+    /// Creates a planar view over raw bytes that hold every plane back to
+    /// back, like a frame from a capture device.
+    ///
+    /// `stride_bytes` is the stride of the first plane, in bytes.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the bytes aren't aligned for the sample type, the stride
+    /// isn't a whole number of samples, or the buffer is too short to hold
+    /// every plane.
+    #[inline]
+    pub fn from_bytes(
+        layout: PlanarLayout,
+        bytes: &'img [u8],
+        width: u32,
+        height: u32,
+        stride_bytes: usize,
+    ) -> Result<Self, ImageError> {
+        let stride = bytes_to_channels::<C>(stride_bytes)?;
+        let samples = channels_from_bytes::<C>(bytes).ok_or(ImageError::MisalignedBuffer {
+            channel_size: size_of::<C>(),
+        })?;
+
+        Self::from_contiguous(layout, samples, width, height, stride)
+    }
+
+    /// The layout of this image's planes.
+    #[inline]
+    pub const fn layout(&self) -> PlanarLayout {
+        self.layout
+    }
+
+    /// The width of the full image, in pixels.
+    #[inline]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height of the full image, in pixels.
+    #[inline]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns `(width, height)` of the full image.
+    #[inline]
+    pub const fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Returns one of the planes.
+    #[inline]
+    pub fn plane(&self, index: usize) -> Option<ImageView<'img, C>> {
+        self.planes.get(index).copied()
+    }
+
+    /// Returns every plane, in order.
+    #[inline]
+    pub fn planes(&self) -> &[ImageView<'img, C>] {
+        &self.planes
+    }
+
+    /// Makes a smaller planar view over a region of this one.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the region doesn't fit or would split a subsampled
+    /// chroma sample.
+    #[inline]
+    pub fn roi(&self, roi: Rect) -> Result<Self, ImageError> {
+        if !roi.fits_within(self.width, self.height) {
+            return Err(ImageError::RoiOutOfBounds {
+                roi,
+                width: self.width,
+                height: self.height,
+            });
+        }
+
+        let planes = self
+            .planes
+            .iter()
+            .enumerate()
+            .map(|(index, plane)| {
+                let plane_roi = self
+                    .layout
+                    .plane_rect(index, roi, self.width, self.height)
+                    .ok_or(ImageError::RoiNotSubsampleAligned { roi })?;
+                plane.roi(plane_roi)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            layout: self.layout,
+            width: roi.width,
+            height: roi.height,
+            planes,
+        })
+    }
+
+    /// Copies this view into a new planar image.
+    #[inline]
+    pub fn to_image(&self) -> PlanarImage<C> {
+        PlanarImage {
+            layout: self.layout,
+            width: self.width,
+            height: self.height,
+            planes: self.planes.iter().map(ImageView::to_image).collect(),
+        }
+    }
+}
+
+/// A planar image that owns its planes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlanarImage<C: Channel + Pixel<Channel = C>> {
+    layout: PlanarLayout,
+    width: u32,
+    height: u32,
+    planes: Vec<Image<C>>,
+}
+
+impl<C: Channel + Pixel<Channel = C>> PlanarImage<C> {
+    /// Creates a new planar image where every sample is zero.
+    #[inline]
+    pub fn new(layout: PlanarLayout, width: u32, height: u32) -> Self {
+        let planes = (0..layout.plane_count())
+            .map(|index| {
+                let (plane_width, plane_height) = layout
+                    .plane_dimensions(index, width, height)
+                    .unwrap_or_default();
+                Image::new(plane_width, plane_height)
+            })
+            .collect();
+
+        Self {
+            layout,
+            width,
+            height,
+            planes,
+        }
+    }
+
+    /// The layout of this image's planes.
+    #[inline]
+    pub const fn layout(&self) -> PlanarLayout {
+        self.layout
+    }
+
+    /// Returns `(width, height)` of the full image.
+    #[inline]
+    pub const fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Returns one of the planes.
+    #[inline]
+    pub fn plane(&self, index: usize) -> Option<&Image<C>> {
+        self.planes.get(index)
+    }
+
+    /// Returns one of the planes mutably.
+    #[inline]
+    pub fn plane_mut(&mut self, index: usize) -> Option<&mut Image<C>> {
+        self.planes.get_mut(index)
+    }
+
+    /// Returns a borrowed view of every plane.
+    #[inline]
+    pub fn view(&self) -> PlanarView<'_, C> {
+        PlanarView {
+            layout: self.layout,
+            width: self.width,
+            height: self.height,
+            planes: self.planes.iter().map(Image::view).collect(),
+        }
+    }
+
+    /// The number of samples needed to hold every plane back to back, with
+    /// no padding.
+    #[inline]
+    pub fn contiguous_len(&self) -> usize {
+        self.planes
+            .iter()
+            .map(|plane| {
+                let row = plane.width() as usize;
+                required_len(row, plane.height(), row)
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nv12_from_one_buffer() {
+        // 4x2 luma with a stride of 6, then one row of interleaved chroma
+        let mut data = vec![0_u8; 6 * 2 + 6];
+        if let Some(chroma) = data.get_mut(12..16) {
+            chroma.copy_from_slice(&[10, 20, 30, 40]);
+        }
+
+        let view = PlanarView::from_contiguous(PlanarLayout::Nv12, &data, 4, 2, 6).unwrap();
+        let chroma = view.plane(1).unwrap();
+        assert_eq!(chroma.dimensions(), (4, 1), "two chroma pairs");
+        assert_eq!(
+            chroma.row(0),
+            Some([10, 20, 30, 40].as_slice()),
+            "uv samples"
+        );
+    }
+
+    #[test]
+    fn i420_roi_follows_subsampling() {
+        let image = PlanarImage::<u8>::new(PlanarLayout::I420, 8, 4);
+        let view = image.view();
+
+        let roi = view.roi(Rect::new(2, 2, 4, 2)).unwrap();
+        assert_eq!(roi.plane(0).unwrap().dimensions(), (4, 2), "luma");
+        assert_eq!(roi.plane(1).unwrap().dimensions(), (2, 1), "u");
+        assert_eq!(roi.plane(2).unwrap().dimensions(), (2, 1), "v");
+
+        assert_eq!(
+            view.roi(Rect::new(1, 0, 2, 2)),
+            Err(ImageError::RoiNotSubsampleAligned {
+                roi: Rect::new(1, 0, 2, 2)
+            }),
+            "odd x splits a chroma sample"
+        );
+    }
+}
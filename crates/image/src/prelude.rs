@@ -0,0 +1,8 @@
+//! The useful traits and types from the `serumcv_image` crate.
+
+pub use super::error::ImageError;
+pub use super::filter::{IntegralImage, Kernel, SeparableKernel};
+pub use super::pixel::{Bgr, Bgra, Channel, Luma, LumaA, Pixel, Rgb, Rgba, Yuv};
+pub use super::planar::{PlanarImage, PlanarLayout, PlanarView};
+pub use super::remap::{Interpolation, RemapMap};
+pub use super::{Border, Image, ImageView, ImageViewMut, Rect};
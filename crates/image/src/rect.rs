@@ -0,0 +1,59 @@
+use core::fmt::Display;
+
+/// An axis-aligned rectangle in pixel coordinates.
+///
+/// This is mostly used to describe a region of interest (ROI) within an
+/// image.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Rect {
+    /// The column of the top-left corner.
+    pub x: u32,
+    /// The row of the top-left corner.
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    /// Creates a new rectangle from its top-left corner and size.
+    #[inline]
+    pub const fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Checks if this rectangle fits entirely inside an image with the given
+    /// dimensions.
+    #[inline]
+    pub const fn fits_within(self, width: u32, height: u32) -> bool {
+        // these can't overflow when compared as `u64`s
+        (self.x as u64 + self.width as u64) <= width as u64
+            && (self.y as u64 + self.height as u64) <= height as u64
+    }
+
+    /// Returns the number of pixels covered by this rectangle.
+    #[inline]
+    pub const fn area(self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    /// Checks if this rectangle covers no pixels at all.
+    #[inline]
+    pub const fn is_empty(self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+}
+
+impl Display for Rect {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_fmt(format_args!(
+            "Rect({}, {}; {} x {})",
+            self.x, self.y, self.width, self.height
+        ))
+    }
+}
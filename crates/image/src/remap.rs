@@ -0,0 +1,440 @@
+//! Moving pixels around with lookup maps.
+//!
+//! A [`RemapMap`] says, for each pixel of an output image, where in the
+//! source image to read from. Building one can be slow (like when it comes
+//! from a lens model), but applying it is just a lookup per pixel, so the
+//! usual plan is to build a map once and apply it to every frame.
+//!
+//! Coordinates put the middle of the top-left pixel at `[0, 0]`.
+
+use crate::error::ImageError;
+use crate::pixel::{Channel as _, Pixel};
+use crate::{Border, Image, ImageView, ImageViewMut};
+
+/// How to read between pixels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Interpolation {
+    /// Uses the closest pixel. This is the fastest, but edges look jagged.
+    Nearest,
+    /// Blends the four closest pixels.
+    #[default]
+    Bilinear,
+}
+
+/// A lookup map from output pixels to source coordinates.
+///
+/// Each coordinate is split into a whole pixel and the fraction past it
+/// when the map is made, so applying it doesn't need to round anything.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemapMap {
+    samples: Vec<Sample>,
+    width: u32,
+    height: u32,
+}
+
+impl RemapMap {
+    /// Makes a map from a source coordinate for each output pixel, row by
+    /// row.
+    ///
+    /// Coordinates that aren't finite (like `NaN`) mark output pixels with
+    /// no source. Those get the border's constant, or the default pixel for
+    /// other borders.
+    ///
+    /// # Errors
+    ///
+    /// This fails if there isn't exactly one coordinate per output pixel.
+    #[inline]
+    pub fn new(coords: &[[f32; 2]], width: u32, height: u32) -> Result<Self, ImageError> {
+        let needed = width as usize * height as usize;
+        if coords.len() != needed {
+            return Err(ImageError::MapSizeMismatch {
+                needed,
+                got: coords.len(),
+            });
+        }
+
+        Ok(Self {
+            samples: coords.iter().copied().map(Sample::new).collect(),
+            width,
+            height,
+        })
+    }
+
+    /// Makes a map by calling a function with the coordinates of each
+    /// output pixel.
+    #[inline]
+    pub fn from_fn<F: FnMut(u32, u32) -> [f32; 2]>(width: u32, height: u32, mut f: F) -> Self {
+        let mut samples = Vec::with_capacity(width as usize * height as usize);
+        for y in 0..height {
+            samples.extend((0..width).map(|x| Sample::new(f(x, y))));
+        }
+
+        Self {
+            samples,
+            width,
+            height,
+        }
+    }
+
+    /// Makes a map that leaves images as they are.
+    #[inline]
+    #[expect(
+        clippy::cast_precision_loss,
+        reason = "images are nowhere near 2^24 pixels across"
+    )]
+    pub fn identity(width: u32, height: u32) -> Self {
+        Self::from_fn(width, height, |x, y| [x as f32, y as f32])
+    }
+
+    /// The width of the output, in pixels.
+    #[inline]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height of the output, in pixels.
+    #[inline]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns `(width, height)` of the output.
+    #[inline]
+    pub const fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Returns the source coordinates for one output pixel.
+    ///
+    /// These are `NaN` for pixels without a source.
+    #[inline]
+    pub fn get(&self, x: u32, y: u32) -> Option<[f32; 2]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        self.samples
+            .get(y as usize * self.width as usize + x as usize)
+            .map(|sample| sample.coords())
+    }
+
+    /// Remaps an image into a new one, the size of this map.
+    #[inline]
+    pub fn apply<P: Pixel>(
+        &self,
+        src: &ImageView<'_, P>,
+        interpolation: Interpolation,
+        border: Border<P>,
+    ) -> Image<P> {
+        let mut image = Image::new(self.width, self.height);
+        self.remap_rows(src, image.view_mut(), interpolation, border);
+        image
+    }
+
+    /// Remaps an image into one you already have, so nothing gets
+    /// allocated.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the destination isn't the size of this map.
+    #[inline]
+    pub fn apply_into<P: Pixel>(
+        &self,
+        src: &ImageView<'_, P>,
+        dst: &mut ImageViewMut<'_, P>,
+        interpolation: Interpolation,
+        border: Border<P>,
+    ) -> Result<(), ImageError> {
+        if dst.dimensions() != self.dimensions() {
+            return Err(ImageError::DimensionMismatch {
+                src_width: self.width,
+                src_height: self.height,
+                dst_width: dst.width(),
+                dst_height: dst.height(),
+            });
+        }
+
+        self.remap_rows(src, dst.reborrow(), interpolation, border);
+        Ok(())
+    }
+
+    /// Fills the destination from the source.
+    fn remap_rows<P: Pixel>(
+        &self,
+        src: &ImageView<'_, P>,
+        mut dst: ImageViewMut<'_, P>,
+        interpolation: Interpolation,
+        border: Border<P>,
+    ) {
+        let sampler = Sampler { src, border };
+        let map_rows = self.samples.chunks(self.width.max(1) as usize);
+
+        for (row, samples) in dst.rows_mut().zip(map_rows) {
+            for (px, &sample) in row.iter_mut().zip(samples) {
+                *px = match interpolation {
+                    Interpolation::Nearest => sampler.nearest(sample),
+                    Interpolation::Bilinear => sampler.bilinear(sample),
+                };
+            }
+        }
+    }
+}
+
+/// One output pixel's source coordinates, split into a whole pixel and the
+/// fraction past it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Sample {
+    left: i32,
+    top: i32,
+    right: f32,
+    down: f32,
+}
+
+impl Sample {
+    /// Where output pixels without a source point.
+    const NOWHERE: Self = Self {
+        left: i32::MIN,
+        top: i32::MIN,
+        right: f32::NAN,
+        down: f32::NAN,
+    };
+
+    #[expect(
+        clippy::cast_possible_truncation,
+        reason = "float to int casts saturate, and anything that far off lands on the border anyway"
+    )]
+    fn new(coords: [f32; 2]) -> Self {
+        let [x, y] = coords;
+        if !(x.is_finite() && y.is_finite()) {
+            return Self::NOWHERE;
+        }
+
+        let (floor_x, floor_y) = (x.floor(), y.floor());
+        Self {
+            left: floor_x as i32,
+            top: floor_y as i32,
+            right: x - floor_x,
+            down: y - floor_y,
+        }
+    }
+
+    /// Whether this sample has no source.
+    const fn is_nowhere(self) -> bool {
+        self.right.is_nan()
+    }
+
+    #[expect(
+        clippy::cast_precision_loss,
+        reason = "coordinates past 2^24 are far off the border either way"
+    )]
+    fn coords(self) -> [f32; 2] {
+        [self.left as f32 + self.right, self.top as f32 + self.down]
+    }
+}
+
+/// Reads pixels from anywhere, inside the source or not.
+struct Sampler<'view, 'img, P: Pixel> {
+    src: &'view ImageView<'img, P>,
+    border: Border<P>,
+}
+
+impl<P: Pixel> Sampler<'_, '_, P> {
+    /// The pixel used when there's nothing else to use.
+    fn outside(&self) -> P {
+        self.border.outside()
+    }
+
+    /// Reads one pixel, going through the border if it's outside.
+    fn pixel(&self, x: i64, y: i64) -> P {
+        if let (Ok(inside_x), Ok(inside_y)) = (u32::try_from(x), u32::try_from(y)) {
+            if let Some(px) = self.src.get(inside_x, inside_y) {
+                return *px;
+            }
+        }
+
+        self.border
+            .resolve(x, self.src.width())
+            .zip(self.border.resolve(y, self.src.height()))
+            .and_then(|(border_x, border_y)| self.src.get(border_x, border_y))
+            .copied()
+            .unwrap_or_else(|| self.outside())
+    }
+
+    /// Blends a sample whose two-by-two square of pixels is all inside the
+    /// source, straight from its channels.
+    ///
+    /// Most of a map's samples land well inside the source, so this skips
+    /// the border (and building pixels) for them.
+    fn bilinear_inside(&self, sample: Sample) -> Option<P> {
+        let (left, top) = (
+            u32::try_from(sample.left).ok()?,
+            u32::try_from(sample.top).ok()?,
+        );
+        if left.checked_add(1)? >= self.src.width() || top.checked_add(1)? >= self.src.height() {
+            return None;
+        }
+
+        let (stride, pair) = (self.src.stride(), 2 * P::CHANNELS);
+        let start = top as usize * stride + left as usize * P::CHANNELS;
+        let raw = self.src.as_raw();
+        let (top_left, top_right) = raw.get(start..start + pair)?.split_at(P::CHANNELS);
+        let (bottom_left, bottom_right) = raw
+            .get(start + stride..start + stride + pair)?
+            .split_at(P::CHANNELS);
+
+        Some(blend(
+            [top_left, top_right, bottom_left, bottom_right],
+            sample,
+        ))
+    }
+
+    /// Reads the closest pixel to a sample. Points halfway between pixels
+    /// go right and down.
+    fn nearest(&self, sample: Sample) -> P {
+        if sample.is_nowhere() {
+            return self.outside();
+        }
+
+        let x = i64::from(sample.left) + i64::from(sample.right >= 0.5);
+        let y = i64::from(sample.top) + i64::from(sample.down >= 0.5);
+        self.pixel(x, y)
+    }
+
+    /// Blends the four closest pixels to a sample.
+    fn bilinear(&self, sample: Sample) -> P {
+        if sample.is_nowhere() {
+            return self.outside();
+        }
+
+        if let Some(blended) = self.bilinear_inside(sample) {
+            return blended;
+        }
+
+        let (left, top) = (i64::from(sample.left), i64::from(sample.top));
+        let corners = [
+            self.pixel(left, top),
+            self.pixel(left + 1, top),
+            self.pixel(left, top + 1),
+            self.pixel(left + 1, top + 1),
+        ];
+        blend(corners.each_ref().map(Pixel::channels), sample)
+    }
+}
+
+/// Blends the channels of a square of pixels, given as top left, top right,
+/// bottom left, then bottom right.
+#[expect(
+    clippy::suboptimal_flops,
+    reason = "`mul_add` is a slow library call on CPUs without FMA, and this runs for every channel"
+)]
+fn blend<P: Pixel>(corners: [&[P::Channel]; 4], sample: Sample) -> P {
+    let [top_left, top_right, bottom_left, bottom_right] = corners;
+    let channels = top_left
+        .iter()
+        .zip(top_right)
+        .zip(bottom_left.iter().zip(bottom_right));
+
+    let mut blended = P::default();
+    for (channel, ((&a, &b), (&c, &d))) in blended.channels_mut().iter_mut().zip(channels) {
+        let upper = (b.to_f32() - a.to_f32()) * sample.right + a.to_f32();
+        let lower = (d.to_f32() - c.to_f32()) * sample.right + c.to_f32();
+        *channel = P::Channel::from_f32((lower - upper) * sample.down + upper);
+    }
+
+    blended
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Rgb;
+
+    /// A 4x3 gradient, where each pixel is `10x + y`.
+    #[expect(clippy::cast_precision_loss, reason = "these are tiny numbers")]
+    fn gradient() -> Image<f32> {
+        Image::from_fn(4, 3, |x, y| (10 * x + y) as f32)
+    }
+
+    #[test]
+    fn identity_maps_copy() {
+        let image = Image::from_fn(
+            5,
+            2,
+            |x, y| Rgb([x, y, 7].map(|c| u8::try_from(c).unwrap())),
+        );
+        let map = RemapMap::identity(5, 2);
+
+        for interpolation in [Interpolation::Nearest, Interpolation::Bilinear] {
+            let copy = map.apply(&image.view(), interpolation, Border::Replicate);
+            assert_eq!(copy, image, "{interpolation:?} should copy exactly");
+        }
+    }
+
+    #[test]
+    fn bilinear_blends_and_borders_apply() {
+        let image = gradient();
+        let map = RemapMap::new(
+            &[
+                [0.5, 0.0],
+                [1.25, 1.5],
+                [-1.0, 0.0],
+                [f32::NAN, 0.0],
+                [3.4, 2.6],
+            ],
+            5,
+            1,
+        )
+        .unwrap();
+
+        let blended = map.apply(
+            &image.view(),
+            Interpolation::Bilinear,
+            Border::Constant(-1.0),
+        );
+        // halfway, a quarter and a half, off the edge, no source, and
+        // partly off the corner
+        let expected = [5.0, 14.0, -1.0, -1.0, 6.92];
+        for (got, want) in blended.pixels().iter().zip(expected) {
+            assert!((got - want).abs() < 1e-4, "got {got}, expected {want}");
+        }
+
+        let reflected = map.apply(&image.view(), Interpolation::Nearest, Border::Reflect);
+        assert_eq!(
+            reflected.pixels(),
+            [10.0, 12.0, 10.0, 0.0, 31.0],
+            "one left of the edge reflects onto column one"
+        );
+    }
+
+    #[test]
+    fn destinations_must_match_the_map() {
+        let image = gradient();
+        let map = RemapMap::identity(4, 3);
+        let mut small = Image::<f32>::new(3, 3);
+
+        assert_eq!(
+            map.apply_into(
+                &image.view(),
+                &mut small.view_mut(),
+                Interpolation::Nearest,
+                Border::Wrap
+            ),
+            Err(ImageError::DimensionMismatch {
+                src_width: 4,
+                src_height: 3,
+                dst_width: 3,
+                dst_height: 3,
+            }),
+            "one column short"
+        );
+        assert_eq!(
+            RemapMap::new(&[[0.0, 0.0]; 11], 4, 3).err(),
+            Some(ImageError::MapSizeMismatch {
+                needed: 12,
+                got: 11
+            }),
+            "one coordinate short"
+        );
+    }
+}
@@ -0,0 +1,583 @@
+//! Borrowed, strided views into image memory.
+//!
+//! Views never own their pixels. They're just a slice of channels with a
+//! width, height, and row stride, so making a region of interest (ROI) is
+//! only a bit of arithmetic.
+
+use crate::error::ImageError;
+use crate::pixel::{self, bytes_to_channels, required_len, row_len, Pixel};
+use crate::{Image, Rect};
+
+/// A read-only view of some image memory.
+///
+/// The view starts at its top-left pixel. Each row is `width` pixels long,
+/// and rows begin every `stride` channels. Any channels between the end of
+/// one row and the start of the next are padding, and views ignore them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageView<'img, P: Pixel> {
+    data: &'img [P::Channel],
+    width: u32,
+    height: u32,
+    stride: usize,
+}
+
+impl<'img, P: Pixel> ImageView<'img, P> {
+    /// Creates a view over a slice of channels.
+    ///
+    /// `stride` is the distance between the start of each row, measured in
+    /// channels (not pixels or bytes).
+    ///
+    /// # Errors
+    ///
+    /// This fails if the stride is shorter than a row or the slice is too
+    /// short to hold every row.
+    #[inline]
+    pub fn from_slice(
+        data: &'img [P::Channel],
+        width: u32,
+        height: u32,
+        stride: usize,
+    ) -> Result<Self, ImageError> {
+        let trimmed = checked_region::<P>(data, width, height, stride)?;
+        Ok(Self {
+            data: trimmed,
+            width,
+            height,
+            stride,
+        })
+    }
+
+    /// Creates a view over a contiguous slice of channels, where rows have no
+    /// padding between them.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the slice is too short for the given dimensions.
+    #[inline]
+    pub fn from_contiguous(
+        data: &'img [P::Channel],
+        width: u32,
+        height: u32,
+    ) -> Result<Self, ImageError> {
+        Self::from_slice(data, width, height, row_len::<P>(width))
+    }
+
+    /// Creates a view over raw bytes, like a frame from a capture device.
+    ///
+    /// `stride_bytes` is the distance between the start of each row, in
+    /// bytes. This never copies, so multi-byte channels must already be in
+    /// native byte order.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the bytes aren't aligned for the channel type, the stride
+    /// isn't a whole number of channels, or there aren't enough bytes for the
+    /// given dimensions.
+    #[inline]
+    pub fn from_bytes(
+        bytes: &'img [u8],
+        width: u32,
+        height: u32,
+        stride_bytes: usize,
+    ) -> Result<Self, ImageError> {
+        let stride = bytes_to_channels::<P::Channel>(stride_bytes)?;
+        let channels = pixel::channels_from_bytes::<P::Channel>(bytes).ok_or(
+            ImageError::MisalignedBuffer {
+                channel_size: size_of::<P::Channel>(),
+            },
+        )?;
+
+        Self::from_slice(channels, width, height, stride)
+    }
+
+    /// The width of this view, in pixels.
+    #[inline]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height of this view, in pixels.
+    #[inline]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns `(width, height)`.
+    #[inline]
+    pub const fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// The distance between the start of each row, in channels.
+    #[inline]
+    pub const fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Checks if the rows of this view sit right next to each other in
+    /// memory.
+    #[inline]
+    pub const fn is_contiguous(&self) -> bool {
+        self.height <= 1 || self.stride == row_len::<P>(self.width)
+    }
+
+    /// Returns the underlying channels, starting at the top-left pixel.
+    ///
+    /// This includes any padding between rows.
+    #[inline]
+    pub const fn as_raw(&self) -> &'img [P::Channel] {
+        self.data
+    }
+
+    /// Returns one row of pixels.
+    #[inline]
+    pub fn row(&self, y: u32) -> Option<&'img [P]> {
+        if y >= self.height {
+            return None;
+        }
+
+        let start = self.stride.checked_mul(y as usize)?;
+        let end = start.checked_add(row_len::<P>(self.width))?;
+        self.data.get(start..end).map(pixel::cast_channels::<P>)
+    }
+
+    /// Returns an iterator over each row of pixels, from top to bottom.
+    #[inline]
+    pub fn rows(&self) -> impl DoubleEndedIterator<Item = &'img [P]> + 'img {
+        let row_len = row_len::<P>(self.width);
+        self.data
+            .chunks(self.stride.max(1))
+            .take(self.height as usize)
+            .map(move |chunk| pixel::cast_channels::<P>(chunk.get(..row_len).unwrap_or_default()))
+    }
+
+    /// Returns an iterator over every pixel, row by row.
+    #[inline]
+    pub fn pixels(&self) -> impl Iterator<Item = &'img P> + 'img {
+        self.rows().flatten()
+    }
+
+    /// Returns the pixel at the given coordinates.
+    #[inline]
+    pub fn get(&self, x: u32, y: u32) -> Option<&'img P> {
+        self.row(y)?.get(x as usize)
+    }
+
+    /// Makes a smaller view over a region of this one.
+    ///
+    /// This doesn't copy anything.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the region doesn't fit inside this view.
+    #[inline]
+    pub fn roi(&self, roi: Rect) -> Result<Self, ImageError> {
+        let start = roi_offset::<P>(roi, self.width, self.height, self.stride)?;
+        Ok(Self {
+            data: self.data.get(start..).unwrap_or_default(),
+            width: roi.width,
+            height: roi.height,
+            stride: self.stride,
+        })
+    }
+
+    /// Copies this view into a new, contiguous image.
+    #[inline]
+    pub fn to_image(&self) -> Image<P> {
+        let mut data = Vec::with_capacity(row_len::<P>(self.width) * self.height as usize);
+        for row in self.rows() {
+            for px in row {
+                data.extend_from_slice(px.channels());
+            }
+        }
+
+        Image::from_parts(data, self.width, self.height)
+    }
+}
+
+/// A mutable view of some image memory.
+///
+/// This works just like [`ImageView`], but lets you write to pixels.
+#[derive(Debug, PartialEq)]
+pub struct ImageViewMut<'img, P: Pixel> {
+    data: &'img mut [P::Channel],
+    width: u32,
+    height: u32,
+    stride: usize,
+}
+
+impl<'img, P: Pixel> ImageViewMut<'img, P> {
+    /// Creates a mutable view over a slice of channels.
+    ///
+    /// `stride` is the distance between the start of each row, measured in
+    /// channels.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the stride is shorter than a row or the slice is too
+    /// short to hold every row.
+    #[inline]
+    pub fn from_slice(
+        data: &'img mut [P::Channel],
+        width: u32,
+        height: u32,
+        stride: usize,
+    ) -> Result<Self, ImageError> {
+        checked_region::<P>(data, width, height, stride)?;
+        Ok(Self {
+            data,
+            width,
+            height,
+            stride,
+        })
+    }
+
+    /// Creates a mutable view over raw bytes.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the bytes aren't aligned for the channel type, the stride
+    /// isn't a whole number of channels, or there aren't enough bytes for the
+    /// given dimensions.
+    #[inline]
+    pub fn from_bytes(
+        bytes: &'img mut [u8],
+        width: u32,
+        height: u32,
+        stride_bytes: usize,
+    ) -> Result<Self, ImageError> {
+        let stride = bytes_to_channels::<P::Channel>(stride_bytes)?;
+        let channels = pixel::channels_from_bytes_mut::<P::Channel>(bytes).ok_or(
+            ImageError::MisalignedBuffer {
+                channel_size: size_of::<P::Channel>(),
+            },
+        )?;
+
+        Self::from_slice(channels, width, height, stride)
+    }
+
+    /// The width of this view, in pixels.
+    #[inline]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height of this view, in pixels.
+    #[inline]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns `(width, height)`.
+    #[inline]
+    pub const fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// The distance between the start of each row, in channels.
+    #[inline]
+    pub const fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Borrows this as a read-only view.
+    #[inline]
+    pub const fn as_view(&self) -> ImageView<'_, P> {
+        ImageView {
+            data: self.data,
+            width: self.width,
+            height: self.height,
+            stride: self.stride,
+        }
+    }
+
+    /// Reborrows this view, so you can pass it along without giving it up.
+    #[inline]
+    pub const fn reborrow(&mut self) -> ImageViewMut<'_, P> {
+        ImageViewMut {
+            data: self.data,
+            width: self.width,
+            height: self.height,
+            stride: self.stride,
+        }
+    }
+
+    /// Returns one row of pixels.
+    #[inline]
+    pub fn row(&self, y: u32) -> Option<&[P]> {
+        self.as_view().row(y)
+    }
+
+    /// Returns one row of pixels mutably.
+    #[inline]
+    pub fn row_mut(&mut self, y: u32) -> Option<&mut [P]> {
+        if y >= self.height {
+            return None;
+        }
+
+        let start = self.stride.checked_mul(y as usize)?;
+        let end = start.checked_add(row_len::<P>(self.width))?;
+        self.data
+            .get_mut(start..end)
+            .map(pixel::cast_channels_mut::<P>)
+    }
+
+    /// Returns an iterator over each row of pixels, from top to bottom.
+    #[inline]
+    pub fn rows_mut(&mut self) -> impl DoubleEndedIterator<Item = &mut [P]> + '_ {
+        let row_len = row_len::<P>(self.width);
+        self.data
+            .chunks_mut(self.stride.max(1))
+            .take(self.height as usize)
+            .map(move |chunk| {
+                pixel::cast_channels_mut::<P>(chunk.get_mut(..row_len).unwrap_or_default())
+            })
+    }
+
+    /// Returns the pixel at the given coordinates.
+    #[inline]
+    pub fn get(&self, x: u32, y: u32) -> Option<&P> {
+        self.row(y)?.get(x as usize)
+    }
+
+    /// Returns the pixel at the given coordinates mutably.
+    #[inline]
+    pub fn get_mut(&mut self, x: u32, y: u32) -> Option<&mut P> {
+        self.row_mut(y)?.get_mut(x as usize)
+    }
+
+    /// Makes a smaller mutable view over a region of this one.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the region doesn't fit inside this view.
+    #[inline]
+    pub fn roi_mut(&mut self, roi: Rect) -> Result<ImageViewMut<'_, P>, ImageError> {
+        self.reborrow().into_roi(roi)
+    }
+
+    /// Turns this view into a smaller view over one of its regions.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the region doesn't fit inside this view.
+    #[inline]
+    pub fn into_roi(self, roi: Rect) -> Result<Self, ImageError> {
+        let start = roi_offset::<P>(roi, self.width, self.height, self.stride)?;
+        Ok(Self {
+            data: self.data.get_mut(start..).unwrap_or_default(),
+            width: roi.width,
+            height: roi.height,
+            stride: self.stride,
+        })
+    }
+
+    /// Sets every pixel in this view to the given value.
+    #[inline]
+    pub fn fill(&mut self, value: P) {
+        for row in self.rows_mut() {
+            row.fill(value);
+        }
+    }
+
+    /// Copies every pixel from another view of the same size.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the views have different dimensions.
+    #[inline]
+    pub fn copy_from(&mut self, src: &ImageView<'_, P>) -> Result<(), ImageError> {
+        if src.dimensions() != self.dimensions() {
+            return Err(ImageError::DimensionMismatch {
+                src_width: src.width(),
+                src_height: src.height(),
+                dst_width: self.width,
+                dst_height: self.height,
+            });
+        }
+
+        for (dst, src_row) in self.rows_mut().zip(src.rows()) {
+            dst.copy_from_slice(src_row);
+        }
+        Ok(())
+    }
+
+    /// Copies this view into a new, contiguous image.
+    #[inline]
+    pub fn to_image(&self) -> Image<P> {
+        self.as_view().to_image()
+    }
+}
+
+impl<'img, P: Pixel> From<ImageViewMut<'img, P>> for ImageView<'img, P> {
+    #[inline]
+    fn from(value: ImageViewMut<'img, P>) -> Self {
+        Self {
+            data: value.data,
+            width: value.width,
+            height: value.height,
+            stride: value.stride,
+        }
+    }
+}
+
+/// Checks that the given memory can hold an image with this layout, then
+/// trims off anything past the last row.
+fn checked_region<P: Pixel>(
+    data: &[P::Channel],
+    width: u32,
+    height: u32,
+    stride: usize,
+) -> Result<&[P::Channel], ImageError> {
+    let row_len = row_len::<P>(width);
+    if stride < row_len {
+        return Err(ImageError::StrideTooShort { stride, row_len });
+    }
+
+    let needed = required_len(row_len, height, stride);
+    data.get(..needed).ok_or(ImageError::BufferTooSmall {
+        needed,
+        got: data.len(),
+    })
+}
+
+/// Finds where a region of interest starts, in channels.
+const fn roi_offset<P: Pixel>(
+    roi: Rect,
+    width: u32,
+    height: u32,
+    stride: usize,
+) -> Result<usize, ImageError> {
+    if !roi.fits_within(width, height) {
+        return Err(ImageError::RoiOutOfBounds { roi, width, height });
+    }
+
+    Ok(roi.y as usize * stride + roi.x as usize * P::CHANNELS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Rgb;
+
+    /// A 4x3 RGB image with two channels of padding at the end of each row.
+    fn padded_rgb() -> Vec<u8> {
+        let mut data = Vec::new();
+        for y in 0_u8..3 {
+            for x in 0_u8..4 {
+                data.extend_from_slice(&[x, y, 7]);
+            }
+            data.extend_from_slice(&[0xAA, 0xAA]);
+        }
+        data
+    }
+
+    #[test]
+    fn strided_rows_skip_padding() {
+        let data = padded_rgb();
+        let view = ImageView::<Rgb<u8>>::from_slice(&data, 4, 3, 14).unwrap();
+
+        assert!(!view.is_contiguous(), "rows have padding between them");
+        assert_eq!(view.rows().count(), 3, "one entry per row");
+        assert!(
+            view.pixels().all(|px| px.0[2] == 7),
+            "padding bytes should never show up as pixels"
+        );
+        assert_eq!(view.get(3, 2), Some(&Rgb([3, 2, 7])), "last pixel");
+        assert_eq!(view.get(4, 0), None, "past the end of a row");
+    }
+
+    #[test]
+    fn roi_is_zero_copy_and_nests() {
+        let data = padded_rgb();
+        let view = ImageView::<Rgb<u8>>::from_slice(&data, 4, 3, 14).unwrap();
+
+        let roi = view.roi(Rect::new(1, 1, 3, 2)).unwrap();
+        assert_eq!(roi.dimensions(), (3, 2), "roi keeps its own size");
+        assert_eq!(roi.get(0, 0), Some(&Rgb([1, 1, 7])), "roi origin moves");
+        assert!(
+            core::ptr::eq(roi.get(0, 0).unwrap(), view.get(1, 1).unwrap()),
+            "roi should borrow the same memory"
+        );
+
+        let inner = roi.roi(Rect::new(2, 1, 1, 1)).unwrap();
+        assert_eq!(inner.get(0, 0), Some(&Rgb([3, 2, 7])), "nested roi");
+
+        assert_eq!(
+            view.roi(Rect::new(3, 0, 2, 1)),
+            Err(ImageError::RoiOutOfBounds {
+                roi: Rect::new(3, 0, 2, 1),
+                width: 4,
+                height: 3,
+            }),
+            "roi hangs off the right edge"
+        );
+    }
+
+    #[test]
+    fn mutable_roi_writes_through() {
+        let mut data = padded_rgb();
+        let mut view = ImageViewMut::<Rgb<u8>>::from_slice(&mut data, 4, 3, 14).unwrap();
+
+        view.roi_mut(Rect::new(2, 0, 2, 3))
+            .unwrap()
+            .fill(Rgb([9, 9, 9]));
+
+        assert_eq!(view.get(1, 1), Some(&Rgb([1, 1, 7])), "outside the roi");
+        assert_eq!(view.get(2, 1), Some(&Rgb([9, 9, 9])), "inside the roi");
+        assert_eq!(
+            data.get(12..14),
+            Some([0xAA, 0xAA].as_slice()),
+            "padding untouched"
+        );
+    }
+
+    #[test]
+    #[expect(
+        clippy::host_endian_bytes,
+        reason = "views read channels in the machine's own byte order"
+    )]
+    fn u16_views_from_bytes() {
+        /// Bytes that always start on a `u16` boundary.
+        #[repr(C, align(2))]
+        struct Aligned([u8; 14]);
+
+        let mut storage = Aligned([0; 14]);
+        for (chunk, px) in storage.0.chunks_exact_mut(2).zip(0_u16..) {
+            chunk.copy_from_slice(&px.to_ne_bytes());
+        }
+
+        let bytes = storage.0.get(..12).unwrap();
+        let view = ImageView::<u16>::from_bytes(bytes, 3, 2, 6).unwrap();
+        assert_eq!(view.get(2, 1), Some(&5), "last pixel");
+
+        let shifted = storage.0.get(1..13).unwrap();
+        assert_eq!(
+            ImageView::<u16>::from_bytes(shifted, 3, 2, 6).err(),
+            Some(ImageError::MisalignedBuffer { channel_size: 2 }),
+            "one byte in isn't on a `u16` boundary"
+        );
+
+        assert_eq!(
+            ImageView::<u16>::from_bytes(bytes, 3, 2, 7).err(),
+            Some(ImageError::StrideNotChannelAligned {
+                stride_bytes: 7,
+                channel_size: 2,
+            }),
+            "odd strides can't hold `u16` channels"
+        );
+    }
+
+    #[test]
+    fn short_buffers_are_rejected() {
+        let data = [0_u8; 11];
+        assert_eq!(
+            ImageView::<Rgb<u8>>::from_contiguous(&data, 2, 2).err(),
+            Some(ImageError::BufferTooSmall {
+                needed: 12,
+                got: 11
+            }),
+            "one channel short"
+        );
+    }
+}
@@ -0,0 +1,409 @@
+//! Software auto exposure.
+
+use crate::config::{VideoCaptureProperties, VideoCaptureProperty as Property};
+use crate::error::VideoCaptureAutoError as AutoError;
+use crate::frame::Frame;
+
+use super::stats::{FrameStatistics, MeteringRegion};
+use super::{AutoStatus, ConvergenceLimits, PropertyRange};
+
+/// The darkest brightness we'll divide by. Anything darker is treated as
+/// this, so black frames don't ask for infinite exposure.
+const DARKEST: f32 = 1.0 / 255.0;
+
+/// How a frame's brightness is judged.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ExposureMetering {
+    /// The average brightness.
+    #[default]
+    Average,
+    /// The brightness that this fraction of pixels are at or below. Using a
+    /// high percentile, like `0.95`, keeps highlights from blowing out.
+    Percentile(f32),
+}
+
+/// Settings for [`AutoExposure`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct AutoExposureOptions {
+    /// The brightness to aim for, from zero (black) to one (white).
+    pub target: f32,
+    /// How to judge each frame's brightness.
+    pub metering: ExposureMetering,
+    /// The part of each frame to measure.
+    pub region: MeteringRegion,
+    /// The exposure time property, and the values it may take.
+    pub exposure: PropertyRange,
+    /// An optional gain property, used once exposure is at its limit.
+    ///
+    /// Gain is assumed to brighten frames in proportion to its value, just
+    /// like exposure.
+    pub gain: Option<PropertyRange>,
+    /// Properties that turn off the device's own auto exposure. They're set
+    /// once, before the first adjustment.
+    ///
+    /// UVC cameras usually want `auto_exposure=1`, which is manual mode.
+    pub manual_mode: Vec<Property>,
+    /// How quickly to converge, and what counts as close enough.
+    pub limits: ConvergenceLimits,
+}
+
+impl Default for AutoExposureOptions {
+    /// Aims a little below middle gray, using UVC's exposure property (in
+    /// 100 µs units) up to one frame at 30 FPS. There's no gain.
+    #[inline]
+    fn default() -> Self {
+        Self {
+            target: 0.45,
+            metering: ExposureMetering::default(),
+            region: MeteringRegion::default(),
+            exposure: PropertyRange::new("exposure_time_absolute", 1, 333),
+            gain: None,
+            manual_mode: Vec::new(),
+            limits: ConvergenceLimits::default(),
+        }
+    }
+}
+
+/// Adjusts a device's exposure (and gain) until frames reach a target
+/// brightness.
+///
+/// When frames are too dark, exposure goes up first, then gain. When
+/// they're too bright, gain comes down first, then exposure. That keeps
+/// noise low whenever there's enough light.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AutoExposure {
+    options: AutoExposureOptions,
+    exposure: Option<i64>,
+    gain: Option<i64>,
+    settle_frames: u32,
+    in_control: bool,
+}
+
+impl AutoExposure {
+    /// Makes a controller with the given settings.
+    #[inline]
+    #[must_use]
+    pub const fn new(options: AutoExposureOptions) -> Self {
+        Self {
+            options,
+            exposure: None,
+            gain: None,
+            settle_frames: 0,
+            in_control: false,
+        }
+    }
+
+    /// The controller's settings.
+    #[inline]
+    pub const fn options(&self) -> &AutoExposureOptions {
+        &self.options
+    }
+
+    /// Measures a frame inside the controller's metering region.
+    ///
+    /// # Errors
+    ///
+    /// This fails like [`FrameStatistics::measure`].
+    #[inline]
+    pub fn measure(&self, frame: &Frame<'_>) -> Result<FrameStatistics, AutoError> {
+        FrameStatistics::measure(frame, self.options.region)
+    }
+
+    /// How bright a frame is, according to the controller's metering.
+    #[inline]
+    pub fn brightness(&self, stats: &FrameStatistics) -> f32 {
+        match self.options.metering {
+            ExposureMetering::Average => stats.mean_luma(),
+            ExposureMetering::Percentile(fraction) => stats.percentile(fraction),
+        }
+    }
+
+    /// Looks at a frame's statistics, then adjusts the device if it needs
+    /// to.
+    ///
+    /// The exposure and gain are read from the device the first time they're
+    /// needed. After that, the controller remembers what it set them to.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the device doesn't have the properties, or won't take
+    /// new values for them.
+    #[inline]
+    pub fn update<D: VideoCaptureProperties + ?Sized>(
+        &mut self,
+        device: &mut D,
+        stats: &FrameStatistics,
+    ) -> Result<AutoStatus, AutoError> {
+        if super::settling(&mut self.settle_frames) {
+            return Ok(AutoStatus::Settling);
+        }
+
+        let brightness = self.brightness(stats);
+        let limits = self.options.limits;
+        if (brightness - self.options.target).abs() <= limits.tolerance {
+            return Ok(AutoStatus::Converged);
+        }
+
+        super::take_control(device, &self.options.manual_mode, &mut self.in_control)?;
+        let exposure = super::read_value(device, &self.options.exposure.key, self.exposure)?;
+        self.exposure = Some(exposure);
+        let gain = match self.options.gain {
+            Some(ref range) => Some(super::read_value(device, &range.key, self.gain)?),
+            None => None,
+        };
+        self.gain = gain;
+
+        let ratio = limits.step(self.options.target / brightness.max(DARKEST));
+        let (new_exposure, new_gain) = self.split(ratio, exposure, gain);
+        if new_exposure == exposure && new_gain == gain {
+            return Ok(AutoStatus::Limited);
+        }
+
+        if new_exposure != exposure {
+            super::write_value(device, &self.options.exposure.key, new_exposure)?;
+            self.exposure = Some(new_exposure);
+        }
+        if let (Some(range), Some(value)) = (self.options.gain.as_ref(), new_gain) {
+            if new_gain != gain {
+                super::write_value(device, &range.key, value)?;
+                self.gain = Some(value);
+            }
+        }
+
+        tracing::trace!("auto exposure: brightness {brightness:.3}, exposure {exposure} -> {new_exposure}, gain {gain:?} -> {new_gain:?}");
+        self.settle_frames = limits.settle_frames;
+        Ok(AutoStatus::Adjusted)
+    }
+
+    /// Forgets what the controller knows about the device, like after it
+    /// reconnects or someone else changes its properties.
+    #[inline]
+    pub const fn reset(&mut self) {
+        self.exposure = None;
+        self.gain = None;
+        self.settle_frames = 0;
+        self.in_control = false;
+    }
+
+    /// Splits a brightness change between exposure and gain.
+    fn split(&self, ratio: f32, exposure: i64, gain: Option<i64>) -> (i64, Option<i64>) {
+        let range = &self.options.exposure;
+
+        match (self.options.gain.as_ref(), gain) {
+            // brighten with exposure, then gain
+            (Some(gain_range), Some(gain_value)) if ratio > 1.0 => {
+                let (new_exposure, left_over) = range.scale(exposure, ratio);
+                let new_gain = if left_over > 1.0 {
+                    gain_range.scale(gain_value, left_over).0
+                } else {
+                    gain_value
+                };
+                (new_exposure, Some(new_gain))
+            }
+            // darken with gain, then exposure
+            (Some(gain_range), Some(gain_value)) => {
+                let (new_gain, left_over) = gain_range.scale(gain_value, ratio);
+                let new_exposure = if left_over < 1.0 {
+                    range.scale(exposure, left_over).0
+                } else {
+                    exposure
+                };
+                (new_exposure, Some(new_gain))
+            }
+            _ => (range.scale(exposure, ratio).0, gain),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{Format, SpecificResolution};
+    use crate::error::VideoCaptureConfigError as ConfigError;
+    use crate::frame::{FrameMetadata, FramePlanes};
+
+    use super::*;
+
+    /// A camera looking at a flat, dim scene.
+    struct FakeCamera {
+        exposure: i64,
+        gain: i64,
+        manual: bool,
+    }
+
+    impl FakeCamera {
+        #[expect(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            reason = "the brightness is clamped to a byte"
+        )]
+        fn capture(&self) -> Vec<u8> {
+            let brightness = 0.6 * self.exposure as f32 * (1.0 + self.gain as f32 / 10.0);
+            vec![brightness.clamp(0.0, 255.0) as u8; 32 * 32]
+        }
+    }
+
+    impl VideoCaptureProperties for FakeCamera {
+        fn properties(&self) -> Vec<Property> {
+            vec![
+                Property::new("exposure_time_absolute".into(), self.exposure.to_string()),
+                Property::new("gain".into(), self.gain.to_string()),
+            ]
+        }
+
+        fn property(&self, key: String) -> Option<Property> {
+            self.properties().into_iter().find(|p| p.key() == key)
+        }
+
+        fn set_property(&mut self, property: &Property) -> Result<(), ConfigError> {
+            let value = property.value().parse().unwrap();
+            match property.key().as_str() {
+                "exposure_time_absolute" => self.exposure = value,
+                "gain" => self.gain = value,
+                "auto_exposure" => self.manual = value == 1,
+                other => panic!("unexpected property `{other}`"),
+            }
+            Ok(())
+        }
+    }
+
+    fn stats(data: &[u8]) -> FrameStatistics {
+        let frame = Frame {
+            data,
+            format: Format::GREY,
+            resolution: SpecificResolution::new(32, 32),
+            stride: 32,
+            planes: FramePlanes::single(data, 32),
+            metadata: FrameMetadata::default(),
+        };
+        FrameStatistics::measure(&frame, MeteringRegion::FULL).unwrap()
+    }
+
+    #[test]
+    fn exposure_converges_then_falls_back_to_gain() {
+        let mut camera = FakeCamera {
+            exposure: 10,
+            gain: 0,
+            manual: false,
+        };
+        let mut controller = AutoExposure::new(AutoExposureOptions {
+            exposure: PropertyRange::new("exposure_time_absolute", 1, 333),
+            gain: Some(PropertyRange::new("gain", 0, 100)),
+            manual_mode: vec![Property::new("auto_exposure".into(), "1")],
+            ..AutoExposureOptions::default()
+        });
+
+        let mut last = AutoStatus::Settling;
+        for _ in 0..60 {
+            let frame_stats = stats(&camera.capture());
+            last = controller.update(&mut camera, &frame_stats).unwrap();
+        }
+        let brightness = controller.brightness(&stats(&camera.capture()));
+        assert_eq!(last, AutoStatus::Converged, "brightness is {brightness}");
+        assert!(camera.manual, "the camera's auto exposure was turned off");
+        assert_eq!(camera.gain, 0, "no gain is needed in this light");
+
+        // aiming brighter runs out of exposure, so gain takes over
+        camera.exposure = 333;
+        let mut bright = AutoExposure::new(AutoExposureOptions {
+            target: 0.9,
+            ..controller.options().clone()
+        });
+        for _ in 0..60 {
+            let frame_stats = stats(&camera.capture());
+            bright.update(&mut camera, &frame_stats).unwrap();
+        }
+        assert_eq!(camera.exposure, 333, "exposure stays maxed out");
+        assert!(camera.gain > 0, "gain made up the difference");
+    }
+
+    #[test]
+    fn black_and_white_frames_move_one_step_at_a_time() {
+        let mut camera = FakeCamera {
+            exposure: 10,
+            gain: 0,
+            manual: false,
+        };
+        let mut controller = AutoExposure::new(AutoExposureOptions {
+            exposure: PropertyRange::new("exposure_time_absolute", 1, 40),
+            ..AutoExposureOptions::default()
+        });
+
+        let black = stats(&[0; 32 * 32]);
+        assert_eq!(
+            controller.update(&mut camera, &black).unwrap(),
+            AutoStatus::Adjusted
+        );
+        assert_eq!(camera.exposure, 20, "doubled, not divided by zero");
+
+        let white = stats(&[u8::MAX; 32 * 32]);
+        controller.reset();
+        assert_eq!(
+            controller.update(&mut camera, &white).unwrap(),
+            AutoStatus::Adjusted
+        );
+        assert!(
+            (10..20).contains(&camera.exposure),
+            "at most halved: {}",
+            camera.exposure
+        );
+
+        // there's no gain to fall back on
+        camera.exposure = 40;
+        controller.reset();
+        assert_eq!(
+            controller.update(&mut camera, &black).unwrap(),
+            AutoStatus::Limited
+        );
+        assert_eq!(camera.exposure, 40);
+    }
+
+    #[test]
+    fn exposure_settles_without_oscillating() {
+        let mut camera = FakeCamera {
+            exposure: 300,
+            gain: 0,
+            manual: false,
+        };
+        // no damping, and no frames to settle, is as jumpy as it gets
+        let mut controller = AutoExposure::new(AutoExposureOptions {
+            limits: ConvergenceLimits {
+                tolerance: 0.01,
+                damping: 1.0,
+                settle_frames: 0,
+                ..ConvergenceLimits::default()
+            },
+            ..AutoExposureOptions::default()
+        });
+
+        let mut exposures = vec![camera.exposure];
+        let mut statuses = Vec::new();
+        for _ in 0..40 {
+            let frame_stats = stats(&camera.capture());
+            statuses.push(controller.update(&mut camera, &frame_stats).unwrap());
+            exposures.push(camera.exposure);
+        }
+
+        let directions: Vec<i64> = exposures
+            .iter()
+            .zip(exposures.iter().skip(1))
+            .map(|(before, after)| after.saturating_sub(*before).signum())
+            .collect();
+        let reversals = directions
+            .iter()
+            .zip(directions.iter().skip(1))
+            .filter(|&(first, second)| first.saturating_mul(*second) < 0)
+            .count();
+        assert!(
+            reversals <= 1,
+            "exposure went back and forth: {exposures:?}"
+        );
+        assert!(
+            statuses
+                .iter()
+                .skip(10)
+                .all(|&status| status == AutoStatus::Converged),
+            "it stays converged: {statuses:?}"
+        );
+    }
+}
@@ -0,0 +1,277 @@
+//! Software auto exposure and auto white balance.
+//!
+//! Some cameras, especially industrial ones, can't adjust themselves (or do
+//! it badly). These controllers measure each frame, then nudge the device's
+//! properties through [`VideoCaptureProperties`] until the picture looks
+//! right.
+//!
+//! A frame borrows its device, so each update happens in two steps: measure
+//! the frame, then let go of it and update the device.
+//!
+//! ```no_run
+//! use serumcv_video_capture::auto::{AutoExposure, AutoExposureOptions, MeteringRegion};
+//! use serumcv_video_capture::backends::v4l::V4LVideoCaptureDevice;
+//! use serumcv_video_capture::config::VideoCaptureProperty;
+//! use serumcv_video_capture::{VideoCaptureConnection as _, VideoCaptureStream as _};
+//!
+//! let mut camera = V4LVideoCaptureDevice::new("/dev/video0".into()).unwrap();
+//! let mut exposure = AutoExposure::new(AutoExposureOptions {
+//!     // only look at the middle of the picture
+//!     region: MeteringRegion::centered(0.5),
+//!     // turn off the camera's own auto exposure
+//!     manual_mode: vec![VideoCaptureProperty::new("auto_exposure".into(), "1")],
+//!     ..AutoExposureOptions::default()
+//! });
+//!
+//! loop {
+//!     let stats = exposure.measure(&camera.read_frame().unwrap()).unwrap();
+//!     let status = exposure.update(&mut camera, &stats).unwrap();
+//!     println!("brightness {:.2}: {status:?}", stats.mean_luma());
+//! }
+//! ```
+
+mod exposure;
+mod stats;
+mod white_balance;
+
+pub use exposure::{AutoExposure, AutoExposureOptions, ExposureMetering};
+pub use stats::{FrameStatistics, MeteringRegion};
+pub use white_balance::{AutoWhiteBalance, AutoWhiteBalanceOptions, WhiteBalanceControls};
+
+use crate::config::{VideoCaptureProperties, VideoCaptureProperty as Property};
+use crate::error::VideoCaptureAutoError as AutoError;
+
+/// What a controller did with the latest frame.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AutoStatus {
+    /// It's waiting for its last change to show up in the frames.
+    Settling,
+    /// It changed the device's properties.
+    Adjusted,
+    /// The frame is already close enough to the target.
+    Converged,
+    /// The frame is off, but every property is already at its limit.
+    Limited,
+    /// The frame is too dark to judge.
+    NoSignal,
+}
+
+/// How quickly a controller moves, and when it stops.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConvergenceLimits {
+    /// How far off a frame can be and still count as converged.
+    ///
+    /// For exposure, this is in brightness, from zero to one. For white
+    /// balance, it's the relative difference between color channels.
+    pub tolerance: f32,
+    /// The most that one update can scale a property by, in either
+    /// direction. Values below one are treated as one, which freezes the
+    /// controller.
+    pub max_step: f32,
+    /// How much of each correction to apply, from zero to one. Lower values
+    /// converge slower, but don't overshoot as much.
+    pub damping: f32,
+    /// How many frames to skip after a change, while the device catches up.
+    pub settle_frames: u32,
+}
+
+impl Default for ConvergenceLimits {
+    /// Within 4%, at most doubling or halving each update, skipping two
+    /// frames after each change.
+    #[inline]
+    fn default() -> Self {
+        Self {
+            tolerance: 0.04,
+            max_step: 2.0,
+            damping: 0.7,
+            settle_frames: 2,
+        }
+    }
+}
+
+impl ConvergenceLimits {
+    /// Turns the correction a frame asks for into the one we'll apply.
+    fn step(&self, wanted: f32) -> f32 {
+        let max_step = self.max_step.max(1.0);
+        wanted
+            .max(f32::MIN_POSITIVE)
+            .powf(self.damping.clamp(0.0, 1.0))
+            .clamp(max_step.recip(), max_step)
+    }
+}
+
+/// A numeric property and the values a controller may give it.
+///
+/// Devices don't say what their properties' limits are, so these have to
+/// come from you (or from `v4l2-ctl --list-ctrls`).
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PropertyRange {
+    /// The property's key, like `exposure_time_absolute`.
+    pub key: String,
+    /// The lowest value to use.
+    pub min: i64,
+    /// The highest value to use.
+    pub max: i64,
+}
+
+impl PropertyRange {
+    /// Makes a range for the property `key`.
+    #[inline]
+    #[must_use]
+    pub fn new(key: &str, min: i64, max: i64) -> Self {
+        Self {
+            key: key.to_owned(),
+            min,
+            max,
+        }
+    }
+
+    /// Scales a value by `ratio`, staying within the range.
+    ///
+    /// Values are treated as if they're at least one, so a value of zero
+    /// can still grow. When rounding would leave the value where it was,
+    /// it moves by one instead. If the range runs out, this also returns how
+    /// much of `ratio` is left over for another property. Otherwise, that's
+    /// one.
+    #[expect(
+        clippy::cast_possible_truncation,
+        reason = "the result is clamped to the range, so it always fits"
+    )]
+    fn scale(&self, value: i64, ratio: f32) -> (i64, f32) {
+        let (low, high) = (self.min.min(self.max), self.max.max(self.min));
+        let base = value.max(1) as f32;
+        let mut scaled = ((base * ratio).round() as i64).clamp(low, high);
+        if ratio > 1.0 {
+            scaled = scaled.max(value);
+        } else {
+            scaled = scaled.min(value);
+        }
+
+        // small values can't move by less than one
+        if scaled == value && ratio > 1.0 && value < high {
+            scaled = value.saturating_add(1);
+        } else if scaled == value && ratio < 1.0 && value > low {
+            scaled = value.saturating_sub(1);
+        }
+
+        if scaled != low && scaled != high {
+            return (scaled, 1.0);
+        }
+        let moved = scaled.max(1) as f32 / base;
+        (scaled, ratio / moved)
+    }
+}
+
+/// Sets the properties that turn off a device's own controls, the first
+/// time this is called.
+fn take_control<D: VideoCaptureProperties + ?Sized>(
+    device: &mut D,
+    manual_mode: &[Property],
+    done: &mut bool,
+) -> Result<(), AutoError> {
+    if !*done {
+        for property in manual_mode {
+            device.set_property(property)?;
+        }
+        *done = true;
+    }
+
+    Ok(())
+}
+
+/// Reads a numeric property, unless we already know what it is.
+fn read_value<D: VideoCaptureProperties + ?Sized>(
+    device: &D,
+    key: &str,
+    known: Option<i64>,
+) -> Result<i64, AutoError> {
+    if let Some(value) = known {
+        return Ok(value);
+    }
+
+    let property = device
+        .property(key.to_owned())
+        .ok_or_else(|| AutoError::PropertyMissing {
+            key: key.to_owned(),
+        })?;
+    property
+        .value()
+        .trim()
+        .parse()
+        .ok()
+        .ok_or_else(|| AutoError::PropertyNotNumeric {
+            key: key.to_owned(),
+            value: property.value(),
+        })
+}
+
+/// Writes a numeric property.
+fn write_value<D: VideoCaptureProperties + ?Sized>(
+    device: &mut D,
+    key: &str,
+    value: i64,
+) -> Result<(), AutoError> {
+    device.set_property(&Property::new(key.to_owned(), value.to_string()))?;
+    Ok(())
+}
+
+/// Counts down the frames left to skip. Returns `true` while skipping.
+const fn settling(frames_left: &mut u32) -> bool {
+    if *frames_left == 0 {
+        return false;
+    }
+
+    *frames_left -= 1;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_scale_within_their_limits() {
+        let range = PropertyRange::new("exposure_time_absolute", 1, 100);
+
+        assert_eq!(range.scale(10, 2.0), (20, 1.0));
+        let (clamped, left_over) = range.scale(80, 2.0);
+        assert_eq!(clamped, 100, "stays under the max");
+        assert!((left_over - 1.6).abs() < 1e-6, "the rest is left over");
+
+        assert_eq!(range.scale(3, 1.1).0, 4, "small values still move");
+        assert_eq!(range.scale(0, 3.0).0, 3, "zero can grow");
+        assert_eq!(range.scale(1, 0.5).0, 1, "stays over the min");
+        let gain = PropertyRange::new("gain", 0, 100);
+        assert_eq!(gain.scale(0, 0.5), (0, 0.5), "zero doesn't round up");
+
+        let limits = ConvergenceLimits {
+            damping: 1.0,
+            ..ConvergenceLimits::default()
+        };
+        assert!((limits.step(8.0) - 2.0).abs() < 1e-6, "steps are capped");
+        assert!((limits.step(0.0) - 0.5).abs() < 1e-6, "in both directions");
+    }
+
+    #[test]
+    fn small_steps_freeze_and_settling_runs_out() {
+        let frozen = ConvergenceLimits {
+            max_step: 0.5,
+            ..ConvergenceLimits::default()
+        };
+        assert!(
+            (frozen.step(8.0) - 1.0).abs() < 1e-6,
+            "steps below one freeze"
+        );
+        assert!((frozen.step(0.0) - 1.0).abs() < 1e-6);
+
+        let backwards = PropertyRange::new("gain", 100, 0);
+        assert_eq!(backwards.scale(90, 2.0).0, 100, "swapped limits still work");
+
+        let mut frames_left = 2;
+        assert!(settling(&mut frames_left));
+        assert!(settling(&mut frames_left));
+        assert!(!settling(&mut frames_left), "then it's done");
+        assert_eq!(frames_left, 0);
+    }
+}
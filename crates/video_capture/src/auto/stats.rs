@@ -0,0 +1,559 @@
+//! Measuring how bright (and how colorful) a frame is.
+
+use serumcv_image::{ImageView, Pixel, PlanarLayout, Rect};
+
+use crate::error::{VideoCaptureAutoError as AutoError, VideoCaptureFrameError as FrameError};
+use crate::frame::Frame;
+
+/// The most pixels we'll look at in one frame. Bigger regions are sampled
+/// on a grid instead.
+const MAX_SAMPLES: u64 = 1 << 16;
+
+/// The chroma value for "no color".
+const NEUTRAL_CHROMA: f32 = 128.0;
+
+/// The part of a frame to measure, as fractions of its width and height.
+///
+/// Fractions keep working when the device changes resolution.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeteringRegion {
+    /// The left edge, from zero to one.
+    pub x: f32,
+    /// The top edge, from zero to one.
+    pub y: f32,
+    /// The width, from zero to one.
+    pub width: f32,
+    /// The height, from zero to one.
+    pub height: f32,
+}
+
+impl MeteringRegion {
+    /// The whole frame.
+    pub const FULL: Self = Self::new(0.0, 0.0, 1.0, 1.0);
+
+    /// Makes a region from its top-left corner and size.
+    #[inline]
+    #[must_use]
+    pub const fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// A region in the middle of the frame, covering `fraction` of its width
+    /// and height.
+    #[inline]
+    #[must_use]
+    pub fn centered(fraction: f32) -> Self {
+        let size = fraction.clamp(0.0, 1.0);
+        let edge = (1.0 - size) / 2.0;
+        Self::new(edge, edge, size, size)
+    }
+
+    /// The pixels this region covers in a frame of the given size.
+    ///
+    /// The region is clipped to the frame. It covers at least one pixel,
+    /// unless the frame is empty.
+    #[inline]
+    pub fn to_rect(self, width: u32, height: u32) -> Rect {
+        let x = fraction_of(self.x, width).min(width.saturating_sub(1));
+        let y = fraction_of(self.y, height).min(height.saturating_sub(1));
+        let w = fraction_of(self.width, width).clamp(1, width.saturating_sub(x).max(1));
+        let h = fraction_of(self.height, height).clamp(1, height.saturating_sub(y).max(1));
+
+        if width == 0 || height == 0 {
+            return Rect::default();
+        }
+        Rect::new(x, y, w, h)
+    }
+}
+
+impl Default for MeteringRegion {
+    /// The whole frame.
+    #[inline]
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+/// Brightness and color statistics for part of a frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameStatistics {
+    histogram: [u32; 256],
+    samples: u32,
+    rgb_means: Option<[f32; 3]>,
+}
+
+impl FrameStatistics {
+    /// Measures the part of `frame` inside `region`.
+    ///
+    /// This works with uncompressed frames: gray, RGB, and both packed and
+    /// planar YUV. Large regions are sampled on an even grid, so this stays
+    /// quick at any resolution.
+    ///
+    /// # Errors
+    ///
+    /// This fails for compressed formats, frames that are shorter than their
+    /// resolution says, and regions that don't cover any pixels.
+    #[inline]
+    pub fn measure(frame: &Frame<'_>, region: MeteringRegion) -> Result<Self, AutoError> {
+        let rect = region.to_rect(frame.resolution.width, frame.resolution.height);
+        if rect.is_empty() {
+            return Err(AutoError::EmptyRegion);
+        }
+        let step = sample_step(rect);
+        let mut sums = Sums::default();
+
+        let color = match &frame.format.array() {
+            b"GREY" => {
+                sample_packed::<u8, _>(frame, rect, step, |&luma| sums.add(luma, [0; 3]))?;
+                Color::None
+            }
+            // little-endian, so the second byte is the most significant
+            b"Y16 " => {
+                sample_packed::<[u8; 2], _>(frame, rect, step, |&[_, high]| {
+                    sums.add(high, [0; 3]);
+                })?;
+                Color::None
+            }
+            b"RGB3" => {
+                sample_packed::<[u8; 3], _>(frame, rect, step, |&[r, g, b]| {
+                    sums.add(luma_of(r, g, b), [r, g, b]);
+                })?;
+                Color::Rgb
+            }
+            b"BGR3" => {
+                sample_packed::<[u8; 3], _>(frame, rect, step, |&[b, g, r]| {
+                    sums.add(luma_of(r, g, b), [r, g, b]);
+                })?;
+                Color::Rgb
+            }
+            b"YUYV" => {
+                sample_packed_422(frame, rect, step, [0, 1, 2, 3], &mut sums)?;
+                Color::Yuv
+            }
+            b"UYVY" => {
+                sample_packed_422(frame, rect, step, [1, 0, 3, 2], &mut sums)?;
+                Color::Yuv
+            }
+            _ if frame.format.planar_layout().is_some() => {
+                sample_planar(frame, rect, step, &mut sums)?;
+                Color::Yuv
+            }
+            _ => {
+                return Err(AutoError::UnsupportedFormat {
+                    format: frame.format,
+                })
+            }
+        };
+
+        if sums.samples == 0 {
+            return Err(AutoError::EmptyRegion);
+        }
+
+        Ok(Self {
+            histogram: sums.histogram,
+            samples: sums.samples,
+            rgb_means: sums.rgb_means(color),
+        })
+    }
+
+    /// How many samples had each luma value, from black to white.
+    #[inline]
+    pub const fn histogram(&self) -> &[u32; 256] {
+        &self.histogram
+    }
+
+    /// How many pixels were measured.
+    #[inline]
+    pub const fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// The average brightness, from zero (black) to one (white).
+    #[inline]
+    pub fn mean_luma(&self) -> f32 {
+        let total: u64 = (0_u64..)
+            .zip(&self.histogram)
+            .map(|(luma, &count)| luma * u64::from(count))
+            .sum();
+        to_unit(total as f32 / self.samples.max(1) as f32)
+    }
+
+    /// The brightness that `fraction` of the samples are at or below, from
+    /// zero to one.
+    ///
+    /// For example, `percentile(0.5)` is the median, and `percentile(0.95)`
+    /// is how bright the highlights are.
+    #[inline]
+    pub fn percentile(&self, fraction: f32) -> f32 {
+        let wanted = self.samples as f32 * fraction.clamp(0.0, 1.0);
+        let mut seen = 0_u32;
+        for (luma, &count) in (0_u16..).zip(&self.histogram) {
+            seen = seen.saturating_add(count);
+            if seen as f32 >= wanted && seen > 0 {
+                return to_unit(f32::from(luma));
+            }
+        }
+
+        1.0
+    }
+
+    /// The fraction of samples that are pure white, from zero to one.
+    #[inline]
+    pub fn clipped(&self) -> f32 {
+        let white = self.histogram.last().copied().unwrap_or_default();
+        white as f32 / self.samples.max(1) as f32
+    }
+
+    /// The average red, green, and blue, each from zero to one.
+    ///
+    /// Gray frames don't have any color, so this is `None` for them.
+    #[inline]
+    pub const fn rgb_means(&self) -> Option<[f32; 3]> {
+        self.rgb_means
+    }
+}
+
+/// What kind of color a frame's samples had.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Color {
+    None,
+    Rgb,
+    Yuv,
+}
+
+/// Running totals while measuring a frame.
+#[derive(Debug)]
+struct Sums {
+    histogram: [u32; 256],
+    samples: u32,
+    /// Either RGB or YUV, depending on the frame.
+    channels: [u64; 3],
+}
+
+impl Default for Sums {
+    fn default() -> Self {
+        Self {
+            histogram: [0; 256],
+            samples: 0,
+            channels: [0; 3],
+        }
+    }
+}
+
+impl Sums {
+    fn add(&mut self, luma: u8, channels: [u8; 3]) {
+        if let Some(count) = self.histogram.get_mut(usize::from(luma)) {
+            *count = count.saturating_add(1);
+        }
+        self.samples = self.samples.saturating_add(1);
+        for (sum, channel) in self.channels.iter_mut().zip(channels) {
+            *sum = sum.saturating_add(u64::from(channel));
+        }
+    }
+
+    /// Averages the color channels, turning YUV into RGB.
+    ///
+    /// The YUV to RGB conversion is linear, so converting the averages gives
+    /// the same answer as averaging every converted pixel (ignoring
+    /// clipping).
+    fn rgb_means(&self, color: Color) -> Option<[f32; 3]> {
+        let samples = self.samples.max(1) as f32;
+        let [first, second, third] = self.channels.map(|sum| sum as f32 / samples);
+
+        match color {
+            Color::None => None,
+            Color::Rgb => Some([first, second, third].map(to_unit)),
+            Color::Yuv => {
+                // BT.601, full range
+                let (luma, cb, cr) = (first, second - NEUTRAL_CHROMA, third - NEUTRAL_CHROMA);
+                let r = 1.402_f32.mul_add(cr, luma);
+                let g = (-0.714_136_f32).mul_add(cr, (-0.344_136_f32).mul_add(cb, luma));
+                let b = 1.772_f32.mul_add(cb, luma);
+                Some([r, g, b].map(|channel| to_unit(channel).clamp(0.0, 1.0)))
+            }
+        }
+    }
+}
+
+/// Scales an 8-bit value to the range zero to one.
+fn to_unit(value: f32) -> f32 {
+    value / 255.0
+}
+
+/// A pixel's brightness, using the BT.601 weights.
+fn luma_of(r: u8, g: u8, b: u8) -> u8 {
+    let weighted = 77 * u32::from(r) + 150 * u32::from(g) + 29 * u32::from(b);
+    u8::try_from(weighted >> 8).unwrap_or(u8::MAX)
+}
+
+/// Scales a fraction of a frame's size into pixels.
+#[expect(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    reason = "the fraction is clamped to zero and one, so this fits in the dimension"
+)]
+fn fraction_of(fraction: f32, full: u32) -> u32 {
+    (fraction.clamp(0.0, 1.0) * full as f32).round() as u32
+}
+
+/// How far apart samples are, so a region has at most `MAX_SAMPLES`.
+fn sample_step(rect: Rect) -> usize {
+    let spacing = rect
+        .area()
+        .checked_div(MAX_SAMPLES)
+        .unwrap_or_default()
+        .isqrt();
+    usize::try_from(spacing).unwrap_or(usize::MAX).max(1)
+}
+
+/// Visits a grid of pixels in a packed frame.
+fn sample_packed<P, F>(
+    frame: &Frame<'_>,
+    rect: Rect,
+    step: usize,
+    mut visit: F,
+) -> Result<(), AutoError>
+where
+    P: Pixel,
+    F: FnMut(&P),
+{
+    let view: ImageView<'_, P> = frame.view()?;
+    let region = view.roi(rect).map_err(FrameError::from)?;
+    for row in region.rows().step_by(step) {
+        row.iter().step_by(step).for_each(&mut visit);
+    }
+
+    Ok(())
+}
+
+/// Visits a grid of pixels in a packed 4:2:2 frame, like `YUYV`.
+///
+/// `order` gives where Y0, U, Y1, and V are in each pair of pixels.
+fn sample_packed_422(
+    frame: &Frame<'_>,
+    rect: Rect,
+    step: usize,
+    order: [usize; 4],
+    sums: &mut Sums,
+) -> Result<(), AutoError> {
+    // start on a whole pair, so the chroma lines up
+    let aligned = Rect::new(rect.x & !1, rect.y, rect.width, rect.height);
+    let view = frame.view::<[u8; 2]>()?;
+    let region = view.roi(aligned).map_err(FrameError::from)?;
+
+    for row in region.rows().step_by(step) {
+        for pair in row.chunks(2).step_by(step.div_ceil(2)) {
+            let bytes = pair.as_flattened();
+            let sample = |index: usize| order.get(index).and_then(|&at| bytes.get(at)).copied();
+            let (u, v) = (sample(1).unwrap_or(128), sample(3).unwrap_or(128));
+
+            // odd widths end with half a pair
+            for luma in [sample(0), sample(2)]
+                .into_iter()
+                .take(pair.len())
+                .flatten()
+            {
+                sums.add(luma, [luma, u, v]);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Visits a grid of pixels in a planar YUV frame.
+fn sample_planar(
+    frame: &Frame<'_>,
+    rect: Rect,
+    step: usize,
+    sums: &mut Sums,
+) -> Result<(), AutoError> {
+    let view = frame.planar_view::<u8>()?;
+    let layout = view.layout();
+    let unsupported = AutoError::UnsupportedFormat {
+        format: frame.format,
+    };
+
+    // where to find each chroma sample: (interleaved, V before U)
+    let (interleaved, swapped) = match layout {
+        PlanarLayout::Nv12 => (true, false),
+        PlanarLayout::Nv21 => (true, true),
+        PlanarLayout::I420 | PlanarLayout::Yuv422p | PlanarLayout::Yuv444p => (false, false),
+        PlanarLayout::Yv12 => (false, true),
+        _ => return Err(unsupported),
+    };
+    let (Some(luma_plane), Some(first)) = (view.plane(0), view.plane(1)) else {
+        return Err(unsupported);
+    };
+    let second = view.plane(2);
+    let (horizontal, vertical) = layout.subsampling();
+
+    let rows = rect.y..rect.y.saturating_add(rect.height);
+    let columns = rect.x..rect.x.saturating_add(rect.width);
+    for y in rows.step_by(step) {
+        let Some(luma_row) = luma_plane.row(y) else {
+            continue;
+        };
+        let chroma_y = y.checked_div(vertical).unwrap_or_default();
+        let first_row = first.row(chroma_y).unwrap_or_default();
+        let second_row = second
+            .and_then(|plane| plane.row(chroma_y))
+            .unwrap_or_default();
+
+        for x in columns.clone().step_by(step) {
+            let Some(&luma) = luma_row.get(x as usize) else {
+                continue;
+            };
+            let chroma_x = x.checked_div(horizontal).unwrap_or_default() as usize;
+            let (u, v) = if interleaved {
+                let at = chroma_x.saturating_mul(2);
+                (first_row.get(at), first_row.get(at.saturating_add(1)))
+            } else {
+                (first_row.get(chroma_x), second_row.get(chroma_x))
+            };
+            let (cb, cr) = if swapped { (v, u) } else { (u, v) };
+
+            sums.add(
+                luma,
+                [luma, cb.copied().unwrap_or(128), cr.copied().unwrap_or(128)],
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Format;
+
+    use super::*;
+
+    #[test]
+    fn statistics_follow_the_metering_region() {
+        // a dark left half and a bright right half
+        let gray: Vec<u8> = (0_u32..16 * 8)
+            .map(|index| if index % 16 < 8 { 20 } else { 220 })
+            .collect();
+        let gray_frame = Frame::packed_for_test(&gray, Format::GREY, 16, 8, 16);
+
+        let whole = FrameStatistics::measure(&gray_frame, MeteringRegion::FULL).unwrap();
+        assert_eq!(whole.samples(), 128);
+        assert!(
+            (whole.mean_luma() - 120.0 / 255.0).abs() < 1e-4,
+            "average of both halves"
+        );
+        assert!((whole.percentile(0.25) - 20.0 / 255.0).abs() < 1e-4);
+        assert!((whole.percentile(0.75) - 220.0 / 255.0).abs() < 1e-4);
+        assert_eq!(whole.rgb_means(), None, "gray frames have no color");
+
+        let right = MeteringRegion::new(0.5, 0.0, 0.5, 1.0);
+        let bright = FrameStatistics::measure(&gray_frame, right).unwrap();
+        assert!(
+            (bright.mean_luma() - 220.0 / 255.0).abs() < 1e-4,
+            "only the right half"
+        );
+
+        // a reddish gray, as YUYV: Y0 U Y1 V
+        let yuyv: Vec<u8> = [100, 128, 100, 160].repeat(4 * 4);
+        let yuyv_frame = Frame::packed_for_test(&yuyv, Format::YUYV, 8, 4, 16);
+        let [r, g, b] = FrameStatistics::measure(&yuyv_frame, MeteringRegion::FULL)
+            .unwrap()
+            .rgb_means()
+            .unwrap();
+        assert!(
+            r > g && (g - b).abs() < 0.1,
+            "red comes out on top: {r}, {g}, {b}"
+        );
+
+        let jpeg = Frame::packed_for_test(&[0xFF, 0xD8], Format::MJPEG, 8, 4, 0);
+        assert!(
+            matches!(
+                FrameStatistics::measure(&jpeg, MeteringRegion::FULL),
+                Err(AutoError::UnsupportedFormat { .. })
+            ),
+            "compressed frames can't be measured"
+        );
+    }
+
+    #[test]
+    fn black_and_white_frames_hit_the_ends() {
+        let black = [0; 16 * 8];
+        let dark = FrameStatistics::measure(
+            &Frame::packed_for_test(&black, Format::GREY, 16, 8, 16),
+            MeteringRegion::FULL,
+        )
+        .unwrap();
+        assert!(dark.mean_luma().abs() < 1e-6);
+        assert!(dark.percentile(0.99).abs() < 1e-6, "nothing is brighter");
+        assert!(dark.clipped().abs() < 1e-6);
+
+        let white = [u8::MAX; 16 * 8 * 3];
+        let bright = FrameStatistics::measure(
+            &Frame::packed_for_test(&white, Format::RGB24, 16, 8, 48),
+            MeteringRegion::FULL,
+        )
+        .unwrap();
+        assert!((bright.mean_luma() - 1.0).abs() < 1e-6);
+        assert!(
+            (bright.percentile(0.01) - 1.0).abs() < 1e-6,
+            "nothing is darker"
+        );
+        assert!(
+            (bright.clipped() - 1.0).abs() < 1e-6,
+            "every pixel is clipped"
+        );
+        assert_eq!(bright.rgb_means(), Some([1.0; 3]));
+    }
+
+    #[test]
+    fn regions_outside_the_frame_are_clipped() {
+        let past_the_corner = MeteringRegion::new(2.0, 2.0, 0.5, 0.5);
+        assert_eq!(
+            past_the_corner.to_rect(16, 8),
+            Rect::new(15, 7, 1, 1),
+            "the last pixel"
+        );
+        let before_the_corner = MeteringRegion::new(-1.0, -1.0, 0.25, 0.25);
+        assert_eq!(before_the_corner.to_rect(16, 8), Rect::new(0, 0, 4, 2));
+        let too_big = MeteringRegion::new(0.5, 0.0, 4.0, 4.0);
+        assert_eq!(
+            too_big.to_rect(16, 8),
+            Rect::new(8, 0, 8, 8),
+            "up to the edges"
+        );
+        let nothing = MeteringRegion::new(0.0, 0.0, 0.0, 0.0);
+        assert_eq!(
+            nothing.to_rect(16, 8),
+            Rect::new(0, 0, 1, 1),
+            "at least one pixel"
+        );
+
+        // only the bottom right pixel is bright
+        let mut gray = [0; 16 * 8];
+        if let Some(last) = gray.last_mut() {
+            *last = 200;
+        }
+        let corner = FrameStatistics::measure(
+            &Frame::packed_for_test(&gray, Format::GREY, 16, 8, 16),
+            past_the_corner,
+        )
+        .unwrap();
+        assert_eq!(corner.samples(), 1);
+        assert!((corner.mean_luma() - 200.0 / 255.0).abs() < 1e-4);
+
+        let empty = Frame::packed_for_test(&[], Format::GREY, 0, 0, 0);
+        assert!(
+            matches!(
+                FrameStatistics::measure(&empty, MeteringRegion::FULL),
+                Err(AutoError::EmptyRegion)
+            ),
+            "empty frames have nothing to measure"
+        );
+    }
+}
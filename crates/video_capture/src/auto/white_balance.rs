@@ -0,0 +1,355 @@
+//! Software auto white balance, using the gray-world assumption.
+
+use crate::config::{VideoCaptureProperties, VideoCaptureProperty as Property};
+use crate::error::VideoCaptureAutoError as AutoError;
+use crate::frame::Frame;
+
+use super::stats::{FrameStatistics, MeteringRegion};
+use super::{AutoStatus, ConvergenceLimits, PropertyRange};
+
+/// The dimmest channel average we'll trust. Color in darker frames is
+/// mostly noise.
+const DIMMEST: f32 = 0.02;
+
+/// The properties that a device uses for white balance.
+#[non_exhaustive]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WhiteBalanceControls {
+    /// Separate red and blue gains, like most industrial cameras have.
+    /// Green is left alone.
+    Gains {
+        red: PropertyRange,
+        blue: PropertyRange,
+    },
+    /// A single color temperature, in kelvin, like most UVC cameras have.
+    Temperature(PropertyRange),
+}
+
+impl Default for WhiteBalanceControls {
+    /// UVC's color temperature property, from tungsten to daylight.
+    #[inline]
+    fn default() -> Self {
+        Self::Temperature(PropertyRange::new("white_balance_temperature", 2800, 6500))
+    }
+}
+
+/// Settings for [`AutoWhiteBalance`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AutoWhiteBalanceOptions {
+    /// The part of each frame to measure.
+    pub region: MeteringRegion,
+    /// The properties to adjust.
+    pub controls: WhiteBalanceControls,
+    /// Properties that turn off the device's own auto white balance. They're
+    /// set once, before the first adjustment.
+    ///
+    /// UVC cameras usually want `white_balance_automatic=0` (or
+    /// `white_balance_temperature_auto=0` on older kernels).
+    pub manual_mode: Vec<Property>,
+    /// How quickly to converge, and what counts as close enough.
+    pub limits: ConvergenceLimits,
+}
+
+/// Adjusts a device's white balance until frames average out to gray.
+///
+/// This is the gray-world assumption: most scenes have about as much of
+/// each color, so any overall tint comes from the light. It works well for
+/// busy scenes, but a frame full of one color will be pulled toward gray.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AutoWhiteBalance {
+    options: AutoWhiteBalanceOptions,
+    /// The last values we know of, in the same order as the controls.
+    values: [Option<i64>; 2],
+    settle_frames: u32,
+    in_control: bool,
+}
+
+impl AutoWhiteBalance {
+    /// Makes a controller with the given settings.
+    #[inline]
+    #[must_use]
+    pub const fn new(options: AutoWhiteBalanceOptions) -> Self {
+        Self {
+            options,
+            values: [None; 2],
+            settle_frames: 0,
+            in_control: false,
+        }
+    }
+
+    /// The controller's settings.
+    #[inline]
+    pub const fn options(&self) -> &AutoWhiteBalanceOptions {
+        &self.options
+    }
+
+    /// Measures a frame inside the controller's metering region.
+    ///
+    /// # Errors
+    ///
+    /// This fails like [`FrameStatistics::measure`].
+    #[inline]
+    pub fn measure(&self, frame: &Frame<'_>) -> Result<FrameStatistics, AutoError> {
+        FrameStatistics::measure(frame, self.options.region)
+    }
+
+    /// Looks at a frame's statistics, then adjusts the device if it needs
+    /// to.
+    ///
+    /// Temperature controls can only move between warm and cool, so they
+    /// converge once red and blue match. Gain controls also bring red and
+    /// blue in line with green.
+    ///
+    /// # Errors
+    ///
+    /// This fails for frames without color, if the device doesn't have the
+    /// properties, or if it won't take new values for them.
+    #[inline]
+    pub fn update<D: VideoCaptureProperties + ?Sized>(
+        &mut self,
+        device: &mut D,
+        stats: &FrameStatistics,
+    ) -> Result<AutoStatus, AutoError> {
+        if super::settling(&mut self.settle_frames) {
+            return Ok(AutoStatus::Settling);
+        }
+
+        let [red, green, blue] = stats.rgb_means().ok_or(AutoError::NoColor)?;
+        if red.min(green).min(blue) < DIMMEST {
+            return Ok(AutoStatus::NoSignal);
+        }
+
+        let limits = self.options.limits;
+        let off = |ratio: f32| (ratio - 1.0).abs() > limits.tolerance;
+        // what each control should be multiplied by
+        let correction = match self.options.controls {
+            WhiteBalanceControls::Gains { .. } => {
+                let (red_ratio, blue_ratio) = (green / red, green / blue);
+                (off(red_ratio) || off(blue_ratio)).then_some([red_ratio, blue_ratio])
+            }
+            // a bluer picture needs a higher temperature setting to cancel
+            // it out, and a redder one needs a lower one
+            WhiteBalanceControls::Temperature(_) => {
+                let ratio = blue / red;
+                off(ratio).then_some([ratio, 1.0])
+            }
+        };
+        let Some(wanted) = correction else {
+            return Ok(AutoStatus::Converged);
+        };
+
+        super::take_control(device, &self.options.manual_mode, &mut self.in_control)?;
+        let mut adjusted = false;
+        let controls = ranges(&self.options.controls);
+        for ((control, known), ratio) in controls.into_iter().zip(&mut self.values).zip(wanted) {
+            let Some(range) = control else {
+                continue;
+            };
+            let value = super::read_value(device, &range.key, *known)?;
+            *known = Some(value);
+
+            let (scaled, _) = range.scale(value, limits.step(ratio));
+            if scaled != value {
+                super::write_value(device, &range.key, scaled)?;
+                *known = Some(scaled);
+                adjusted = true;
+                tracing::trace!("auto white balance: `{}` {value} -> {scaled}", range.key);
+            }
+        }
+
+        if !adjusted {
+            return Ok(AutoStatus::Limited);
+        }
+        self.settle_frames = limits.settle_frames;
+        Ok(AutoStatus::Adjusted)
+    }
+
+    /// Forgets what the controller knows about the device, like after it
+    /// reconnects or someone else changes its properties.
+    #[inline]
+    pub const fn reset(&mut self) {
+        self.values = [None; 2];
+        self.settle_frames = 0;
+        self.in_control = false;
+    }
+}
+
+/// The properties to adjust, in the same order as `AutoWhiteBalance::values`.
+const fn ranges(controls: &WhiteBalanceControls) -> [Option<&PropertyRange>; 2] {
+    match *controls {
+        WhiteBalanceControls::Gains { ref red, ref blue } => [Some(red), Some(blue)],
+        WhiteBalanceControls::Temperature(ref temperature) => [Some(temperature), None],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{Format, SpecificResolution};
+    use crate::error::VideoCaptureConfigError as ConfigError;
+    use crate::frame::{FrameMetadata, FramePlanes};
+
+    use super::*;
+
+    /// A camera under warm light, with adjustable red and blue gains.
+    struct FakeCamera {
+        red_balance: i64,
+        blue_balance: i64,
+    }
+
+    impl FakeCamera {
+        #[expect(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            reason = "each channel is clamped to a byte"
+        )]
+        fn capture(&self) -> Vec<u8> {
+            let channel =
+                |light: f32, gain: i64| (light * gain as f32 / 100.0).clamp(0.0, 255.0) as u8;
+            let pixel = [
+                channel(180.0, self.red_balance),
+                120,
+                channel(70.0, self.blue_balance),
+            ];
+            pixel.repeat(16 * 16)
+        }
+    }
+
+    impl VideoCaptureProperties for FakeCamera {
+        fn properties(&self) -> Vec<Property> {
+            vec![
+                Property::new("red_balance".into(), self.red_balance.to_string()),
+                Property::new("blue_balance".into(), self.blue_balance.to_string()),
+            ]
+        }
+
+        fn property(&self, key: String) -> Option<Property> {
+            self.properties().into_iter().find(|p| p.key() == key)
+        }
+
+        fn set_property(&mut self, property: &Property) -> Result<(), ConfigError> {
+            let value = property.value().parse().unwrap();
+            match property.key().as_str() {
+                "red_balance" => self.red_balance = value,
+                "blue_balance" => self.blue_balance = value,
+                other => panic!("unexpected property `{other}`"),
+            }
+            Ok(())
+        }
+    }
+
+    fn stats(data: &[u8]) -> FrameStatistics {
+        let frame = Frame {
+            data,
+            format: Format::RGB24,
+            resolution: SpecificResolution::new(16, 16),
+            stride: 16 * 3,
+            planes: FramePlanes::single(data, 16 * 3),
+            metadata: FrameMetadata::default(),
+        };
+        FrameStatistics::measure(&frame, MeteringRegion::FULL).unwrap()
+    }
+
+    #[test]
+    fn gray_world_balances_red_and_blue() {
+        let mut camera = FakeCamera {
+            red_balance: 100,
+            blue_balance: 100,
+        };
+        let mut controller = AutoWhiteBalance::new(AutoWhiteBalanceOptions {
+            controls: WhiteBalanceControls::Gains {
+                red: PropertyRange::new("red_balance", 10, 400),
+                blue: PropertyRange::new("blue_balance", 10, 400),
+            },
+            ..AutoWhiteBalanceOptions::default()
+        });
+
+        let mut last = AutoStatus::Settling;
+        for _ in 0..60 {
+            let frame_stats = stats(&camera.capture());
+            last = controller.update(&mut camera, &frame_stats).unwrap();
+        }
+
+        let [red, green, blue] = stats(&camera.capture()).rgb_means().unwrap();
+        assert_eq!(last, AutoStatus::Converged, "means: {red}, {green}, {blue}");
+        assert!(
+            camera.red_balance < 100,
+            "the warm light's red was turned down"
+        );
+        assert!(camera.blue_balance > 100, "and its blue was turned up");
+    }
+
+    #[test]
+    fn dark_white_and_gray_frames_are_left_alone() {
+        let mut camera = FakeCamera {
+            red_balance: 100,
+            blue_balance: 100,
+        };
+        let mut controller = AutoWhiteBalance::new(AutoWhiteBalanceOptions {
+            controls: WhiteBalanceControls::Gains {
+                red: PropertyRange::new("red_balance", 10, 400),
+                blue: PropertyRange::new("blue_balance", 10, 400),
+            },
+            ..AutoWhiteBalanceOptions::default()
+        });
+
+        let black = stats(&[0; 16 * 16 * 3]);
+        assert_eq!(
+            controller.update(&mut camera, &black).unwrap(),
+            AutoStatus::NoSignal
+        );
+        let white = stats(&[u8::MAX; 16 * 16 * 3]);
+        assert_eq!(
+            controller.update(&mut camera, &white).unwrap(),
+            AutoStatus::Converged,
+            "clipped frames look gray"
+        );
+        assert_eq!((camera.red_balance, camera.blue_balance), (100, 100));
+
+        let gray = [128; 16 * 16];
+        let gray_frame = Frame {
+            data: &gray,
+            format: Format::GREY,
+            resolution: SpecificResolution::new(16, 16),
+            stride: 16,
+            planes: FramePlanes::single(&gray, 16),
+            metadata: FrameMetadata::default(),
+        };
+        let gray_stats = controller.measure(&gray_frame).unwrap();
+        assert!(matches!(
+            controller.update(&mut camera, &gray_stats),
+            Err(AutoError::NoColor)
+        ));
+    }
+
+    #[test]
+    fn regions_past_the_edge_measure_the_corner() {
+        // warm on the left, neutral on the right
+        let data: Vec<u8> = (0_u32..16 * 16)
+            .flat_map(|index| {
+                if index % 16 < 8 {
+                    [180, 120, 70]
+                } else {
+                    [120; 3]
+                }
+            })
+            .collect();
+        let frame = Frame {
+            data: &data,
+            format: Format::RGB24,
+            resolution: SpecificResolution::new(16, 16),
+            stride: 16 * 3,
+            planes: FramePlanes::single(&data, 16 * 3),
+            metadata: FrameMetadata::default(),
+        };
+        let controller = AutoWhiteBalance::new(AutoWhiteBalanceOptions {
+            region: MeteringRegion::new(1.5, 0.0, 1.0, 1.0),
+            ..AutoWhiteBalanceOptions::default()
+        });
+
+        let [red, green, blue] = controller.measure(&frame).unwrap().rgb_means().unwrap();
+        assert!(
+            (red - green).abs() < 1e-4 && (green - blue).abs() < 1e-4,
+            "only the neutral side: {red}, {green}, {blue}"
+        );
+    }
+}
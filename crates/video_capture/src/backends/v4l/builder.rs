@@ -0,0 +1,507 @@
+//! Opening a device with everything set up before it starts streaming.
+//!
+//! [`VideoCaptureConnection::new`] starts streaming in whatever mode the
+//! device was last left in. The builder applies the image configuration and
+//! properties first, so the device's first `STREAMON` is already in the
+//! right mode.
+//!
+//! [`VideoCaptureConnection::new`]: crate::VideoCaptureConnection::new
+
+use core::time::Duration;
+use std::path::PathBuf;
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+
+use crate::config::{
+    VideoCaptureConfiguration as _, VideoCaptureImageConfiguration as ImageConfiguration,
+    VideoCaptureImageConstraints as ImageConstraints, VideoCaptureProfile,
+    VideoCaptureProperty as Property,
+};
+use crate::error::{
+    VideoCaptureConfigError as ConfigError, VideoCaptureConnectionError as ConnectionError,
+};
+use crate::frame::CorruptFramePolicy;
+
+use super::kernel::{Kernel, LinuxKernel};
+use super::raw::{V4L2_MEMORY_MMAP, V4L2_MEMORY_USERPTR};
+use super::stream::StreamSettings;
+use super::V4LVideoCaptureDevice;
+
+/// How a device shares its frame buffers with us.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum V4LIoMethod {
+    /// The driver allocates the buffers, and we map them into our memory.
+    /// Every streaming driver supports this.
+    #[default]
+    Mmap,
+    /// We allocate the buffers, then lend them to the driver. Not every
+    /// driver supports this.
+    UserPtr,
+}
+
+impl V4LIoMethod {
+    /// The kernel's name for this method. (`V4L2_MEMORY_*`)
+    pub(super) const fn memory(self) -> u32 {
+        match self {
+            Self::Mmap => V4L2_MEMORY_MMAP,
+            Self::UserPtr => V4L2_MEMORY_USERPTR,
+        }
+    }
+}
+
+/// What to do with the first frames after a device starts streaming.
+///
+/// Some drivers don't fill in everything about their stream until a frame
+/// has gone through, and many cameras give a few dark frames while their
+/// exposure settles.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum V4LWarmUp {
+    /// Don't read anything before handing over the device.
+    None,
+    /// Read and throw away this many frames.
+    Frames(u32),
+}
+
+impl Default for V4LWarmUp {
+    /// A single frame, which is enough for buggy drivers.
+    #[inline]
+    fn default() -> Self {
+        Self::Frames(1)
+    }
+}
+
+/// The image configuration that the builder should ask for.
+#[derive(Clone, Debug, PartialEq)]
+enum ImageRequest {
+    Exact(ImageConfiguration),
+    Constraints(ImageConstraints),
+}
+
+/// Opens a Video4Linux capture device, setting it up before it starts
+/// streaming.
+///
+/// Make one with [`V4LVideoCaptureDevice::builder`]. Anything that isn't set
+/// is left as the device (or its profile) has it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct V4LVideoCaptureBuilder {
+    source: PathBuf,
+    image: Option<ImageRequest>,
+    properties: Vec<Property>,
+    profiles: Vec<VideoCaptureProfile>,
+    settings: StreamSettings,
+    corrupt_frames: CorruptFramePolicy,
+    start_streaming: bool,
+}
+
+impl V4LVideoCaptureBuilder {
+    /// Starts a builder for the device at `source`.
+    #[inline]
+    #[must_use]
+    pub fn new(source: PathBuf) -> Self {
+        Self {
+            source,
+            image: None,
+            properties: Vec::new(),
+            profiles: Vec::new(),
+            settings: StreamSettings::default(),
+            corrupt_frames: CorruptFramePolicy::default(),
+            start_streaming: true,
+        }
+    }
+
+    /// Asks for an exact image configuration.
+    ///
+    /// This replaces any [`constraints`](Self::constraints).
+    #[inline]
+    #[must_use]
+    pub fn image_configuration(mut self, conf: ImageConfiguration) -> Self {
+        self.image = Some(ImageRequest::Exact(conf));
+        self
+    }
+
+    /// Picks the device's best image configuration that meets the
+    /// constraints.
+    ///
+    /// This replaces any [`image_configuration`](Self::image_configuration).
+    #[inline]
+    #[must_use]
+    pub fn constraints(mut self, constraints: ImageConstraints) -> Self {
+        self.image = Some(ImageRequest::Constraints(constraints));
+        self
+    }
+
+    /// Sets a property once the image configuration is in place.
+    ///
+    /// Properties are set in the order they're given.
+    #[inline]
+    #[must_use]
+    pub fn property(mut self, property: Property) -> Self {
+        self.properties.push(property);
+        self
+    }
+
+    /// Sets each of these properties, like [`property`](Self::property).
+    #[inline]
+    #[must_use]
+    pub fn properties<I: IntoIterator<Item = Property>>(mut self, properties: I) -> Self {
+        self.properties.extend(properties);
+        self
+    }
+
+    /// Adds a profile to pick from when the device connects, and again
+    /// whenever it reconnects.
+    ///
+    /// The device uses whichever profile
+    /// [`find_profile`](crate::config::find_profile) picks for it. Profiles
+    /// for other devices are skipped.
+    #[inline]
+    #[must_use]
+    pub fn profile(mut self, profile: VideoCaptureProfile) -> Self {
+        self.profiles.push(profile);
+        self
+    }
+
+    /// Adds each of these profiles, like [`profile`](Self::profile).
+    #[inline]
+    #[must_use]
+    pub fn profiles<I: IntoIterator<Item = VideoCaptureProfile>>(mut self, profiles: I) -> Self {
+        self.profiles.extend(profiles);
+        self
+    }
+
+    /// Chooses how frame buffers are shared with the device.
+    ///
+    /// By default, they're memory-mapped.
+    #[inline]
+    #[must_use]
+    pub const fn io_method(mut self, io_method: V4LIoMethod) -> Self {
+        self.settings.io_method = io_method;
+        self
+    }
+
+    /// Asks the driver for this many frame buffers. The driver might give
+    /// more or fewer.
+    ///
+    /// More buffers mean fewer dropped frames, but older frames when reads
+    /// fall behind. The default is four.
+    #[inline]
+    #[must_use]
+    pub const fn buffer_count(mut self, count: u32) -> Self {
+        self.settings.buffer_count = count;
+        self
+    }
+
+    /// Makes [`read_frame`] give up after waiting this long for a frame.
+    /// This also limits each warm-up frame.
+    ///
+    /// By default, reads wait as long as it takes.
+    ///
+    /// [`read_frame`]: crate::VideoCaptureStream::read_frame
+    #[inline]
+    #[must_use]
+    pub const fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.settings.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Chooses what to do with the first frames after streaming starts.
+    ///
+    /// Reconnecting warms up the same way.
+    #[inline]
+    #[must_use]
+    pub const fn warm_up(mut self, warm_up: V4LWarmUp) -> Self {
+        self.settings.warm_up = warm_up;
+        self
+    }
+
+    /// Chooses what the device does with frames that look damaged.
+    #[inline]
+    #[must_use]
+    pub const fn corrupt_frame_policy(mut self, policy: CorruptFramePolicy) -> Self {
+        self.corrupt_frames = policy;
+        self
+    }
+
+    /// Chooses whether the device starts streaming once it's open.
+    ///
+    /// When it doesn't, call
+    /// [`start_streaming`](V4LVideoCaptureDevice::start_streaming) later.
+    /// There's no warm-up then.
+    #[inline]
+    #[must_use]
+    pub const fn start_streaming(mut self, start: bool) -> Self {
+        self.start_streaming = start;
+        self
+    }
+
+    /// Opens the device and sets it up.
+    ///
+    /// The matching profile goes first, then the image configuration, then
+    /// the properties. The stream starts after all of them.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the device can't be opened, if it won't take the
+    /// configuration or properties, or if it doesn't start streaming.
+    #[inline]
+    pub fn open<'path, 'conn>(
+        self,
+    ) -> Result<V4LVideoCaptureDevice<'path, 'conn>, ConnectionError> {
+        self.open_with(Arc::new(LinuxKernel))
+    }
+
+    /// Opens the device like [`open`](Self::open), making every call through
+    /// the given kernel.
+    pub(super) fn open_with<'path, 'conn>(
+        self,
+        kernel: Arc<dyn Kernel>,
+    ) -> Result<V4LVideoCaptureDevice<'path, 'conn>, ConnectionError> {
+        let mut device = V4LVideoCaptureDevice::open_stopped(
+            kernel,
+            &self.source,
+            self.settings,
+            self.profiles.clone(),
+        )?;
+        device.set_corrupt_frame_policy(self.corrupt_frames);
+
+        let source = device.source_as_string();
+        self.configure(&mut device)
+            .map_err(|e| ConnectionError::CouldntConfigure {
+                source,
+                err_msg: e.to_string(),
+            })?;
+
+        if self.start_streaming {
+            device.start_streaming()?;
+            device.warm_up()?;
+        }
+
+        Ok(device)
+    }
+
+    /// Applies the image configuration and properties to a stopped device.
+    fn configure(&self, device: &mut V4LVideoCaptureDevice<'_, '_>) -> Result<(), ConfigError> {
+        let conf = match self.image {
+            Some(ImageRequest::Exact(exact)) => Some(exact),
+            Some(ImageRequest::Constraints(ref constraints)) => {
+                let picked = constraints
+                    .pick(&device.supported_image_configurations()?)
+                    .ok_or_else(|| ConfigError::NoMatchingConfiguration {
+                        source: device.source_as_string(),
+                    })?;
+                Some(picked)
+            }
+            None => None,
+        };
+
+        if let Some(ref wanted) = conf {
+            let chosen = device.reconfigure(wanted)?.configuration;
+            tracing::debug!("Opening `{}` with `{chosen}`.", device.source_as_string());
+        }
+
+        device.set_properties(&self.properties)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fraction::Fraction;
+    use v4l::control::Type;
+
+    use super::super::fake::{uvc_camera, FakeKernel, Issued};
+    use super::super::kernel::{FrameInterval, FrameSize};
+    use super::super::raw::V4L2_BUF_TYPE_VIDEO_CAPTURE;
+    use super::*;
+    use crate::config::{Format, ResolutionSetting, SpecificResolution};
+
+    const SOURCE: &str = "/dev/video0";
+    const BRIGHTNESS: u32 = 0x0098_0900;
+
+    /// A camera with two formats, where the fastest and biggest
+    /// configurations are different ones.
+    fn camera() -> FakeKernel {
+        let sizes = |sizes: &[(u32, u32)]| -> Vec<FrameSize> {
+            sizes
+                .iter()
+                .map(|&(width, height)| FrameSize::Discrete(SpecificResolution::new(width, height)))
+                .collect()
+        };
+        let fps = |rates: &[u32]| -> Vec<FrameInterval> {
+            rates
+                .iter()
+                .map(|&denominator| FrameInterval::Discrete {
+                    numerator: 1,
+                    denominator,
+                })
+                .collect()
+        };
+
+        uvc_camera()
+            .with_formats(V4L2_BUF_TYPE_VIDEO_CAPTURE, &[*b"YUYV", *b"MJPG"])
+            .with_framesizes(*b"YUYV", &sizes(&[(640, 480), (1280, 720)]))
+            .with_frameintervals(*b"YUYV", (640, 480), &fps(&[30, 15]))
+            .with_frameintervals(*b"YUYV", (1280, 720), &fps(&[10]))
+            .with_framesizes(*b"MJPG", &sizes(&[(1280, 720), (1920, 1080)]))
+            .with_frameintervals(*b"MJPG", (1280, 720), &fps(&[60, 30]))
+            .with_frameintervals(*b"MJPG", (1920, 1080), &fps(&[30]))
+            .with_current_format(V4L2_BUF_TYPE_VIDEO_CAPTURE, *b"YUYV", (320, 240))
+            .with_control(BRIGHTNESS, "Brightness", Type::Integer, (0, 255), 128)
+    }
+
+    /// Opens the camera with `constraints`, and returns the format that the
+    /// builder set.
+    fn picked(constraints: ImageConstraints) -> Option<Issued> {
+        let kernel = camera();
+        V4LVideoCaptureDevice::builder(PathBuf::from(SOURCE))
+            .constraints(constraints)
+            .start_streaming(false)
+            .open_with(Arc::new(kernel.clone()))
+            .unwrap();
+
+        kernel
+            .issued()
+            .into_iter()
+            .find(|issued| matches!(*issued, Issued::SetFormat { .. }))
+    }
+
+    fn set_format(fourcc: [u8; 4], width: u32, height: u32) -> Option<Issued> {
+        Some(Issued::SetFormat {
+            buf_type: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+            fourcc,
+            resolution: SpecificResolution::new(width, height),
+        })
+    }
+
+    #[test]
+    fn constraints_pick_the_best_configuration() {
+        let any = ImageConstraints::default();
+        assert_eq!(
+            picked(any.clone()),
+            set_format(*b"MJPG", 1920, 1080),
+            "the biggest"
+        );
+
+        let preferred = ImageConstraints {
+            formats: vec![Format::YUYV, Format::MJPEG],
+            ..any
+        };
+        assert_eq!(
+            picked(preferred.clone()),
+            set_format(*b"YUYV", 1280, 720),
+            "the first format wins over size"
+        );
+
+        let fast = ImageConstraints {
+            min_framerate: Some(Fraction::new(15_u64, 1_u64)),
+            ..preferred
+        };
+        assert_eq!(
+            picked(fast),
+            set_format(*b"YUYV", 640, 480),
+            "too slow at 720p"
+        );
+
+        let close = ImageConstraints {
+            resolution: ResolutionSetting::Closest(SpecificResolution::new(1200, 700)),
+            ..any
+        };
+        let kernel = camera();
+        let device = V4LVideoCaptureDevice::builder(PathBuf::from(SOURCE))
+            .constraints(close)
+            .start_streaming(false)
+            .open_with(Arc::new(kernel.clone()))
+            .unwrap();
+        assert!(
+            kernel.issued().contains(&Issued::SetFrameInterval {
+                buf_type: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+                numerator: 1,
+                denominator: 60,
+            }),
+            "the fastest framerate at the closest size"
+        );
+        let conf = device.image_configuration().unwrap();
+        assert_eq!(
+            (conf.format, conf.resolution),
+            (Format::MJPEG, SpecificResolution::new(1280, 720))
+        );
+    }
+
+    #[test]
+    fn constraints_that_nothing_meets_fail() {
+        let kernel = camera();
+        let impossible = ImageConstraints {
+            formats: vec![Format::YUYV],
+            min_framerate: Some(Fraction::new(60_u64, 1_u64)),
+            ..ImageConstraints::default()
+        };
+
+        let err = V4LVideoCaptureDevice::builder(PathBuf::from(SOURCE))
+            .constraints(impossible)
+            .open_with(Arc::new(kernel.clone()))
+            .unwrap_err();
+        let expected = ConfigError::NoMatchingConfiguration {
+            source: String::from(SOURCE),
+        };
+        assert!(
+            matches!(
+                err,
+                ConnectionError::CouldntConfigure { ref err_msg, .. }
+                    if *err_msg == expected.to_string()
+            ),
+            "nothing fits: {err}"
+        );
+        assert!(
+            !kernel
+                .issued()
+                .iter()
+                .any(|issued| matches!(*issued, Issued::SetFormat { .. } | Issued::StreamOn(_))),
+            "the device was left alone"
+        );
+    }
+
+    #[test]
+    fn properties_are_set_after_the_format() {
+        let kernel = camera();
+        let device = V4LVideoCaptureDevice::builder(PathBuf::from(SOURCE))
+            .property(Property::new(String::from("brightness"), "42"))
+            .image_configuration(ImageConfiguration {
+                format: Format::MJPEG,
+                resolution: SpecificResolution::new(1280, 720),
+                framerate: Fraction::new(30_u64, 1_u64),
+            })
+            .warm_up(V4LWarmUp::None)
+            .open_with(Arc::new(kernel.clone()))
+            .unwrap();
+
+        let issued = kernel.issued();
+        assert_eq!(
+            issued.get(1..),
+            Some(
+                [
+                    Issued::SetFormat {
+                        buf_type: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+                        fourcc: *b"MJPG",
+                        resolution: SpecificResolution::new(1280, 720),
+                    },
+                    Issued::SetFrameInterval {
+                        buf_type: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+                        numerator: 1,
+                        denominator: 30,
+                    },
+                    Issued::SetControl {
+                        id: BRIGHTNESS,
+                        value: 42,
+                    },
+                    Issued::StreamOn(V4L2_BUF_TYPE_VIDEO_CAPTURE),
+                ]
+                .as_slice()
+            ),
+            "format, framerate, properties, then streaming"
+        );
+        assert!(device.is_streaming());
+    }
+}
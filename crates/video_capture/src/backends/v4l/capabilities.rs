@@ -0,0 +1,266 @@
+//! What a Video4Linux device says it can do.
+
+use core::ffi::CStr;
+use core::fmt::Display;
+use std::fs::File;
+use std::os::fd::AsRawFd as _;
+use std::{io, path::Path};
+
+use nix::ioctl_read;
+
+use super::raw::zeroed;
+
+/// The raw `v4l2_capability` structure.
+///
+/// See: https://docs.kernel.org/userspace-api/media/v4l/vidioc-querycap.html
+#[repr(C)]
+struct V4l2Capability {
+    driver: [u8; 16],
+    card: [u8; 32],
+    bus_info: [u8; 32],
+    version: u32,
+    capabilities: u32,
+    device_caps: u32,
+    reserved: [u32; 3],
+}
+
+const VIDIOC_QUERYCAP: u8 = 0;
+const IOCTL_VIDEO_COMMAND: u8 = b'V';
+
+ioctl_read!(
+    vidioc_querycap,
+    IOCTL_VIDEO_COMMAND,
+    VIDIOC_QUERYCAP,
+    V4l2Capability
+);
+
+/// Everything a Video4Linux device reports from `VIDIOC_QUERYCAP`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct V4LCapabilities {
+    /// The name of the driver, like `uvcvideo`.
+    pub driver: String,
+    /// The name of the device, like `HD Pro Webcam C920`.
+    pub card: String,
+    /// Where the device is attached, like `usb-0000:00:14.0-1`.
+    pub bus_info: String,
+    /// The version of the Video4Linux subsystem (usually the kernel's).
+    pub version: V4LVersion,
+    /// What the whole physical device can do, across all of its nodes.
+    pub capabilities: V4LCapabilityFlags,
+    /// What this specific `/dev/videoY` node can do.
+    pub device_capabilities: V4LCapabilityFlags,
+}
+
+impl V4LCapabilities {
+    /// Asks the video node at the given path for its capabilities.
+    #[tracing::instrument]
+    pub(super) fn query(path: &Path) -> Result<Self, io::Error> {
+        let file = File::open(path)?;
+
+        // the kernel expects zeroed memory, which it'll fill in
+        let mut raw = zeroed::<V4l2Capability>();
+
+        // SAFETY: the kernel will either fill the struct or return an error.
+        unsafe {
+            vidioc_querycap(file.as_raw_fd(), &raw mut raw)?;
+        }
+        tracing::trace!("ioctl `VIDIOC_QUERYCAP` completed successfully!");
+
+        let capabilities = V4LCapabilityFlags(raw.capabilities);
+
+        // older drivers don't fill in `device_caps`. when they don't, the
+        // whole device's capabilities are all we've got
+        let device_capabilities = if capabilities.contains(V4LCapabilityFlags::DEVICE_CAPS) {
+            V4LCapabilityFlags(raw.device_caps)
+        } else {
+            capabilities
+        };
+
+        Ok(Self {
+            driver: fixed_str(&raw.driver),
+            card: fixed_str(&raw.card),
+            bus_info: fixed_str(&raw.bus_info),
+            version: V4LVersion::from_raw(raw.version),
+            capabilities,
+            device_capabilities,
+        })
+    }
+
+    /// Checks if this node can only capture with the multi-planar API.
+    ///
+    /// Some nodes support both. When they do, we use the simpler,
+    /// single-planar one.
+    #[inline]
+    pub const fn needs_multiplanar(&self) -> bool {
+        self.device_capabilities.is_multiplanar() && !self.device_capabilities.is_capture()
+    }
+}
+
+/// The capability flags from `VIDIOC_QUERYCAP`.
+///
+/// See the `V4L2_CAP_*` constants in `videodev2.h` for all of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct V4LCapabilityFlags(pub u32);
+
+impl V4LCapabilityFlags {
+    pub const VIDEO_CAPTURE: Self = Self(0x0000_0001);
+    pub const VIDEO_OUTPUT: Self = Self(0x0000_0002);
+    pub const VIDEO_OVERLAY: Self = Self(0x0000_0004);
+    pub const VIDEO_CAPTURE_MPLANE: Self = Self(0x0000_1000);
+    pub const VIDEO_OUTPUT_MPLANE: Self = Self(0x0000_2000);
+    pub const VIDEO_M2M_MPLANE: Self = Self(0x0000_4000);
+    pub const VIDEO_M2M: Self = Self(0x0000_8000);
+    pub const TUNER: Self = Self(0x0001_0000);
+    pub const AUDIO: Self = Self(0x0002_0000);
+    pub const EXT_PIX_FORMAT: Self = Self(0x0020_0000);
+    pub const META_CAPTURE: Self = Self(0x0080_0000);
+    pub const READ_WRITE: Self = Self(0x0100_0000);
+    pub const STREAMING: Self = Self(0x0400_0000);
+    pub const META_OUTPUT: Self = Self(0x0800_0000);
+    pub const TOUCH: Self = Self(0x1000_0000);
+    pub const IO_MC: Self = Self(0x2000_0000);
+    pub const DEVICE_CAPS: Self = Self(0x8000_0000);
+
+    /// The flags we have names for, in the order they're displayed.
+    const NAMED: [(Self, &'static str); 17] = [
+        (Self::VIDEO_CAPTURE, "capture"),
+        (Self::VIDEO_OUTPUT, "output"),
+        (Self::VIDEO_OVERLAY, "overlay"),
+        (Self::VIDEO_CAPTURE_MPLANE, "capture-mplane"),
+        (Self::VIDEO_OUTPUT_MPLANE, "output-mplane"),
+        (Self::VIDEO_M2M_MPLANE, "m2m-mplane"),
+        (Self::VIDEO_M2M, "m2m"),
+        (Self::TUNER, "tuner"),
+        (Self::AUDIO, "audio"),
+        (Self::EXT_PIX_FORMAT, "ext-pix-format"),
+        (Self::META_CAPTURE, "meta-capture"),
+        (Self::READ_WRITE, "read-write"),
+        (Self::STREAMING, "streaming"),
+        (Self::META_OUTPUT, "meta-output"),
+        (Self::TOUCH, "touch"),
+        (Self::IO_MC, "io-mc"),
+        (Self::DEVICE_CAPS, "device-caps"),
+    ];
+
+    /// Checks if every flag in `other` is set.
+    #[inline]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Can this capture single-planar video?
+    #[inline]
+    pub const fn is_capture(self) -> bool {
+        self.contains(Self::VIDEO_CAPTURE)
+    }
+
+    /// Can this capture multi-planar video?
+    #[inline]
+    pub const fn is_multiplanar(self) -> bool {
+        self.contains(Self::VIDEO_CAPTURE_MPLANE)
+    }
+
+    /// Can this output video?
+    #[inline]
+    pub const fn is_output(self) -> bool {
+        self.contains(Self::VIDEO_OUTPUT) || self.contains(Self::VIDEO_OUTPUT_MPLANE)
+    }
+
+    /// Does this produce metadata (instead of, or alongside, video)?
+    #[inline]
+    pub const fn is_metadata(self) -> bool {
+        self.contains(Self::META_CAPTURE)
+    }
+
+    /// Does this support streaming I/O (mmap, userptr, or dmabuf buffers)?
+    #[inline]
+    pub const fn has_streaming(self) -> bool {
+        self.contains(Self::STREAMING)
+    }
+
+    /// Can frames be read with plain `read()` and `write()` calls?
+    #[inline]
+    pub const fn has_read_write(self) -> bool {
+        self.contains(Self::READ_WRITE)
+    }
+}
+
+/// Flags display as a comma-separated list of names, like
+/// `capture, streaming`.
+impl Display for V4LCapabilityFlags {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut names = Self::NAMED
+            .iter()
+            .filter(|named| self.contains(named.0))
+            .map(|named| named.1);
+
+        if let Some(first) = names.next() {
+            f.write_str(first)?;
+        }
+        for name in names {
+            f.write_fmt(format_args!(", {name}"))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A version number, packed the way the kernel's `KERNEL_VERSION` macro does
+/// it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct V4LVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+}
+
+impl V4LVersion {
+    /// Unpacks a `KERNEL_VERSION(major, minor, patch)` number.
+    #[inline]
+    pub const fn from_raw(raw: u32) -> Self {
+        let [_, major, minor, patch] = raw.to_be_bytes();
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+/// Versions display as `major.minor.patch`.
+impl Display for V4LVersion {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_fmt(format_args!("{}.{}.{}", self.major, self.minor, self.patch))
+    }
+}
+
+/// Reads a NUL-padded string from a fixed-size kernel buffer.
+pub(super) fn fixed_str(bytes: &[u8]) -> String {
+    CStr::from_bytes_until_nul(bytes).map_or_else(
+        |_| String::from_utf8_lossy(bytes).into_owned(),
+        |cstr| cstr.to_string_lossy().into_owned(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_and_versions_read_like_v4l2_ctl() {
+        let flags = V4LCapabilityFlags(0x8420_0001);
+        assert!(flags.is_capture(), "capture");
+        assert!(flags.has_streaming(), "streaming");
+        assert!(!flags.is_multiplanar(), "not mplane");
+        assert_eq!(
+            flags.to_string(),
+            "capture, ext-pix-format, streaming, device-caps",
+            "names"
+        );
+
+        assert_eq!(V4LVersion::from_raw(0x0006_0803).to_string(), "6.8.3");
+        assert_eq!(fixed_str(b"uvcvideo\0\0\0\0"), "uvcvideo", "trims nuls");
+    }
+}
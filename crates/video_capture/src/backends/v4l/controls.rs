@@ -0,0 +1,217 @@
+//! Video4Linux controls, exposed as properties.
+//!
+//! Each control's name is turned into a key the same way `v4l2-ctl` does it,
+//! so "White Balance Temperature, Auto" becomes
+//! `white_balance_temperature_auto`.
+
+use v4l::control::{Control, Description, Flags, MenuItem, Type, Value};
+
+use crate::config::VideoCaptureProperty as Property;
+use crate::error::VideoCaptureConfigError as ConfigError;
+
+use super::device::V4LDevice;
+
+/// A control that we know how to read and write, plus its current value.
+#[derive(Debug)]
+pub(super) struct KnownControl {
+    description: Description,
+    value: i64,
+}
+
+impl KnownControl {
+    /// This control's property key.
+    pub(super) fn key(&self) -> String {
+        control_key(&self.description.name)
+    }
+
+    /// Checks if this control can be written right now.
+    pub(super) fn is_writable(&self) -> bool {
+        !self
+            .description
+            .flags
+            .intersects(Flags::READ_ONLY | Flags::INACTIVE | Flags::DISABLED)
+    }
+
+    /// Turns this control into a property.
+    pub(super) fn to_property(&self) -> Property {
+        Property::new(self.key(), self.value.to_string())
+    }
+}
+
+/// Lists every control with a simple numeric value, along with that value.
+pub(super) fn known_controls(
+    device: &V4LDevice,
+    source: &str,
+) -> Result<Vec<KnownControl>, ConfigError> {
+    let descriptions = device
+        .controls()
+        .map_err(|e| ConfigError::CouldntGetFormat {
+            source: source.to_owned(),
+            err_msg: format!("Failed to list controls. IO error: {e}"),
+        })?;
+
+    let mut controls = Vec::with_capacity(descriptions.len());
+    for description in descriptions {
+        if !is_supported(&description) {
+            continue;
+        }
+
+        // some drivers list controls they can't actually read. skip those
+        let value = match device.control(description.id) {
+            Ok(Control {
+                value: Value::Integer(value),
+                ..
+            }) => value,
+            Ok(Control {
+                value: Value::Boolean(value),
+                ..
+            }) => i64::from(value),
+            Ok(_) => continue,
+            Err(e) => {
+                tracing::debug!(
+                    "Device at source `{source}` couldn't read control `{}`: {e}",
+                    description.name
+                );
+                continue;
+            }
+        };
+
+        controls.push(KnownControl { description, value });
+    }
+
+    Ok(controls)
+}
+
+/// Writes each property to its matching control, in order.
+///
+/// The controls are only listed once, up front.
+pub(super) fn set_controls(
+    device: &V4LDevice,
+    source: &str,
+    properties: &[Property],
+) -> Result<(), ConfigError> {
+    if properties.is_empty() {
+        return Ok(());
+    }
+
+    let known = known_controls(device, source)?;
+    for property in properties {
+        set_control(device, source, &known, property)?;
+    }
+
+    Ok(())
+}
+
+/// Writes a property to the matching control.
+fn set_control(
+    device: &V4LDevice,
+    source: &str,
+    known: &[KnownControl],
+    property: &Property,
+) -> Result<(), ConfigError> {
+    let key = property.key();
+    let control = known
+        .iter()
+        .find(|control| control.key() == key)
+        .ok_or_else(|| ConfigError::PropertyNotFound {
+            source: source.to_owned(),
+            property_name: key.clone(),
+        })?;
+
+    let value = parse_value(&control.description, &property.value()).ok_or_else(|| {
+        ConfigError::InvalidPropertyValue {
+            source: source.to_owned(),
+            property_name: key.clone(),
+            value: property.value(),
+        }
+    })?;
+
+    device
+        .set_control(Control {
+            id: control.description.id,
+            value,
+        })
+        .map_err(|e| {
+            super::errno::config_error(source, e, |err| ConfigError::PropertyWriteFailure {
+                source: source.to_owned(),
+                err_msg: format!("Failed to set `{key}`. IO error: {err}"),
+            })
+        })
+}
+
+/// Turns a control's name into a property key.
+pub(super) fn control_key(name: &str) -> String {
+    let mut key = String::with_capacity(name.len());
+    for ch in name.chars() {
+        if ch.is_ascii_alphanumeric() {
+            key.push(ch.to_ascii_lowercase());
+        } else if !key.is_empty() && !key.ends_with('_') {
+            key.push('_');
+        }
+    }
+
+    key.trim_end_matches('_').to_owned()
+}
+
+/// We only handle controls with a single numeric value.
+fn is_supported(description: &Description) -> bool {
+    matches!(
+        description.typ,
+        Type::Integer | Type::Integer64 | Type::Boolean | Type::Menu
+    ) && !description
+        .flags
+        .intersects(Flags::DISABLED | Flags::WRITE_ONLY)
+}
+
+/// Parses a property value for the given control.
+///
+/// Booleans take `0`, `1`, `true`, or `false`. Menus take either an item's
+/// index or its name.
+fn parse_value(description: &Description, value: &str) -> Option<Value> {
+    let number = match (description.typ, value) {
+        (Type::Boolean, "true") => 1,
+        (Type::Boolean, "false") => 0,
+        (Type::Menu, _) => match value.parse::<i64>() {
+            Ok(index) => index,
+            Err(_) => menu_index(description, value)?,
+        },
+        _ => value.parse::<i64>().ok()?,
+    };
+
+    if number < description.minimum || number > description.maximum {
+        return None;
+    }
+
+    Some(match description.typ {
+        Type::Boolean => Value::Boolean(number != 0),
+        _ => Value::Integer(number),
+    })
+}
+
+/// Finds a menu item by name.
+fn menu_index(description: &Description, name: &str) -> Option<i64> {
+    description
+        .items
+        .as_ref()?
+        .iter()
+        .find(|entry| match entry.1 {
+            MenuItem::Name(ref item_name) => control_key(item_name) == control_key(name),
+            MenuItem::Value(_) => false,
+        })
+        .map(|entry| i64::from(entry.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::control_key;
+
+    #[test]
+    fn keys_match_v4l2_ctl() {
+        assert_eq!(control_key("Brightness"), "brightness");
+        assert_eq!(
+            control_key("White Balance Temperature, Auto"),
+            "white_balance_temperature_auto"
+        );
+        assert_eq!(control_key("Exposure (Absolute)"), "exposure_absolute");
+    }
+}
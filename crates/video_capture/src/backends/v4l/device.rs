@@ -0,0 +1,105 @@
+//! An open video node, and the kernel that its calls go through.
+
+use core::fmt::Debug;
+use std::io;
+use std::os::fd::RawFd;
+use std::path::Path;
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+
+use v4l::control::{Control, Description};
+use v4l::device::Handle;
+use v4l::Device;
+
+use crate::config::SpecificResolution;
+
+use super::format::PixFormat;
+use super::kernel::Kernel;
+
+/// An open Video4Linux node.
+///
+/// Every call on the node goes through the kernel it was opened with, so
+/// tests can swap in a fake one.
+pub struct V4LDevice {
+    device: Device,
+    kernel: Arc<dyn Kernel>,
+}
+
+impl V4LDevice {
+    /// Opens the video node at `path`.
+    pub(super) fn open(kernel: Arc<dyn Kernel>, path: &Path) -> io::Result<Self> {
+        let device = kernel.open(path)?;
+        Ok(Self { device, kernel })
+    }
+
+    /// The kernel that this node's calls go through.
+    pub(super) fn kernel(&self) -> &Arc<dyn Kernel> {
+        &self.kernel
+    }
+
+    /// The node's file descriptor.
+    pub(super) fn fd(&self) -> RawFd {
+        self.device.handle().fd()
+    }
+
+    /// A handle that keeps the node open, for streams to hold on to.
+    pub(super) fn handle(&self) -> Arc<Handle> {
+        self.device.handle()
+    }
+
+    /// Gets the format that a stream is using.
+    pub(super) fn format(&self, buf_type: u32) -> io::Result<PixFormat> {
+        self.kernel.format(self.fd(), buf_type)
+    }
+
+    /// Asks a stream to use a format, then returns what the driver chose
+    /// instead.
+    pub(super) fn set_format(
+        &self,
+        buf_type: u32,
+        fourcc: [u8; 4],
+        resolution: SpecificResolution,
+    ) -> io::Result<PixFormat> {
+        self.kernel
+            .set_format(self.fd(), buf_type, fourcc, resolution)
+    }
+
+    /// Asks a stream to use a frame interval of `numerator / denominator`
+    /// seconds.
+    pub(super) fn set_frame_interval(
+        &self,
+        buf_type: u32,
+        numerator: u32,
+        denominator: u32,
+    ) -> io::Result<()> {
+        self.kernel
+            .set_frame_interval(self.fd(), buf_type, numerator, denominator)
+    }
+
+    /// Lists every control on the node.
+    pub(super) fn controls(&self) -> io::Result<Vec<Description>> {
+        self.kernel.controls(&self.device)
+    }
+
+    /// Reads a control's value.
+    pub(super) fn control(&self, id: u32) -> io::Result<Control> {
+        self.kernel.control(&self.device, id)
+    }
+
+    /// Writes a control's value.
+    pub(super) fn set_control(&self, control: Control) -> io::Result<()> {
+        self.kernel.set_control(&self.device, control)
+    }
+}
+
+impl Debug for V4LDevice {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("V4LDevice")
+            .field("fd", &self.fd())
+            .field("kernel", &self.kernel)
+            .finish()
+    }
+}
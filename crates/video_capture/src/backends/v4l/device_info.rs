@@ -1,24 +1,51 @@
 use std::{fs, io, os::fd::AsRawFd, path::Path};
 
-use core::ffi::{c_char, CStr};
-
 use nix::ioctl_readwrite;
 
-/// A struct that contains information about some Linux media device.
+use super::capabilities::{fixed_str, V4LVersion};
+use super::raw::zeroed;
+
+/// The raw `media_device_info` structure.
 ///
 /// See: https://docs.kernel.org/userspace-api/media/mediactl/media-ioc-device-info.html
 #[repr(C)]
-pub(super) struct MediaDeviceInfo {
-    driver: [c_char; 16],
-    model: [c_char; 32],
-    serial: [c_char; 40],
-    bus_info: [c_char; 32],
+struct RawMediaDeviceInfo {
+    driver: [u8; 16],
+    model: [u8; 32],
+    serial: [u8; 40],
+    bus_info: [u8; 32],
     media_version: u32,
     hw_revision: u32,
     driver_version: u32,
     reserved: [u32; 31],
 }
 
+/// What a Linux media device says about itself.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(super) struct MediaDeviceInfo {
+    /// The device's model, like `C922 Pro Stream Webcam`.
+    pub model: String,
+    /// The device's serial number. Some devices leave this empty.
+    pub serial: String,
+    pub info: V4LMediaInfo,
+}
+
+/// Everything the media device reports about itself, other than its model
+/// and serial.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct V4LMediaInfo {
+    /// The name of the media driver, like `uvcvideo`.
+    pub driver: String,
+    /// Where the device is attached, like `usb-0000:00:14.0-1`.
+    pub bus_info: String,
+    /// The version of the media controller API.
+    pub media_version: V4LVersion,
+    /// The hardware revision, in a format that only the driver understands.
+    pub hw_revision: u32,
+    /// The version of the driver.
+    pub driver_version: V4LVersion,
+}
+
 const MEDIA_IOC_DEVICE_INFO_SEQ_NUM: u8 = 0x00;
 const IOCTL_MEDIA_COMMAND_IDENT: u8 = b'|';
 
@@ -27,18 +54,15 @@ ioctl_readwrite!(
     media_ioc_device_info,
     IOCTL_MEDIA_COMMAND_IDENT,
     MEDIA_IOC_DEVICE_INFO_SEQ_NUM,
-    MediaDeviceInfo
+    RawMediaDeviceInfo
 );
 
 impl MediaDeviceInfo {
     /// Attempts to get information about the media device at the given path.
     #[tracing::instrument]
-    pub(crate) fn get(path: &Path) -> Result<Self, io::Error> {
-        // create an uninitialized MediaDeviceInfo
-        //
-        // SAFETY: This is fine since the kernel will write to this zeroed memory.
-        let mut info = unsafe { core::mem::zeroed::<Self>() };
-        tracing::trace!("successfully zeroed media_device_info struct memory");
+    pub(super) fn get(path: &Path) -> Result<Self, io::Error> {
+        // the kernel fills in this zeroed struct
+        let mut raw = zeroed::<RawMediaDeviceInfo>();
 
         // grab the file descriptor
         let file = fs::File::open(path)?;
@@ -55,50 +79,83 @@ impl MediaDeviceInfo {
         //
         // If it does fail, we return using the question mark operator.
         unsafe {
-            media_ioc_device_info(fd, &mut info)?;
+            media_ioc_device_info(fd, &raw mut raw)?;
         }
         tracing::trace!("ioctl `MEDIA_IOC_DEVICE_INFO` completed successfully!");
-        Ok(info)
-    }
-
-    pub(crate) fn model(&self) -> String {
-        // SAFETY: if the string isn't valid, that will be reported and the string
-        // will be given a safe default
-        unsafe {
-            CStr::from_ptr(self.model.as_ptr())
-                .to_str()
-                .unwrap_or_else(|_| {
-                    tracing::error!("`ioctl` to get capture device model contained invalid UTF-8");
-                    "Model was not valid UTF-8"
-                })
-                .to_string()
-        }
+        Ok(Self::from_raw(&raw))
     }
 
-    pub(crate) fn serial(&self) -> String {
-        // SAFETY: if the string isn't valid, that will be reported and the string
-        // will be given a safe default
-        unsafe {
-            CStr::from_ptr(self.serial.as_ptr())
-                .to_str()
-                .unwrap_or_else(|_| {
-                    tracing::error!("`ioctl` to get capture device serial contained invalid UTF-8");
-                    "Serial was not valid UTF-8"
-                })
-                .to_string()
+    /// Reads the kernel's strings and version numbers.
+    ///
+    /// Strings that aren't valid UTF-8 have their bad bytes replaced.
+    fn from_raw(raw: &RawMediaDeviceInfo) -> Self {
+        Self {
+            model: fixed_str(&raw.model),
+            serial: fixed_str(&raw.serial),
+            info: V4LMediaInfo {
+                driver: fixed_str(&raw.driver),
+                bus_info: fixed_str(&raw.bus_info),
+                media_version: V4LVersion::from_raw(raw.media_version),
+                hw_revision: raw.hw_revision,
+                driver_version: V4LVersion::from_raw(raw.driver_version),
+            },
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::path::PathBuf;
-
     use super::*;
 
+    /// Copies a string into a fixed-size kernel buffer.
+    fn fixed<const N: usize>(value: &str) -> [u8; N] {
+        let mut buf = [0; N];
+        for (to, from) in buf.iter_mut().zip(value.bytes()) {
+            *to = from;
+        }
+        buf
+    }
+
     #[test]
     fn test_media_device_info() {
-        let info = MediaDeviceInfo::get(&PathBuf::from("/dev/media0")).unwrap();
-        assert_eq!(String::from("C922 Pro Stream Webcam"), info.model());
+        let raw = RawMediaDeviceInfo {
+            driver: fixed("uvcvideo"),
+            model: fixed("C922 Pro Stream Webcam"),
+            serial: fixed("ABCD1234"),
+            bus_info: fixed("usb-0000:00:14.0-1"),
+            media_version: 0x0006_0803,
+            hw_revision: 0x0016,
+            driver_version: 0x0006_0803,
+            reserved: [0; 31],
+        };
+
+        let info = MediaDeviceInfo::from_raw(&raw);
+        assert_eq!(String::from("C922 Pro Stream Webcam"), info.model);
+        assert_eq!(info.serial, "ABCD1234", "serial");
+        assert_eq!(info.info.driver, "uvcvideo", "driver");
+        assert_eq!(info.info.driver_version.to_string(), "6.8.3", "version");
+
+        // a serial that fills its whole buffer has no nul at the end
+        let full = RawMediaDeviceInfo {
+            serial: [b'7'; 40],
+            ..raw
+        };
+        assert_eq!(
+            MediaDeviceInfo::from_raw(&full).serial.len(),
+            40,
+            "unterminated strings are kept whole"
+        );
+
+        // bad bytes are replaced, and the rest of the string is kept
+        let mut model = fixed::<32>("Cam");
+        if let Some(byte) = model.get_mut(1) {
+            *byte = 0xFF;
+        }
+        let broken = RawMediaDeviceInfo { model, ..raw };
+        assert_eq!(
+            MediaDeviceInfo::from_raw(&broken).model,
+            "C\u{FFFD}m",
+            "invalid utf-8"
+        );
     }
 }
@@ -0,0 +1,293 @@
+//! Turns the kernel's error numbers into our error types.
+//!
+//! Video4Linux reports most problems through `errno`, and the same number
+//! can mean different things depending on the call. A few numbers mean the
+//! same thing everywhere, like `ENODEV`, so every error type is sorted by
+//! one classifier first.
+
+use std::io::{self, ErrorKind};
+
+use nix::errno::Errno;
+
+use crate::error::{
+    VideoCaptureConfigError as ConfigError, VideoCaptureConnectionError as ConnectionError,
+    VideoCaptureUsageError as UsageError,
+};
+use crate::frame::FrameCorruption;
+
+/// Finds the raw error number for an IO error.
+///
+/// Some errors (like `v4l`'s poll timeouts) are made without one, so those
+/// get the closest match for their kind.
+fn errno_of(err: &io::Error) -> Option<Errno> {
+    err.raw_os_error()
+        .map(Errno::from_raw)
+        .or_else(|| match err.kind() {
+            ErrorKind::WouldBlock => Some(Errno::EAGAIN),
+            ErrorKind::TimedOut => Some(Errno::ETIMEDOUT),
+            ErrorKind::PermissionDenied => Some(Errno::EACCES),
+            _ => None,
+        })
+}
+
+/// What an error number says about a device, whatever the call was.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Classified {
+    /// Nothing happened in time.
+    Timeout(i32),
+    /// The device is gone, like after being unplugged.
+    Disconnected(i32),
+    /// Someone else is using the device.
+    Busy(i32),
+    PermissionDenied(i32),
+    /// Anything else. What it means depends on the call.
+    Other(Option<Errno>),
+}
+
+/// Sorts an IO error from any call on a device.
+///
+/// Every error type is made from this, so the same number always means the
+/// same thing.
+fn classify(err: &io::Error) -> Classified {
+    match errno_of(err) {
+        Some(errno @ (Errno::EAGAIN | Errno::ETIMEDOUT)) => Classified::Timeout(errno as i32),
+        Some(errno @ (Errno::ENODEV | Errno::ENXIO | Errno::ESHUTDOWN)) => {
+            Classified::Disconnected(errno as i32)
+        }
+        Some(errno @ Errno::EBUSY) => Classified::Busy(errno as i32),
+        Some(errno @ (Errno::EACCES | Errno::EPERM)) => Classified::PermissionDenied(errno as i32),
+        other => Classified::Other(other),
+    }
+}
+
+/// Makes an error for a failed read from a capture device.
+pub(super) fn usage_error(source: String, err: &io::Error) -> UsageError {
+    match classify(err) {
+        Classified::Timeout(errno) => UsageError::Timeout { source, errno },
+        Classified::Disconnected(errno) => UsageError::Disconnected { source, errno },
+        Classified::PermissionDenied(errno) => UsageError::PermissionDenied { source, errno },
+        Classified::Busy(errno) => UsageError::DeviceBusy { source, errno },
+        // `VIDIOC_DQBUF` says this for lost signals and bad transfers. the
+        // stream keeps going
+        Classified::Other(Some(errno @ Errno::EIO)) => UsageError::CorruptFrame {
+            source,
+            corruption: FrameCorruption::DriverError {
+                errno: errno as i32,
+            },
+        },
+        // ...and this when there are no buffers to dequeue from
+        Classified::Other(Some(errno @ (Errno::EINVAL | Errno::EPIPE))) => {
+            UsageError::NotStreaming {
+                source,
+                errno: errno as i32,
+            }
+        }
+        Classified::Other(_) => UsageError::IoError {
+            source,
+            err_msg: err.to_string(),
+        },
+    }
+}
+
+/// Makes an error for a failed configuration call.
+///
+/// Busy, disconnected, and timed out devices get their own variants.
+/// Anything else is up to `otherwise`.
+pub(super) fn config_error<F: FnOnce(io::Error) -> ConfigError>(
+    source: &str,
+    err: io::Error,
+    otherwise: F,
+) -> ConfigError {
+    match classify(&err) {
+        Classified::Busy(errno) => ConfigError::DeviceBusy {
+            source: source.to_owned(),
+            errno,
+        },
+        Classified::Disconnected(errno) => ConfigError::Disconnected {
+            source: source.to_owned(),
+            errno,
+        },
+        Classified::Timeout(errno) => ConfigError::Timeout {
+            source: source.to_owned(),
+            errno,
+        },
+        Classified::PermissionDenied(_) | Classified::Other(_) => otherwise(err),
+    }
+}
+
+/// Makes an error for a failed call while connecting, streaming, or
+/// stopping.
+///
+/// Busy, disconnected, missing, and timed out devices get their own
+/// variants. Anything else is up to `otherwise`.
+pub(super) fn connection_error<F: FnOnce(io::Error) -> ConnectionError>(
+    source: &str,
+    err: io::Error,
+    otherwise: F,
+) -> ConnectionError {
+    match classify(&err) {
+        Classified::Busy(_) => ConnectionError::CaptureDeviceBusy {
+            source: source.to_owned(),
+            err_msg: err.to_string(),
+        },
+        Classified::Disconnected(errno) => ConnectionError::Disconnected {
+            source: source.to_owned(),
+            errno,
+        },
+        Classified::Timeout(errno) => ConnectionError::Timeout {
+            source: source.to_owned(),
+            errno,
+        },
+        Classified::Other(Some(Errno::ENOENT)) => ConnectionError::SourceDoesntExist {
+            source: source.to_owned(),
+        },
+        Classified::PermissionDenied(_) | Classified::Other(_) => otherwise(err),
+    }
+}
+
+/// The usual fallback for [`connection_error`], for calls without a better
+/// variant.
+pub(super) fn odd_io_error(source: &str, err: &io::Error) -> ConnectionError {
+    ConnectionError::OddIOError {
+        source: source.to_owned(),
+        err_kind: err.kind(),
+        err_msg: err.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errnos_pick_usage_errors() {
+        let read = |err: io::Error| usage_error(String::from("/dev/video0"), &err);
+
+        let timeout = read(Errno::EAGAIN.into());
+        assert!(timeout.is_transient(), "EAGAIN is a timeout: {timeout}");
+        assert_eq!(timeout.errno(), Some(Errno::EAGAIN as i32));
+        assert!(
+            read(io::Error::new(ErrorKind::TimedOut, "VIDIOC_DQBUF")).is_transient(),
+            "poll timeouts have no errno"
+        );
+
+        let unplugged = read(Errno::ENODEV.into());
+        assert!(
+            unplugged.is_disconnect(),
+            "ENODEV is a disconnect: {unplugged}"
+        );
+        assert!(!unplugged.is_transient(), "disconnects aren't transient");
+        assert_eq!(unplugged.errno(), Some(Errno::ENODEV as i32));
+
+        let late = read(Errno::ETIMEDOUT.into());
+        assert!(
+            matches!(late, UsageError::Timeout { errno, .. } if errno == Errno::ETIMEDOUT as i32),
+            "ETIMEDOUT is a timeout: {late}"
+        );
+
+        let corrupt = read(Errno::EIO.into());
+        assert!(
+            matches!(corrupt, UsageError::CorruptFrame { .. }),
+            "EIO is a corrupt frame: {corrupt}"
+        );
+        assert_eq!(corrupt.errno(), Some(Errno::EIO as i32));
+        assert!(matches!(
+            read(Errno::EINVAL.into()),
+            UsageError::NotStreaming { .. }
+        ));
+        assert!(matches!(
+            read(Errno::EPERM.into()),
+            UsageError::PermissionDenied { .. }
+        ));
+
+        let busy = read(Errno::EBUSY.into());
+        assert!(
+            matches!(busy, UsageError::DeviceBusy { .. }),
+            "EBUSY is a busy device: {busy}"
+        );
+        assert_eq!(busy.errno(), Some(Errno::EBUSY as i32));
+
+        let other = read(io::Error::other("driver dequeued a buffer we don't have"));
+        assert!(
+            matches!(other, UsageError::IoError { .. }) && other.errno().is_none(),
+            "errors without an errno stay generic"
+        );
+    }
+
+    #[test]
+    fn errnos_pick_config_errors() {
+        let write = |err: io::Error| {
+            config_error("/dev/video0", err, |e| ConfigError::PropertyWriteFailure {
+                source: String::from("/dev/video0"),
+                err_msg: e.to_string(),
+            })
+        };
+
+        assert!(write(Errno::EBUSY.into()).is_transient(), "busy devices");
+
+        let unplugged = write(Errno::ENODEV.into());
+        assert!(unplugged.is_disconnect(), "unplugged devices: {unplugged}");
+        assert_eq!(unplugged.errno(), Some(Errno::ENODEV as i32));
+
+        let late = write(Errno::ETIMEDOUT.into());
+        assert!(
+            matches!(late, ConfigError::Timeout { .. }) && late.is_transient(),
+            "ETIMEDOUT is a timeout: {late}"
+        );
+        assert_eq!(late.errno(), Some(Errno::ETIMEDOUT as i32));
+
+        for errno in [Errno::EIO, Errno::ERANGE] {
+            let other = write(errno.into());
+            assert!(
+                matches!(other, ConfigError::PropertyWriteFailure { .. }),
+                "{errno} uses the fallback: {other}"
+            );
+        }
+    }
+
+    #[test]
+    fn errnos_pick_connection_errors() {
+        let stream = |err: io::Error| {
+            connection_error("/dev/video0", err, |e| odd_io_error("/dev/video0", &e))
+        };
+
+        let unplugged = stream(Errno::ENODEV.into());
+        assert!(
+            matches!(unplugged, ConnectionError::Disconnected { .. }) && unplugged.is_disconnect(),
+            "ENODEV is a disconnect: {unplugged}"
+        );
+        assert_eq!(unplugged.errno(), Some(Errno::ENODEV as i32));
+        for errno in [Errno::ENXIO, Errno::ESHUTDOWN] {
+            assert!(stream(errno.into()).is_disconnect(), "{errno} too");
+        }
+
+        let late = stream(Errno::ETIMEDOUT.into());
+        assert!(
+            matches!(late, ConnectionError::Timeout { .. }) && late.is_transient(),
+            "ETIMEDOUT is a timeout: {late}"
+        );
+        assert_eq!(late.errno(), Some(Errno::ETIMEDOUT as i32));
+
+        let broken = stream(Errno::EIO.into());
+        assert!(
+            matches!(broken, ConnectionError::OddIOError { .. }),
+            "EIO uses the fallback: {broken}"
+        );
+        assert_eq!(broken.errno(), None, "the fallback has no errno");
+
+        assert!(
+            matches!(
+                stream(Errno::EBUSY.into()),
+                ConnectionError::CaptureDeviceBusy { .. }
+            ),
+            "busy devices"
+        );
+        assert!(
+            matches!(
+                stream(Errno::ENOENT.into()),
+                ConnectionError::SourceDoesntExist { .. }
+            ),
+            "missing devices"
+        );
+    }
+}
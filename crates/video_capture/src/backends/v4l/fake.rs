@@ -0,0 +1,872 @@
+//! A pretend kernel, for testing the backend without any hardware.
+//!
+//! Build one up with the `with_*` methods, then hand it to anything that
+//! takes a [`Kernel`]. Each device node gets a `/dev` entry, a device number,
+//! and a sysfs `uevent`, just like on a real system. Calls can be made to
+//! fail with [`FakeKernel::failing`].
+//!
+//! Open devices are backed by `/dev/null`, so there's a real descriptor to
+//! pass around, but none of their calls reach it. Streams hand out ordinary
+//! memory as their buffers, and a frame is ready whenever a buffer is
+//! queued.
+
+extern crate alloc;
+
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::sync::Arc;
+use core::time::Duration;
+use std::io::{self, ErrorKind};
+use std::os::fd::RawFd;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use fraction::Fraction;
+use nix::errno::Errno;
+use v4l::control::{Control, Description, Flags, Type, Value};
+use v4l::Device;
+
+use crate::config::SpecificResolution;
+
+use super::capabilities::{V4LCapabilities, V4LCapabilityFlags, V4LVersion};
+use super::device_info::{MediaDeviceInfo, V4LMediaInfo};
+use super::format::{PixFormat, PlaneFormat};
+use super::kernel::{FrameInterval, FrameSize, Kernel};
+use super::raw::{
+    MappedBuffer, V4l2Buffer, V4L2_BUF_TYPE_META_CAPTURE, V4L2_BUF_TYPE_VIDEO_CAPTURE,
+    V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE,
+};
+use super::topology::{MediaEntity, MediaInterface, MediaInterfaceKind, MediaLink, MediaTopology};
+
+/// The calls that a [`FakeKernel`] can be told to fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) enum Call {
+    MediaDeviceInfo,
+    MediaTopology,
+    QueryCap,
+    EnumFormats,
+    EnumFramesizes,
+    EnumFrameintervals,
+    FrameInterval,
+    Open,
+    GetFormat,
+    SetFormat,
+    SetFrameInterval,
+    QueryControls,
+    GetControl,
+    SetControl,
+    RequestBuffers,
+    QueryBuffer,
+    QueueBuffer,
+    DequeueBuffer,
+    StreamOn,
+    StreamOff,
+}
+
+/// A call that changed something about the open device.
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum Issued {
+    Open(PathBuf),
+    SetFormat {
+        buf_type: u32,
+        fourcc: [u8; 4],
+        resolution: SpecificResolution,
+    },
+    SetFrameInterval {
+        buf_type: u32,
+        numerator: u32,
+        denominator: u32,
+    },
+    SetControl {
+        id: u32,
+        value: i64,
+    },
+    StreamOn(u32),
+    StreamOff(u32),
+}
+
+/// A kernel that replays canned responses.
+///
+/// Calls on file descriptors ignore the descriptor, so each fake describes
+/// one open device at a time. What that device is doing right now (its
+/// format, controls, and stream) is shared between clones, so a test can
+/// keep one to look at after handing another to the backend.
+#[derive(Clone, Debug, Default)]
+pub(super) struct FakeKernel {
+    /// Regular files, like sysfs `uevent`s.
+    files: BTreeMap<PathBuf, String>,
+    /// Device nodes and their numbers.
+    nodes: BTreeMap<PathBuf, (u32, u32)>,
+    links: BTreeMap<PathBuf, PathBuf>,
+    media: BTreeMap<PathBuf, (MediaDeviceInfo, MediaTopology)>,
+    capabilities: BTreeMap<PathBuf, V4LCapabilities>,
+    formats: BTreeMap<u32, Vec<[u8; 4]>>,
+    framesizes: BTreeMap<[u8; 4], Vec<FrameSize>>,
+    frameintervals: BTreeMap<([u8; 4], u32, u32), Vec<FrameInterval>>,
+    failures: BTreeMap<Call, Errno>,
+    /// Failures for calls on just one buffer type.
+    stream_failures: BTreeMap<(Call, u32), Errno>,
+    /// What each node's buffers start with, by buffer index.
+    contents: BTreeMap<PathBuf, Vec<Vec<u8>>>,
+    state: Arc<Mutex<FakeState>>,
+}
+
+/// What the open device is doing right now.
+#[derive(Debug, Default)]
+struct FakeState {
+    /// The format each buffer type is using.
+    formats: BTreeMap<u32, PixFormat>,
+    frame_interval: Option<(u32, u32)>,
+    controls: Vec<FakeControl>,
+    /// Each buffer type's stream.
+    streams: BTreeMap<u32, FakeStream>,
+    /// The buffer type that each descriptor streams.
+    streaming_fds: BTreeMap<RawFd, u32>,
+    unplugged: bool,
+    /// Descriptors that were open when we were unplugged. They stay broken,
+    /// even after we're plugged back in.
+    dead: BTreeSet<RawFd>,
+    /// Descriptors opened since we were last plugged in, and their nodes.
+    open: BTreeMap<RawFd, PathBuf>,
+    issued: Vec<Issued>,
+}
+
+/// One buffer type's stream.
+#[derive(Debug, Default)]
+struct FakeStream {
+    /// How many buffers the driver has handed out.
+    buffers: u32,
+    /// The buffers we've been given, oldest first.
+    queued: VecDeque<u32>,
+    streaming: bool,
+    /// The sequence number of the next frame.
+    sequence: u32,
+}
+
+impl FakeState {
+    fn stream(&mut self, buf_type: u32) -> &mut FakeStream {
+        self.streams.entry(buf_type).or_default()
+    }
+
+    /// Checks if a stream has a finished buffer.
+    ///
+    /// Metadata is finished just before its frame, so the metadata stream
+    /// never gets ahead of the capture stream.
+    fn has_frame(&self, buf_type: u32) -> bool {
+        let Some(stream) = self.streams.get(&buf_type) else {
+            return false;
+        };
+
+        let caught_up = buf_type != V4L2_BUF_TYPE_META_CAPTURE
+            || self
+                .streams
+                .get(&V4L2_BUF_TYPE_VIDEO_CAPTURE)
+                .is_some_and(|video| stream.sequence < video.sequence);
+        stream.streaming && !stream.queued.is_empty() && caught_up
+    }
+}
+
+/// A control and its current value.
+#[derive(Clone, Debug)]
+struct FakeControl {
+    id: u32,
+    name: String,
+    typ: Type,
+    minimum: i64,
+    maximum: i64,
+    value: i64,
+}
+
+impl FakeControl {
+    fn description(&self) -> Description {
+        Description {
+            id: self.id,
+            typ: self.typ,
+            name: self.name.clone(),
+            minimum: self.minimum,
+            maximum: self.maximum,
+            step: 1,
+            default: self.minimum,
+            flags: Flags::empty(),
+            items: None,
+        }
+    }
+}
+
+impl FakeKernel {
+    /// Adds a device node, along with its sysfs `uevent`.
+    pub(super) fn with_node(mut self, path: &str, major: u32, minor: u32) -> Self {
+        let name = path.strip_prefix("/dev/").unwrap_or(path);
+        self.files.insert(
+            PathBuf::from(format!("/sys/dev/char/{major}:{minor}/uevent")),
+            format!("MAJOR={major}\nMINOR={minor}\nDEVNAME={name}\n"),
+        );
+        self.nodes.insert(PathBuf::from(path), (major, minor));
+        self
+    }
+
+    /// Adds a symlink. Relative targets start from the link's directory.
+    pub(super) fn with_link(mut self, link: &str, target: &str) -> Self {
+        self.links
+            .insert(PathBuf::from(link), PathBuf::from(target));
+        self
+    }
+
+    /// Adds a media device node, with what it says about itself.
+    pub(super) fn with_media(
+        mut self,
+        path: &str,
+        (major, minor): (u32, u32),
+        info: MediaDeviceInfo,
+        topology: MediaTopology,
+    ) -> Self {
+        self.media.insert(PathBuf::from(path), (info, topology));
+        self.with_node(path, major, minor)
+    }
+
+    /// Adds a video node, with what it can do.
+    pub(super) fn with_video(
+        mut self,
+        path: &str,
+        (major, minor): (u32, u32),
+        flags: V4LCapabilityFlags,
+    ) -> Self {
+        let capabilities = V4LCapabilities {
+            driver: String::from("uvcvideo"),
+            card: String::from("Fake Camera"),
+            bus_info: String::from("usb-0000:00:14.0-1"),
+            version: V4LVersion::from_raw(0x0006_0803),
+            capabilities: flags,
+            device_capabilities: flags,
+        };
+        self.capabilities.insert(PathBuf::from(path), capabilities);
+        self.with_node(path, major, minor)
+    }
+
+    /// Sets the formats that the open device lists for a buffer type.
+    pub(super) fn with_formats(mut self, buf_type: u32, fourccs: &[[u8; 4]]) -> Self {
+        self.formats.insert(buf_type, fourccs.to_vec());
+        self
+    }
+
+    /// Sets the frame sizes that the open device lists for a format.
+    pub(super) fn with_framesizes(mut self, fourcc: [u8; 4], sizes: &[FrameSize]) -> Self {
+        self.framesizes.insert(fourcc, sizes.to_vec());
+        self
+    }
+
+    /// Sets the frame intervals that the open device lists for a format and
+    /// frame size.
+    pub(super) fn with_frameintervals(
+        mut self,
+        fourcc: [u8; 4],
+        (width, height): (u32, u32),
+        intervals: &[FrameInterval],
+    ) -> Self {
+        self.frameintervals
+            .insert((fourcc, width, height), intervals.to_vec());
+        self
+    }
+
+    /// Sets the frame interval that the open device is using.
+    pub(super) fn with_frame_interval(self, numerator: u32, denominator: u32) -> Self {
+        self.state().frame_interval = Some((numerator, denominator));
+        self
+    }
+
+    /// Sets the format that the open device is using for a buffer type.
+    pub(super) fn with_current_format(
+        self,
+        buf_type: u32,
+        fourcc: [u8; 4],
+        (width, height): (u32, u32),
+    ) -> Self {
+        let format = packed_format(fourcc, SpecificResolution::new(width, height));
+        self.state().formats.insert(buf_type, format);
+        self
+    }
+
+    /// Adds an integer, boolean, or menu control, with its current value.
+    pub(super) fn with_control(
+        self,
+        id: u32,
+        name: &str,
+        typ: Type,
+        (minimum, maximum): (i64, i64),
+        value: i64,
+    ) -> Self {
+        self.state().controls.push(FakeControl {
+            id,
+            name: String::from(name),
+            typ,
+            minimum,
+            maximum,
+            value,
+        });
+        self
+    }
+
+    /// Makes a call fail with the given error code.
+    pub(super) fn failing(mut self, call: Call, errno: Errno) -> Self {
+        self.failures.insert(call, errno);
+        self
+    }
+
+    /// Makes a call fail, but only for one buffer type.
+    pub(super) fn failing_stream(mut self, call: Call, buf_type: u32, errno: Errno) -> Self {
+        self.stream_failures.insert((call, buf_type), errno);
+        self
+    }
+
+    /// Fills a node's buffers, by index, whenever they're mapped.
+    pub(super) fn with_buffer_contents(mut self, path: &str, contents: Vec<Vec<u8>>) -> Self {
+        self.contents.insert(PathBuf::from(path), contents);
+        self
+    }
+
+    /// Unplugs the device. Calls on it fail with `ENODEV` until it's plugged
+    /// back in.
+    pub(super) fn unplug(&self) {
+        let mut state = self.state();
+        let open = core::mem::take(&mut state.open);
+        state.dead.extend(open.into_keys());
+        state.unplugged = true;
+        state.streams.clear();
+        state.streaming_fds.clear();
+    }
+
+    /// Plugs the device back in.
+    pub(super) fn replug(&self) {
+        self.state().unplugged = false;
+    }
+
+    /// Every call that changed the open device, in order.
+    pub(super) fn issued(&self) -> Vec<Issued> {
+        self.state().issued.clone()
+    }
+
+    /// Checks if the open device is streaming a buffer type.
+    pub(super) fn is_streaming(&self, buf_type: u32) -> bool {
+        self.state()
+            .streams
+            .get(&buf_type)
+            .is_some_and(|stream| stream.streaming)
+    }
+
+    fn state(&self) -> MutexGuard<'_, FakeState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn check(&self, call: Call) -> io::Result<()> {
+        match self.failures.get(&call) {
+            Some(&errno) => Err(errno.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks a call on one buffer type's stream.
+    fn check_stream(&self, call: Call, buf_type: u32) -> io::Result<()> {
+        match self.stream_failures.get(&(call, buf_type)) {
+            Some(&errno) => Err(errno.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks a call on the open device, then locks its state.
+    fn open_device(&self, call: Call, fd: RawFd) -> io::Result<MutexGuard<'_, FakeState>> {
+        self.check(call)?;
+        let state = self.state();
+        if state.unplugged || state.dead.contains(&fd) {
+            return Err(Errno::ENODEV.into());
+        }
+        Ok(state)
+    }
+
+    /// Follows a symlink, if the path is one.
+    fn follow(&self, path: &Path) -> PathBuf {
+        self.links.get(path).map_or_else(
+            || path.to_owned(),
+            |target| {
+                let joined = path.parent().unwrap_or(path).join(target);
+                let mut resolved = PathBuf::new();
+                for component in joined.components() {
+                    if component == std::path::Component::ParentDir {
+                        resolved.pop();
+                    } else {
+                        resolved.push(component);
+                    }
+                }
+                resolved
+            },
+        )
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.files.contains_key(path)
+            || self.nodes.contains_key(path)
+            || self.links.contains_key(path)
+    }
+
+    /// Fails like the real kernel would for paths that aren't there, or
+    /// aren't the right kind of device.
+    fn missing(&self, path: &Path) -> io::Error {
+        if self.exists(path) {
+            Errno::ENOTTY.into()
+        } else {
+            ErrorKind::NotFound.into()
+        }
+    }
+}
+
+impl Kernel for FakeKernel {
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        match self.links.get(path) {
+            Some(target) => Ok(target.clone()),
+            None if self.exists(path) => Err(ErrorKind::InvalidInput.into()),
+            None => Err(ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        Ok(self
+            .files
+            .keys()
+            .chain(self.nodes.keys())
+            .chain(self.links.keys())
+            .filter(|path| path.parent() == Some(dir))
+            .cloned()
+            .collect())
+    }
+
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        self.files
+            .get(path)
+            .cloned()
+            .ok_or_else(|| ErrorKind::NotFound.into())
+    }
+
+    fn device_number(&self, path: &Path) -> io::Result<(u32, u32)> {
+        let followed = self.follow(path);
+        self.nodes
+            .get(&followed)
+            .copied()
+            .ok_or_else(|| self.missing(&followed))
+    }
+
+    fn media_device_info(&self, media: &Path) -> io::Result<MediaDeviceInfo> {
+        self.check(Call::MediaDeviceInfo)?;
+        self.media
+            .get(media)
+            .map(|entry| entry.0.clone())
+            .ok_or_else(|| self.missing(media))
+    }
+
+    fn media_topology(&self, media: &Path) -> io::Result<MediaTopology> {
+        self.check(Call::MediaTopology)?;
+        self.media
+            .get(media)
+            .map(|entry| entry.1.clone())
+            .ok_or_else(|| self.missing(media))
+    }
+
+    fn capabilities(&self, video: &Path) -> io::Result<V4LCapabilities> {
+        self.check(Call::QueryCap)?;
+        self.capabilities
+            .get(video)
+            .cloned()
+            .ok_or_else(|| self.missing(video))
+    }
+
+    fn enum_formats(&self, _fd: RawFd, buf_type: u32) -> io::Result<Vec<[u8; 4]>> {
+        self.check(Call::EnumFormats)?;
+        Ok(self.formats.get(&buf_type).cloned().unwrap_or_default())
+    }
+
+    fn enum_framesizes(&self, _fd: RawFd, fourcc: [u8; 4]) -> io::Result<Vec<FrameSize>> {
+        self.check(Call::EnumFramesizes)?;
+        Ok(self.framesizes.get(&fourcc).cloned().unwrap_or_default())
+    }
+
+    fn enum_frameintervals(
+        &self,
+        _fd: RawFd,
+        fourcc: [u8; 4],
+        resolution: SpecificResolution,
+    ) -> io::Result<Vec<FrameInterval>> {
+        self.check(Call::EnumFrameintervals)?;
+        Ok(self
+            .frameintervals
+            .get(&(fourcc, resolution.width, resolution.height))
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    fn frame_interval(&self, fd: RawFd, _buf_type: u32) -> io::Result<Fraction> {
+        let state = self.open_device(Call::FrameInterval, fd)?;
+        let (numerator, denominator) = state.frame_interval.ok_or(Errno::EINVAL)?;
+        Ok(Fraction::new(numerator, denominator))
+    }
+
+    fn open(&self, video: &Path) -> io::Result<Device> {
+        self.check(Call::Open)?;
+        let mut state = self.state();
+        if state.unplugged || !self.capabilities.contains_key(video) {
+            return Err(ErrorKind::NotFound.into());
+        }
+
+        let device = Device::with_path("/dev/null")?;
+        let fd = device.handle().fd();
+        state.dead.remove(&fd);
+        state.open.insert(fd, video.to_owned());
+        state.issued.push(Issued::Open(video.to_owned()));
+        Ok(device)
+    }
+
+    fn format(&self, fd: RawFd, buf_type: u32) -> io::Result<PixFormat> {
+        let state = self.open_device(Call::GetFormat, fd)?;
+        state
+            .formats
+            .get(&buf_type)
+            .cloned()
+            .ok_or_else(|| Errno::EINVAL.into())
+    }
+
+    fn set_format(
+        &self,
+        fd: RawFd,
+        buf_type: u32,
+        fourcc: [u8; 4],
+        resolution: SpecificResolution,
+    ) -> io::Result<PixFormat> {
+        let mut state = self.open_device(Call::SetFormat, fd)?;
+        if state.stream(buf_type).buffers > 0 {
+            return Err(Errno::EBUSY.into());
+        }
+
+        state.issued.push(Issued::SetFormat {
+            buf_type,
+            fourcc,
+            resolution,
+        });
+        let format = packed_format(fourcc, resolution);
+        state.formats.insert(buf_type, format.clone());
+        Ok(format)
+    }
+
+    fn set_frame_interval(
+        &self,
+        fd: RawFd,
+        buf_type: u32,
+        numerator: u32,
+        denominator: u32,
+    ) -> io::Result<()> {
+        let mut state = self.open_device(Call::SetFrameInterval, fd)?;
+        state.issued.push(Issued::SetFrameInterval {
+            buf_type,
+            numerator,
+            denominator,
+        });
+        state.frame_interval = Some((numerator, denominator));
+        Ok(())
+    }
+
+    fn controls(&self, device: &Device) -> io::Result<Vec<Description>> {
+        let state = self.open_device(Call::QueryControls, device.handle().fd())?;
+        Ok(state
+            .controls
+            .iter()
+            .map(FakeControl::description)
+            .collect())
+    }
+
+    fn control(&self, device: &Device, id: u32) -> io::Result<Control> {
+        let state = self.open_device(Call::GetControl, device.handle().fd())?;
+        let control = state
+            .controls
+            .iter()
+            .find(|control| control.id == id)
+            .ok_or(Errno::EINVAL)?;
+
+        let value = match control.typ {
+            Type::Boolean => Value::Boolean(control.value != 0),
+            _ => Value::Integer(control.value),
+        };
+        Ok(Control { id, value })
+    }
+
+    fn set_control(&self, device: &Device, control: Control) -> io::Result<()> {
+        let mut state = self.open_device(Call::SetControl, device.handle().fd())?;
+        let value = match control.value {
+            Value::Integer(value) => value,
+            Value::Boolean(value) => i64::from(value),
+            _ => return Err(Errno::EINVAL.into()),
+        };
+
+        let found = state
+            .controls
+            .iter_mut()
+            .find(|known| known.id == control.id)
+            .ok_or(Errno::EINVAL)?;
+        found.value = value;
+        state.issued.push(Issued::SetControl {
+            id: control.id,
+            value,
+        });
+        Ok(())
+    }
+
+    fn request_buffers(
+        &self,
+        fd: RawFd,
+        buf_type: u32,
+        _memory: u32,
+        count: u32,
+    ) -> io::Result<u32> {
+        self.check_stream(Call::RequestBuffers, buf_type)?;
+        let mut state = self.open_device(Call::RequestBuffers, fd)?;
+        // the fake only streams single-planar buffers
+        if buf_type == V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE {
+            return Err(Errno::EINVAL.into());
+        }
+
+        let stream = state.stream(buf_type);
+        if stream.streaming {
+            return Err(Errno::EBUSY.into());
+        }
+        stream.buffers = count;
+        stream.queued.clear();
+        state.streaming_fds.insert(fd, buf_type);
+        Ok(count)
+    }
+
+    fn query_buffer(&self, fd: RawFd, buffer: &mut V4l2Buffer) -> io::Result<()> {
+        let mut state = self.open_device(Call::QueryBuffer, fd)?;
+        if buffer.index >= state.stream(buffer.r#type).buffers {
+            return Err(Errno::EINVAL.into());
+        }
+
+        let size = state
+            .formats
+            .get(&buffer.r#type)
+            .ok_or(Errno::EINVAL)?
+            .first_plane()
+            .size;
+        buffer.length = size;
+        buffer.m.offset = buffer.index.saturating_mul(size);
+        Ok(())
+    }
+
+    fn map_buffer(&self, fd: RawFd, length: u32, offset: u32) -> io::Result<MappedBuffer> {
+        // buffers are laid out one after another
+        let index = offset.checked_div(length).unwrap_or_default();
+        let node = self.state().open.get(&fd).cloned();
+        let contents = node
+            .and_then(|path| self.contents.get(&path))
+            .and_then(|contents| contents.get(index as usize))
+            .map_or(&[][..], Vec::as_slice);
+
+        MappedBuffer::anonymous(length, contents)
+    }
+
+    fn queue_buffer(&self, fd: RawFd, buffer: &mut V4l2Buffer) -> io::Result<()> {
+        self.check_stream(Call::QueueBuffer, buffer.r#type)?;
+        let mut state = self.open_device(Call::QueueBuffer, fd)?;
+        let stream = state.stream(buffer.r#type);
+        if buffer.index >= stream.buffers {
+            return Err(Errno::EINVAL.into());
+        }
+
+        stream.queued.push_back(buffer.index);
+        Ok(())
+    }
+
+    fn dequeue_buffer(&self, fd: RawFd, buffer: &mut V4l2Buffer) -> io::Result<()> {
+        self.check_stream(Call::DequeueBuffer, buffer.r#type)?;
+        let mut state = self.open_device(Call::DequeueBuffer, fd)?;
+        if !state.stream(buffer.r#type).streaming {
+            return Err(Errno::EINVAL.into());
+        }
+        if !state.has_frame(buffer.r#type) {
+            return Err(Errno::EAGAIN.into());
+        }
+
+        let size = state
+            .formats
+            .get(&buffer.r#type)
+            .map_or(0, |format| format.first_plane().size);
+        let stream = state.stream(buffer.r#type);
+        buffer.index = stream.queued.pop_front().ok_or(Errno::EAGAIN)?;
+        buffer.bytesused = size;
+        buffer.flags = 0;
+        buffer.sequence = stream.sequence;
+        stream.sequence = stream.sequence.wrapping_add(1);
+        Ok(())
+    }
+
+    fn stream_on(&self, fd: RawFd, buf_type: u32) -> io::Result<()> {
+        self.check_stream(Call::StreamOn, buf_type)?;
+        let mut state = self.open_device(Call::StreamOn, fd)?;
+        let stream = state.stream(buf_type);
+        if stream.buffers == 0 {
+            return Err(Errno::EINVAL.into());
+        }
+
+        stream.streaming = true;
+        stream.sequence = 0;
+
+        // metadata sequence numbers follow the frames they go with
+        if buf_type == V4L2_BUF_TYPE_META_CAPTURE {
+            let frames = state
+                .streams
+                .get(&V4L2_BUF_TYPE_VIDEO_CAPTURE)
+                .map_or(0, |video| video.sequence);
+            state.stream(buf_type).sequence = frames;
+        }
+        state.issued.push(Issued::StreamOn(buf_type));
+        Ok(())
+    }
+
+    fn stream_off(&self, fd: RawFd, buf_type: u32) -> io::Result<()> {
+        self.check_stream(Call::StreamOff, buf_type)?;
+        let mut state = self.open_device(Call::StreamOff, fd)?;
+        let stream = state.stream(buf_type);
+        stream.streaming = false;
+        stream.queued.clear();
+        state.issued.push(Issued::StreamOff(buf_type));
+        Ok(())
+    }
+
+    fn wait_readable(&self, fds: &[RawFd], _timeout: Option<Duration>) -> io::Result<Vec<usize>> {
+        let state = self.state();
+
+        // errors (like not streaming) count as readable, just like frames.
+        // otherwise, the time runs out right away
+        let ready = |fd: &RawFd| {
+            if state.unplugged || state.dead.contains(fd) {
+                return true;
+            }
+            state.streaming_fds.get(fd).is_none_or(|&buf_type| {
+                !state
+                    .streams
+                    .get(&buf_type)
+                    .is_some_and(|stream| stream.streaming)
+                    || state.has_frame(buf_type)
+            })
+        };
+
+        Ok(fds
+            .iter()
+            .enumerate()
+            .filter(|&(_, fd)| ready(fd))
+            .map(|(index, _)| index)
+            .collect())
+    }
+
+    fn wait_writable(&self, fds: &[RawFd], _timeout: Option<Duration>) -> io::Result<Vec<usize>> {
+        let state = self.state();
+
+        // nothing plays output buffers back, so only errors are writable
+        let ready = |fd: &RawFd| {
+            state.unplugged
+                || state.dead.contains(fd)
+                || state.streaming_fds.get(fd).is_none_or(|&buf_type| {
+                    !state
+                        .streams
+                        .get(&buf_type)
+                        .is_some_and(|stream| stream.streaming)
+                })
+        };
+
+        Ok(fds
+            .iter()
+            .enumerate()
+            .filter(|&(_, fd)| ready(fd))
+            .map(|(index, _)| index)
+            .collect())
+    }
+}
+
+/// A format with one plane of two bytes per pixel, like YUYV.
+fn packed_format(fourcc: [u8; 4], resolution: SpecificResolution) -> PixFormat {
+    let stride = resolution.width.saturating_mul(2);
+    PixFormat {
+        width: resolution.width,
+        height: resolution.height,
+        fourcc,
+        planes: vec![PlaneFormat {
+            size: stride.saturating_mul(resolution.height),
+            stride,
+        }],
+    }
+}
+
+/// A UVC webcam, the way it shows up on a typical laptop.
+///
+/// `/dev/media0` owns a capture node at `/dev/video0` and a metadata node
+/// at `/dev/video1`. There's also a `by-id` link to the capture node.
+pub(super) fn uvc_camera() -> FakeKernel {
+    let info = MediaDeviceInfo {
+        model: String::from("C922 Pro Stream Webcam"),
+        serial: String::from("ABCD1234"),
+        info: V4LMediaInfo {
+            driver: String::from("uvcvideo"),
+            bus_info: String::from("usb-0000:00:14.0-1"),
+            ..V4LMediaInfo::default()
+        },
+    };
+
+    // one entity for each video node, with an interface link to each
+    let video = |id: u32, minor: u32| MediaInterface {
+        id,
+        kind: MediaInterfaceKind::Video,
+        flags: 0,
+        major: 81,
+        minor,
+    };
+    let topology = MediaTopology {
+        version: 1,
+        entities: vec![
+            MediaEntity {
+                id: 1,
+                name: String::from("C922 Pro Stream Webcam"),
+                function: 0x0001_0001,
+                flags: 1,
+            },
+            MediaEntity {
+                id: 4,
+                name: String::from("C922 Pro Stream Webcam"),
+                function: 0x0001_0001,
+                flags: 0,
+            },
+        ],
+        interfaces: vec![video(3, 0), video(6, 1)],
+        pads: Vec::new(),
+        links: vec![
+            MediaLink {
+                id: 7,
+                source_id: 3,
+                sink_id: 1,
+                flags: 0x1000_0003,
+            },
+            MediaLink {
+                id: 8,
+                source_id: 6,
+                sink_id: 4,
+                flags: 0x1000_0003,
+            },
+        ],
+    };
+
+    let capture =
+        V4LCapabilityFlags(V4LCapabilityFlags::VIDEO_CAPTURE.0 | V4LCapabilityFlags::STREAMING.0);
+    let metadata =
+        V4LCapabilityFlags(V4LCapabilityFlags::META_CAPTURE.0 | V4LCapabilityFlags::STREAMING.0);
+
+    FakeKernel::default()
+        .with_media("/dev/media0", (237, 0), info, topology)
+        .with_video("/dev/video0", (81, 0), capture)
+        .with_video("/dev/video1", (81, 1), metadata)
+        .with_link(
+            "/dev/v4l/by-id/usb-046d_C922_Pro_Stream_Webcam_ABCD1234-video-index0",
+            "../../video0",
+        )
+}
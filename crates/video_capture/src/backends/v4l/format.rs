@@ -0,0 +1,148 @@
+//! Asking a device which pixel format it uses, and changing it.
+//!
+//! Single-planar and multi-planar devices describe their formats with
+//! different structs, but both come down to a size, a FourCC, and a size and
+//! stride for each plane. Single-planar formats just have one plane.
+//! Metadata formats are just a FourCC and a buffer size, so they come out as
+//! one plane with no size or stride.
+
+use std::io;
+
+use crate::config::SpecificResolution;
+
+use super::raw::{
+    vidioc_g_fmt, vidioc_s_fmt, zeroed, V4l2Format, V4l2MetaFormat, V4l2PixFormat,
+    V4l2PixFormatMplane, V4L2_BUF_TYPE_META_CAPTURE, V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE,
+    V4L2_FIELD_ANY, VIDEO_MAX_PLANES,
+};
+
+/// A negotiated pixel format.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) struct PixFormat {
+    pub width: u32,
+    pub height: u32,
+    pub fourcc: [u8; 4],
+    /// The size and stride of each memory plane, in bytes.
+    pub planes: Vec<PlaneFormat>,
+}
+
+/// The size and stride of one memory plane.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(super) struct PlaneFormat {
+    pub size: u32,
+    pub stride: u32,
+}
+
+impl PixFormat {
+    /// The frame size.
+    pub(super) const fn resolution(&self) -> SpecificResolution {
+        SpecificResolution::new(self.width, self.height)
+    }
+
+    /// The first plane, which is the only one for single-planar formats.
+    pub(super) fn first_plane(&self) -> PlaneFormat {
+        self.planes.first().copied().unwrap_or_default()
+    }
+
+    fn from_single(raw: &V4l2PixFormat) -> Self {
+        Self {
+            width: raw.width,
+            height: raw.height,
+            fourcc: raw.pixelformat.to_le_bytes(),
+            planes: vec![PlaneFormat {
+                size: raw.sizeimage,
+                stride: raw.bytesperline,
+            }],
+        }
+    }
+
+    fn from_multi(raw: &V4l2PixFormatMplane) -> Self {
+        let plane_fmt = raw.plane_fmt;
+        let count = usize::from(raw.num_planes).min(VIDEO_MAX_PLANES);
+
+        Self {
+            width: raw.width,
+            height: raw.height,
+            fourcc: raw.pixelformat.to_le_bytes(),
+            planes: plane_fmt
+                .iter()
+                .take(count)
+                .map(|plane| PlaneFormat {
+                    size: plane.sizeimage,
+                    stride: plane.bytesperline,
+                })
+                .collect(),
+        }
+    }
+
+    fn from_meta(raw: V4l2MetaFormat) -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            fourcc: raw.dataformat.to_le_bytes(),
+            planes: vec![PlaneFormat {
+                size: raw.buffersize,
+                stride: 0,
+            }],
+        }
+    }
+
+    /// Reads whichever format the buffer type uses.
+    fn from_raw(format: &V4l2Format) -> Self {
+        if format.r#type == V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE {
+            // SAFETY: multi-planar buffer types use the multi-planar format
+            Self::from_multi(&unsafe { format.fmt.pix_mp })
+        } else if format.r#type == V4L2_BUF_TYPE_META_CAPTURE {
+            // SAFETY: metadata buffer types use the metadata format
+            Self::from_meta(unsafe { format.fmt.meta })
+        } else {
+            // SAFETY: every other video buffer type uses the plain format
+            Self::from_single(&unsafe { format.fmt.pix })
+        }
+    }
+}
+
+/// Asks the device for the format that a buffer type is using.
+pub(super) fn get(fd: i32, buf_type: u32) -> io::Result<PixFormat> {
+    let mut format = zeroed::<V4l2Format>();
+    format.r#type = buf_type;
+
+    // SAFETY: the kernel fills in the struct or returns an error.
+    unsafe { vidioc_g_fmt(fd, &raw mut format)? };
+    Ok(PixFormat::from_raw(&format))
+}
+
+/// Asks the device to use a format, then returns what it chose instead.
+///
+/// Multi-planar drivers pick the number of planes and their strides for us.
+pub(super) fn set(
+    fd: i32,
+    buf_type: u32,
+    fourcc: [u8; 4],
+    resolution: SpecificResolution,
+) -> io::Result<PixFormat> {
+    let mut format = zeroed::<V4l2Format>();
+    format.r#type = buf_type;
+
+    if buf_type == V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE {
+        // SAFETY: the struct is zeroed, so every union field is valid.
+        let mut pix_mp = unsafe { format.fmt.pix_mp };
+        pix_mp.width = resolution.width;
+        pix_mp.height = resolution.height;
+        pix_mp.pixelformat = u32::from_le_bytes(fourcc);
+        pix_mp.field = V4L2_FIELD_ANY;
+        format.fmt.pix_mp = pix_mp;
+    } else {
+        // SAFETY: same here.
+        let mut pix = unsafe { format.fmt.pix };
+        pix.width = resolution.width;
+        pix.height = resolution.height;
+        pix.pixelformat = u32::from_le_bytes(fourcc);
+        pix.field = V4L2_FIELD_ANY;
+        format.fmt.pix = pix;
+    }
+
+    // SAFETY: the kernel adjusts the struct or returns an error.
+    unsafe { vidioc_s_fmt(fd, &raw mut format)? };
+    Ok(PixFormat::from_raw(&format))
+}
@@ -1,9 +1,12 @@
-use fraction::{Fraction, One};
+use std::io;
+
+use fraction::Fraction;
 use nix::ioctl_readwrite;
 
-use crate::error::VideoCaptureConfigError as ConfigError;
+use super::raw::V4L2_BUF_TYPE_VIDEO_OUTPUT;
 
 const VIDIOC_G_PARM: u8 = 21;
+const VIDIOC_S_PARM: u8 = 22;
 const IOCTL_MEDIA_COMMAND: u8 = b'V';
 
 ioctl_readwrite!(
@@ -13,6 +16,13 @@ ioctl_readwrite!(
     V4l2StreamParm
 );
 
+ioctl_readwrite!(
+    vidioc_s_parm,
+    IOCTL_MEDIA_COMMAND,
+    VIDIOC_S_PARM,
+    V4l2StreamParm
+);
+
 #[repr(C)]
 pub(super) struct V4l2StreamParm {
     r#type: u32,
@@ -22,52 +32,91 @@ pub(super) struct V4l2StreamParm {
 impl V4l2StreamParm {
     /// Talks to the kernel to fill a `v4l2_stream_parm` structure.
     ///
+    /// The buffer type picks which stream to ask about, like
+    /// `V4L2_BUF_TYPE_VIDEO_CAPTURE` or `V4L2_BUF_TYPE_VIDEO_OUTPUT`.
+    ///
     /// # Errors
     ///
     /// This can fail if the `ioctl` call has its invariants broken.
     ///
     /// See [the kernel docs](https://docs.kernel.org/userspace-api/media/v4l/vidioc-g-parm.html#c.V4L.VIDIOC_G_PARM) for more information.
     #[tracing::instrument]
-    pub(crate) fn new(source_str: String, fd: i32) -> Result<Self, ConfigError> {
+    pub(crate) fn get(fd: i32, buf_type: u32) -> io::Result<Self> {
         // SAFETY: this creates a zeroed-out version of the `V412StreamParm`
         // struct, which is expected by the kernel.
         //
         // the kernel will then fill in the device details as necessary.
         let mut stream_parm = unsafe { core::mem::zeroed::<Self>() };
+        stream_parm.r#type = buf_type;
         tracing::trace!("successfully zeroed v4l2_stream_parm struct memory");
 
         // SAFETY: the kernel should fill in the struct correctly or return an
         // error code we can use to fail gracefully.
-        let result = unsafe { vidioc_g_parm(fd, &mut stream_parm) };
+        unsafe { vidioc_g_parm(fd, &raw mut stream_parm)? };
         tracing::trace!("completed ioctl call w/ `VIDIOC_G_PARM`");
 
-        match result {
-            Ok(_) => Ok(stream_parm),
-            Err(errno) => Err(ConfigError::CouldntGetFormat {
-                source: source_str,
-                err_msg: format!("ioctl call for `VIDIOC_G_PARM` failed with error code {errno}."),
-            }),
+        Ok(stream_parm)
+    }
+
+    /// Asks a stream to use the given frame interval, then returns what it
+    /// chose instead.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the device doesn't let us change its frame interval.
+    #[tracing::instrument]
+    pub(crate) fn set_frame_interval(
+        fd: i32,
+        buf_type: u32,
+        numerator: u32,
+        denominator: u32,
+    ) -> io::Result<Self> {
+        // SAFETY: the kernel expects zeroed memory for the fields we don't
+        // set. it'll adjust the rest.
+        let mut stream_parm = unsafe { core::mem::zeroed::<Self>() };
+        stream_parm.r#type = buf_type;
+        let time_per_frame = V4l2Fract {
+            numerator,
+            denominator,
+        };
+        if buf_type == V4L2_BUF_TYPE_VIDEO_OUTPUT {
+            stream_parm.parm.v4l2_outputparm = V4l2OutputParm {
+                time_per_frame,
+                // SAFETY: the union was zeroed, so its output fields are too
+                ..unsafe { stream_parm.parm.v4l2_outputparm }
+            };
+        } else {
+            stream_parm.parm.v4l2_captureparm = V4l2CaptureParm {
+                time_per_frame,
+                // SAFETY: the union was zeroed, so its capture fields are too
+                ..unsafe { stream_parm.parm.v4l2_captureparm }
+            };
         }
+
+        // SAFETY: the kernel reads the struct, then fills in what it chose
+        // or returns an error code.
+        unsafe { vidioc_s_parm(fd, &raw mut stream_parm)? };
+        tracing::trace!("completed ioctl call w/ `VIDIOC_S_PARM`");
+
+        Ok(stream_parm)
     }
 
-    /// Gets the frame interval from the internal v4l2_captureparm union field.
+    /// Gets the frame interval from the internal union field that matches
+    /// the buffer type.
     pub(crate) fn get_frame_interval(&self) -> Fraction {
-        // get the union type
-        //
-        // SAFETY: we ALWAYS do a `VIDIOC_G_PARM`, which doesn't set anything.
-        // in other words, we'll never use the other union fields.
-        let frame_interval = unsafe { self.parm.v4l2_captureparm }.time_per_frame;
+        // get the union type. the kernel fills in the field for the buffer
+        // type we asked about, so that's the only one we read
+        let frame_interval = if self.r#type == V4L2_BUF_TYPE_VIDEO_OUTPUT {
+            // SAFETY: this is an output stream's parameters
+            unsafe { self.parm.v4l2_outputparm }.time_per_frame
+        } else {
+            // SAFETY: this is a capture stream's parameters
+            unsafe { self.parm.v4l2_captureparm }.time_per_frame
+        };
 
         // make it into a `fraction::Fraction`
         Fraction::new(frame_interval.numerator, frame_interval.denominator)
     }
-
-    /// Creates a frame rate from the internal frame interval.
-    ///
-    /// A frame rate is 1 / `frame_interval`.
-    pub(crate) fn get_frame_rate(&self) -> Fraction {
-        Fraction::one() / self.get_frame_interval()
-    }
 }
 
 #[repr(C)]
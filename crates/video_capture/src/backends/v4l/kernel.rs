@@ -0,0 +1,393 @@
+//! Everything the backend asks of the kernel while finding, configuring, and
+//! streaming from devices.
+//!
+//! Instead of calling `ioctl`s or reading `/sys` and `/dev` directly, the
+//! backend goes through the [`Kernel`] trait. Real devices use
+//! [`LinuxKernel`]. Tests use a fake that replays canned responses, so they
+//! can run without any hardware.
+
+use core::fmt::Debug;
+use core::time::Duration;
+use std::fs;
+use std::io;
+use std::os::fd::RawFd;
+use std::os::unix::fs::MetadataExt as _;
+use std::path::{Path, PathBuf};
+
+use fraction::Fraction;
+use nix::errno::Errno;
+use v4l::control::{Control, Description};
+use v4l::Device;
+
+use crate::config::SpecificResolution;
+
+use super::capabilities::V4LCapabilities;
+use super::device_info::MediaDeviceInfo;
+use super::format::{self, PixFormat};
+use super::framerate::V4l2StreamParm;
+use super::poll;
+use super::raw::{
+    vidioc_dqbuf, vidioc_enum_fmt, vidioc_enum_frameintervals, vidioc_enum_framesizes, vidioc_qbuf,
+    vidioc_querybuf, vidioc_reqbufs, vidioc_streamoff, vidioc_streamon, zeroed, MappedBuffer,
+    V4l2Buffer, V4l2FmtDesc, V4l2FrmIvalEnum, V4l2FrmSizeEnum, V4l2RequestBuffers,
+    V4L2_FRMIVAL_TYPE_DISCRETE, V4L2_FRMSIZE_TYPE_DISCRETE,
+};
+use super::topology::{split_device_number, MediaTopology};
+
+/// The kernel calls and files that the backend uses to find, configure, and
+/// stream from devices.
+///
+/// Device-wide calls take a path. Calls on an open device take its file
+/// descriptor, except for controls, which take the whole device since the
+/// `v4l` crate makes those calls for us.
+pub(super) trait Kernel: Debug + Send + Sync {
+    /// Reads where a symlink points.
+    ///
+    /// Files that aren't symlinks fail with [`io::ErrorKind::InvalidInput`].
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf>;
+
+    /// Lists the paths in a directory.
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<PathBuf>>;
+
+    /// Reads a whole file, like a sysfs `uevent`.
+    fn read_to_string(&self, path: &Path) -> io::Result<String>;
+
+    /// Gets the major and minor numbers of a device node, following
+    /// symlinks.
+    fn device_number(&self, path: &Path) -> io::Result<(u32, u32)>;
+
+    /// Asks a media device about itself. (`MEDIA_IOC_DEVICE_INFO`)
+    fn media_device_info(&self, media: &Path) -> io::Result<MediaDeviceInfo>;
+
+    /// Reads a media device's graph. (`MEDIA_IOC_G_TOPOLOGY`)
+    fn media_topology(&self, media: &Path) -> io::Result<MediaTopology>;
+
+    /// Asks a video node what it can do. (`VIDIOC_QUERYCAP`)
+    fn capabilities(&self, video: &Path) -> io::Result<V4LCapabilities>;
+
+    /// Lists the FourCC of every format for a buffer type.
+    /// (`VIDIOC_ENUM_FMT`)
+    fn enum_formats(&self, fd: RawFd, buf_type: u32) -> io::Result<Vec<[u8; 4]>>;
+
+    /// Lists the frame sizes for a format. (`VIDIOC_ENUM_FRAMESIZES`)
+    fn enum_framesizes(&self, fd: RawFd, fourcc: [u8; 4]) -> io::Result<Vec<FrameSize>>;
+
+    /// Lists the frame intervals for a format and frame size.
+    /// (`VIDIOC_ENUM_FRAMEINTERVALS`)
+    fn enum_frameintervals(
+        &self,
+        fd: RawFd,
+        fourcc: [u8; 4],
+        resolution: SpecificResolution,
+    ) -> io::Result<Vec<FrameInterval>>;
+
+    /// Gets the frame interval that a stream is using, in seconds.
+    /// (`VIDIOC_G_PARM`)
+    fn frame_interval(&self, fd: RawFd, buf_type: u32) -> io::Result<Fraction>;
+
+    /// Opens a video node for reading and writing, without blocking.
+    fn open(&self, video: &Path) -> io::Result<Device>;
+
+    /// Gets the format that a stream is using. (`VIDIOC_G_FMT`)
+    fn format(&self, fd: RawFd, buf_type: u32) -> io::Result<PixFormat>;
+
+    /// Asks a stream to use a format, then returns what the driver chose
+    /// instead. (`VIDIOC_S_FMT`)
+    fn set_format(
+        &self,
+        fd: RawFd,
+        buf_type: u32,
+        fourcc: [u8; 4],
+        resolution: SpecificResolution,
+    ) -> io::Result<PixFormat>;
+
+    /// Asks a stream to use a frame interval of `numerator / denominator`
+    /// seconds. (`VIDIOC_S_PARM`)
+    fn set_frame_interval(
+        &self,
+        fd: RawFd,
+        buf_type: u32,
+        numerator: u32,
+        denominator: u32,
+    ) -> io::Result<()>;
+
+    /// Lists every control on a device. (`VIDIOC_QUERY_EXT_CTRL`)
+    fn controls(&self, device: &Device) -> io::Result<Vec<Description>>;
+
+    /// Reads a control's value. (`VIDIOC_G_EXT_CTRLS`)
+    fn control(&self, device: &Device, id: u32) -> io::Result<Control>;
+
+    /// Writes a control's value. (`VIDIOC_S_EXT_CTRLS`)
+    fn set_control(&self, device: &Device, control: Control) -> io::Result<()>;
+
+    /// Asks for `count` buffers, and returns how many the driver gave us.
+    /// Asking for zero frees them. (`VIDIOC_REQBUFS`)
+    fn request_buffers(&self, fd: RawFd, buf_type: u32, memory: u32, count: u32)
+        -> io::Result<u32>;
+
+    /// Asks where one of the driver's buffers is, and how big it is.
+    /// (`VIDIOC_QUERYBUF`)
+    fn query_buffer(&self, fd: RawFd, buffer: &mut V4l2Buffer) -> io::Result<()>;
+
+    /// Maps a buffer that [`Kernel::query_buffer`] described.
+    fn map_buffer(&self, fd: RawFd, length: u32, offset: u32) -> io::Result<MappedBuffer>;
+
+    /// Gives a buffer to the driver. (`VIDIOC_QBUF`)
+    fn queue_buffer(&self, fd: RawFd, buffer: &mut V4l2Buffer) -> io::Result<()>;
+
+    /// Takes a finished buffer back from the driver. (`VIDIOC_DQBUF`)
+    fn dequeue_buffer(&self, fd: RawFd, buffer: &mut V4l2Buffer) -> io::Result<()>;
+
+    /// Starts a stream. (`VIDIOC_STREAMON`)
+    fn stream_on(&self, fd: RawFd, buf_type: u32) -> io::Result<()>;
+
+    /// Stops a stream, and gives every buffer back to us.
+    /// (`VIDIOC_STREAMOFF`)
+    fn stream_off(&self, fd: RawFd, buf_type: u32) -> io::Result<()>;
+
+    /// Waits until one of the descriptors is readable, then lists the ready
+    /// ones. The list is empty if the time ran out.
+    fn wait_readable(&self, fds: &[RawFd], timeout: Option<Duration>) -> io::Result<Vec<usize>>;
+
+    /// Waits until one of the descriptors is writable, like an output
+    /// device with a buffer to give back, then lists the ready ones. The
+    /// list is empty if the time ran out.
+    fn wait_writable(&self, fds: &[RawFd], timeout: Option<Duration>) -> io::Result<Vec<usize>>;
+}
+
+/// One of the frame sizes that a device lists for a format.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum FrameSize {
+    Discrete(SpecificResolution),
+    /// A range of sizes. We don't use these yet.
+    Stepwise,
+}
+
+/// One of the frame intervals that a device lists for a frame size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum FrameInterval {
+    /// An interval of `numerator / denominator` seconds.
+    Discrete { numerator: u32, denominator: u32 },
+    /// A range of intervals. We don't use these yet.
+    Stepwise,
+}
+
+/// The real kernel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(super) struct LinuxKernel;
+
+impl Kernel for LinuxKernel {
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::read_link(path)
+    }
+
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        Ok(fs::read_dir(dir)?
+            .flatten()
+            .map(|entry| entry.path())
+            .collect())
+    }
+
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+
+    fn device_number(&self, path: &Path) -> io::Result<(u32, u32)> {
+        let rdev = fs::metadata(path)?.rdev();
+        Ok(split_device_number(rdev))
+    }
+
+    fn media_device_info(&self, media: &Path) -> io::Result<MediaDeviceInfo> {
+        MediaDeviceInfo::get(media)
+    }
+
+    fn media_topology(&self, media: &Path) -> io::Result<MediaTopology> {
+        MediaTopology::query(media)
+    }
+
+    fn capabilities(&self, video: &Path) -> io::Result<V4LCapabilities> {
+        V4LCapabilities::query(video)
+    }
+
+    fn enum_formats(&self, fd: RawFd, buf_type: u32) -> io::Result<Vec<[u8; 4]>> {
+        enumerate(|index| {
+            let mut desc = zeroed::<V4l2FmtDesc>();
+            desc.index = index;
+            desc.r#type = buf_type;
+
+            // SAFETY: the kernel fills in the struct or returns an error.
+            unsafe { vidioc_enum_fmt(fd, &raw mut desc) }?;
+            Ok(desc.pixelformat.to_le_bytes())
+        })
+    }
+
+    fn enum_framesizes(&self, fd: RawFd, fourcc: [u8; 4]) -> io::Result<Vec<FrameSize>> {
+        enumerate(|index| {
+            let mut size = zeroed::<V4l2FrmSizeEnum>();
+            size.index = index;
+            size.pixel_format = u32::from_le_bytes(fourcc);
+
+            // SAFETY: the kernel fills in the struct or returns an error.
+            unsafe { vidioc_enum_framesizes(fd, &raw mut size) }?;
+            Ok(match (size.r#type, size.size) {
+                (V4L2_FRMSIZE_TYPE_DISCRETE, [width, height, ..]) => {
+                    FrameSize::Discrete(SpecificResolution::new(width, height))
+                }
+                _ => FrameSize::Stepwise,
+            })
+        })
+    }
+
+    fn enum_frameintervals(
+        &self,
+        fd: RawFd,
+        fourcc: [u8; 4],
+        resolution: SpecificResolution,
+    ) -> io::Result<Vec<FrameInterval>> {
+        enumerate(|index| {
+            let mut interval = zeroed::<V4l2FrmIvalEnum>();
+            interval.index = index;
+            interval.pixel_format = u32::from_le_bytes(fourcc);
+            interval.width = resolution.width;
+            interval.height = resolution.height;
+
+            // SAFETY: the kernel fills in the struct or returns an error.
+            unsafe { vidioc_enum_frameintervals(fd, &raw mut interval) }?;
+            Ok(match (interval.r#type, interval.interval) {
+                (V4L2_FRMIVAL_TYPE_DISCRETE, [numerator, denominator, ..]) => {
+                    FrameInterval::Discrete {
+                        numerator,
+                        denominator,
+                    }
+                }
+                _ => FrameInterval::Stepwise,
+            })
+        })
+    }
+
+    fn frame_interval(&self, fd: RawFd, buf_type: u32) -> io::Result<Fraction> {
+        V4l2StreamParm::get(fd, buf_type).map(|parm| parm.get_frame_interval())
+    }
+
+    fn open(&self, video: &Path) -> io::Result<Device> {
+        Device::with_path(video)
+    }
+
+    fn format(&self, fd: RawFd, buf_type: u32) -> io::Result<PixFormat> {
+        format::get(fd, buf_type)
+    }
+
+    fn set_format(
+        &self,
+        fd: RawFd,
+        buf_type: u32,
+        fourcc: [u8; 4],
+        resolution: SpecificResolution,
+    ) -> io::Result<PixFormat> {
+        format::set(fd, buf_type, fourcc, resolution)
+    }
+
+    fn set_frame_interval(
+        &self,
+        fd: RawFd,
+        buf_type: u32,
+        numerator: u32,
+        denominator: u32,
+    ) -> io::Result<()> {
+        V4l2StreamParm::set_frame_interval(fd, buf_type, numerator, denominator).map(drop)
+    }
+
+    fn controls(&self, device: &Device) -> io::Result<Vec<Description>> {
+        device.query_controls()
+    }
+
+    fn control(&self, device: &Device, id: u32) -> io::Result<Control> {
+        device.control(id)
+    }
+
+    fn set_control(&self, device: &Device, control: Control) -> io::Result<()> {
+        device.set_control(control)
+    }
+
+    fn request_buffers(
+        &self,
+        fd: RawFd,
+        buf_type: u32,
+        memory: u32,
+        count: u32,
+    ) -> io::Result<u32> {
+        let mut request = zeroed::<V4l2RequestBuffers>();
+        request.count = count;
+        request.r#type = buf_type;
+        request.memory = memory;
+
+        // SAFETY: the kernel allocates (or frees) buffers or returns an
+        // error.
+        unsafe { vidioc_reqbufs(fd, &raw mut request)? };
+        Ok(request.count)
+    }
+
+    fn query_buffer(&self, fd: RawFd, buffer: &mut V4l2Buffer) -> io::Result<()> {
+        // SAFETY: the kernel fills in the struct or returns an error. the
+        // caller made room for any planes it points to.
+        unsafe { vidioc_querybuf(fd, buffer)? };
+        Ok(())
+    }
+
+    fn map_buffer(&self, fd: RawFd, length: u32, offset: u32) -> io::Result<MappedBuffer> {
+        MappedBuffer::map(fd, length, offset)
+    }
+
+    fn queue_buffer(&self, fd: RawFd, buffer: &mut V4l2Buffer) -> io::Result<()> {
+        // SAFETY: the kernel reads the struct or returns an error. any planes
+        // it points to belong to the caller.
+        unsafe { vidioc_qbuf(fd, buffer)? };
+        Ok(())
+    }
+
+    fn dequeue_buffer(&self, fd: RawFd, buffer: &mut V4l2Buffer) -> io::Result<()> {
+        // SAFETY: the kernel fills in the struct or returns an error. the
+        // caller made room for any planes it points to.
+        unsafe { vidioc_dqbuf(fd, buffer)? };
+        Ok(())
+    }
+
+    fn stream_on(&self, fd: RawFd, buf_type: u32) -> io::Result<()> {
+        let raw_type = buf_type.cast_signed();
+        // SAFETY: the kernel reads the buffer type and starts streaming.
+        unsafe { vidioc_streamon(fd, &raw const raw_type)? };
+        Ok(())
+    }
+
+    fn stream_off(&self, fd: RawFd, buf_type: u32) -> io::Result<()> {
+        let raw_type = buf_type.cast_signed();
+        // SAFETY: the kernel reads the buffer type and stops streaming.
+        unsafe { vidioc_streamoff(fd, &raw const raw_type)? };
+        Ok(())
+    }
+
+    fn wait_readable(&self, fds: &[RawFd], timeout: Option<Duration>) -> io::Result<Vec<usize>> {
+        poll::wait_readable(fds, timeout)
+    }
+
+    fn wait_writable(&self, fds: &[RawFd], timeout: Option<Duration>) -> io::Result<Vec<usize>> {
+        poll::wait_writable(fds, timeout)
+    }
+}
+
+/// Calls an enumerating `ioctl` with each index until the kernel says
+/// there's nothing left.
+fn enumerate<T, F: FnMut(u32) -> Result<T, Errno>>(mut call: F) -> io::Result<Vec<T>> {
+    let mut found = Vec::new();
+
+    for index in 0.. {
+        match call(index) {
+            Ok(item) => found.push(item),
+            // the kernel says `EINVAL` once we're past the last one
+            Err(Errno::EINVAL) => break,
+            Err(errno) => return Err(errno.into()),
+        }
+    }
+
+    Ok(found)
+}
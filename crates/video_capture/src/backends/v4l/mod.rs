@@ -3,32 +3,67 @@
 extern crate alloc;
 
 use alloc::borrow::Cow;
-use device_info::MediaDeviceInfo;
+use alloc::sync::Arc;
+use core::time::Duration;
 use fraction::{Fraction, One};
-use std::io::ErrorKind;
+use kernel::{FrameInterval, FrameSize, Kernel, LinuxKernel};
+use nix::errno::Errno;
+use std::os::fd::RawFd;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
-use v4l::io::traits::CaptureStream;
+use std::time::Instant;
 use v4l::prelude::*;
-use v4l::video::Output;
-use v4l::{buffer::Type, io::traits::Stream as _};
 
-use crate::config::{Format, SpecificResolution};
+use crate::config::Format;
+use crate::frame::{CorruptFramePolicy, Frame};
 use crate::{
-    config::{VideoCaptureConfiguration, VideoCaptureImageConfiguration as ImageConfiguration},
+    config::{
+        self, VideoCaptureConfiguration, VideoCaptureImageConfiguration as ImageConfiguration,
+        VideoCaptureProfile, VideoCaptureProfiles, VideoCaptureProperties,
+        VideoCaptureProperty as Property,
+    },
     error::VideoCaptureConfigError as ConfigError,
     error::VideoCaptureUsageError as UsageError,
     ConnectionError, VideoCapture, VideoCaptureConnection, VideoCaptureDescriptor,
     VideoCaptureStream,
 };
 
+pub use builder::{V4LIoMethod, V4LVideoCaptureBuilder, V4LWarmUp};
+pub use capabilities::{V4LCapabilities, V4LCapabilityFlags, V4LVersion};
+pub use device::V4LDevice;
+pub use device_info::V4LMediaInfo;
+pub use output::V4LOutput;
+pub use poll::wait_for_frames;
 pub use source::V4LSource;
+pub use stream::V4LStream;
+pub use topology::{
+    MediaEntity, MediaInterface, MediaInterfaceKind, MediaLink, MediaNode, MediaNodeKind, MediaPad,
+    MediaTopology,
+};
 
 use super::Backend;
+use stream::StreamSettings;
+use uvc_meta::MetadataStream;
 
+mod builder;
+mod capabilities;
+mod controls;
+mod device;
 mod device_info;
+mod errno;
+#[cfg(test)]
+mod fake;
+mod format;
 mod framerate;
+mod kernel;
+mod mplane;
+mod output;
+mod poll;
+mod raw;
+mod single;
 mod source;
+mod stream;
+mod topology;
+mod uvc_meta;
 
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
 pub struct V4LBackend;
@@ -78,6 +113,51 @@ pub struct V4LVideoCaptureDescriptor {
     pub device_identifier: String,
     /// Model number/etc.
     pub device_model: String,
+    /// What the video node can do, from `VIDIOC_QUERYCAP`.
+    pub capabilities: V4LCapabilities,
+    /// The rest of the media device's info.
+    pub media_info: V4LMediaInfo,
+}
+
+impl V4LVideoCaptureDescriptor {
+    /// Asks the media device and video node what they are, without starting
+    /// a stream.
+    ///
+    /// # Errors
+    ///
+    /// This fails if either device can't be read.
+    #[inline]
+    pub fn from_source(source: &V4LSource) -> Result<Self, ConnectionError> {
+        Self::from_source_with(&LinuxKernel, source)
+    }
+
+    fn from_source_with(kernel: &dyn Kernel, source: &V4LSource) -> Result<Self, ConnectionError> {
+        let user_source = source.user_source_string();
+        let device_info = kernel.media_device_info(&source.media).map_err(|e| {
+            errno::connection_error(&user_source, e, |err| {
+                ConnectionError::CouldntGetDeviceInfo {
+                    source: user_source.clone(),
+                    err_msg: err.to_string(),
+                }
+            })
+        })?;
+
+        let capabilities = kernel.capabilities(&source.video).map_err(|e| {
+            errno::connection_error(&user_source, e, |err| {
+                ConnectionError::CouldntGetDeviceInfo {
+                    source: user_source.clone(),
+                    err_msg: format!("Failed to query capabilities. IO error: {err}"),
+                }
+            })
+        })?;
+
+        Ok(Self {
+            device_identifier: device_info.serial,
+            device_model: device_info.model,
+            capabilities,
+            media_info: device_info.info,
+        })
+    }
 }
 
 impl VideoCaptureDescriptor for V4LVideoCaptureDescriptor {
@@ -101,95 +181,472 @@ pub type V4LBuffer = UserptrStream;
 
 /// A capture device using the Video4Linux backend.
 pub type V4LVideoCaptureDevice<'path, 'conn> =
-    VideoCapture<V4LVideoCaptureDescriptor, v4l::Device, V4LSource, V4LBuffer>;
+    VideoCapture<V4LVideoCaptureDescriptor, V4LDevice, V4LSource, V4LStream>;
 
 impl V4LVideoCaptureDevice<'_, '_> {
-    fn source_as_string(&self) -> String {
-        self.source.user_source_string()
+    /// Starts setting up a device to open, so it can be configured before it
+    /// starts streaming.
+    #[inline]
+    #[must_use]
+    pub fn builder(source: PathBuf) -> V4LVideoCaptureBuilder {
+        V4LVideoCaptureBuilder::new(source)
     }
-}
-
-impl<'path> VideoCaptureConnection<'path, V4LSource> for V4LVideoCaptureDevice<'path, '_> {
-    type Source = PathBuf;
 
+    /// Connects to a device, applying whichever of the given profiles is
+    /// for it.
+    ///
+    /// The device picks from these profiles again whenever it reconnects.
+    ///
+    /// # Errors
+    ///
+    /// This fails like [`new`](VideoCaptureConnection::new), or if the
+    /// matching profile can't be applied.
     #[inline]
-    fn new(source: Self::Source) -> Result<Self, ConnectionError> {
+    pub fn new_with_profiles(
+        source: PathBuf,
+        profiles: Vec<VideoCaptureProfile>,
+    ) -> Result<Self, ConnectionError> {
+        V4LVideoCaptureBuilder::new(source)
+            .profiles(profiles)
+            .open()
+    }
+
+    /// Opens a device and applies its profile, without starting a stream.
+    fn open_stopped(
+        kernel: Arc<dyn Kernel>,
+        source: &Path,
+        settings: StreamSettings,
+        profiles: Vec<VideoCaptureProfile>,
+    ) -> Result<Self, ConnectionError> {
         let path_string = Cow::from(source.to_string_lossy().to_string());
         tracing::debug!("creating a new Video4Linux capture device at path `{path_string}`...",);
 
         // compute the necessary paths
         tracing::trace!("getting media + video source...");
-        let checked_source = V4LSource::new(&source)?;
+        let checked_source = V4LSource::resolve(&*kernel, source)?;
         tracing::trace!("made the sources for V4L device! see: `{checked_source:?}`");
 
         // grab device info
         tracing::trace!("getting media device info...");
-        let device_info =
-            MediaDeviceInfo::get(&source).map_err(|e| ConnectionError::CouldntGetDeviceInfo {
-                source: path_string.to_string(),
-                err_msg: e.to_string(),
-            })?;
-
-        let (device_identifier, device_model) = (device_info.serial(), device_info.model());
+        let descriptor = V4LVideoCaptureDescriptor::from_source_with(&*kernel, &checked_source)?;
         tracing::trace!("media device info obtained!");
 
-        // make info into a descriptor
-        let descriptor = V4LVideoCaptureDescriptor {
-            device_identifier,
-            device_model,
-        };
-
         // attempt to access the device by path
         // TODO: hey, check the fs error if it doesn't exist or the camera
         // just failed to connect.
 
         tracing::trace!("creating device...");
-        let device = Device::with_path(&source).map_err(|e| {
-            // check if the file exists
-            match e.kind() {
-                ErrorKind::NotFound => ConnectionError::SourceDoesntExist {
-                    source: path_string.to_string(),
-                },
-                err_kind => ConnectionError::OddIOError {
-                    source: source.display().to_string(),
-                    err_kind,
-                    err_msg: e.to_string(),
-                },
-            }
+        let device = V4LDevice::open(kernel, &checked_source.video).map_err(|e| {
+            errno::connection_error(&path_string, e, |err| {
+                errno::odd_io_error(&path_string, &err)
+            })
         })?;
         tracing::trace!("device created!");
 
-        tracing::trace!("starting stream...");
-        let mut stream = V4LBuffer::new(&device, Type::VideoCapture).map_err(|e| {
-            // check if the file exists
-            match e.kind() {
-                ErrorKind::NotFound => ConnectionError::SourceDoesntExist {
-                    source: path_string.to_string(),
-                },
-                err => ConnectionError::OddIOError {
-                    source: source.display().to_string(),
-                    err_kind: err,
-                    err_msg: e.to_string(),
-                },
-            }
-        })?;
-        tracing::trace!("stream started!");
-
-        // unused dummy frame to make buggy drivers fill info about the
-        // device's capabilities
-        stream.next().map_err(|e| ConnectionError::WarmUpFailed {
-            source: path_string.to_string(),
-            err_msg: e.to_string(),
-        })?;
+        // apply the user's profile for this device before we start streaming
+        let profile = config::find_profile(
+            &profiles,
+            &descriptor.device_identifier,
+            &descriptor.device_model,
+        )
+        .cloned();
+        if let Some(ref wanted) = profile {
+            tracing::debug!("applying the profile for `{path_string}`...");
+            apply_profile_to_device(
+                &device,
+                &path_string,
+                descriptor.capabilities.needs_multiplanar(),
+                wanted,
+            )
+            .map_err(|e| ConnectionError::CouldntApplyProfile {
+                source: path_string.to_string(),
+                err_msg: e.to_string(),
+            })?;
+        }
 
         Ok(Self {
             descriptor,
             device,
             source: checked_source,
-            stream,
+            stream: V4LStream::new(settings),
+            profiles,
+            profile,
+            corrupt_frames: CorruptFramePolicy::default(),
+            skipped_frames: 0,
+        })
+    }
+
+    /// Reads and throws away frames, like the warm-up policy says.
+    ///
+    /// Corrupt frames count too, since they still get drivers going.
+    fn warm_up(&mut self) -> Result<(), ConnectionError> {
+        let frames = match self.stream.settings().warm_up {
+            V4LWarmUp::None => 0,
+            V4LWarmUp::Frames(frames) => frames,
+        };
+
+        let timeout = self.stream.settings().read_timeout;
+        for _ in 0..frames {
+            let arrived = self.stream.advance(timeout).map_err(|e| {
+                errno::connection_error(&self.source_as_string(), e, |err| {
+                    ConnectionError::WarmUpFailed {
+                        source: self.source_as_string(),
+                        err_msg: err.to_string(),
+                    }
+                })
+            })?;
+
+            if !arrived {
+                return Err(ConnectionError::WarmUpFailed {
+                    source: self.source_as_string(),
+                    err_msg: String::from("no frame arrived in time"),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn source_as_string(&self) -> String {
+        self.source.user_source_string()
+    }
+
+    /// Makes an error for a stream that wouldn't stop.
+    fn stop_error(&self, err: std::io::Error) -> ConnectionError {
+        errno::connection_error(&self.source_as_string(), err, |e| {
+            ConnectionError::StopError {
+                source: self.source_as_string(),
+                err_msg: e.to_string(),
+            }
+        })
+    }
+
+    /// The profiles that the device picks from when it connects.
+    #[inline]
+    pub fn profiles(&self) -> &[VideoCaptureProfile] {
+        &self.profiles
+    }
+
+    /// Changes the profiles that the device picks from when it reconnects.
+    #[inline]
+    pub fn set_profiles(&mut self, profiles: Vec<VideoCaptureProfile>) {
+        self.profiles = profiles;
+    }
+
+    /// Sets each property in order, listing the device's controls just once.
+    ///
+    /// # Errors
+    ///
+    /// This stops at the first property that the device doesn't have, or
+    /// that it won't take.
+    #[inline]
+    pub fn set_properties(&mut self, properties: &[Property]) -> Result<(), ConfigError> {
+        controls::set_controls(&self.device, &self.source_as_string(), properties)
+    }
+
+    /// How long [`read_frame`](VideoCaptureStream::read_frame) waits for a
+    /// frame. `None` means it waits as long as it takes.
+    #[inline]
+    pub const fn read_timeout(&self) -> Option<Duration> {
+        self.stream.settings().read_timeout
+    }
+
+    /// Changes how long [`read_frame`](VideoCaptureStream::read_frame) waits
+    /// for a frame. With `None`, it waits as long as it takes.
+    #[inline]
+    pub const fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.stream.set_read_timeout(timeout);
+    }
+
+    /// How the device shares its frame buffers with us.
+    #[inline]
+    pub const fn io_method(&self) -> V4LIoMethod {
+        self.stream.settings().io_method
+    }
+
+    /// Opens the device's UVC metadata node, so that each frame comes with
+    /// the camera's own timestamps in [`FrameMetadata::hardware`].
+    ///
+    /// Frames are matched to their metadata by sequence number. Opening it
+    /// again does nothing.
+    ///
+    /// [`FrameMetadata::hardware`]: crate::frame::FrameMetadata::hardware
+    ///
+    /// # Errors
+    ///
+    /// This fails if the device doesn't have a metadata node, or if the node
+    /// can't be streamed from.
+    #[inline]
+    pub fn open_metadata(&mut self) -> Result<(), ConnectionError> {
+        if self.stream.has_metadata() {
+            return Ok(());
+        }
+
+        let kernel = Arc::clone(self.device.kernel());
+        let node = self.source.metadata_node_with(&*kernel)?.ok_or_else(|| {
+            ConnectionError::NoMetadataNode {
+                source: self.source_as_string(),
+            }
+        })?;
+
+        tracing::debug!("opening metadata node at `{}`...", node.display());
+        let metadata = MetadataStream::new(kernel, &node).map_err(|e| {
+            errno::connection_error(&self.source_as_string(), e, |err| {
+                ConnectionError::CouldntOpenMetadata {
+                    source: self.source_as_string(),
+                    node: node.display().to_string(),
+                    err_msg: err.to_string(),
+                }
+            })
+        })?;
+        self.stream.set_metadata(Some(metadata));
+
+        Ok(())
+    }
+
+    /// Stops reading the device's metadata node, if it was open.
+    #[inline]
+    pub fn close_metadata(&mut self) {
+        self.stream.set_metadata(None);
+    }
+
+    /// Checks if the device's metadata node is open.
+    #[inline]
+    pub const fn has_metadata(&self) -> bool {
+        self.stream.has_metadata()
+    }
+
+    /// Starts streaming, if the device isn't already.
+    ///
+    /// Buffers are allocated for the device's current image configuration.
+    /// Devices start streaming as soon as they're opened, so this is only
+    /// needed after [`stop_streaming`](Self::stop_streaming).
+    ///
+    /// # Errors
+    ///
+    /// This fails if the device is busy or can't allocate its buffers.
+    #[inline]
+    pub fn start_streaming(&mut self) -> Result<(), ConnectionError> {
+        let multiplanar = self.is_multiplanar();
+        self.stream.start(&self.device, multiplanar).map_err(|e| {
+            let source = self.source_as_string();
+            errno::connection_error(&source, e, |err| errno::odd_io_error(&source, &err))
         })
     }
 
+    /// Stops streaming and frees the device's buffers. Reading a frame then
+    /// fails with [`UsageError::NotStreaming`] until streaming starts again.
+    ///
+    /// Stopping a device that isn't streaming does nothing.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the device doesn't stop when asked.
+    #[inline]
+    pub fn stop_streaming(&mut self) -> Result<(), ConnectionError> {
+        self.stream.release().map_err(|e| self.stop_error(e))
+    }
+
+    /// Checks if the device is streaming.
+    #[inline]
+    pub const fn is_streaming(&self) -> bool {
+        self.stream.is_streaming()
+    }
+
+    /// Changes the device's image configuration, stopping and restarting the
+    /// stream around it if the device was streaming.
+    ///
+    /// This is what [`set_image_configuration`] does, but it also says how
+    /// long the switch took.
+    ///
+    /// [`set_image_configuration`]: VideoCaptureConfiguration::set_image_configuration
+    ///
+    /// # Errors
+    ///
+    /// This fails if the stream won't stop or restart, or if the device
+    /// doesn't take the configuration. The stream is restarted either way.
+    /// If the device doesn't take the configuration and won't restart, the
+    /// configuration's error is returned, and the restart's is logged.
+    #[inline]
+    pub fn reconfigure(
+        &mut self,
+        conf: &ImageConfiguration,
+    ) -> Result<config::VideoCaptureReconfiguration, ConfigError> {
+        let started = Instant::now();
+        let restarted = self.is_streaming();
+
+        let configuration = self.while_stopped(|device| {
+            configure_device(
+                &device.device,
+                &device.source_as_string(),
+                device.is_multiplanar(),
+                conf,
+            )
+        })?;
+
+        let switch_time = started.elapsed();
+        tracing::debug!(
+            "Device at `{}` switched to `{configuration}` in {switch_time:?}.",
+            self.source_as_string()
+        );
+
+        Ok(config::VideoCaptureReconfiguration {
+            configuration,
+            switch_time,
+            restarted,
+        })
+    }
+
+    /// Stops streaming while `change` runs, then starts again if the device
+    /// was streaming before.
+    fn while_stopped<T, F: FnOnce(&Self) -> Result<T, ConfigError>>(
+        &mut self,
+        change: F,
+    ) -> Result<T, ConfigError> {
+        let was_streaming = self.is_streaming();
+        if was_streaming {
+            self.stream
+                .release()
+                .map_err(|e| ConfigError::CouldntStopStream {
+                    source: self.source_as_string(),
+                    err_msg: e.to_string(),
+                })?;
+        }
+
+        let changed = change(self);
+
+        if was_streaming {
+            let multiplanar = self.is_multiplanar();
+            if let Err(e) = self.stream.start(&self.device, multiplanar) {
+                // a failed change is why we're here, so it's the one to return
+                if let Err(ref change_error) = changed {
+                    tracing::warn!(
+                        "Couldn't restart the device at `{}` after failing to change it ({change_error}). See: {e}",
+                        self.source_as_string()
+                    );
+                    return changed;
+                }
+
+                return Err(ConfigError::CouldntRestartStream {
+                    source: self.source_as_string(),
+                    err_msg: e.to_string(),
+                });
+            }
+        }
+
+        changed
+    }
+
+    /// Reads a frame only if one is ready right now.
+    ///
+    /// # Errors
+    ///
+    /// This fails with [`UsageError::Timeout`] if no frame is ready.
+    /// Otherwise, it fails like [`read_frame`](VideoCaptureStream::read_frame).
+    #[inline]
+    pub fn try_read_frame(&mut self) -> Result<Frame<'_>, UsageError> {
+        self.read_frame_within(Duration::ZERO, Errno::EAGAIN)
+    }
+
+    /// Reads with a time limit.
+    ///
+    /// `errno` is what to report if the time runs out.
+    fn read_frame_within(
+        &mut self,
+        timeout: Duration,
+        errno: Errno,
+    ) -> Result<Frame<'_>, UsageError> {
+        self.read_checked_frame(Some(timeout), errno)
+    }
+
+    /// Reads frames until one gets past the corrupt frame policy.
+    ///
+    /// The `timeout` covers any skipped frames, too. Either way, this gives up
+    /// after [`CorruptFramePolicy::MAX_SKIPS`] skips in a row.
+    fn read_checked_frame(
+        &mut self,
+        timeout: Option<Duration>,
+        errno: Errno,
+    ) -> Result<Frame<'_>, UsageError> {
+        // timeouts too long to add are as good as waiting forever
+        let deadline = timeout.and_then(|limit| Instant::now().checked_add(limit));
+
+        let mut skipped: u32 = 0;
+        let corruption = loop {
+            let remaining = deadline.map(|end| end.saturating_duration_since(Instant::now()));
+            let found = match self.stream.advance(remaining) {
+                Ok(true) => self
+                    .stream
+                    .frame()
+                    .map_err(|e| errno::usage_error(self.source_as_string(), &e))?
+                    .find_corruption(),
+                Ok(false) => {
+                    return Err(UsageError::Timeout {
+                        source: self.source_as_string(),
+                        errno: errno as i32,
+                    });
+                }
+                Err(e) => match errno::usage_error(self.source_as_string(), &e) {
+                    // the driver lost a frame without giving us anything, so
+                    // there's nothing to deliver
+                    UsageError::CorruptFrame { corruption, .. }
+                        if self.corrupt_frames == CorruptFramePolicy::Skip =>
+                    {
+                        Some(corruption)
+                    }
+                    err => return Err(err),
+                },
+            };
+
+            match (found, self.corrupt_frames) {
+                (Some(corruption), CorruptFramePolicy::Skip) => {
+                    self.skipped_frames = self.skipped_frames.saturating_add(1);
+                    skipped = skipped.saturating_add(1);
+                    if skipped >= CorruptFramePolicy::MAX_SKIPS {
+                        return Err(UsageError::TooManyCorruptFrames {
+                            source: self.source_as_string(),
+                            skipped,
+                            last: corruption,
+                        });
+                    }
+
+                    tracing::debug!(
+                        "Skipping a corrupt frame from `{}`: {corruption}",
+                        self.source_as_string()
+                    );
+                }
+                (Some(corruption), CorruptFramePolicy::Error) => {
+                    return Err(UsageError::CorruptFrame {
+                        source: self.source_as_string(),
+                        corruption,
+                    });
+                }
+                _ => break found,
+            }
+        };
+
+        let mut frame = self
+            .stream
+            .frame()
+            .map_err(|e| errno::usage_error(self.source_as_string(), &e))?;
+        frame.metadata.corruption = corruption;
+        Ok(frame)
+    }
+
+    /// Checks if this device has to use the multi-planar API.
+    const fn is_multiplanar(&self) -> bool {
+        self.descriptor.capabilities.needs_multiplanar()
+    }
+}
+
+impl<'path> VideoCaptureConnection<'path, V4LSource> for V4LVideoCaptureDevice<'path, '_> {
+    type Source = PathBuf;
+
+    #[inline]
+    fn new(source: Self::Source) -> Result<Self, ConnectionError> {
+        V4LVideoCaptureBuilder::new(source).open()
+    }
+
     #[inline]
     fn new_first() -> Result<Self, ConnectionError> {
         // try to find any device on the system
@@ -202,29 +659,30 @@ impl<'path> VideoCaptureConnection<'path, V4LSource> for V4LVideoCaptureDevice<'
 
     #[inline]
     fn disconnect(&mut self) -> Result<(), ConnectionError> {
-        self.stream.stop().map_err(|e| ConnectionError::StopError {
-            source: self.source_as_string(),
-            err_msg: e.to_string(),
-        })
+        self.stream.stop().map_err(|e| self.stop_error(e))
     }
 
     #[inline]
     fn reconnect(&mut self) -> Result<(), ConnectionError> {
-        // check if we already have a valid stream
-        if self.source.video.exists() && self.source.media.exists() {
+        // the old node keeps answering until the device goes away
+        let buf_type = capture_buf_type(self.is_multiplanar());
+        if self.device.format(buf_type).is_ok() {
             return Err(ConnectionError::AlreadyConnected {
                 source: self.source_as_string(),
             });
         }
 
         // grab device info
-        let device_info = MediaDeviceInfo::get(&self.source.media).map_err(|e| {
-            ConnectionError::CouldntGetDeviceInfo {
-                source: self.source_as_string(),
-                err_msg: e.to_string(),
-            }
+        let kernel = Arc::clone(self.device.kernel());
+        let device_info = kernel.media_device_info(&self.source.media).map_err(|e| {
+            errno::connection_error(&self.source_as_string(), e, |err| {
+                ConnectionError::CouldntGetDeviceInfo {
+                    source: self.source_as_string(),
+                    err_msg: err.to_string(),
+                }
+            })
         })?;
-        let (device_identifier, device_model) = (device_info.serial(), device_info.model());
+        let (device_identifier, device_model) = (device_info.serial, device_info.model);
 
         // see if the device model changed
         if device_model != self.descriptor.device_model {
@@ -245,32 +703,42 @@ impl<'path> VideoCaptureConnection<'path, V4LSource> for V4LVideoCaptureDevice<'
         }
 
         // attempt to access the device by path
-        #[allow(clippy::map_err_ignore)]
-        // TODO: hey, check the fs error if it doesn't exist or the camera
-        // just failed to connect.
-        let device = Device::with_path(&self.source.video).map_err(|_| {
-            ConnectionError::SourceDoesntExist {
-                source: self.source_as_string(),
-            }
+        let device = V4LDevice::open(kernel, &self.source.video).map_err(|e| {
+            let source = self.source_as_string();
+            errno::connection_error(&source, e, |err| errno::odd_io_error(&source, &err))
         })?;
 
-        // make a stream connected to the device
-        // FIXME: this creates a buffer that the user didn't ask for!
-        let mut stream = MmapStream::with_buffers(&device, Type::VideoCapture, 4).map_err(|e| {
-            ConnectionError::CaptureDeviceBusy {
+        // a replugged device forgets everything, so set it up again. a
+        // profile applied by hand is kept if none of ours match
+        let profile = config::find_profile(&self.profiles, &device_identifier, &device_model)
+            .or(self.profile.as_ref())
+            .cloned();
+        if let Some(ref wanted) = profile {
+            apply_profile_to_device(
+                &device,
+                &self.source_as_string(),
+                self.is_multiplanar(),
+                wanted,
+            )
+            .map_err(|e| ConnectionError::CouldntApplyProfile {
                 source: self.source_as_string(),
                 err_msg: e.to_string(),
-            }
-        })?;
+            })?;
+        }
+        self.profile = profile;
 
-        // this performs warm-up or something...
-        // TODO: look at fr v4l docs to see what that means lol
-        stream.next().map_err(|e| ConnectionError::WarmUpFailed {
-            source: self.source_as_string(),
-            err_msg: e.to_string(),
-        })?;
+        // the old buffers belong to the old device, so let them go first
+        if let Err(e) = self.stream.release() {
+            tracing::debug!(
+                "Failed to stop the old stream at `{}`: {e}",
+                self.source_as_string()
+            );
+        }
+        self.device = device;
+        self.start_streaming()?;
 
-        Ok(())
+        // like when opening, make buggy drivers fill in their info
+        self.warm_up()
     }
 }
 
@@ -284,164 +752,915 @@ where
     type Buffer = MmapStream<'conn>;
     type Source = V4LSource;
     type SourceInput = &'path Path;
-    type Metadata = v4l::buffer::Metadata;
 
     #[inline]
-    fn read_frame<'func>(
+    fn read_frame<'func>(&'func mut self) -> Result<Frame<'func>, UsageError>
+    where
+        'path: 'func,
+    {
+        // without a timeout, this never runs out of time
+        let timeout = self.read_timeout();
+        self.read_checked_frame(timeout, Errno::ETIMEDOUT)
+    }
+
+    #[inline]
+    fn read_frame_timeout<'func>(
         &'func mut self,
-    ) -> Result<(&'func [u8], &'func Self::Metadata), UsageError>
+        timeout: Duration,
+    ) -> Result<Frame<'func>, UsageError>
     where
         'path: 'func,
     {
-        self.stream.next().map_err(|e| UsageError::IoError {
-            source: self.source.user_source_string(),
-            err_msg: e.to_string(),
-        })
+        self.read_frame_within(timeout, Errno::ETIMEDOUT)
     }
 }
 
 impl VideoCaptureConfiguration for V4LVideoCaptureDevice<'_, '_> {
     #[inline]
     fn supported_image_configurations(&self) -> Result<Vec<ImageConfiguration>, ConfigError> {
-        let no_cfgs_err = |e: std::io::Error| ConfigError::DeviceDoesntListConfigurations {
-            source: self.source_as_string(),
-            err_msg: e.to_string(),
+        list_image_configurations(
+            &**self.device.kernel(),
+            self.device.fd(),
+            &self.source_as_string(),
+            capture_buf_type(self.is_multiplanar()),
+        )
+    }
+
+    #[inline]
+    fn image_configuration(&self) -> Result<ImageConfiguration, ConfigError> {
+        let format_err = |e: std::io::Error| {
+            errno::config_error(&self.source_as_string(), e, |err| {
+                ConfigError::CouldntGetFormat {
+                    source: self.source_as_string(),
+                    err_msg: err.to_string(),
+                }
+            })
         };
+        let buf_type = capture_buf_type(self.is_multiplanar());
+        let format = self.device.format(buf_type).map_err(format_err)?;
+
+        let framerate = read_framerate(
+            &**self.device.kernel(),
+            self.device.fd(),
+            &self.source_as_string(),
+            buf_type,
+        )?;
+
+        Ok(ImageConfiguration {
+            format: Format::new(format.fourcc),
+            resolution: format.resolution(),
+            framerate,
+        })
+    }
+
+    #[inline]
+    fn set_image_configuration(
+        &mut self,
+        conf: &ImageConfiguration,
+    ) -> Result<ImageConfiguration, ConfigError> {
+        self.reconfigure(conf)
+            .map(|reconfiguration| reconfiguration.configuration)
+    }
+}
+
+impl VideoCaptureProperties for V4LVideoCaptureDevice<'_, '_> {
+    #[inline]
+    fn properties(&self) -> Vec<Property> {
+        match controls::known_controls(&self.device, &self.source_as_string()) {
+            Ok(known) => known
+                .iter()
+                .map(controls::KnownControl::to_property)
+                .collect(),
+            Err(e) => {
+                tracing::warn!("Failed to list properties. See: {e}");
+                Vec::new()
+            }
+        }
+    }
+
+    #[inline]
+    fn property(&self, key: String) -> Option<Property> {
+        self.properties()
+            .into_iter()
+            .find(|property| property.key() == key)
+    }
+
+    #[inline]
+    fn set_property(&mut self, property: &Property) -> Result<(), ConfigError> {
+        self.set_properties(core::slice::from_ref(property))
+    }
+}
+
+impl VideoCaptureProfiles for V4LVideoCaptureDevice<'_, '_> {
+    #[inline]
+    fn apply_profile(&mut self, profile: &VideoCaptureProfile) -> Result<(), ConfigError> {
+        // only a new image configuration needs the stream stopped
+        if profile.image_configuration.is_some() {
+            self.while_stopped(|device| {
+                apply_profile_to_device(
+                    &device.device,
+                    &device.source_as_string(),
+                    device.is_multiplanar(),
+                    profile,
+                )
+            })?;
+        } else {
+            apply_profile_to_device(
+                &self.device,
+                &self.source_as_string(),
+                self.is_multiplanar(),
+                profile,
+            )?;
+        }
+        self.profile = Some(profile.clone());
+        Ok(())
+    }
+
+    #[inline]
+    fn export_profile(&self) -> Result<VideoCaptureProfile, ConfigError> {
+        let mut profile = self.profile.clone().unwrap_or_else(|| {
+            VideoCaptureProfile::new(
+                self.descriptor.device_identifier(),
+                self.descriptor.device_model(),
+            )
+        });
+
+        // only keep the properties we could write back later
+        let known = controls::known_controls(&self.device, &self.source_as_string())?;
+        profile.properties = known
+            .iter()
+            .filter(|control| control.is_writable())
+            .map(controls::KnownControl::to_property)
+            .collect();
+        profile.image_configuration = Some(self.image_configuration()?);
+
+        Ok(profile)
+    }
+
+    #[inline]
+    fn profile(&self) -> Option<&VideoCaptureProfile> {
+        self.profile.as_ref()
+    }
+}
 
-        // a list to store the supported fmts
-        let mut supported_formats = Vec::new();
+/// Lists every format, resolution, and framerate that a device supports
+/// for the given buffer type.
+fn list_image_configurations(
+    kernel: &dyn Kernel,
+    fd: RawFd,
+    source: &str,
+    buf_type: u32,
+) -> Result<Vec<ImageConfiguration>, ConfigError> {
+    let no_cfgs_err = |e: std::io::Error| {
+        errno::config_error(source, e, |err| {
+            ConfigError::DeviceDoesntListConfigurations {
+                source: source.to_owned(),
+                err_msg: err.to_string(),
+            }
+        })
+    };
+
+    // a list to store the supported fmts
+    let mut supported_formats = Vec::new();
+
+    for fourcc in kernel.enum_formats(fd, buf_type).map_err(no_cfgs_err)? {
+        let format_rs = Format::new(fourcc);
 
-        // ask device for supported formats
-        let formats = self.device.enum_formats().map_err(no_cfgs_err)?;
+        let resolutions = kernel.enum_framesizes(fd, fourcc).map_err(no_cfgs_err)?;
 
-        for format in formats {
-            let fourcc = format.fourcc;
-            let format_rs = Format::new(fourcc.repr);
+        for resolution in resolutions {
+            // check the available framerates for this framesize
+            let FrameSize::Discrete(discrete_resolution) = resolution else {
+                tracing::warn!("Device at source `{source}` returned a stepwise resolution for `{format_rs}`. These aren't currently supported.");
+                continue;
+            };
 
-            let resolutions = self
-                .device
-                .enum_framesizes(format.fourcc)
+            let frame_intervals = kernel
+                .enum_frameintervals(fd, fourcc, discrete_resolution)
                 .map_err(no_cfgs_err)?;
 
-            for resolution in resolutions {
-                // check the available framerates for this framesize
-                let discrete_resolution = match resolution.size {
-                    v4l::framesize::FrameSizeEnum::Discrete(res) => res,
-                    v4l::framesize::FrameSizeEnum::Stepwise(framesize) => {
-                        tracing::warn!("Device at source `{}` returned a stepwise resolution (`{framesize}`). These aren't currently supported.", self.source_as_string());
-                        continue;
-                    }
+            for frame_interval in frame_intervals {
+                let FrameInterval::Discrete {
+                    numerator,
+                    denominator,
+                } = frame_interval
+                else {
+                    tracing::warn!("Device at source `{source}` returned a stepwise framerate for `{format_rs}` at `{discrete_resolution}`. These aren't currently supported.");
+                    continue;
                 };
 
-                let frame_intervals = self
-                    .device
-                    .enum_frameintervals(
-                        fourcc,
-                        discrete_resolution.width,
-                        discrete_resolution.height,
-                    )
-                    .map_err(no_cfgs_err)?;
-
-                for frame_interval in frame_intervals {
-                    let interval = match frame_interval.interval {
-                        v4l::frameinterval::FrameIntervalEnum::Discrete(frac) => frac,
-                        v4l::frameinterval::FrameIntervalEnum::Stepwise(rate) => {
-                            tracing::warn!("Device at source `{}` returned a stepwise framerate (`{rate}`). These aren't currently supported.", self.source_as_string());
-                            continue;
-                        }
-                    };
-
-                    // compute the frame rate (a frame rate is 1 / frame_interval)
-                    let interval_frac = Fraction::new(interval.numerator, interval.denominator);
-                    let rate = Fraction::one() / interval_frac;
-
-                    supported_formats.push(ImageConfiguration {
-                        format: format_rs,
-                        resolution: SpecificResolution {
-                            width: discrete_resolution.width,
-                            height: discrete_resolution.height,
-                        },
-                        framerate: rate,
-                    });
-                }
+                // compute the frame rate (a frame rate is 1 / frame_interval)
+                let interval_frac = Fraction::new(numerator, denominator);
+                let rate = Fraction::one() / interval_frac;
+
+                supported_formats.push(ImageConfiguration {
+                    format: format_rs,
+                    resolution: discrete_resolution,
+                    framerate: rate,
+                });
             }
         }
-
-        Ok(supported_formats)
     }
 
-    #[inline]
-    fn image_configuration(&self) -> Result<ImageConfiguration, ConfigError> {
-        let format = self
-            .device
-            .format()
-            .map_err(|e| ConfigError::CouldntGetFormat {
-                source: self.source_as_string(),
-                err_msg: e.to_string(),
-            })?;
+    Ok(supported_formats)
+}
 
-        let fd = self.device.handle().fd();
-        let v4l2_stream_parm = framerate::V4l2StreamParm::new(self.source_as_string(), fd)?;
-        let framerate = v4l2_stream_parm.get_frame_rate();
+/// Reads the framerate that a device is using for the given buffer type.
+fn read_framerate(
+    kernel: &dyn Kernel,
+    fd: RawFd,
+    source: &str,
+    buf_type: u32,
+) -> Result<config::Framerate, ConfigError> {
+    let interval = kernel.frame_interval(fd, buf_type).map_err(|e| {
+        errno::config_error(source, e, |err| ConfigError::CouldntGetFormat {
+            source: source.to_owned(),
+            err_msg: format!("ioctl call for `VIDIOC_G_PARM` failed. IO error: {err}"),
+        })
+    })?;
 
-        Ok(ImageConfiguration {
-            format: Format::new(format.fourcc.repr),
-            resolution: SpecificResolution::new(format.width, format.height),
-            framerate,
+    // a frame rate is 1 / frame_interval
+    Ok(Fraction::one() / interval)
+}
+
+/// Sets a device's format, resolution, and framerate, then reads back what
+/// the device actually chose.
+fn configure_device(
+    device: &V4LDevice,
+    source: &str,
+    multiplanar: bool,
+    conf: &ImageConfiguration,
+) -> Result<ImageConfiguration, ConfigError> {
+    let write_err = |e: std::io::Error| {
+        errno::config_error(source, e, |err| ConfigError::PropertyWriteFailure {
+            source: source.to_owned(),
+            err_msg: format!("Failed to change image configuration. IO Error {err}"),
         })
+    };
+
+    // send it to the device and get back the info we wanted. multi-planar
+    // devices also pick how many planes to use (and their strides) here
+    let buf_type = capture_buf_type(multiplanar);
+    let actual = device
+        .set_format(buf_type, conf.format.array(), conf.resolution)
+        .map_err(write_err)?;
+    if multiplanar {
+        tracing::debug!(
+            "Device at source `{source}` chose planes: {:?}",
+            actual.planes
+        );
     }
+    let (actual_format, actual_resolution) = (Format::new(actual.fourcc), actual.resolution());
 
-    #[inline]
-    #[must_use = "The capture device may have used another image configuration
-    that does not match the input. Consider checking the output config before continuing."]
-    fn set_image_configuration(
-        &self,
-        conf: &ImageConfiguration,
-    ) -> Result<ImageConfiguration, ConfigError> {
-        // make a v4l format from the given img conf
-        let expected = v4l::Format::new(
-            conf.resolution.width,
-            conf.resolution.height,
-            v4l::FourCC {
-                repr: conf.format.array(),
-            },
+    // devices take a frame interval, which is 1 / framerate
+    let interval =
+        conf.framerate
+            .denom()
+            .zip(conf.framerate.numer())
+            .and_then(|(&numer, &denom)| {
+                Some((u32::try_from(numer).ok()?, u32::try_from(denom).ok()?))
+            });
+    if let Some((numerator, denominator)) = interval {
+        device
+            .set_frame_interval(buf_type, numerator, denominator)
+            .map_err(|e| {
+                errno::config_error(source, e, |err| ConfigError::PropertyWriteFailure {
+                    source: source.to_owned(),
+                    err_msg: format!("Failed to change framerate. IO Error {err}"),
+                })
+            })?;
+    } else {
+        tracing::warn!(
+            "Device at source `{source}` can't use framerate `{}`. Leaving it alone.",
+            conf.framerate
         );
+    }
 
-        // send it to the device and get back the info we wanted
-        let actual =
-            self.device
-                .set_format(&expected)
-                .map_err(|e| ConfigError::PropertyWriteFailure {
-                    source: self.source_as_string(),
-                    err_msg: format!("Failed to change image configuration. IO Error {e}"),
-                })?;
+    // let's also check the framerate. we gotta do it manually, unfortunately
+    let framerate = read_framerate(&**device.kernel(), device.fd(), source, buf_type)?;
 
-        // let's also check the framerate. we gotta do it manually, unfortunately
-        let framerate =
-            framerate::V4l2StreamParm::new(self.source_as_string(), self.device.handle().fd())?
-                .get_frame_rate();
+    // create a img conf from all that info
+    let actual_conf = ImageConfiguration {
+        format: actual_format,
+        resolution: actual_resolution,
+        framerate,
+    };
 
-        // create a img conf from all that info
-        let actual_conf = ImageConfiguration {
-            format: actual.into(),
-            resolution: actual.into(),
-            framerate,
+    // compare them and tell user if they're not the same.
+    //
+    // note that this isn't an error. the trait accounts for the mismatch by returning it.
+    if conf != &actual_conf {
+        tracing::warn!(
+            "Device at source `{source}` has format mismatch.\n
+                - Expected: `{conf}`\n
+                - Got: `{actual_conf}`",
+        );
+    }
+
+    Ok(actual_conf)
+}
+
+/// Applies a profile's image configuration and properties to a device.
+fn apply_profile_to_device(
+    device: &V4LDevice,
+    source: &str,
+    multiplanar: bool,
+    profile: &VideoCaptureProfile,
+) -> Result<(), ConfigError> {
+    if let Some(ref conf) = profile.image_configuration {
+        configure_device(device, source, multiplanar, conf)?;
+    }
+
+    controls::set_controls(device, source, &profile.properties)
+}
+
+/// The buffer type that a capture device streams with.
+const fn capture_buf_type(multiplanar: bool) -> u32 {
+    if multiplanar {
+        raw::V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE
+    } else {
+        raw::V4L2_BUF_TYPE_VIDEO_CAPTURE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nix::errno::Errno;
+
+    use v4l::control::Type;
+
+    use super::fake::{uvc_camera, Call, FakeKernel, Issued};
+    use super::raw::{
+        V4L2_BUF_TYPE_META_CAPTURE, V4L2_BUF_TYPE_VIDEO_CAPTURE, V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE,
+    };
+    use super::*;
+    use crate::config::SpecificResolution;
+
+    const SOURCE: &str = "/dev/video0";
+
+    /// A camera that does MJPEG at two sizes, plus a format it only
+    /// describes stepwise.
+    fn camera() -> FakeKernel {
+        uvc_camera()
+            .with_formats(V4L2_BUF_TYPE_VIDEO_CAPTURE, &[*b"MJPG", *b"YUYV"])
+            .with_framesizes(
+                *b"MJPG",
+                &[
+                    FrameSize::Discrete(SpecificResolution::new(1920, 1080)),
+                    FrameSize::Discrete(SpecificResolution::new(640, 480)),
+                ],
+            )
+            .with_frameintervals(
+                *b"MJPG",
+                (1920, 1080),
+                &[FrameInterval::Discrete {
+                    numerator: 1,
+                    denominator: 30,
+                }],
+            )
+            .with_frameintervals(
+                *b"MJPG",
+                (640, 480),
+                &[
+                    FrameInterval::Discrete {
+                        numerator: 1,
+                        denominator: 60,
+                    },
+                    FrameInterval::Stepwise,
+                ],
+            )
+            .with_framesizes(*b"YUYV", &[FrameSize::Stepwise])
+            .with_frame_interval(1, 30)
+    }
+
+    #[test]
+    fn lists_discrete_configurations() {
+        let configurations =
+            list_image_configurations(&camera(), 0, SOURCE, V4L2_BUF_TYPE_VIDEO_CAPTURE).unwrap();
+
+        let mjpeg = |width, height, fps: u32| ImageConfiguration {
+            format: Format::new(*b"MJPG"),
+            resolution: SpecificResolution::new(width, height),
+            framerate: Fraction::new(fps, 1_u32),
         };
+        assert_eq!(
+            configurations,
+            vec![mjpeg(1920, 1080, 30), mjpeg(640, 480, 60)],
+            "stepwise sizes and intervals are skipped"
+        );
+    }
 
-        // compare them and tell user if they're not the same.
-        //
-        // note that this isn't an error. the trait accounts for the mismatch by returning it.
-        if conf != &actual_conf {
-            tracing::warn!(
-                "Device at source `{}` has format mismatch.\n
-                    - Expected: `{}`\n
-                    - Got: `{}`",
-                self.source_as_string(),
-                expected,
-                actual
+    #[test]
+    fn configuration_errors() {
+        for call in [
+            Call::EnumFormats,
+            Call::EnumFramesizes,
+            Call::EnumFrameintervals,
+        ] {
+            let kernel = camera().failing(call, Errno::EIO);
+            let err = list_image_configurations(&kernel, 0, SOURCE, V4L2_BUF_TYPE_VIDEO_CAPTURE)
+                .unwrap_err();
+
+            assert!(
+                matches!(
+                    err,
+                    ConfigError::DeviceDoesntListConfigurations { ref source, .. } if source == SOURCE
+                ),
+                "failing {call:?} gave {err}"
             );
         }
 
-        Ok(actual_conf)
+        // unplugged devices say so, no matter which call noticed
+        let kernel = camera().failing(Call::EnumFramesizes, Errno::ENODEV);
+        let err =
+            list_image_configurations(&kernel, 0, SOURCE, V4L2_BUF_TYPE_VIDEO_CAPTURE).unwrap_err();
+        assert!(err.is_disconnect(), "got {err}");
+    }
+
+    #[test]
+    fn reads_framerate() {
+        let framerate = read_framerate(&camera(), 0, SOURCE, V4L2_BUF_TYPE_VIDEO_CAPTURE);
+        assert_eq!(framerate.unwrap(), Fraction::new(30_u32, 1_u32));
+
+        let kernel = camera().failing(Call::FrameInterval, Errno::EIO);
+        assert!(matches!(
+            read_framerate(&kernel, 0, SOURCE, V4L2_BUF_TYPE_VIDEO_CAPTURE),
+            Err(ConfigError::CouldntGetFormat { .. })
+        ));
+    }
+
+    #[test]
+    fn describes_devices() {
+        let kernel = camera();
+        let source = V4LSource::resolve(&kernel, Path::new(SOURCE)).unwrap();
+
+        let descriptor = V4LVideoCaptureDescriptor::from_source_with(&kernel, &source).unwrap();
+        assert_eq!(descriptor.device_identifier(), "ABCD1234");
+        assert_eq!(descriptor.device_model(), "C922 Pro Stream Webcam");
+        assert!(descriptor.capabilities.device_capabilities.is_capture());
+
+        for call in [Call::MediaDeviceInfo, Call::QueryCap] {
+            let broken = kernel.clone().failing(call, Errno::EACCES);
+            assert!(
+                matches!(
+                    V4LVideoCaptureDescriptor::from_source_with(&broken, &source),
+                    Err(ConnectionError::CouldntGetDeviceInfo { source: ref given, .. }) if given == SOURCE
+                ),
+                "failing {call:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn opens_and_streams_through_the_kernel() {
+        let kernel = camera()
+            .with_current_format(V4L2_BUF_TYPE_VIDEO_CAPTURE, *b"YUYV", (320, 240))
+            .with_control(0x0098_0900, "Brightness", Type::Integer, (0, 255), 128);
+        let mut device = V4LVideoCaptureDevice::builder(PathBuf::from(SOURCE))
+            .io_method(V4LIoMethod::UserPtr)
+            .buffer_count(2)
+            .open_with(Arc::new(kernel.clone()))
+            .unwrap();
+        assert!(
+            kernel.is_streaming(V4L2_BUF_TYPE_VIDEO_CAPTURE),
+            "devices stream once they're open"
+        );
+        assert_eq!(
+            kernel.issued().first(),
+            Some(&Issued::Open(PathBuf::from(SOURCE)))
+        );
+
+        let frame = device.read_frame().unwrap();
+        assert_eq!(
+            frame.resolution,
+            SpecificResolution::new(320, 240),
+            "without a configuration, the current one is kept"
+        );
+
+        device
+            .set_property(&Property::new(String::from("brightness"), "200"))
+            .unwrap();
+        assert!(kernel.issued().contains(&Issued::SetControl {
+            id: 0x0098_0900,
+            value: 200
+        }));
+        assert_eq!(
+            device.property(String::from("brightness")).unwrap().value(),
+            "200"
+        );
+
+        kernel.unplug();
+        assert!(device.read_frame().is_err(), "unplugged devices can't read");
+        kernel.replug();
+        device.reconnect().unwrap();
+        assert!(
+            kernel.is_streaming(V4L2_BUF_TYPE_VIDEO_CAPTURE),
+            "reconnecting streams again"
+        );
+        device.read_frame().unwrap();
+    }
+
+    #[test]
+    fn applies_profiles_on_open_and_reconnect() {
+        let kernel = camera()
+            .with_current_format(V4L2_BUF_TYPE_VIDEO_CAPTURE, *b"YUYV", (320, 240))
+            .with_control(0x0098_0900, "Brightness", Type::Integer, (0, 255), 128);
+        let mut profile =
+            VideoCaptureProfile::new("ABCD1234".into(), "C922 Pro Stream Webcam".into());
+        profile.image_configuration = Some(ImageConfiguration {
+            format: Format::new(*b"MJPG"),
+            resolution: SpecificResolution::new(640, 480),
+            framerate: Fraction::new(60_u32, 1_u32),
+        });
+        profile.properties = vec![Property::new(String::from("brightness"), "42")];
+        let mut for_model = VideoCaptureProfile::new(String::new(), profile.device_model.clone());
+        for_model.properties = vec![Property::new(String::from("brightness"), "7")];
+        let for_other = VideoCaptureProfile::new(String::new(), "Some Other Camera".into());
+
+        let mut device = V4LVideoCaptureDevice::builder(PathBuf::from(SOURCE))
+            .profiles([for_other, for_model, profile.clone()])
+            .warm_up(V4LWarmUp::None)
+            .open_with(Arc::new(kernel.clone()))
+            .unwrap();
+        assert_eq!(device.profile(), Some(&profile), "the exact device wins");
+        assert_eq!(device.profiles().len(), 3, "kept for reconnecting");
+
+        let applied = vec![
+            Issued::SetFormat {
+                buf_type: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+                fourcc: *b"MJPG",
+                resolution: SpecificResolution::new(640, 480),
+            },
+            Issued::SetFrameInterval {
+                buf_type: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+                numerator: 1,
+                denominator: 60,
+            },
+            Issued::SetControl {
+                id: 0x0098_0900,
+                value: 42,
+            },
+        ];
+        let issued = kernel.issued();
+        assert_eq!(
+            issued.get(1..4),
+            Some(applied.as_slice()),
+            "applied on open"
+        );
+
+        assert!(
+            matches!(
+                device.reconnect(),
+                Err(ConnectionError::AlreadyConnected { .. })
+            ),
+            "still plugged in"
+        );
+
+        kernel.unplug();
+        kernel.replug();
+        device.reconnect().unwrap();
+        let reopened = kernel.issued().split_off(issued.len());
+        assert_eq!(reopened.first(), Some(&Issued::Open(PathBuf::from(SOURCE))));
+        assert_eq!(
+            reopened.get(1..4),
+            Some(applied.as_slice()),
+            "applied again on reconnect"
+        );
+        assert!(
+            kernel.is_streaming(V4L2_BUF_TYPE_VIDEO_CAPTURE),
+            "reconnecting streams again"
+        );
+    }
+
+    #[test]
+    fn skips_profiles_for_other_devices() {
+        let kernel = camera()
+            .with_current_format(V4L2_BUF_TYPE_VIDEO_CAPTURE, *b"YUYV", (320, 240))
+            .with_control(0x0098_0900, "Brightness", Type::Integer, (0, 255), 128);
+        let mut profile = VideoCaptureProfile::new(String::new(), "Some Other Camera".into());
+        profile.properties = vec![Property::new(String::from("brightness"), "42")];
+
+        let mut device = V4LVideoCaptureDevice::builder(PathBuf::from(SOURCE))
+            .profile(profile)
+            .warm_up(V4LWarmUp::None)
+            .open_with(Arc::new(kernel.clone()))
+            .unwrap();
+        assert_eq!(device.profile(), None, "nothing matched");
+        assert!(
+            kernel.is_streaming(V4L2_BUF_TYPE_VIDEO_CAPTURE),
+            "opened anyway"
+        );
+
+        kernel.unplug();
+        kernel.replug();
+        device.reconnect().unwrap();
+        assert_eq!(device.profile(), None, "still nothing on reconnect");
+        assert!(
+            !kernel
+                .issued()
+                .iter()
+                .any(|issued| matches!(*issued, Issued::SetControl { .. })),
+            "nothing was applied"
+        );
+    }
+
+    #[test]
+    fn sets_the_framerate_on_multiplanar_devices() {
+        let mplane = V4LCapabilityFlags(
+            V4LCapabilityFlags::VIDEO_CAPTURE_MPLANE.0 | V4LCapabilityFlags::STREAMING.0,
+        );
+        let kernel = camera()
+            .with_video(SOURCE, (81, 0), mplane)
+            .with_current_format(V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE, *b"NV12", (320, 240));
+        let conf = ImageConfiguration {
+            format: Format::new(*b"NV12"),
+            resolution: SpecificResolution::new(640, 480),
+            framerate: Fraction::new(15_u32, 1_u32),
+        };
+
+        let device = V4LVideoCaptureDevice::builder(PathBuf::from(SOURCE))
+            .image_configuration(conf)
+            .start_streaming(false)
+            .open_with(Arc::new(kernel.clone()))
+            .unwrap();
+        assert!(
+            kernel.issued().contains(&Issued::SetFrameInterval {
+                buf_type: V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE,
+                numerator: 1,
+                denominator: 15,
+            }),
+            "issued {:?}",
+            kernel.issued()
+        );
+        assert_eq!(device.image_configuration().unwrap(), conf);
+    }
+
+    #[test]
+    fn stops_and_starts_streaming() {
+        let kernel =
+            camera().with_current_format(V4L2_BUF_TYPE_VIDEO_CAPTURE, *b"YUYV", (320, 240));
+        let mut device = V4LVideoCaptureDevice::builder(PathBuf::from(SOURCE))
+            .open_with(Arc::new(kernel.clone()))
+            .unwrap();
+        device.read_frame().unwrap();
+
+        device.stop_streaming().unwrap();
+        assert!(!device.is_streaming(), "stopped");
+        assert!(
+            !kernel.is_streaming(V4L2_BUF_TYPE_VIDEO_CAPTURE),
+            "the kernel was told"
+        );
+        assert!(
+            matches!(device.read_frame(), Err(UsageError::NotStreaming { .. })),
+            "nothing to read while stopped"
+        );
+        device.stop_streaming().unwrap();
+
+        device.start_streaming().unwrap();
+        assert!(
+            kernel.is_streaming(V4L2_BUF_TYPE_VIDEO_CAPTURE),
+            "streaming again"
+        );
+        assert_eq!(
+            device.read_frame().unwrap().metadata.sequence,
+            0,
+            "sequence numbers start over"
+        );
+    }
+
+    #[test]
+    fn reconfiguring_restarts_the_stream() {
+        let kernel =
+            camera().with_current_format(V4L2_BUF_TYPE_VIDEO_CAPTURE, *b"YUYV", (320, 240));
+        let mut device = V4LVideoCaptureDevice::builder(PathBuf::from(SOURCE))
+            .open_with(Arc::new(kernel.clone()))
+            .unwrap();
+        device.read_frame().unwrap();
+        let conf = ImageConfiguration {
+            format: Format::new(*b"MJPG"),
+            resolution: SpecificResolution::new(640, 480),
+            framerate: Fraction::new(60_u32, 1_u32),
+        };
+
+        let before = kernel.issued().len();
+        let switched = device.reconfigure(&conf).unwrap();
+        assert!(switched.restarted, "it was streaming");
+        assert_eq!(switched.configuration, conf);
+        assert_eq!(
+            kernel.issued().get(before..),
+            Some(
+                [
+                    Issued::StreamOff(V4L2_BUF_TYPE_VIDEO_CAPTURE),
+                    Issued::SetFormat {
+                        buf_type: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+                        fourcc: *b"MJPG",
+                        resolution: conf.resolution,
+                    },
+                    Issued::SetFrameInterval {
+                        buf_type: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+                        numerator: 1,
+                        denominator: 60,
+                    },
+                    Issued::StreamOn(V4L2_BUF_TYPE_VIDEO_CAPTURE),
+                ]
+                .as_slice()
+            ),
+            "stopped, changed, then started"
+        );
+        assert_eq!(
+            device.read_frame().unwrap().resolution,
+            conf.resolution,
+            "frames come in the new size"
+        );
+
+        // stopped devices stay stopped
+        device.stop_streaming().unwrap();
+        let switched = device.reconfigure(&conf).unwrap();
+        assert!(!switched.restarted, "it wasn't streaming");
+        assert!(!device.is_streaming(), "still stopped");
+        assert!(
+            !kernel.is_streaming(V4L2_BUF_TYPE_VIDEO_CAPTURE),
+            "never started"
+        );
+    }
+
+    #[test]
+    fn failed_changes_come_before_failed_restarts() {
+        let kernel =
+            camera().with_current_format(V4L2_BUF_TYPE_VIDEO_CAPTURE, *b"YUYV", (320, 240));
+        let mut device = V4LVideoCaptureDevice::builder(PathBuf::from(SOURCE))
+            .open_with(Arc::new(kernel.clone()))
+            .unwrap();
+        device.read_frame().unwrap();
+
+        let err = device
+            .while_stopped(|_| -> Result<(), ConfigError> {
+                // the restart fails too
+                kernel.unplug();
+                Err(ConfigError::NoMatchingConfiguration {
+                    source: String::from(SOURCE),
+                })
+            })
+            .unwrap_err();
+        assert!(
+            matches!(err, ConfigError::NoMatchingConfiguration { .. }),
+            "the change's error: {err}"
+        );
+    }
+
+    #[test]
+    fn skipping_gives_up_eventually() {
+        // every frame is lost, so there's never one to deliver
+        let kernel = camera()
+            .with_current_format(V4L2_BUF_TYPE_VIDEO_CAPTURE, *b"YUYV", (320, 240))
+            .failing_stream(Call::DequeueBuffer, V4L2_BUF_TYPE_VIDEO_CAPTURE, Errno::EIO);
+        let mut device = V4LVideoCaptureDevice::builder(PathBuf::from(SOURCE))
+            .corrupt_frame_policy(CorruptFramePolicy::Skip)
+            .warm_up(V4LWarmUp::None)
+            .open_with(Arc::new(kernel))
+            .unwrap();
+        assert_eq!(device.read_timeout(), None, "no timeout to save us");
+
+        let err = device.read_frame().unwrap_err();
+        assert!(
+            matches!(
+                err,
+                UsageError::TooManyCorruptFrames { skipped, .. }
+                    if skipped == CorruptFramePolicy::MAX_SKIPS
+            ),
+            "gave up: {err}"
+        );
+        assert_eq!(
+            device.skipped_frames(),
+            u64::from(CorruptFramePolicy::MAX_SKIPS),
+            "every skip counts"
+        );
+    }
+
+    #[test]
+    fn unplugged_streams_say_so() {
+        let kernel = camera()
+            .with_current_format(V4L2_BUF_TYPE_VIDEO_CAPTURE, *b"YUYV", (320, 240))
+            .failing(Call::StreamOn, Errno::ENODEV);
+        let mut device = V4LVideoCaptureDevice::builder(PathBuf::from(SOURCE))
+            .start_streaming(false)
+            .open_with(Arc::new(kernel))
+            .unwrap();
+
+        let err = device.start_streaming().unwrap_err();
+        assert!(
+            matches!(err, ConnectionError::Disconnected { errno, .. } if errno == Errno::ENODEV as i32),
+            "STREAMON's errno is kept: {err}"
+        );
+    }
+
+    #[test]
+    fn stopping_releases_both_streams() {
+        let kernel = camera()
+            .with_current_format(V4L2_BUF_TYPE_VIDEO_CAPTURE, *b"YUYV", (320, 240))
+            .with_current_format(V4L2_BUF_TYPE_META_CAPTURE, *b"UVCH", (16, 1))
+            .failing_stream(Call::StreamOff, V4L2_BUF_TYPE_META_CAPTURE, Errno::EIO);
+        let mut device = V4LVideoCaptureDevice::builder(PathBuf::from(SOURCE))
+            .open_with(Arc::new(kernel.clone()))
+            .unwrap();
+        device.open_metadata().unwrap();
+        device.read_frame().unwrap();
+        assert!(kernel.is_streaming(V4L2_BUF_TYPE_META_CAPTURE), "metadata");
+
+        assert!(
+            matches!(device.disconnect(), Err(ConnectionError::StopError { .. })),
+            "the metadata node wouldn't stop"
+        );
+        assert!(
+            !kernel.is_streaming(V4L2_BUF_TYPE_VIDEO_CAPTURE),
+            "the video node stopped anyway"
+        );
+        assert!(!device.has_metadata(), "the metadata node was closed");
+        assert!(!device.is_streaming(), "the buffers were freed");
+    }
+
+    /// Load vivid with `modprobe vivid` to run this.
+    #[test]
+    #[ignore = "needs the vivid driver loaded"]
+    fn vivid_reconfigures_while_streaming() {
+        let path = V4LBackend::list_connected_devices()
+            .into_iter()
+            .find(|path| {
+                V4LCapabilities::query(path).is_ok_and(|caps| {
+                    caps.driver == "vivid" && caps.device_capabilities.is_capture()
+                })
+            })
+            .expect("a vivid capture device");
+        let mut device = V4LVideoCaptureDevice::new(path).unwrap();
+        assert!(device.is_streaming(), "devices stream once they're open");
+
+        device.stop_streaming().unwrap();
+        assert!(matches!(
+            device.read_frame(),
+            Err(UsageError::NotStreaming { .. })
+        ));
+        device.start_streaming().unwrap();
+        device.read_frame().unwrap();
+
+        // pick a different size than the current one
+        let current = device.image_configuration().unwrap();
+        let other = device
+            .supported_image_configurations()
+            .unwrap()
+            .into_iter()
+            .find(|conf| conf.format == current.format && conf.resolution != current.resolution)
+            .expect("another resolution");
+
+        let switched = device.reconfigure(&other).unwrap();
+        assert!(switched.restarted, "the stream was restarted");
+        assert_eq!(switched.configuration.resolution, other.resolution);
+        assert_eq!(
+            device.read_frame().unwrap().resolution,
+            other.resolution,
+            "frames come in the new size"
+        );
+    }
+
+    /// Load vivid with `modprobe vivid` to run this.
+    #[test]
+    #[ignore = "needs the vivid driver loaded"]
+    fn vivid_opens_with_the_builder() {
+        let path = V4LBackend::list_connected_devices()
+            .into_iter()
+            .find(|path| {
+                V4LCapabilities::query(path).is_ok_and(|caps| {
+                    caps.driver == "vivid" && caps.device_capabilities.is_capture()
+                })
+            })
+            .expect("a vivid capture device");
+
+        let constraints = config::VideoCaptureImageConstraints {
+            resolution: config::ResolutionSetting::Lowest,
+            ..config::VideoCaptureImageConstraints::default()
+        };
+        let mut device = V4LVideoCaptureDevice::builder(path)
+            .constraints(constraints)
+            .io_method(V4LIoMethod::UserPtr)
+            .buffer_count(2)
+            .read_timeout(Duration::from_secs(2))
+            .warm_up(V4LWarmUp::Frames(3))
+            .open()
+            .unwrap();
+        assert_eq!(device.io_method(), V4LIoMethod::UserPtr);
+        assert_eq!(device.read_timeout(), Some(Duration::from_secs(2)));
+
+        let smallest = device
+            .supported_image_configurations()
+            .unwrap()
+            .into_iter()
+            .map(|conf| u64::from(conf.resolution.width) * u64::from(conf.resolution.height))
+            .min()
+            .unwrap();
+        let frame = device.read_frame().unwrap();
+        assert_eq!(
+            u64::from(frame.resolution.width) * u64::from(frame.resolution.height),
+            smallest,
+            "the first stream already used the smallest size"
+        );
     }
 }
@@ -0,0 +1,297 @@
+//! Multi-planar capture, for devices that only speak
+//! `V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE`.
+//!
+//! The `v4l` crate only streams single-planar buffers, so this talks to the
+//! kernel directly. Each buffer has one memory mapping per plane.
+//!
+//! See: https://docs.kernel.org/userspace-api/media/v4l/planar-apis.html
+
+use core::time::Duration;
+use std::io;
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+
+use v4l::device::Handle;
+
+use super::builder::V4LIoMethod;
+use super::device::V4LDevice;
+use super::format::PixFormat;
+use super::kernel::Kernel;
+use super::raw::{
+    zeroed, CaptureBuffer, UserBuffer, V4l2Buffer, V4l2Plane, V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE,
+    VIDEO_MAX_PLANES,
+};
+
+/// A dequeued buffer's planes, with how much of each the device filled.
+pub(super) struct DequeuedFrame<'buf> {
+    pub planes: Vec<(&'buf [u8], usize)>,
+    pub flags: u32,
+    pub sequence: u32,
+    pub timestamp: core::time::Duration,
+    pub bytes_used: usize,
+}
+
+/// What the driver told us about a dequeued buffer.
+struct Lent {
+    index: u32,
+    /// Where each plane's data starts and ends.
+    planes: Vec<(usize, usize)>,
+    flags: u32,
+    sequence: u32,
+    timestamp: core::time::Duration,
+}
+
+/// A multi-planar capture stream.
+pub(super) struct MplaneStream {
+    kernel: Arc<dyn Kernel>,
+    handle: Arc<Handle>,
+    buffers: Vec<Vec<CaptureBuffer>>,
+    format: PixFormat,
+    /// How the buffers are shared with the driver. (`V4L2_MEMORY_*`)
+    memory: u32,
+    /// The buffer we lent out with the last frame. It's queued again once
+    /// that frame can't be used anymore.
+    lent: Option<Lent>,
+    streaming: bool,
+}
+
+impl MplaneStream {
+    /// Sets up `count` buffers with a piece for each plane, queues them all,
+    /// and starts streaming.
+    ///
+    /// The driver might give us more or fewer buffers than we asked for.
+    pub(super) fn new(device: &V4LDevice, io_method: V4LIoMethod, count: u32) -> io::Result<Self> {
+        let kernel = Arc::clone(device.kernel());
+        let handle = device.handle();
+        let fd = handle.fd();
+        let format = device.format(V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE)?;
+        let memory = io_method.memory();
+
+        let granted =
+            kernel.request_buffers(fd, V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE, memory, count)?;
+
+        let mut stream = Self {
+            kernel,
+            handle,
+            buffers: Vec::with_capacity(granted as usize),
+            format,
+            memory,
+            lent: None,
+            streaming: false,
+        };
+
+        for index in 0..granted {
+            let pieces = match io_method {
+                V4LIoMethod::Mmap => stream.map_planes(index)?,
+                V4LIoMethod::UserPtr => stream
+                    .format
+                    .planes
+                    .iter()
+                    .map(|plane| UserBuffer::new(plane.size).map(CaptureBuffer::User))
+                    .collect::<io::Result<_>>()?,
+            };
+            stream.buffers.push(pieces);
+            stream.queue(index)?;
+        }
+
+        stream
+            .kernel
+            .stream_on(fd, V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE)?;
+        stream.streaming = true;
+
+        Ok(stream)
+    }
+
+    /// The format that this stream's frames are in.
+    pub(super) const fn format(&self) -> &PixFormat {
+        &self.format
+    }
+
+    /// Maps each plane of one of the driver's buffers.
+    fn map_planes(&self, index: u32) -> io::Result<Vec<CaptureBuffer>> {
+        let fd = self.handle.fd();
+        let mut planes = [zeroed::<V4l2Plane>(); VIDEO_MAX_PLANES];
+        let mut buffer = self.raw_buffer(index, &mut planes);
+        // `planes` has room for as many planes as we said
+        self.kernel.query_buffer(fd, &mut buffer)?;
+
+        planes
+            .iter()
+            .take(buffer.length as usize)
+            .map(|plane| {
+                // SAFETY: the kernel set `mem_offset`, since we asked for mmap.
+                let offset = unsafe { plane.m.mem_offset };
+                self.kernel
+                    .map_buffer(fd, plane.length, offset)
+                    .map(CaptureBuffer::Mapped)
+            })
+            .collect()
+    }
+
+    /// Makes a `v4l2_buffer` that points at the given plane array.
+    fn raw_buffer(&self, index: u32, planes: &mut [V4l2Plane; VIDEO_MAX_PLANES]) -> V4l2Buffer {
+        let mut buffer = zeroed::<V4l2Buffer>();
+        buffer.index = index;
+        buffer.r#type = V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE;
+        buffer.memory = self.memory;
+        buffer.m.planes = planes.as_mut_ptr();
+        buffer.length =
+            u32::try_from(self.format.planes.len().clamp(1, VIDEO_MAX_PLANES)).unwrap_or(1);
+        buffer
+    }
+
+    /// Gives a buffer back to the driver.
+    fn queue(&self, index: u32) -> io::Result<()> {
+        let mut planes = [zeroed::<V4l2Plane>(); VIDEO_MAX_PLANES];
+
+        // buffers that we allocated have to say where they are each time
+        let ours = self
+            .buffers
+            .get(index as usize)
+            .map_or(&[][..], Vec::as_slice);
+        for (plane, piece) in planes.iter_mut().zip(ours) {
+            if let Some(address) = piece.user_address() {
+                plane.m.userptr = address;
+                plane.length = u32::try_from(piece.len()).map_err(io::Error::other)?;
+            }
+        }
+
+        let mut buffer = self.raw_buffer(index, &mut planes);
+        // `planes` has room for as many planes as we said
+        self.kernel.queue_buffer(self.handle.fd(), &mut buffer)
+    }
+
+    /// Gives back the last frame's buffer, then waits for the next one.
+    ///
+    /// Returns `false` if no frame arrived in time. With no `timeout`, this
+    /// waits as long as it takes.
+    pub(super) fn advance(&mut self, timeout: Option<Duration>) -> io::Result<bool> {
+        if let Some(lent) = self.lent.take() {
+            self.queue(lent.index)?;
+        }
+
+        // the device is opened in non-blocking mode, so wait until it's ready
+        let fd = self.handle.fd();
+        if self.kernel.wait_readable(&[fd], timeout)?.is_empty() {
+            return Ok(false);
+        }
+
+        let mut planes = [zeroed::<V4l2Plane>(); VIDEO_MAX_PLANES];
+        let mut buffer = self.raw_buffer(0, &mut planes);
+        // `planes` has room for as many planes as we said
+        self.kernel.dequeue_buffer(fd, &mut buffer)?;
+
+        let mapped = self
+            .buffers
+            .get(buffer.index as usize)
+            .ok_or_else(|| io::Error::other("driver dequeued a buffer we don't have"))?;
+        let plane_ranges = mapped
+            .iter()
+            .zip(planes.iter())
+            .map(|(plane, info)| {
+                let used = (info.bytesused as usize).min(plane.len());
+                let start = (info.data_offset as usize).min(used);
+                (start, used)
+            })
+            .collect();
+
+        self.lent = Some(Lent {
+            index: buffer.index,
+            planes: plane_ranges,
+            flags: buffer.flags,
+            sequence: buffer.sequence,
+            timestamp: buffer.timestamp(),
+        });
+        Ok(true)
+    }
+
+    /// The last frame that [`MplaneStream::advance`] dequeued.
+    pub(super) fn current(&self) -> Option<DequeuedFrame<'_>> {
+        let lent = self.lent.as_ref()?;
+        let mapped = self.buffers.get(lent.index as usize)?;
+
+        let planes: Vec<(&[u8], usize)> = mapped
+            .iter()
+            .zip(&lent.planes)
+            .map(|(plane, &(start, used))| {
+                // SAFETY: this buffer is dequeued. the driver won't write to
+                // it until we queue it again, which needs `&mut self`.
+                let bytes = unsafe { plane.as_slice() };
+                (bytes.get(start..used).unwrap_or_default(), used - start)
+            })
+            .collect();
+
+        Some(DequeuedFrame {
+            bytes_used: planes.iter().map(|plane| plane.1).sum(),
+            planes,
+            flags: lent.flags,
+            sequence: lent.sequence,
+            timestamp: lent.timestamp,
+        })
+    }
+
+    /// Asks the device to stop streaming.
+    pub(super) fn stop(&mut self) -> io::Result<()> {
+        self.kernel
+            .stream_off(self.handle.fd(), V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE)?;
+        self.streaming = false;
+        self.lent = None;
+        Ok(())
+    }
+}
+
+impl Drop for MplaneStream {
+    fn drop(&mut self) {
+        if self.streaming {
+            if let Err(e) = self.stop() {
+                tracing::warn!("Failed to stop multi-planar stream: {e}");
+            }
+        }
+
+        // the buffers have to be unmapped before they can be freed
+        self.buffers.clear();
+
+        // release the buffers, so the device can change formats again
+        let freed = self.kernel.request_buffers(
+            self.handle.fd(),
+            V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE,
+            self.memory,
+            0,
+        );
+        if let Err(e) = freed {
+            tracing::debug!("Failed to free multi-planar buffers: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::backends::v4l::{V4LBackend, V4LCapabilities, V4LVideoCaptureDevice};
+    use crate::backends::Backend as _;
+    use crate::{VideoCaptureConnection as _, VideoCaptureStream as _};
+
+    /// Load vivid with `modprobe vivid multiplanar=2` to run this.
+    #[test]
+    #[ignore = "needs the vivid driver loaded with `multiplanar=2`"]
+    fn vivid_streams_planes() {
+        let path: PathBuf = V4LBackend::list_connected_devices()
+            .into_iter()
+            .find(|path| {
+                V4LCapabilities::query(path)
+                    .is_ok_and(|caps| caps.driver == "vivid" && caps.needs_multiplanar())
+            })
+            .expect("a multi-planar vivid device");
+
+        let mut device = V4LVideoCaptureDevice::new(path).unwrap();
+        let frame = device.read_frame().unwrap();
+
+        assert!(!frame.planes.is_empty(), "frames have planes");
+        for plane in frame.planes.iter() {
+            assert!(!plane.data.is_empty(), "planes have data");
+        }
+    }
+}
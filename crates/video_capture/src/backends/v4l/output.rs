@@ -0,0 +1,636 @@
+//! Video4Linux output devices, like `v4l2loopback` nodes.
+//!
+//! Frames written to an output device show up for any other application
+//! that captures from it, just like a webcam.
+
+use core::fmt::Debug;
+use core::time::Duration;
+use std::io;
+use std::path::{Path, PathBuf};
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+
+use nix::errno::Errno;
+use v4l::device::Handle;
+
+use crate::config::{
+    Format, VideoCaptureConfiguration, VideoCaptureImageConfiguration as ImageConfiguration,
+};
+use crate::error::{
+    VideoCaptureConfigError as ConfigError, VideoCaptureConnectionError as ConnectionError,
+    VideoCaptureUsageError as UsageError,
+};
+use crate::frame::Frame;
+
+use super::capabilities::V4LCapabilities;
+use super::device::V4LDevice;
+use super::format::PixFormat;
+use super::kernel::{Kernel, LinuxKernel};
+use super::raw::{zeroed, MappedBuffer, V4l2Buffer, V4L2_BUF_TYPE_VIDEO_OUTPUT, V4L2_MEMORY_MMAP};
+
+/// Progressive frames, without any interlacing. (`V4L2_FIELD_NONE`)
+const V4L2_FIELD_NONE: u32 = 1;
+
+/// How many buffers we ask the driver for.
+const BUFFER_COUNT: u32 = 4;
+
+/// A Video4Linux output device, which other applications see as a webcam.
+///
+/// This mirrors the capture API: open it by path, pick an image
+/// configuration with [`VideoCaptureConfiguration`], then write
+/// [`Frame`]s to it.
+///
+/// Buffers are allocated on the first write. Changing the image
+/// configuration stops the device and frees them, so the next write starts
+/// it again.
+pub struct V4LOutput {
+    path: PathBuf,
+    device: V4LDevice,
+    capabilities: V4LCapabilities,
+    stream: Option<OutputStream>,
+    /// How long a write waits for a free buffer. `None` waits forever.
+    write_timeout: Option<Duration>,
+}
+
+impl V4LOutput {
+    /// Opens the output device at the given path.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the path doesn't exist or isn't a video output device.
+    #[inline]
+    pub fn new(path: &Path) -> Result<Self, ConnectionError> {
+        Self::open_with(Arc::new(LinuxKernel), path)
+    }
+
+    /// Opens the output device at the given path, through `kernel`.
+    pub(super) fn open_with(kernel: Arc<dyn Kernel>, path: &Path) -> Result<Self, ConnectionError> {
+        let source = path.display().to_string();
+        tracing::debug!("opening Video4Linux output device at `{source}`...");
+
+        let capabilities = kernel.capabilities(path).map_err(|e| {
+            super::errno::connection_error(&source, e, |err| {
+                ConnectionError::CouldntGetDeviceInfo {
+                    source: source.clone(),
+                    err_msg: format!("Failed to query capabilities. IO error: {err}"),
+                }
+            })
+        })?;
+        if !capabilities.device_capabilities.is_output() {
+            return Err(ConnectionError::NotAnOutputDevice { source });
+        }
+
+        let device = V4LDevice::open(kernel, path).map_err(|e| {
+            super::errno::connection_error(&source, e, |err| {
+                super::errno::odd_io_error(&source, &err)
+            })
+        })?;
+
+        Ok(Self {
+            path: path.to_owned(),
+            device,
+            capabilities,
+            stream: None,
+            write_timeout: None,
+        })
+    }
+
+    /// The path that this device was opened with.
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// What the output device says it can do.
+    #[inline]
+    pub const fn capabilities(&self) -> &V4LCapabilities {
+        &self.capabilities
+    }
+
+    /// How long a write waits for the device to give back a buffer. `None`
+    /// waits forever.
+    #[inline]
+    pub const fn write_timeout(&self) -> Option<Duration> {
+        self.write_timeout
+    }
+
+    /// Changes how long a write waits for the device to give back a buffer.
+    #[inline]
+    pub const fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+        self.write_timeout = timeout;
+    }
+
+    /// Sends a frame to the device.
+    ///
+    /// The frame has to match the device's format and resolution. Packed
+    /// frames are copied row by row, so their stride doesn't need to match.
+    /// Other frames are copied as-is, one plane after the other.
+    ///
+    /// This waits for the device to give back a buffer if they're all in
+    /// use, for as long as [`V4LOutput::write_timeout`] allows.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the frame doesn't match the device's configuration, is
+    /// too large for its buffers, or the device stops responding. Running out
+    /// of time fails with [`UsageError::Timeout`].
+    #[inline]
+    pub fn write_frame(&mut self, frame: &Frame<'_>) -> Result<(), UsageError> {
+        let io_err = |e: io::Error| super::errno::usage_error(self.path.display().to_string(), &e);
+
+        let stream = match self.stream {
+            Some(ref mut stream) => stream,
+            None => self
+                .stream
+                .insert(OutputStream::new(&self.device).map_err(io_err)?),
+        };
+
+        let (expected_format, expected_resolution) = (
+            Format::new(stream.format.fourcc),
+            stream.format.resolution(),
+        );
+        if frame.format != expected_format || frame.resolution != expected_resolution {
+            return Err(UsageError::FrameDoesntMatchOutput {
+                source: self.path.display().to_string(),
+                expected_format,
+                expected_resolution,
+                format: frame.format,
+                resolution: frame.resolution,
+            });
+        }
+
+        let size = output_len(frame, stream.format.first_plane().stride as usize);
+        let capacity = stream.capacity();
+        if size > capacity {
+            return Err(UsageError::FrameTooLarge {
+                source: self.path.display().to_string(),
+                size,
+                capacity,
+            });
+        }
+
+        stream.write(frame, self.write_timeout).map_err(io_err)
+    }
+
+    /// Stops streaming and frees the device's buffers, so its image
+    /// configuration can change again.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the device doesn't stop when asked.
+    #[inline]
+    pub fn stop(&mut self) -> Result<(), ConnectionError> {
+        if let Some(mut stream) = self.stream.take() {
+            stream.stop().map_err(|e| {
+                super::errno::connection_error(&self.source_as_string(), e, |err| {
+                    ConnectionError::StopError {
+                        source: self.source_as_string(),
+                        err_msg: err.to_string(),
+                    }
+                })
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn source_as_string(&self) -> String {
+        self.path.display().to_string()
+    }
+}
+
+impl VideoCaptureConfiguration for V4LOutput {
+    #[inline]
+    fn supported_image_configurations(&self) -> Result<Vec<ImageConfiguration>, ConfigError> {
+        super::list_image_configurations(
+            &**self.device.kernel(),
+            self.device.fd(),
+            &self.source_as_string(),
+            V4L2_BUF_TYPE_VIDEO_OUTPUT,
+        )
+    }
+
+    #[inline]
+    fn image_configuration(&self) -> Result<ImageConfiguration, ConfigError> {
+        let format = self
+            .device
+            .format(V4L2_BUF_TYPE_VIDEO_OUTPUT)
+            .map_err(|e| {
+                super::errno::config_error(&self.source_as_string(), e, |err| {
+                    ConfigError::CouldntGetFormat {
+                        source: self.source_as_string(),
+                        err_msg: err.to_string(),
+                    }
+                })
+            })?;
+
+        let framerate = super::read_framerate(
+            &**self.device.kernel(),
+            self.device.fd(),
+            &self.source_as_string(),
+            V4L2_BUF_TYPE_VIDEO_OUTPUT,
+        )?;
+
+        Ok(ImageConfiguration {
+            format: Format::new(format.fourcc),
+            resolution: format.resolution(),
+            framerate,
+        })
+    }
+
+    #[inline]
+    fn set_image_configuration(
+        &mut self,
+        conf: &ImageConfiguration,
+    ) -> Result<ImageConfiguration, ConfigError> {
+        let source = self.source_as_string();
+
+        // buffers are allocated again on the next write
+        self.stop().map_err(|e| ConfigError::CouldntStopStream {
+            source: source.clone(),
+            err_msg: e.to_string(),
+        })?;
+
+        self.device
+            .set_format(
+                V4L2_BUF_TYPE_VIDEO_OUTPUT,
+                conf.format.array(),
+                conf.resolution,
+            )
+            .map_err(|e| {
+                super::errno::config_error(&source, e, |err| ConfigError::PropertyWriteFailure {
+                    source: source.clone(),
+                    err_msg: format!("Failed to change image configuration. IO Error {err}"),
+                })
+            })?;
+
+        // devices take a frame interval, which is 1 / framerate
+        let interval =
+            conf.framerate
+                .denom()
+                .zip(conf.framerate.numer())
+                .and_then(|(&numer, &denom)| {
+                    Some((u32::try_from(numer).ok()?, u32::try_from(denom).ok()?))
+                });
+        if let Some((numer, denom)) = interval {
+            self.device
+                .set_frame_interval(V4L2_BUF_TYPE_VIDEO_OUTPUT, numer, denom)
+                .map_err(|e| {
+                    super::errno::config_error(&source, e, |err| {
+                        ConfigError::PropertyWriteFailure {
+                            source: source.clone(),
+                            err_msg: format!(
+                                "ioctl call for `VIDIOC_S_PARM` failed. IO error: {err}"
+                            ),
+                        }
+                    })
+                })?;
+        } else {
+            tracing::warn!(
+                "Output device at `{source}` can't use framerate `{}`. Leaving it alone.",
+                conf.framerate
+            );
+        }
+
+        let actual = self.image_configuration()?;
+        if conf != &actual {
+            tracing::warn!(
+                "Output device at `{source}` has format mismatch.\n
+                    - Expected: `{conf}`\n
+                    - Got: `{actual}`",
+            );
+        }
+
+        Ok(actual)
+    }
+}
+
+impl Debug for V4LOutput {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("V4LOutput")
+            .field("path", &self.path)
+            .field("capabilities", &self.capabilities)
+            .field("streaming", &self.stream.is_some())
+            .field("write_timeout", &self.write_timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An output stream using memory-mapped buffers.
+struct OutputStream {
+    kernel: Arc<dyn Kernel>,
+    handle: Arc<Handle>,
+    buffers: Vec<MappedBuffer>,
+    format: PixFormat,
+    /// Buffers that we haven't given to the driver yet.
+    unused: Vec<u32>,
+    streaming: bool,
+}
+
+impl OutputStream {
+    /// Allocates and maps buffers for the device's current format.
+    fn new(device: &V4LDevice) -> io::Result<Self> {
+        let kernel = Arc::clone(device.kernel());
+        let handle = device.handle();
+        let fd = handle.fd();
+        let format = device.format(V4L2_BUF_TYPE_VIDEO_OUTPUT)?;
+
+        let granted = kernel.request_buffers(
+            fd,
+            V4L2_BUF_TYPE_VIDEO_OUTPUT,
+            V4L2_MEMORY_MMAP,
+            BUFFER_COUNT,
+        )?;
+
+        let mut stream = Self {
+            kernel,
+            handle,
+            buffers: Vec::with_capacity(granted as usize),
+            format,
+            // popping from the back hands them out in order
+            unused: (0..granted).rev().collect(),
+            streaming: false,
+        };
+
+        for index in 0..granted {
+            let mut buffer = raw_buffer(index);
+            stream.kernel.query_buffer(fd, &mut buffer)?;
+
+            // SAFETY: the kernel set `offset`, since we asked for mmap.
+            let offset = unsafe { buffer.m.offset };
+            stream
+                .buffers
+                .push(stream.kernel.map_buffer(fd, buffer.length, offset)?);
+        }
+
+        Ok(stream)
+    }
+
+    /// The size of the smallest buffer, in bytes.
+    fn capacity(&self) -> usize {
+        self.buffers
+            .iter()
+            .map(MappedBuffer::len)
+            .min()
+            .unwrap_or_default()
+    }
+
+    /// Copies a frame into a free buffer, then queues it.
+    ///
+    /// If every buffer is queued, this waits at most `timeout` for the
+    /// device to give one back.
+    fn write(&mut self, frame: &Frame<'_>, timeout: Option<Duration>) -> io::Result<()> {
+        let index = if let Some(index) = self.unused.pop() {
+            index
+        } else {
+            // the device is opened in non-blocking mode, so wait until it's
+            // done with one of the buffers
+            let fd = self.handle.fd();
+            if self.kernel.wait_writable(&[fd], timeout)?.is_empty() {
+                return Err(Errno::ETIMEDOUT.into());
+            }
+
+            let mut buffer = raw_buffer(0);
+            self.kernel.dequeue_buffer(fd, &mut buffer)?;
+            buffer.index
+        };
+
+        // the buffer stays ours until the driver takes it
+        let queued = self.fill_and_queue(index, frame);
+        if queued.is_err() {
+            self.unused.push(index);
+        }
+        queued?;
+
+        if !self.streaming {
+            self.kernel
+                .stream_on(self.handle.fd(), V4L2_BUF_TYPE_VIDEO_OUTPUT)?;
+            self.streaming = true;
+        }
+
+        Ok(())
+    }
+
+    /// Copies a frame into one of our buffers, then gives it to the driver.
+    fn fill_and_queue(&mut self, index: u32, frame: &Frame<'_>) -> io::Result<()> {
+        let stride = self.format.first_plane().stride as usize;
+        let mapped = self
+            .buffers
+            .get_mut(index as usize)
+            .ok_or_else(|| io::Error::other("driver dequeued a buffer we don't have"))?;
+
+        // SAFETY: the buffer was either never queued or was just dequeued, so
+        // the driver isn't reading it.
+        let written = copy_frame(frame, stride, unsafe { mapped.as_mut_slice() });
+
+        let mut buffer = raw_buffer(index);
+        buffer.bytesused = u32::try_from(written).map_err(io::Error::other)?;
+        buffer.field = V4L2_FIELD_NONE;
+        self.kernel.queue_buffer(self.handle.fd(), &mut buffer)
+    }
+
+    /// Asks the device to stop streaming.
+    fn stop(&mut self) -> io::Result<()> {
+        self.kernel
+            .stream_off(self.handle.fd(), V4L2_BUF_TYPE_VIDEO_OUTPUT)?;
+        self.streaming = false;
+
+        // stopping gives every buffer back to us
+        self.unused = (0..u32::try_from(self.buffers.len()).unwrap_or_default())
+            .rev()
+            .collect();
+        Ok(())
+    }
+}
+
+impl Drop for OutputStream {
+    fn drop(&mut self) {
+        if self.streaming {
+            if let Err(e) = self.stop() {
+                tracing::warn!("Failed to stop output stream: {e}");
+            }
+        }
+
+        // the buffers have to be unmapped before they can be freed
+        self.buffers.clear();
+
+        // release the buffers, so the device can change formats again
+        let freed = self.kernel.request_buffers(
+            self.handle.fd(),
+            V4L2_BUF_TYPE_VIDEO_OUTPUT,
+            V4L2_MEMORY_MMAP,
+            0,
+        );
+        if let Err(e) = freed {
+            tracing::debug!("Failed to free output buffers: {e}");
+        }
+    }
+}
+
+/// Makes a `v4l2_buffer` for one of the output buffers.
+const fn raw_buffer(index: u32) -> V4l2Buffer {
+    let mut buffer = zeroed::<V4l2Buffer>();
+    buffer.index = index;
+    buffer.r#type = V4L2_BUF_TYPE_VIDEO_OUTPUT;
+    buffer.memory = V4L2_MEMORY_MMAP;
+    buffer
+}
+
+/// The length of a packed frame's rows, and the stride they're read with.
+///
+/// Returns `None` for frames that aren't packed, or when the output doesn't
+/// say what its stride is.
+fn packed_rows(frame: &Frame<'_>, out_stride: usize) -> Option<(usize, usize)> {
+    let bytes_per_pixel = frame.format.bytes_per_pixel()?;
+    if frame.planes.len() > 1 || out_stride == 0 {
+        return None;
+    }
+
+    let row_len = frame.resolution.width as usize * bytes_per_pixel;
+    let in_stride = match frame.stride {
+        0 => row_len,
+        stride => stride,
+    };
+    Some((row_len, in_stride))
+}
+
+/// How many bytes a frame takes up once it's copied into an output buffer.
+fn output_len(frame: &Frame<'_>, out_stride: usize) -> usize {
+    if packed_rows(frame, out_stride).is_some() {
+        out_stride * frame.resolution.height as usize
+    } else {
+        frame.planes.iter().map(|plane| plane.data.len()).sum()
+    }
+}
+
+/// Copies a frame into an output buffer, returning how many bytes were
+/// written.
+///
+/// Packed frames are repacked with the output's stride. Everything else is
+/// copied as-is, one plane after another.
+fn copy_frame(frame: &Frame<'_>, out_stride: usize, out: &mut [u8]) -> usize {
+    if let Some((row_len, in_stride)) = packed_rows(frame, out_stride) {
+        let rows = frame
+            .data
+            .chunks(in_stride)
+            .zip(out.chunks_mut(out_stride))
+            .take(frame.resolution.height as usize);
+        for (from, to) in rows {
+            let len = row_len.min(from.len()).min(to.len());
+            if let Some((src, dst)) = from.get(..len).zip(to.get_mut(..len)) {
+                dst.copy_from_slice(src);
+            }
+        }
+
+        return output_len(frame, out_stride).min(out.len());
+    }
+
+    let mut written = 0;
+    for plane in frame.planes.iter() {
+        let Some(dst) = out.get_mut(written..written + plane.data.len()) else {
+            break;
+        };
+        dst.copy_from_slice(plane.data);
+        written += plane.data.len();
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::capabilities::V4LCapabilityFlags;
+    use super::super::fake::{Call, FakeKernel};
+    use super::*;
+    use crate::config::SpecificResolution;
+    use crate::frame::{FrameMetadata, FramePlanes};
+
+    const SINK: &str = "/dev/video9";
+
+    /// A `v4l2loopback` node that takes 4x2 YUYV frames.
+    fn loopback() -> FakeKernel {
+        FakeKernel::default()
+            .with_video(
+                SINK,
+                (81, 9),
+                V4LCapabilityFlags(
+                    V4LCapabilityFlags::VIDEO_OUTPUT.0 | V4LCapabilityFlags::STREAMING.0,
+                ),
+            )
+            .with_current_format(V4L2_BUF_TYPE_VIDEO_OUTPUT, *b"YUYV", (4, 2))
+    }
+
+    /// Writes a 4x2 YUYV frame.
+    fn write(output: &mut V4LOutput) -> Result<(), UsageError> {
+        let data = [0; 16];
+        output.write_frame(&Frame {
+            data: &data,
+            format: Format::YUYV,
+            resolution: SpecificResolution::new(4, 2),
+            stride: 8,
+            planes: FramePlanes::single(&data, 8),
+            metadata: FrameMetadata::default(),
+        })
+    }
+
+    #[test]
+    fn writes_wait_for_the_write_timeout() {
+        let kernel = loopback();
+        let mut output = V4LOutput::open_with(Arc::new(kernel.clone()), Path::new(SINK)).unwrap();
+        output.set_write_timeout(Some(Duration::from_millis(10)));
+
+        for _ in 0..BUFFER_COUNT {
+            write(&mut output).unwrap();
+        }
+        assert!(kernel.is_streaming(V4L2_BUF_TYPE_VIDEO_OUTPUT), "started");
+
+        // the fake never plays the buffers back
+        let err = write(&mut output).unwrap_err();
+        assert!(
+            matches!(err, UsageError::Timeout { .. }),
+            "timed out: {err}"
+        );
+    }
+
+    #[test]
+    fn failed_writes_keep_their_buffer() {
+        let kernel = loopback().failing(Call::QueueBuffer, Errno::ENODEV);
+        let mut output = V4LOutput::open_with(Arc::new(kernel), Path::new(SINK)).unwrap();
+
+        for _ in 0..=BUFFER_COUNT {
+            let err = write(&mut output).unwrap_err();
+            assert!(matches!(err, UsageError::Disconnected { .. }), "{err}");
+        }
+        let unused = output.stream.as_ref().map(|stream| stream.unused.len());
+        assert_eq!(unused, Some(BUFFER_COUNT as usize), "nothing was lost");
+    }
+
+    #[test]
+    fn packed_frames_are_repacked_with_the_outputs_stride() {
+        // 2x2 GREY, with one byte of padding per row
+        let data = [1, 2, 0, 3, 4, 0];
+        let frame = Frame {
+            data: &data,
+            format: Format::GREY,
+            resolution: SpecificResolution::new(2, 2),
+            stride: 3,
+            planes: FramePlanes::single(&data, 3),
+            metadata: FrameMetadata::default(),
+        };
+
+        let mut out = [9; 8];
+        assert_eq!(copy_frame(&frame, 4, &mut out), 8, "two output rows");
+        assert_eq!(out, [1, 2, 9, 9, 3, 4, 9, 9], "rows land on the stride");
+
+        let jpeg = Frame {
+            format: Format::MJPEG,
+            stride: 0,
+            ..frame
+        };
+        let mut out = [0; 8];
+        assert_eq!(copy_frame(&jpeg, 4, &mut out), 6, "compressed is as-is");
+        assert_eq!(out.get(..6), Some(data.as_slice()), "same bytes");
+    }
+}
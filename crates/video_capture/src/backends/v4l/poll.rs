@@ -0,0 +1,186 @@
+//! Waiting for frames, without blocking forever.
+//!
+//! Devices are opened in non-blocking mode, and a frame is ready once its
+//! file descriptor is readable. A device that isn't streaming (or was
+//! unplugged) also wakes us up. Dequeuing from it then says what went wrong.
+
+use core::time::Duration;
+use std::io;
+use std::os::fd::{BorrowedFd, RawFd};
+use std::time::Instant;
+
+use nix::errno::Errno;
+use nix::poll::{PollFd, PollFlags, PollTimeout};
+
+use crate::error::VideoCaptureUsageError as UsageError;
+
+use super::V4LVideoCaptureDevice;
+
+/// Waits until at least one of the given devices has a frame ready.
+///
+/// Returns the index of every ready device, in order. With no `timeout`,
+/// this waits as long as it takes. With no devices, nothing can become
+/// ready, so this returns an empty list right away.
+///
+/// # Errors
+///
+/// This fails with [`UsageError::Timeout`] if no device is ready in time.
+#[inline]
+pub fn wait_for_frames(
+    devices: &[&V4LVideoCaptureDevice<'_, '_>],
+    timeout: Option<Duration>,
+) -> Result<Vec<usize>, UsageError> {
+    if devices.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let fds: Vec<RawFd> = devices.iter().map(|device| device.device.fd()).collect();
+    let sources = || {
+        devices
+            .iter()
+            .map(|device| device.source.user_source_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let ready =
+        wait_readable(&fds, timeout).map_err(|e| super::errno::usage_error(sources(), &e))?;
+    if ready.is_empty() {
+        return Err(UsageError::Timeout {
+            source: sources(),
+            errno: Errno::ETIMEDOUT as i32,
+        });
+    }
+
+    Ok(ready)
+}
+
+/// Polls file descriptors until one is readable, then lists the ready
+/// ones. The list is empty if the time ran out.
+///
+/// Errors count as readable, so they're reported by whatever reads next.
+pub(super) fn wait_readable(fds: &[RawFd], timeout: Option<Duration>) -> io::Result<Vec<usize>> {
+    wait_for(fds, PollFlags::POLLIN, timeout)
+}
+
+/// Polls file descriptors until one is writable, then lists the ready
+/// ones. The list is empty if the time ran out.
+///
+/// Errors count as writable, so they're reported by whatever writes next.
+pub(super) fn wait_writable(fds: &[RawFd], timeout: Option<Duration>) -> io::Result<Vec<usize>> {
+    wait_for(fds, PollFlags::POLLOUT, timeout)
+}
+
+/// Polls file descriptors until one of them has an event, then lists the
+/// ready ones.
+fn wait_for(fds: &[RawFd], events: PollFlags, timeout: Option<Duration>) -> io::Result<Vec<usize>> {
+    // timeouts too long to add are as good as waiting forever
+    let deadline = timeout.and_then(|limit| Instant::now().checked_add(limit));
+
+    let mut poll_fds: Vec<PollFd<'_>> = fds
+        .iter()
+        .map(|&fd| {
+            // SAFETY: the caller's devices own these descriptors, and they
+            // outlive this function.
+            let borrowed = unsafe { BorrowedFd::borrow_raw(fd) };
+            PollFd::new(borrowed, events)
+        })
+        .collect();
+
+    loop {
+        let remaining = deadline.map(|end| end.saturating_duration_since(Instant::now()));
+
+        match nix::poll::poll(&mut poll_fds, poll_timeout(remaining)) {
+            Ok(0) => return Ok(Vec::new()),
+            Ok(_) => break,
+            // a signal woke us up early. keep waiting for what's left
+            Err(Errno::EINTR) => {}
+            Err(errno) => return Err(errno.into()),
+        }
+    }
+
+    Ok(poll_fds
+        .iter()
+        .enumerate()
+        .filter(|&(_, poll_fd)| poll_fd.revents().is_some_and(|revents| !revents.is_empty()))
+        .map(|(index, _)| index)
+        .collect())
+}
+
+/// Converts a timeout to whole milliseconds, rounding up so that tiny
+/// timeouts still wait a little.
+fn poll_timeout(timeout: Option<Duration>) -> PollTimeout {
+    timeout.map_or(PollTimeout::NONE, |limit| {
+        let millis = limit.as_nanos().div_ceil(1_000_000);
+        i32::try_from(millis)
+            .ok()
+            .and_then(|ms| PollTimeout::try_from(ms).ok())
+            .unwrap_or(PollTimeout::MAX)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write as _;
+    use std::os::fd::AsRawFd as _;
+
+    use super::*;
+
+    #[test]
+    fn waits_for_readable_descriptors() {
+        let (idle, _idle_writer) = io::pipe().unwrap();
+        let (busy, mut busy_writer) = io::pipe().unwrap();
+        let fds = [idle.as_raw_fd(), busy.as_raw_fd()];
+
+        assert_eq!(
+            wait_readable(&fds, Some(Duration::from_millis(10))).unwrap(),
+            Vec::<usize>::new(),
+            "nothing is ready yet"
+        );
+
+        busy_writer.write_all(b"frame").unwrap();
+        assert_eq!(
+            wait_readable(&fds, Some(Duration::ZERO)).unwrap(),
+            vec![1],
+            "only the pipe with data is ready"
+        );
+        assert_eq!(wait_readable(&fds, None).unwrap(), vec![1], "no timeout");
+    }
+
+    #[test]
+    fn no_devices_are_never_ready() {
+        assert_eq!(
+            wait_for_frames(&[], None).unwrap(),
+            Vec::<usize>::new(),
+            "returns instead of waiting forever"
+        );
+    }
+
+    #[test]
+    fn waits_for_writable_descriptors() {
+        let (reader, writer) = io::pipe().unwrap();
+        let fds = [reader.as_raw_fd(), writer.as_raw_fd()];
+
+        assert_eq!(
+            wait_writable(&fds, Some(Duration::ZERO)).unwrap(),
+            vec![1],
+            "only the write end is writable"
+        );
+    }
+
+    #[test]
+    fn timeouts_round_up() {
+        assert_eq!(poll_timeout(None), PollTimeout::NONE);
+        assert_eq!(poll_timeout(Some(Duration::ZERO)), PollTimeout::ZERO);
+        assert_eq!(
+            poll_timeout(Some(Duration::from_micros(1))).as_millis(),
+            Some(1),
+            "tiny timeouts still wait"
+        );
+        assert_eq!(
+            poll_timeout(Some(Duration::MAX)),
+            PollTimeout::MAX,
+            "huge timeouts are clamped"
+        );
+    }
+}
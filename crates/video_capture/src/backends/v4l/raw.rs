@@ -0,0 +1,473 @@
+//! Raw Video4Linux structs and `ioctl`s that the `v4l` crate doesn't give us.
+//!
+//! These mirror `videodev2.h`, so they're only used to talk to the kernel.
+
+use core::alloc::Layout;
+use core::ffi::{c_ulong, c_void};
+use core::num::NonZeroUsize;
+use core::ptr::NonNull;
+use core::time::Duration;
+use std::io;
+use std::os::fd::BorrowedFd;
+
+extern crate alloc;
+
+use nix::libc;
+use nix::sys::mman::{mmap, munmap, MapFlags, ProtFlags};
+use nix::{ioctl_readwrite, ioctl_write_ptr};
+
+pub(super) const V4L2_BUF_TYPE_VIDEO_CAPTURE: u32 = 1;
+pub(super) const V4L2_BUF_TYPE_VIDEO_OUTPUT: u32 = 2;
+pub(super) const V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE: u32 = 9;
+pub(super) const V4L2_MEMORY_MMAP: u32 = 1;
+pub(super) const V4L2_MEMORY_USERPTR: u32 = 2;
+pub(super) const V4L2_BUF_TYPE_META_CAPTURE: u32 = 13;
+pub(super) const V4L2_FIELD_ANY: u32 = 0;
+/// Frame sizes that are a single value, instead of a range.
+pub(super) const V4L2_FRMSIZE_TYPE_DISCRETE: u32 = 1;
+/// Frame intervals that are a single value, instead of a range.
+pub(super) const V4L2_FRMIVAL_TYPE_DISCRETE: u32 = 1;
+
+/// Set on buffers that the driver couldn't fill properly.
+pub(super) const V4L2_BUF_FLAG_ERROR: u32 = 0x0000_0040;
+
+/// The most planes the kernel allows. (`VIDEO_MAX_PLANES`)
+pub(super) const VIDEO_MAX_PLANES: usize = 8;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(super) struct V4l2PixFormat {
+    pub width: u32,
+    pub height: u32,
+    pub pixelformat: u32,
+    pub field: u32,
+    pub bytesperline: u32,
+    pub sizeimage: u32,
+    pub colorspace: u32,
+    pub r#priv: u32,
+    pub flags: u32,
+    pub ycbcr_enc: u32,
+    pub quantization: u32,
+    pub xfer_func: u32,
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub(super) struct V4l2PlanePixFormat {
+    pub sizeimage: u32,
+    pub bytesperline: u32,
+    pub reserved: [u16; 6],
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub(super) struct V4l2PixFormatMplane {
+    pub width: u32,
+    pub height: u32,
+    pub pixelformat: u32,
+    pub field: u32,
+    pub colorspace: u32,
+    pub plane_fmt: [V4l2PlanePixFormat; VIDEO_MAX_PLANES],
+    pub num_planes: u8,
+    pub flags: u8,
+    pub ycbcr_enc: u8,
+    pub quantization: u8,
+    pub xfer_func: u8,
+    pub reserved: [u8; 7],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(super) union V4l2FormatUnion {
+    pub pix: V4l2PixFormat,
+    pub pix_mp: V4l2PixFormatMplane,
+    pub meta: V4l2MetaFormat,
+    pub raw_data: [u8; 200],
+    // the kernel's union holds pointers, so it's pointer-aligned
+    pub align: [*mut c_void; 0],
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub(super) struct V4l2MetaFormat {
+    pub dataformat: u32,
+    pub buffersize: u32,
+}
+
+#[repr(C)]
+pub(super) struct V4l2Format {
+    pub r#type: u32,
+    pub fmt: V4l2FormatUnion,
+}
+
+#[repr(C)]
+pub(super) struct V4l2FmtDesc {
+    pub index: u32,
+    pub r#type: u32,
+    pub flags: u32,
+    pub description: [u8; 32],
+    pub pixelformat: u32,
+    pub mbus_code: u32,
+    pub reserved: [u32; 3],
+}
+
+/// `v4l2_frmsizeenum`. The kernel's union is flattened into `size`: discrete
+/// sizes use the first two values, and stepwise sizes use all six.
+#[repr(C)]
+pub(super) struct V4l2FrmSizeEnum {
+    pub index: u32,
+    pub pixel_format: u32,
+    pub r#type: u32,
+    pub size: [u32; 6],
+    pub reserved: [u32; 2],
+}
+
+/// `v4l2_frmivalenum`. Like [`V4l2FrmSizeEnum`], the union is flattened.
+/// Discrete intervals are the first two values of `interval`.
+#[repr(C)]
+pub(super) struct V4l2FrmIvalEnum {
+    pub index: u32,
+    pub pixel_format: u32,
+    pub width: u32,
+    pub height: u32,
+    pub r#type: u32,
+    pub interval: [u32; 6],
+    pub reserved: [u32; 2],
+}
+
+#[repr(C)]
+pub(super) struct V4l2RequestBuffers {
+    pub count: u32,
+    pub r#type: u32,
+    pub memory: u32,
+    pub capabilities: u32,
+    pub flags: u8,
+    pub reserved: [u8; 3],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(super) union V4l2PlaneMemory {
+    pub mem_offset: u32,
+    pub userptr: c_ulong,
+    pub fd: i32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(super) struct V4l2Plane {
+    pub bytesused: u32,
+    pub length: u32,
+    pub m: V4l2PlaneMemory,
+    pub data_offset: u32,
+    pub reserved: [u32; 11],
+}
+
+#[repr(C)]
+pub(super) struct V4l2Timecode {
+    pub r#type: u32,
+    pub flags: u32,
+    pub frames: u8,
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    pub userbits: [u8; 4],
+}
+
+#[repr(C)]
+pub(super) union V4l2BufferMemory {
+    pub offset: u32,
+    pub userptr: c_ulong,
+    pub planes: *mut V4l2Plane,
+    pub fd: i32,
+}
+
+#[repr(C)]
+pub(super) struct V4l2Buffer {
+    pub index: u32,
+    pub r#type: u32,
+    pub bytesused: u32,
+    pub flags: u32,
+    pub field: u32,
+    pub timestamp: libc::timeval,
+    pub timecode: V4l2Timecode,
+    pub sequence: u32,
+    pub memory: u32,
+    pub m: V4l2BufferMemory,
+    pub length: u32,
+    pub reserved2: u32,
+    pub request_fd: i32,
+}
+
+impl V4l2Buffer {
+    /// When the driver captured this buffer.
+    pub(super) fn timestamp(&self) -> Duration {
+        Duration::new(
+            u64::try_from(self.timestamp.tv_sec).unwrap_or_default(),
+            u32::try_from(self.timestamp.tv_usec)
+                .unwrap_or_default()
+                .saturating_mul(1000),
+        )
+    }
+}
+
+const IOCTL_VIDEO_COMMAND: u8 = b'V';
+
+ioctl_readwrite!(vidioc_enum_fmt, IOCTL_VIDEO_COMMAND, 2, V4l2FmtDesc);
+ioctl_readwrite!(vidioc_g_fmt, IOCTL_VIDEO_COMMAND, 4, V4l2Format);
+ioctl_readwrite!(vidioc_s_fmt, IOCTL_VIDEO_COMMAND, 5, V4l2Format);
+ioctl_readwrite!(vidioc_reqbufs, IOCTL_VIDEO_COMMAND, 8, V4l2RequestBuffers);
+ioctl_readwrite!(vidioc_querybuf, IOCTL_VIDEO_COMMAND, 9, V4l2Buffer);
+ioctl_readwrite!(vidioc_qbuf, IOCTL_VIDEO_COMMAND, 15, V4l2Buffer);
+ioctl_readwrite!(vidioc_dqbuf, IOCTL_VIDEO_COMMAND, 17, V4l2Buffer);
+ioctl_write_ptr!(vidioc_streamon, IOCTL_VIDEO_COMMAND, 18, i32);
+ioctl_write_ptr!(vidioc_streamoff, IOCTL_VIDEO_COMMAND, 19, i32);
+ioctl_readwrite!(
+    vidioc_enum_framesizes,
+    IOCTL_VIDEO_COMMAND,
+    74,
+    V4l2FrmSizeEnum
+);
+ioctl_readwrite!(
+    vidioc_enum_frameintervals,
+    IOCTL_VIDEO_COMMAND,
+    75,
+    V4l2FrmIvalEnum
+);
+
+/// Makes a zeroed kernel struct.
+pub(super) const fn zeroed<T>() -> T {
+    // SAFETY: this is only used for the plain-old-data kernel structs in this
+    // module,
+    // where all zeroes is a valid value.
+    unsafe { core::mem::zeroed::<T>() }
+}
+
+/// A driver's buffer, mapped into our memory. It's unmapped when dropped.
+///
+/// The driver owns the memory while the buffer is queued, so it should only
+/// be read or written between dequeueing and queueing it again.
+pub(super) struct MappedBuffer {
+    ptr: NonNull<c_void>,
+    len: usize,
+}
+
+// SAFETY: the mapping belongs to this buffer alone, so it can move to another
+// thread along with its owner.
+unsafe impl Send for MappedBuffer {}
+
+impl MappedBuffer {
+    /// Maps the buffer that the kernel described with `length` and `offset`.
+    pub(super) fn map(fd: i32, length: u32, offset: u32) -> io::Result<Self> {
+        let len = NonZeroUsize::new(length as usize)
+            .ok_or_else(|| io::Error::other("driver gave a buffer with no length"))?;
+
+        // SAFETY: the device's fd outlives this borrow, and the kernel gave us
+        // the offset and length to map.
+        let ptr = unsafe {
+            mmap(
+                None,
+                len,
+                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                MapFlags::MAP_SHARED,
+                BorrowedFd::borrow_raw(fd),
+                libc::off_t::from(offset),
+            )?
+        };
+
+        Ok(Self {
+            ptr,
+            len: len.get(),
+        })
+    }
+
+    /// Maps `length` bytes of memory that no device owns, for fake drivers
+    /// to hand out. It starts with `contents`, then zeroes.
+    #[cfg(test)]
+    pub(super) fn anonymous(length: u32, contents: &[u8]) -> io::Result<Self> {
+        let len = NonZeroUsize::new(length as usize)
+            .ok_or_else(|| io::Error::other("driver gave a buffer with no length"))?;
+
+        // SAFETY: the mapping is new, so nothing else can be using it.
+        let ptr = unsafe {
+            nix::sys::mman::mmap_anonymous(
+                None,
+                len,
+                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                MapFlags::MAP_PRIVATE,
+            )?
+        };
+
+        let mut mapped = Self {
+            ptr,
+            len: len.get(),
+        };
+        // SAFETY: no driver has this memory.
+        let bytes = unsafe { mapped.as_mut_slice() };
+        for (to, from) in bytes.iter_mut().zip(contents) {
+            *to = *from;
+        }
+        Ok(mapped)
+    }
+
+    /// The buffer's size, in bytes.
+    pub(super) const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The buffer's bytes.
+    ///
+    /// # Safety
+    ///
+    /// The buffer can't be queued, or the driver might write to it while
+    /// it's borrowed.
+    pub(super) const unsafe fn as_slice(&self) -> &[u8] {
+        // SAFETY: the mapping is `len` bytes long and lives as long as `self`.
+        // the caller promises that the driver isn't using it.
+        unsafe { core::slice::from_raw_parts(self.ptr.as_ptr().cast::<u8>(), self.len) }
+    }
+
+    /// The buffer's bytes, for filling in.
+    ///
+    /// # Safety
+    ///
+    /// The buffer can't be queued, or the driver might read it while it's
+    /// being written.
+    pub(super) const unsafe fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: the mapping is `len` bytes long and lives as long as `self`.
+        // the caller promises that the driver isn't using it.
+        unsafe { core::slice::from_raw_parts_mut(self.ptr.as_ptr().cast::<u8>(), self.len) }
+    }
+}
+
+impl Drop for MappedBuffer {
+    fn drop(&mut self) {
+        // SAFETY: the buffer was mapped with exactly this pointer and length,
+        // and nothing can borrow it anymore.
+        if let Err(e) = unsafe { munmap(self.ptr, self.len) } {
+            tracing::warn!("Failed to unmap a Video4Linux buffer: {e}");
+        }
+    }
+}
+
+/// Memory that we allocate ourselves, then lend to the driver by address.
+/// It's freed when dropped.
+///
+/// Like [`MappedBuffer`], the driver owns it while it's queued.
+pub(super) struct UserBuffer {
+    ptr: NonNull<u8>,
+    layout: Layout,
+}
+
+// SAFETY: the allocation belongs to this buffer alone, so it can move to
+// another thread along with its owner.
+unsafe impl Send for UserBuffer {}
+
+impl UserBuffer {
+    /// Allocates `length` zeroed bytes, aligned to a page like drivers
+    /// expect.
+    pub(super) fn new(length: u32) -> io::Result<Self> {
+        // SAFETY: `sysconf` only reads a system setting.
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+        let align = usize::try_from(page).unwrap_or(4096);
+
+        let layout = Layout::from_size_align(length as usize, align).map_err(io::Error::other)?;
+        if layout.size() == 0 {
+            return Err(io::Error::other("driver asked for a buffer with no length"));
+        }
+
+        // SAFETY: the layout isn't zero-sized.
+        let ptr = NonNull::new(unsafe { alloc::alloc::alloc_zeroed(layout) })
+            .ok_or_else(|| io::Error::from(io::ErrorKind::OutOfMemory))?;
+        Ok(Self { ptr, layout })
+    }
+
+    /// The buffer's size, in bytes.
+    pub(super) const fn len(&self) -> usize {
+        self.layout.size()
+    }
+
+    /// The address that the driver should write to.
+    pub(super) fn address(&self) -> c_ulong {
+        self.ptr.as_ptr() as c_ulong
+    }
+
+    /// The buffer's bytes.
+    ///
+    /// # Safety
+    ///
+    /// The buffer can't be queued, or the driver might write to it while
+    /// it's borrowed.
+    pub(super) const unsafe fn as_slice(&self) -> &[u8] {
+        // SAFETY: the allocation is `len` bytes long and lives as long as
+        // `self`. the caller promises that the driver isn't using it.
+        unsafe { core::slice::from_raw_parts(self.ptr.as_ptr(), self.len()) }
+    }
+}
+
+impl Drop for UserBuffer {
+    fn drop(&mut self) {
+        // SAFETY: the buffer was allocated with exactly this layout, and
+        // nothing can borrow it anymore.
+        unsafe { alloc::alloc::dealloc(self.ptr.as_ptr(), self.layout) };
+    }
+}
+
+/// A capture buffer, either mapped from the driver or lent to it.
+pub(super) enum CaptureBuffer {
+    Mapped(MappedBuffer),
+    User(UserBuffer),
+}
+
+impl CaptureBuffer {
+    /// The buffer's size, in bytes.
+    pub(super) const fn len(&self) -> usize {
+        match *self {
+            Self::Mapped(ref mapped) => mapped.len(),
+            Self::User(ref user) => user.len(),
+        }
+    }
+
+    /// The buffer's address, for lending it to the driver. Mapped buffers
+    /// don't have one.
+    pub(super) fn user_address(&self) -> Option<c_ulong> {
+        match *self {
+            Self::Mapped(_) => None,
+            Self::User(ref user) => Some(user.address()),
+        }
+    }
+
+    /// The buffer's bytes.
+    ///
+    /// # Safety
+    ///
+    /// The buffer can't be queued, or the driver might write to it while
+    /// it's borrowed.
+    pub(super) const unsafe fn as_slice(&self) -> &[u8] {
+        match *self {
+            // SAFETY: the caller promises that the driver isn't using it.
+            Self::Mapped(ref mapped) => unsafe { mapped.as_slice() },
+            // SAFETY: same here.
+            Self::User(ref user) => unsafe { user.as_slice() },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn structs_match_the_kernel() {
+        assert_eq!(size_of::<V4l2PixFormat>(), 48, "pix_format");
+        assert_eq!(size_of::<V4l2PixFormatMplane>(), 192, "pix_format_mplane");
+        assert_eq!(size_of::<V4l2Format>(), 208, "format");
+        assert_eq!(size_of::<V4l2Plane>(), 64, "plane");
+        assert_eq!(size_of::<V4l2Buffer>(), 88, "buffer");
+        assert_eq!(size_of::<V4l2FmtDesc>(), 64, "fmtdesc");
+        assert_eq!(size_of::<V4l2RequestBuffers>(), 20, "requestbuffers");
+        assert_eq!(size_of::<V4l2FrmSizeEnum>(), 44, "frmsizeenum");
+        assert_eq!(size_of::<V4l2FrmIvalEnum>(), 52, "frmivalenum");
+    }
+}
@@ -0,0 +1,199 @@
+//! Single-planar capture, for most devices.
+//!
+//! This talks to the kernel directly instead of going through the `v4l`
+//! crate, so that the last frame stays around after it's dequeued. That
+//! lets us check a frame before deciding whether to hand it out.
+
+use core::time::Duration;
+use std::io;
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+
+use v4l::device::Handle;
+
+use super::builder::V4LIoMethod;
+use super::device::V4LDevice;
+use super::format::PixFormat;
+use super::kernel::Kernel;
+use super::raw::{zeroed, CaptureBuffer, UserBuffer, V4l2Buffer, V4L2_BUF_TYPE_VIDEO_CAPTURE};
+
+/// What the driver told us about a dequeued buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct Dequeued {
+    pub index: u32,
+    pub bytes_used: usize,
+    pub flags: u32,
+    pub sequence: u32,
+    pub timestamp: Duration,
+}
+
+/// A single-planar capture stream.
+pub(super) struct SingleStream {
+    kernel: Arc<dyn Kernel>,
+    handle: Arc<Handle>,
+    buffers: Vec<CaptureBuffer>,
+    format: PixFormat,
+    /// How the buffers are shared with the driver. (`V4L2_MEMORY_*`)
+    memory: u32,
+    /// The buffer we lent out with the last frame. It's queued again once
+    /// that frame can't be used anymore.
+    lent: Option<Dequeued>,
+    streaming: bool,
+}
+
+impl SingleStream {
+    /// Sets up `count` buffers for the device's current format, queues them
+    /// all, and starts streaming.
+    ///
+    /// The driver might give us more or fewer buffers than we asked for.
+    pub(super) fn new(device: &V4LDevice, io_method: V4LIoMethod, count: u32) -> io::Result<Self> {
+        let kernel = Arc::clone(device.kernel());
+        let handle = device.handle();
+        let fd = handle.fd();
+        let format = device.format(V4L2_BUF_TYPE_VIDEO_CAPTURE)?;
+        let memory = io_method.memory();
+
+        let granted = kernel.request_buffers(fd, V4L2_BUF_TYPE_VIDEO_CAPTURE, memory, count)?;
+
+        let mut stream = Self {
+            kernel,
+            handle,
+            buffers: Vec::with_capacity(granted as usize),
+            format,
+            memory,
+            lent: None,
+            streaming: false,
+        };
+
+        for index in 0..granted {
+            let buffer = match io_method {
+                V4LIoMethod::Mmap => {
+                    let mut buffer = raw_buffer(index, memory);
+                    stream.kernel.query_buffer(fd, &mut buffer)?;
+
+                    // SAFETY: the kernel set `offset`, since we asked for
+                    // mmap.
+                    let offset = unsafe { buffer.m.offset };
+                    CaptureBuffer::Mapped(stream.kernel.map_buffer(fd, buffer.length, offset)?)
+                }
+                V4LIoMethod::UserPtr => {
+                    CaptureBuffer::User(UserBuffer::new(stream.format.first_plane().size)?)
+                }
+            };
+            stream.buffers.push(buffer);
+            stream.queue(index)?;
+        }
+
+        stream.kernel.stream_on(fd, V4L2_BUF_TYPE_VIDEO_CAPTURE)?;
+        stream.streaming = true;
+
+        Ok(stream)
+    }
+
+    /// The format that this stream's frames are in.
+    pub(super) const fn format(&self) -> &PixFormat {
+        &self.format
+    }
+
+    /// Gives a buffer back to the driver.
+    fn queue(&self, index: u32) -> io::Result<()> {
+        let mut buffer = raw_buffer(index, self.memory);
+
+        // buffers that we allocated have to say where they are each time
+        if let Some(ours) = self.buffers.get(index as usize) {
+            if let Some(address) = ours.user_address() {
+                buffer.m.userptr = address;
+                buffer.length = u32::try_from(ours.len()).map_err(io::Error::other)?;
+            }
+        }
+
+        self.kernel.queue_buffer(self.handle.fd(), &mut buffer)
+    }
+
+    /// Gives back the last frame's buffer, then waits for the next one.
+    ///
+    /// Returns `false` if no frame arrived in time. With no `timeout`, this
+    /// waits as long as it takes.
+    pub(super) fn advance(&mut self, timeout: Option<Duration>) -> io::Result<bool> {
+        if let Some(lent) = self.lent.take() {
+            self.queue(lent.index)?;
+        }
+
+        // the device is opened in non-blocking mode, so wait until it's ready
+        let fd = self.handle.fd();
+        if self.kernel.wait_readable(&[fd], timeout)?.is_empty() {
+            return Ok(false);
+        }
+
+        let mut buffer = raw_buffer(0, self.memory);
+        self.kernel.dequeue_buffer(fd, &mut buffer)?;
+        if buffer.index as usize >= self.buffers.len() {
+            return Err(io::Error::other("driver dequeued a buffer we don't have"));
+        }
+
+        self.lent = Some(Dequeued {
+            index: buffer.index,
+            bytes_used: buffer.bytesused as usize,
+            flags: buffer.flags,
+            sequence: buffer.sequence,
+            timestamp: buffer.timestamp(),
+        });
+        Ok(true)
+    }
+
+    /// The last frame that [`SingleStream::advance`] dequeued, trimmed to
+    /// what the device filled.
+    pub(super) fn current(&self) -> Option<(&[u8], Dequeued)> {
+        let lent = self.lent?;
+        let buffer = self.buffers.get(lent.index as usize)?;
+
+        // SAFETY: this buffer is dequeued. the driver won't write to it until
+        // we queue it again, which needs `&mut self`.
+        let bytes = unsafe { buffer.as_slice() };
+        Some((bytes.get(..lent.bytes_used).unwrap_or(bytes), lent))
+    }
+
+    /// Asks the device to stop streaming.
+    pub(super) fn stop(&mut self) -> io::Result<()> {
+        self.kernel
+            .stream_off(self.handle.fd(), V4L2_BUF_TYPE_VIDEO_CAPTURE)?;
+        self.streaming = false;
+        self.lent = None;
+        Ok(())
+    }
+}
+
+impl Drop for SingleStream {
+    fn drop(&mut self) {
+        if self.streaming {
+            if let Err(e) = self.stop() {
+                tracing::warn!("Failed to stop capture stream: {e}");
+            }
+        }
+
+        // the buffers have to be unmapped before they can be freed
+        self.buffers.clear();
+
+        // release the buffers, so the device can change formats again
+        let freed = self.kernel.request_buffers(
+            self.handle.fd(),
+            V4L2_BUF_TYPE_VIDEO_CAPTURE,
+            self.memory,
+            0,
+        );
+        if let Err(e) = freed {
+            tracing::debug!("Failed to free capture buffers: {e}");
+        }
+    }
+}
+
+/// Makes a `v4l2_buffer` for one of the capture buffers.
+const fn raw_buffer(index: u32, memory: u32) -> V4l2Buffer {
+    let mut buffer = zeroed::<V4l2Buffer>();
+    buffer.index = index;
+    buffer.r#type = V4L2_BUF_TYPE_VIDEO_CAPTURE;
+    buffer.memory = memory;
+    buffer
+}
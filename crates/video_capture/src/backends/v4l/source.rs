@@ -1,6 +1,5 @@
-use std::fs;
 use std::io::ErrorKind;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
 extern crate alloc;
 
@@ -8,6 +7,9 @@ use anyhow::{anyhow, bail};
 
 use crate::error::VideoCaptureConnectionError as ConnectionError;
 
+use super::kernel::{Kernel, LinuxKernel};
+use super::topology::{media_devices, MediaNode, MediaNodeKind, MediaTopology};
+
 /// Determining both of these upon instantiation allows users to
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub struct V4LSource {
@@ -20,10 +22,26 @@ pub struct V4LSource {
 }
 
 impl V4LSource {
-    pub(crate) fn new(user_input: &Path) -> Result<Self, ConnectionError> {
+    /// Finds the `/dev/mediaX` and `/dev/videoY` files for the given path.
+    ///
+    /// The path can point to either of them (or a symlink to one).
+    ///
+    /// # Errors
+    ///
+    /// This fails if the path doesn't exist or doesn't belong to a
+    /// Video4Linux capture device.
+    #[inline]
+    pub fn new(user_input: &Path) -> Result<Self, ConnectionError> {
+        Self::resolve(&LinuxKernel, user_input)
+    }
+
+    /// Finds the media and video nodes for the given path, asking the given
+    /// kernel.
+    pub(super) fn resolve(kernel: &dyn Kernel, user_input: &Path) -> Result<Self, ConnectionError> {
         // grab the path if it exists...
-        let path = match fs::read_link(user_input) {
-            Ok(p) => p,
+        let path = match kernel.read_link(user_input) {
+            // links like `/dev/v4l/by-id/...` are relative to where they live
+            Ok(p) => resolve_link(user_input, &p),
             Err(e) => {
                 // this isn't an error. we just have a file, not a symlink!
                 // let's see if it even exists and handle the various error cases
@@ -31,14 +49,14 @@ impl V4LSource {
                     ErrorKind::InvalidInput => {} // this is good. the file exists and isn't a symlink
                     ErrorKind::NotFound => {
                         return Err(ConnectionError::CouldntGetDeviceInfo {
-                            source: user_input.to_string_lossy().into(), 
-                            err_msg: "The device representation in `/dev/` was not found.".into() 
+                            source: user_input.to_string_lossy().into(),
+                            err_msg: "The device representation in `/dev/` was not found.".into()
                         })
                     },
                     ek => {
                         return Err(ConnectionError::CouldntGetDeviceInfo {
-                            source: user_input.to_string_lossy().into(), 
-                            err_msg: format!("The given device couldn't be accessed as a symlink and wasn't a normal file. See: {ek}") 
+                            source: user_input.to_string_lossy().into(),
+                            err_msg: format!("The given device couldn't be accessed as a symlink and wasn't a normal file. See: {ek}")
                         })
                     },
                 };
@@ -62,16 +80,18 @@ impl V4LSource {
             return Ok(Self {
                 given: user_input.to_owned(),
                 media: path.clone(),
-                video: find_video_y(&path).map_err(|e| ConnectionError::CouldntGetDeviceInfo {
-                    source: user_input.to_string_lossy().into(),
-                    err_msg: e.to_string(),
+                video: find_video_y(kernel, &path).map_err(|e| {
+                    ConnectionError::CouldntGetDeviceInfo {
+                        source: user_input.to_string_lossy().into(),
+                        err_msg: e.to_string(),
+                    }
                 })?,
             });
         } else if file_name.starts_with("video") {
             // we have `/dev/videoY`. let's quickly grab its `/dev/mediaX`
             return Ok(Self {
                 given: user_input.to_owned(),
-                media: find_media_x(&path, user_input).map_err(|e| {
+                media: find_media_x(kernel, &path, user_input).map_err(|e| {
                     ConnectionError::CouldntGetDeviceInfo {
                         source: user_input.to_string_lossy().into(),
                         err_msg: e.to_string(),
@@ -86,78 +106,365 @@ impl V4LSource {
         })
     }
 
+    /// Lists every node that belongs to this device: video, metadata,
+    /// subdevices, and so on.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the media device's topology can't be read.
+    #[inline]
+    pub fn nodes(&self) -> Result<Vec<MediaNode>, ConnectionError> {
+        self.nodes_with(&LinuxKernel)
+    }
+
+    fn nodes_with(&self, kernel: &dyn Kernel) -> Result<Vec<MediaNode>, ConnectionError> {
+        self.topology_with(kernel)
+            .map(|topology| topology.device_nodes(kernel))
+    }
+
+    /// Reads the media device's whole topology.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the media device can't be read.
+    #[inline]
+    pub fn topology(&self) -> Result<MediaTopology, ConnectionError> {
+        self.topology_with(&LinuxKernel)
+    }
+
+    fn topology_with(&self, kernel: &dyn Kernel) -> Result<MediaTopology, ConnectionError> {
+        kernel.media_topology(&self.media).map_err(|e| {
+            super::errno::connection_error(&self.user_source_string(), e, |err| {
+                ConnectionError::CouldntGetDeviceInfo {
+                    source: self.user_source_string(),
+                    err_msg: format!("Failed to read the media topology. IO error: {err}"),
+                }
+            })
+        })
+    }
+
+    /// Finds the metadata node that goes with this device's video node, if
+    /// it has one.
+    ///
+    /// The two are paired by where they sit in the media device's topology,
+    /// so cameras with several video nodes (or ones without metadata) still
+    /// get the right node.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the media device's topology can't be read.
+    #[inline]
+    pub fn metadata_node(&self) -> Result<Option<PathBuf>, ConnectionError> {
+        self.metadata_node_with(&LinuxKernel)
+    }
+
+    pub(super) fn metadata_node_with(
+        &self,
+        kernel: &dyn Kernel,
+    ) -> Result<Option<PathBuf>, ConnectionError> {
+        let topology = self.topology_with(kernel)?;
+        let Ok(video) = kernel.device_number(&self.video) else {
+            return Ok(None);
+        };
+
+        Ok(topology.metadata_node_for(kernel, video))
+    }
+
     /// Makes a string from the user's given path input.
     pub(crate) fn user_source_string(&self) -> String {
         self.given.display().to_string()
     }
 }
 
-/// Given a `/dev/videoY` file, let's find a `/dev/mediaX` file that matches!
+/// Given a `/dev/videoY` file, let's find the `/dev/mediaX` file that owns it.
 ///
-/// I'm using `anyhow` for this and converting the display message. Just makes things easier.
-fn find_media_x(video_y_path: &Path, user_input: &Path) -> anyhow::Result<PathBuf> {
-    let video_y_filename = video_y_path
-        .file_name()
-        .ok_or_else(|| anyhow!("failed to get filename for `videoY` file"))?;
-
-    let devices_dir = PathBuf::from("/sys/class/video4linux/")
-        .join(video_y_filename)
-        .join("device");
-
-    // let video_y_path_canon = video_y_path.canonicalize().map_err(|e| anyhow!("failed to get real path of `videoY` file. err: {e}"))?;
-
-    Ok(std::fs::read_dir(&devices_dir).map_err(|e| anyhow!("Couldn't find a Video4Linux capture device (unparsed source: `{}`) directory at `{}`. IO Error: `{e}`", user_input.display(), devices_dir.display()))?
-        .flatten()
-        .find(|entry| entry.path().to_string_lossy().to_string().contains("media"))
-        .ok_or_else(|| anyhow!("didn't find a matching `mediaX` for the `videoY file."))?
-        .path()
-    ).map(|v| fs::read_link(&v).map_or(v, |link| link))
+/// This asks every media device for its topology, then picks the one with an
+/// interface for the node's device number.
+fn find_media_x(
+    kernel: &dyn Kernel,
+    video_y_path: &Path,
+    user_input: &Path,
+) -> anyhow::Result<PathBuf> {
+    let (major, minor) = kernel.device_number(video_y_path).map_err(|e| {
+        anyhow!(
+            "Couldn't read the device number of `{}` (unparsed source: `{}`). IO error: {e}",
+            video_y_path.display(),
+            user_input.display()
+        )
+    })?;
+
+    media_devices(kernel)
+        .into_iter()
+        .find(|media| {
+            kernel
+                .media_topology(media)
+                .is_ok_and(|topology| topology.has_device_number(major, minor))
+        })
+        .ok_or_else(|| {
+            anyhow!(
+                "No media device has an interface for `{}`.",
+                video_y_path.display()
+            )
+        })
 }
 
-/// Given a `/dev/mediaX` file, let's find a `/dev/videoY` file that matches.
+/// Given a `/dev/mediaX` file, let's find the `/dev/videoY` file that captures
+/// its frames.
 ///
-/// I'm using `anyhow` for this and converting the display message. Just makes things easier.
-fn find_video_y(media_x_path: &Path) -> anyhow::Result<PathBuf> {
-    // get the filename
-    let media_x_filename = media_x_path
-        .file_name()
-        .ok_or_else(|| anyhow!("failed to get filename for `videoY` file"))?;
-
-    let media_x_path_canon = media_x_path
-        .canonicalize()
-        .map_err(|e| anyhow!("failed to get real path of `videoY` file. err: {e}"))?;
-
-    // make a list of dirs in `/sys/class/video4linux`.
-    // then, check each dir for a matching mediaX, and return the `videoY` path that worked!
-    fs::read_dir("/sys/class/video4linux/")
-        .map_err(|e| anyhow!("The `/sys/class/video4linux/` directory should exist and be readable. Got IO error: {e}"))?
-        .flatten()
-        // get the path of each `/sys/class/v.../videoY` entry
-        .map(|entry| entry.path())
-        // filter the paths to make sure they're all directories
-        .filter(|path| path.is_dir()) 
-        // add `device/mediaX` to them
-        .map(|dir| dir.join("device").join(media_x_filename))
-        // make sure they have filenames
-        .filter_map(|d| d.file_name().map(|filename| (d.clone(), filename.to_owned()))) 
-        .map(|(dir, filename)| {tracing::trace!("to find video here: {dir:?}. the fr one is `{media_x_path_canon:?}`"); (dir, filename)})
-        // see if we found any 
-        .find(|(_, filename)| filename.as_os_str() == media_x_filename)  
-        .ok_or_else(|| anyhow!("No Video4Linux devices appear to own the given `mediaX` file at `{}`.", media_x_path.display()))
-        .map(|(path, _)| { 
-            // let's remove comps off the path until we find the `videoY` part
-            for component in path.components() {
-                let strd = component.as_os_str().to_string_lossy();
-                if strd.contains("video") && strd != "video4linux" {
-                    let real_path = PathBuf::from("/dev/").join(strd.to_string());
-                    return if real_path.exists() {
-                        Ok(real_path)
-                    } else {
-                        bail!("The videoY we found (`{}`) for the given path does not exist.", real_path.display())
-                    };
-                }
+/// Metadata and output nodes are skipped, so this is always a node you can
+/// stream from.
+fn find_video_y(kernel: &dyn Kernel, media_x_path: &Path) -> anyhow::Result<PathBuf> {
+    let topology = kernel.media_topology(media_x_path).map_err(|e| {
+        anyhow!(
+            "Failed to read the topology of `{}`. IO error: {e}",
+            media_x_path.display()
+        )
+    })?;
+
+    let Some(node) = topology
+        .device_nodes(kernel)
+        .into_iter()
+        .find(|node| node.kind == MediaNodeKind::VideoCapture)
+    else {
+        bail!(
+            "The media device at `{}` has no video capture nodes.",
+            media_x_path.display()
+        )
+    };
+
+    Ok(node.path)
+}
+
+/// Makes a symlink's target into a path that doesn't depend on the current
+/// directory.
+///
+/// `..` and `.` are removed without touching the filesystem, since device
+/// links only ever point into `/dev`.
+fn resolve_link(link: &Path, target: &Path) -> PathBuf {
+    let joined = link
+        .parent()
+        .map_or_else(|| target.to_owned(), |parent| parent.join(target));
+
+    let mut resolved = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::ParentDir => {
+                resolved.pop();
             }
-            bail!("No path contained `videoY`... which is weird. All of them should.")        
-        })?
+            Component::CurDir => {}
+            other => resolved.push(other),
+        }
+    }
+    resolved
+}
 
+#[cfg(test)]
+mod tests {
+    use nix::errno::Errno;
+
+    use super::super::capabilities::V4LCapabilityFlags;
+    use super::super::device_info::MediaDeviceInfo;
+    use super::super::fake::{uvc_camera, Call, FakeKernel};
+    use super::super::topology::{
+        MediaEntity, MediaInterface, MediaInterfaceKind, MediaLink, MediaPad,
+    };
+    use super::*;
+
+    const BY_ID: &str = "/dev/v4l/by-id/usb-046d_C922_Pro_Stream_Webcam_ABCD1234-video-index0";
+
+    #[test]
+    fn resolves_every_kind_of_path() {
+        let kernel = uvc_camera();
+        let expected = |given: &str| V4LSource {
+            given: PathBuf::from(given),
+            media: PathBuf::from("/dev/media0"),
+            video: PathBuf::from("/dev/video0"),
+        };
+
+        for given in ["/dev/media0", "/dev/video0", BY_ID] {
+            assert_eq!(
+                V4LSource::resolve(&kernel, Path::new(given)).unwrap(),
+                expected(given),
+                "from `{given}`"
+            );
+        }
+    }
+
+    #[test]
+    fn resolve_errors() {
+        let kernel = uvc_camera();
+        let err = |given: &str, kernel: &dyn Kernel| {
+            V4LSource::resolve(kernel, Path::new(given)).unwrap_err()
+        };
+
+        assert!(
+            matches!(
+                err("/dev/video9", &kernel),
+                ConnectionError::CouldntGetDeviceInfo { ref source, ref err_msg }
+                    if source == "/dev/video9" && err_msg.contains("not found")
+            ),
+            "missing nodes"
+        );
+
+        // a video node that no media device claims
+        let orphan =
+            kernel
+                .clone()
+                .with_video("/dev/video4", (81, 4), V4LCapabilityFlags::VIDEO_CAPTURE);
+        assert!(
+            matches!(
+                err("/dev/video4", &orphan),
+                ConnectionError::CouldntGetDeviceInfo { ref err_msg, .. }
+                    if err_msg.contains("No media device")
+            ),
+            "orphaned video nodes"
+        );
+
+        // the media device is there, but won't say what's in it
+        let broken = kernel.failing(Call::MediaTopology, Errno::EIO);
+        assert!(
+            matches!(
+                err("/dev/media0", &broken),
+                ConnectionError::CouldntGetDeviceInfo { ref err_msg, .. }
+                    if err_msg.contains("topology")
+            ),
+            "unreadable topology"
+        );
+    }
+
+    #[test]
+    fn pairs_metadata_nodes() {
+        let kernel = uvc_camera();
+        let source = V4LSource::resolve(&kernel, Path::new(BY_ID)).unwrap();
+
+        assert_eq!(
+            source.metadata_node_with(&kernel).unwrap(),
+            Some(PathBuf::from("/dev/video1"))
+        );
+    }
+
+    /// A device with two capture nodes, `/dev/video0` and `/dev/video2`, but
+    /// only one metadata node, `/dev/video3`.
+    ///
+    /// Each node's entity ID is ten times its minor number, plus one. Its
+    /// interface's ID is one more than that.
+    fn two_streams(links: &[MediaLink], pads: &[MediaPad]) -> FakeKernel {
+        let entity = |minor: u32| MediaEntity {
+            id: minor * 10 + 1,
+            name: format!("node {minor}"),
+            function: 0x0001_0001,
+            flags: 0,
+        };
+        let interface = |minor: u32| MediaInterface {
+            id: minor * 10 + 2,
+            kind: MediaInterfaceKind::Video,
+            flags: 0,
+            major: 81,
+            minor,
+        };
+        let interface_link = |minor: u32| MediaLink {
+            id: minor * 10 + 3,
+            source_id: minor * 10 + 2,
+            sink_id: minor * 10 + 1,
+            flags: 0x1000_0003,
+        };
+
+        let mut all_links: Vec<_> = [0, 2, 3].into_iter().map(interface_link).collect();
+        all_links.extend_from_slice(links);
+        let topology = MediaTopology {
+            version: 1,
+            entities: [0, 2, 3].into_iter().map(entity).collect(),
+            interfaces: [0, 2, 3].into_iter().map(interface).collect(),
+            pads: pads.to_vec(),
+            links: all_links,
+        };
+
+        let capture = V4LCapabilityFlags::VIDEO_CAPTURE;
+        FakeKernel::default()
+            .with_media(
+                "/dev/media0",
+                (237, 0),
+                MediaDeviceInfo::default(),
+                topology,
+            )
+            .with_video("/dev/video0", (81, 0), capture)
+            .with_video("/dev/video2", (81, 2), capture)
+            .with_video("/dev/video3", (81, 3), V4LCapabilityFlags::META_CAPTURE)
+    }
+
+    /// Looks up the metadata node for a capture node.
+    fn metadata_for(kernel: &FakeKernel, video: &str) -> Option<PathBuf> {
+        V4LSource::resolve(kernel, Path::new(video))
+            .unwrap()
+            .metadata_node_with(kernel)
+            .unwrap()
+    }
+
+    #[test]
+    fn pairs_unlinked_metadata_with_the_entity_before_it() {
+        // like `uvcvideo`: the metadata entity isn't linked to anything
+        let kernel = two_streams(&[], &[]);
+
+        assert_eq!(metadata_for(&kernel, "/dev/video0"), None, "not ours");
+        assert_eq!(
+            metadata_for(&kernel, "/dev/video2"),
+            Some(PathBuf::from("/dev/video3")),
+            "registered right after"
+        );
+    }
+
+    #[test]
+    fn pairs_linked_metadata_by_its_source() {
+        // a sensor (entity 100) sends frames to `/dev/video0`, and embedded
+        // data to `/dev/video3`
+        let pad = |id: u32, entity_id: u32, flags: u32| MediaPad {
+            id,
+            entity_id,
+            flags,
+            index: 0,
+        };
+        let data_link = |id: u32, source_id: u32, sink_id: u32| MediaLink {
+            id,
+            source_id,
+            sink_id,
+            flags: 0x3,
+        };
+        let kernel = two_streams(
+            &[data_link(200, 101, 5), data_link(201, 102, 35)],
+            &[
+                pad(101, 100, 2),
+                pad(102, 100, 2),
+                pad(5, 1, 1),
+                pad(35, 31, 1),
+            ],
+        );
+
+        assert_eq!(
+            metadata_for(&kernel, "/dev/video0"),
+            Some(PathBuf::from("/dev/video3")),
+            "fed by the same sensor"
+        );
+        assert_eq!(
+            metadata_for(&kernel, "/dev/video2"),
+            None,
+            "linked metadata isn't paired by order"
+        );
+    }
+
+    #[test]
+    fn relative_links_are_resolved() {
+        assert_eq!(
+            resolve_link(Path::new("/dev/v4l/by-id/cam"), Path::new("../../video0")),
+            PathBuf::from("/dev/video0")
+        );
+        assert_eq!(
+            resolve_link(Path::new("/dev/cam"), Path::new("./video2")),
+            PathBuf::from("/dev/video2")
+        );
+        assert_eq!(
+            resolve_link(Path::new("/dev/cam"), Path::new("/dev/video3")),
+            PathBuf::from("/dev/video3"),
+            "absolute targets"
+        );
+    }
 }
@@ -0,0 +1,288 @@
+//! A Video4Linux stream that remembers what its frames look like.
+
+use core::fmt::Debug;
+use core::time::Duration;
+use std::io;
+
+use nix::errno::Errno;
+
+use crate::config::Format;
+use crate::frame::{
+    Frame, FrameCorruption, FrameMetadata, FramePlane, FramePlanes, HardwareTimestamps,
+};
+
+use super::builder::{V4LIoMethod, V4LWarmUp};
+use super::device::V4LDevice;
+use super::format::PixFormat;
+use super::mplane::{DequeuedFrame, MplaneStream};
+use super::raw::V4L2_BUF_FLAG_ERROR;
+use super::single::{Dequeued, SingleStream};
+use super::uvc_meta::MetadataStream;
+
+/// A capture stream for a Video4Linux device.
+///
+/// The device can't change its format while buffers are allocated, so we
+/// grab the format once, right when the stream is made, and hand it out with
+/// each frame.
+///
+/// UVC cameras can also stream a metadata node alongside. Its timestamps are
+/// matched to each frame by sequence number.
+pub struct V4LStream {
+    /// The device's buffers. These are only allocated while streaming.
+    inner: Option<StreamKind>,
+    settings: StreamSettings,
+    metadata: Option<MetadataStream>,
+    /// The metadata node's timestamps for the current frame.
+    hardware: Option<HardwareTimestamps>,
+}
+
+/// How a stream's buffers are set up, and how long reads wait.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct StreamSettings {
+    pub io_method: V4LIoMethod,
+    /// How many buffers to ask the driver for.
+    pub buffer_count: u32,
+    /// How long a plain read waits for a frame. `None` waits forever.
+    pub read_timeout: Option<Duration>,
+    /// What to do with the first frames after connecting.
+    pub warm_up: V4LWarmUp,
+}
+
+impl Default for StreamSettings {
+    fn default() -> Self {
+        Self {
+            io_method: V4LIoMethod::default(),
+            buffer_count: 4,
+            read_timeout: None,
+            warm_up: V4LWarmUp::default(),
+        }
+    }
+}
+
+/// Single-planar and multi-planar devices need different buffers.
+enum StreamKind {
+    Single(SingleStream),
+    Multi(MplaneStream),
+}
+
+impl V4LStream {
+    /// Makes a stream that isn't streaming yet. Nothing is allocated until
+    /// [`V4LStream::start`].
+    pub(super) const fn new(settings: StreamSettings) -> Self {
+        Self {
+            inner: None,
+            settings,
+            metadata: None,
+            hardware: None,
+        }
+    }
+
+    /// Allocates buffers and starts streaming, if we aren't already.
+    ///
+    /// The buffers are sized for the device's current format.
+    pub(crate) fn start(&mut self, device: &V4LDevice, multiplanar: bool) -> io::Result<()> {
+        if self.inner.is_some() {
+            return Ok(());
+        }
+
+        let StreamSettings {
+            io_method,
+            buffer_count,
+            ..
+        } = self.settings;
+        self.inner = Some(if multiplanar {
+            StreamKind::Multi(MplaneStream::new(device, io_method, buffer_count)?)
+        } else {
+            StreamKind::Single(SingleStream::new(device, io_method, buffer_count)?)
+        });
+
+        // sequence numbers start over with each stream
+        if let Some(ref mut metadata) = self.metadata {
+            metadata.forget();
+        }
+
+        Ok(())
+    }
+
+    /// Stops streaming and frees the buffers, so the device can change its
+    /// format.
+    pub(crate) fn release(&mut self) -> io::Result<()> {
+        self.hardware = None;
+
+        // dropping the stream frees its buffers, even if stopping fails
+        match self.inner.take() {
+            Some(StreamKind::Single(mut stream)) => stream.stop(),
+            Some(StreamKind::Multi(mut stream)) => stream.stop(),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks if the device is streaming.
+    pub(crate) const fn is_streaming(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// How this stream's buffers are set up, and how long reads wait.
+    pub(super) const fn settings(&self) -> StreamSettings {
+        self.settings
+    }
+
+    /// Changes how long a plain read waits for a frame.
+    pub(super) const fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.settings.read_timeout = timeout;
+    }
+
+    /// Starts (or stops) matching frames with a metadata node's timestamps.
+    pub(super) fn set_metadata(&mut self, metadata: Option<MetadataStream>) {
+        self.metadata = metadata;
+    }
+
+    /// Checks if frames are being matched with a metadata node.
+    pub(crate) const fn has_metadata(&self) -> bool {
+        self.metadata.is_some()
+    }
+
+    /// Gives back the current frame's buffer, then waits for the next one.
+    ///
+    /// Returns `false` if no frame arrived in time. With no `timeout`, this
+    /// waits as long as it takes. Streams that were stopped fail with
+    /// `EINVAL`, like the kernel does.
+    pub(crate) fn advance(&mut self, timeout: Option<Duration>) -> io::Result<bool> {
+        self.hardware = None;
+
+        let (arrived, latest) = match self.inner {
+            Some(StreamKind::Single(ref mut stream)) => {
+                let arrived = stream.advance(timeout)?;
+                (arrived, stream.current().map(|current| current.1.sequence))
+            }
+            Some(StreamKind::Multi(ref mut stream)) => {
+                let arrived = stream.advance(timeout)?;
+                (arrived, stream.current().map(|current| current.sequence))
+            }
+            None => return Err(Errno::EINVAL.into()),
+        };
+
+        if let Some((metadata, sequence)) = self.metadata.as_mut().zip(latest) {
+            self.hardware = metadata.timestamps_for(sequence);
+        }
+
+        Ok(arrived)
+    }
+
+    /// The frame that [`V4LStream::advance`] last waited for.
+    ///
+    /// Frames that the driver flagged as damaged say so in their metadata.
+    pub(crate) fn frame(&self) -> io::Result<Frame<'_>> {
+        let current = match self.inner {
+            Some(StreamKind::Single(ref stream)) => stream
+                .current()
+                .map(|(data, dequeued)| single_frame(stream.format(), data, dequeued)),
+            Some(StreamKind::Multi(ref stream)) => stream
+                .current()
+                .map(|dequeued| multiplanar_frame(stream.format(), &dequeued))
+                .transpose()?,
+            None => return Err(Errno::EINVAL.into()),
+        };
+
+        let mut frame = current.ok_or_else(|| io::Error::other("no frame has been read yet"))?;
+        frame.metadata.hardware = self.hardware;
+        Ok(frame)
+    }
+
+    /// Stops streaming, and closes the metadata node if it was open.
+    ///
+    /// Both streams are released, even if one of them won't stop. The first
+    /// error is returned.
+    pub(crate) fn stop(&mut self) -> io::Result<()> {
+        let metadata = self
+            .metadata
+            .take()
+            .map_or(Ok(()), |mut metadata| metadata.stop());
+        let video = self.release();
+
+        metadata.and(video)
+    }
+}
+
+/// Says if the driver flagged a buffer as damaged.
+const fn flagged(flags: u32) -> Option<FrameCorruption> {
+    if flags & V4L2_BUF_FLAG_ERROR == 0 {
+        None
+    } else {
+        Some(FrameCorruption::Flagged)
+    }
+}
+
+/// Turns a dequeued single-planar buffer into a frame.
+fn single_frame<'stream>(
+    format: &PixFormat,
+    data: &'stream [u8],
+    dequeued: Dequeued,
+) -> Frame<'stream> {
+    let stride = format.first_plane().stride as usize;
+
+    Frame {
+        data,
+        format: Format::new(format.fourcc),
+        resolution: format.resolution(),
+        stride,
+        planes: FramePlanes::single(data, stride),
+        metadata: FrameMetadata {
+            sequence: dequeued.sequence,
+            timestamp: dequeued.timestamp,
+            bytes_used: dequeued.bytes_used,
+            hardware: None,
+            corruption: flagged(dequeued.flags),
+        },
+    }
+}
+
+/// Turns a dequeued multi-planar buffer into a frame.
+fn multiplanar_frame<'stream>(
+    format: &PixFormat,
+    dequeued: &DequeuedFrame<'stream>,
+) -> io::Result<Frame<'stream>> {
+    let plane_list: Vec<FramePlane<'_>> = dequeued
+        .planes
+        .iter()
+        .zip(&format.planes)
+        .map(|(&(data, _), plane_format)| FramePlane {
+            data,
+            stride: plane_format.stride as usize,
+        })
+        .collect();
+    let planes = FramePlanes::from_slice(&plane_list)
+        .ok_or_else(|| io::Error::other("device gave a frame with too many planes"))?;
+    let first = planes.first().copied().unwrap_or_default();
+
+    Ok(Frame {
+        data: first.data,
+        format: Format::new(format.fourcc),
+        resolution: format.resolution(),
+        stride: first.stride,
+        planes,
+        metadata: FrameMetadata {
+            sequence: dequeued.sequence,
+            timestamp: dequeued.timestamp,
+            bytes_used: dequeued.bytes_used,
+            hardware: None,
+            corruption: flagged(dequeued.flags),
+        },
+    })
+}
+
+impl Debug for V4LStream {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut debug = f.debug_struct("V4LStream");
+        match self.inner {
+            Some(StreamKind::Single(ref stream)) => debug.field("format", stream.format()),
+            Some(StreamKind::Multi(ref stream)) => debug.field("format", stream.format()),
+            None => debug.field("streaming", &false),
+        };
+        debug
+            .field("settings", &self.settings)
+            .field("has_metadata", &self.has_metadata())
+            .finish_non_exhaustive()
+    }
+}
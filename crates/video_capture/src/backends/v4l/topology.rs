@@ -0,0 +1,561 @@
+//! The media controller's graph of entities, interfaces, pads, and links.
+//!
+//! A physical camera usually shows up as one `/dev/mediaX` and several nodes:
+//! a video node for frames, a metadata node, and sometimes subdevices. The
+//! topology is the only place that says exactly which nodes belong together.
+//!
+//! See: https://docs.kernel.org/userspace-api/media/mediactl/media-ioc-g-topology.html
+
+use core::fmt::Display;
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd as _;
+use std::path::{Path, PathBuf};
+
+use nix::ioctl_readwrite;
+
+use super::capabilities::fixed_str;
+use super::kernel::Kernel;
+
+/// The raw `media_v2_topology` structure.
+#[repr(C)]
+struct MediaV2Topology {
+    topology_version: u64,
+    num_entities: u32,
+    reserved1: u32,
+    ptr_entities: u64,
+    num_interfaces: u32,
+    reserved2: u32,
+    ptr_interfaces: u64,
+    num_pads: u32,
+    reserved3: u32,
+    ptr_pads: u64,
+    num_links: u32,
+    reserved4: u32,
+    ptr_links: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct MediaV2Entity {
+    id: u32,
+    name: [u8; 64],
+    function: u32,
+    flags: u32,
+    reserved: [u32; 5],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct MediaV2Interface {
+    id: u32,
+    intf_type: u32,
+    flags: u32,
+    reserved: [u32; 9],
+    // this is a union in the kernel, but `devnode` is the only member
+    major: u32,
+    minor: u32,
+    raw: [u32; 14],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct MediaV2Pad {
+    id: u32,
+    entity_id: u32,
+    flags: u32,
+    index: u32,
+    reserved: [u32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct MediaV2Link {
+    id: u32,
+    source_id: u32,
+    sink_id: u32,
+    flags: u32,
+    reserved: [u32; 6],
+}
+
+const MEDIA_IOC_G_TOPOLOGY_SEQ_NUM: u8 = 0x04;
+const IOCTL_MEDIA_COMMAND_IDENT: u8 = b'|';
+
+ioctl_readwrite!(
+    media_ioc_g_topology,
+    IOCTL_MEDIA_COMMAND_IDENT,
+    MEDIA_IOC_G_TOPOLOGY_SEQ_NUM,
+    MediaV2Topology
+);
+
+/// The link type bits in `MediaLink::flags`.
+const MEDIA_LNK_FL_LINK_TYPE: u32 = 0xf << 28;
+/// Links from an interface to the entity it controls.
+const MEDIA_LNK_FL_INTERFACE_LINK: u32 = 1 << 28;
+/// A link between two pads. (`MEDIA_LNK_FL_DATA_LINK`)
+const MEDIA_LNK_FL_DATA_LINK: u32 = 0;
+
+/// The whole graph for one media device.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MediaTopology {
+    /// Changes whenever the graph does.
+    pub version: u64,
+    pub entities: Vec<MediaEntity>,
+    pub interfaces: Vec<MediaInterface>,
+    pub pads: Vec<MediaPad>,
+    pub links: Vec<MediaLink>,
+}
+
+/// Something in the pipeline, like a sensor, an ISP, or a DMA engine.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MediaEntity {
+    pub id: u32,
+    pub name: String,
+    /// One of the kernel's `MEDIA_ENT_F_*` values.
+    pub function: u32,
+    pub flags: u32,
+}
+
+/// A way for userspace to talk to entities. These are usually device nodes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MediaInterface {
+    pub id: u32,
+    pub kind: MediaInterfaceKind,
+    pub flags: u32,
+    /// The device node's major number.
+    pub major: u32,
+    /// The device node's minor number.
+    pub minor: u32,
+}
+
+/// The kinds of interface that Video4Linux devices use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum MediaInterfaceKind {
+    /// A `/dev/videoY` node. These carry frames or metadata.
+    Video,
+    /// A `/dev/vbiY` node.
+    Vbi,
+    /// A `/dev/radioY` node.
+    Radio,
+    /// A `/dev/v4l-subdevY` node.
+    Subdevice,
+    /// A `/dev/swradioY` node.
+    SoftwareRadio,
+    /// A `/dev/v4l-touchY` node.
+    Touch,
+    /// Anything else (like DVB or ALSA), with its `MEDIA_INTF_T_*` value.
+    Other(u32),
+}
+
+impl MediaInterfaceKind {
+    const fn from_raw(raw: u32) -> Self {
+        match raw {
+            0x200 => Self::Video,
+            0x201 => Self::Vbi,
+            0x202 => Self::Radio,
+            0x203 => Self::Subdevice,
+            0x204 => Self::SoftwareRadio,
+            0x205 => Self::Touch,
+            other => Self::Other(other),
+        }
+    }
+}
+
+/// A connection point on an entity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MediaPad {
+    pub id: u32,
+    pub entity_id: u32,
+    /// `MEDIA_PAD_FL_*` values. Sinks are `1`, sources are `2`.
+    pub flags: u32,
+    /// The pad's index on its entity.
+    pub index: u32,
+}
+
+/// A link between two pads, or from an interface to an entity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MediaLink {
+    pub id: u32,
+    /// A pad ID, or an interface ID for interface links.
+    pub source_id: u32,
+    /// A pad ID, or an entity ID for interface links.
+    pub sink_id: u32,
+    /// `MEDIA_LNK_FL_*` values.
+    pub flags: u32,
+}
+
+impl MediaLink {
+    /// Checks if this links an interface to an entity (instead of two pads).
+    #[inline]
+    pub const fn is_interface_link(&self) -> bool {
+        self.flags & MEDIA_LNK_FL_LINK_TYPE == MEDIA_LNK_FL_INTERFACE_LINK
+    }
+}
+
+/// A device node that belongs to a media device.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MediaNode {
+    /// Where the node is, like `/dev/video0`.
+    pub path: PathBuf,
+    pub kind: MediaNodeKind,
+    /// The name of the entity that the node controls.
+    pub entity_name: Option<String>,
+}
+
+/// What a device node is for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum MediaNodeKind {
+    /// A video node that can capture frames.
+    VideoCapture,
+    /// A video node that outputs frames.
+    VideoOutput,
+    /// A video node that carries metadata, like UVC timestamps.
+    Metadata,
+    /// A subdevice, like a sensor or an ISP.
+    Subdevice,
+    /// Any other kind of node.
+    Other(MediaInterfaceKind),
+}
+
+/// Node kinds display as short names, like `video capture` or `metadata`.
+impl Display for MediaNodeKind {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::VideoCapture => f.write_str("video capture"),
+            Self::VideoOutput => f.write_str("video output"),
+            Self::Metadata => f.write_str("metadata"),
+            Self::Subdevice => f.write_str("subdevice"),
+            Self::Other(_) => f.write_str("other"),
+        }
+    }
+}
+
+impl MediaTopology {
+    /// Reads the topology of the media device at the given path.
+    #[tracing::instrument]
+    pub(super) fn query(media_path: &Path) -> Result<Self, io::Error> {
+        let file = File::open(media_path)?;
+        let fd = file.as_raw_fd();
+
+        // the graph can change between calls. when it does, we just ask again
+        loop {
+            // first, ask how big everything is...
+            //
+            // SAFETY: the kernel expects zeroed memory. null pointers tell it
+            // to only fill in the counts.
+            let mut counts = unsafe { core::mem::zeroed::<MediaV2Topology>() };
+            // SAFETY: the kernel will either fill the struct or return an error.
+            unsafe { media_ioc_g_topology(fd, &raw mut counts)? };
+
+            // ...then make room for it all
+            let mut entities = zeroed_vec::<MediaV2Entity>(counts.num_entities);
+            let mut interfaces = zeroed_vec::<MediaV2Interface>(counts.num_interfaces);
+            let mut pads = zeroed_vec::<MediaV2Pad>(counts.num_pads);
+            let mut links = zeroed_vec::<MediaV2Link>(counts.num_links);
+
+            let mut topology = MediaV2Topology {
+                topology_version: 0,
+                num_entities: counts.num_entities,
+                reserved1: 0,
+                ptr_entities: entities.as_mut_ptr().addr() as u64,
+                num_interfaces: counts.num_interfaces,
+                reserved2: 0,
+                ptr_interfaces: interfaces.as_mut_ptr().addr() as u64,
+                num_pads: counts.num_pads,
+                reserved3: 0,
+                ptr_pads: pads.as_mut_ptr().addr() as u64,
+                num_links: counts.num_links,
+                reserved4: 0,
+                ptr_links: links.as_mut_ptr().addr() as u64,
+            };
+
+            // SAFETY: each pointer has room for as many elements as its count
+            // says, and the kernel won't write more than that.
+            unsafe { media_ioc_g_topology(fd, &raw mut topology)? };
+
+            if topology.topology_version != counts.topology_version {
+                tracing::debug!("media topology changed while reading it. retrying...");
+                continue;
+            }
+
+            return Ok(Self {
+                version: topology.topology_version,
+                entities: entities
+                    .iter()
+                    .map(|raw| MediaEntity {
+                        id: raw.id,
+                        name: fixed_str(&raw.name),
+                        function: raw.function,
+                        flags: raw.flags,
+                    })
+                    .collect(),
+                interfaces: interfaces
+                    .iter()
+                    .map(|raw| MediaInterface {
+                        id: raw.id,
+                        kind: MediaInterfaceKind::from_raw(raw.intf_type),
+                        flags: raw.flags,
+                        major: raw.major,
+                        minor: raw.minor,
+                    })
+                    .collect(),
+                pads: pads
+                    .iter()
+                    .map(|raw| MediaPad {
+                        id: raw.id,
+                        entity_id: raw.entity_id,
+                        flags: raw.flags,
+                        index: raw.index,
+                    })
+                    .collect(),
+                links: links
+                    .iter()
+                    .map(|raw| MediaLink {
+                        id: raw.id,
+                        source_id: raw.source_id,
+                        sink_id: raw.sink_id,
+                        flags: raw.flags,
+                    })
+                    .collect(),
+            });
+        }
+    }
+
+    /// Finds the entity that an interface controls.
+    #[inline]
+    pub fn entity_for(&self, interface: &MediaInterface) -> Option<&MediaEntity> {
+        let link = self
+            .links
+            .iter()
+            .find(|link| link.is_interface_link() && link.source_id == interface.id)?;
+
+        self.entities
+            .iter()
+            .find(|entity| entity.id == link.sink_id)
+    }
+
+    /// Checks if any of this device's interfaces is the given device number.
+    #[inline]
+    pub fn has_device_number(&self, major: u32, minor: u32) -> bool {
+        self.interfaces
+            .iter()
+            .any(|interface| interface.major == major && interface.minor == minor)
+    }
+
+    /// Lists every device node that belongs to this media device.
+    ///
+    /// Video nodes are asked what they do, so metadata nodes can be told
+    /// apart from the ones with frames.
+    pub(super) fn device_nodes(&self, kernel: &dyn Kernel) -> Vec<MediaNode> {
+        self.interface_nodes(kernel)
+            .into_iter()
+            .map(|(_, node)| node)
+            .collect()
+    }
+
+    /// Finds the metadata node that goes with the capture node that has the
+    /// given device number.
+    ///
+    /// Drivers that link their metadata entities into the pipeline are
+    /// paired by those links, since both entities are fed by the same
+    /// source. `uvcvideo` doesn't link its metadata entities at all, but it
+    /// registers each one right after its capture entity. So, an unlinked
+    /// metadata entity goes with the closest capture entity before it.
+    pub(super) fn metadata_node_for(
+        &self,
+        kernel: &dyn Kernel,
+        (major, minor): (u32, u32),
+    ) -> Option<PathBuf> {
+        let nodes = self.interface_nodes(kernel);
+        let entities = |kind: MediaNodeKind| {
+            nodes
+                .iter()
+                .filter(move |pair| pair.1.kind == kind)
+                .filter_map(|&(interface, ref node)| Some((self.entity_for(interface)?.id, node)))
+        };
+
+        let video = nodes
+            .iter()
+            .find(|&&(interface, ref node)| {
+                node.kind == MediaNodeKind::VideoCapture
+                    && interface.major == major
+                    && interface.minor == minor
+            })
+            .and_then(|&(interface, _)| self.entity_for(interface))?
+            .id;
+        let feeds_video = self.sources_of(video);
+
+        // linked metadata comes from the same place as the frames
+        let linked = entities(MediaNodeKind::Metadata).find(|&(id, _)| {
+            self.sources_of(id)
+                .iter()
+                .any(|source| feeds_video.contains(source))
+        });
+        if let Some((_, node)) = linked {
+            return Some(node.path.clone());
+        }
+
+        // otherwise, it was registered after our capture entity, but before
+        // the next one
+        let next_video = entities(MediaNodeKind::VideoCapture)
+            .map(|(id, _)| id)
+            .filter(|&id| id > video)
+            .min();
+        entities(MediaNodeKind::Metadata)
+            .filter(|&(id, _)| id > video && next_video.is_none_or(|next| id < next))
+            .filter(|&(id, _)| self.sources_of(id).is_empty())
+            .min_by_key(|&(id, _)| id)
+            .map(|(_, node)| node.path.clone())
+    }
+
+    /// Lists the entities with data links into the given one.
+    fn sources_of(&self, entity_id: u32) -> Vec<u32> {
+        let entity_of_pad = |pad_id: u32| {
+            self.pads
+                .iter()
+                .find(|pad| pad.id == pad_id)
+                .map(|pad| pad.entity_id)
+        };
+
+        self.links
+            .iter()
+            .filter(|link| link.flags & MEDIA_LNK_FL_LINK_TYPE == MEDIA_LNK_FL_DATA_LINK)
+            .filter(|link| entity_of_pad(link.sink_id) == Some(entity_id))
+            .filter_map(|link| entity_of_pad(link.source_id))
+            .collect()
+    }
+
+    /// Lists every device node, along with its interface.
+    fn interface_nodes(&self, kernel: &dyn Kernel) -> Vec<(&MediaInterface, MediaNode)> {
+        self.interfaces
+            .iter()
+            .filter_map(|interface| {
+                let path = devnode_path(kernel, interface.major, interface.minor)?;
+
+                let kind = match interface.kind {
+                    MediaInterfaceKind::Subdevice => MediaNodeKind::Subdevice,
+                    MediaInterfaceKind::Video => video_node_kind(kernel, &path),
+                    other => MediaNodeKind::Other(other),
+                };
+
+                Some((
+                    interface,
+                    MediaNode {
+                        path,
+                        kind,
+                        entity_name: self.entity_for(interface).map(|entity| entity.name.clone()),
+                    },
+                ))
+            })
+            .collect()
+    }
+}
+
+/// Makes a `Vec` of zeroed kernel structs.
+fn zeroed_vec<T: Copy>(len: u32) -> Vec<T> {
+    // SAFETY: these are only used for plain-old-data kernel structs, where
+    // all zeroes is a valid value.
+    let zeroed = unsafe { core::mem::zeroed::<T>() };
+    vec![zeroed; len as usize]
+}
+
+/// Asks a video node if it carries frames or metadata.
+fn video_node_kind(kernel: &dyn Kernel, path: &Path) -> MediaNodeKind {
+    match kernel.capabilities(path) {
+        Ok(caps) if caps.device_capabilities.is_metadata() => MediaNodeKind::Metadata,
+        Ok(caps) if caps.device_capabilities.is_output() => MediaNodeKind::VideoOutput,
+        Ok(_) => MediaNodeKind::VideoCapture,
+        Err(e) => {
+            tracing::debug!("couldn't query `{}`: {e}", path.display());
+            MediaNodeKind::Other(MediaInterfaceKind::Video)
+        }
+    }
+}
+
+/// Finds the `/dev` path for a device number, using sysfs.
+fn devnode_path(kernel: &dyn Kernel, major: u32, minor: u32) -> Option<PathBuf> {
+    let uevent = kernel
+        .read_to_string(Path::new(&format!("/sys/dev/char/{major}:{minor}/uevent")))
+        .ok()?;
+    devname_from_uevent(&uevent).map(|name| Path::new("/dev").join(name))
+}
+
+/// Grabs `DEVNAME` from a sysfs `uevent` file.
+fn devname_from_uevent(uevent: &str) -> Option<&str> {
+    uevent
+        .lines()
+        .find_map(|line| line.strip_prefix("DEVNAME="))
+}
+
+/// Splits a character device's number into its major and minor parts.
+///
+/// This is glibc's `gnu_dev_major` and `gnu_dev_minor`.
+#[expect(
+    clippy::cast_possible_truncation,
+    reason = "the masks keep each part within 32 bits"
+)]
+pub(super) const fn split_device_number(rdev: u64) -> (u32, u32) {
+    let major = ((rdev >> 8) & 0xfff) | ((rdev >> 32) & !0xfff);
+    let minor = (rdev & 0xff) | ((rdev >> 12) & !0xff);
+    (major as u32, minor as u32)
+}
+
+/// Lists every `/dev/mediaX` file.
+pub(super) fn media_devices(kernel: &dyn Kernel) -> Vec<PathBuf> {
+    let Ok(entries) = kernel.read_dir(Path::new("/dev")) else {
+        return Vec::new();
+    };
+
+    let mut found: Vec<_> = entries
+        .into_iter()
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("media"))
+        })
+        .collect();
+    found.sort();
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_numbers_map_to_nodes() {
+        // 81:3 is a typical `/dev/video3`
+        assert_eq!(split_device_number(0x5103), (81, 3), "small numbers");
+        assert_eq!(
+            split_device_number(0x0000_1000_0010_5100),
+            (0x1051, 0x100),
+            "large numbers use the high bits"
+        );
+
+        let uevent = "MAJOR=81\nMINOR=3\nDEVNAME=video3\n";
+        assert_eq!(devname_from_uevent(uevent), Some("video3"));
+        assert_eq!(devname_from_uevent("MAJOR=81\n"), None, "no name");
+    }
+
+    #[test]
+    fn finds_device_nodes() {
+        let kernel = super::super::fake::uvc_camera();
+        let topology = kernel.media_topology(Path::new("/dev/media0")).unwrap();
+
+        let nodes: Vec<_> = topology
+            .device_nodes(&kernel)
+            .into_iter()
+            .map(|node| (node.path, node.kind))
+            .collect();
+        assert_eq!(
+            nodes,
+            vec![
+                (PathBuf::from("/dev/video0"), MediaNodeKind::VideoCapture),
+                (PathBuf::from("/dev/video1"), MediaNodeKind::Metadata),
+            ]
+        );
+        assert_eq!(media_devices(&kernel), vec![PathBuf::from("/dev/media0")]);
+    }
+}
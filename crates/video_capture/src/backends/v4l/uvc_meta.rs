@@ -0,0 +1,388 @@
+//! UVC metadata nodes, which carry the payload headers for each frame.
+//!
+//! `uvcvideo` gives each streaming interface a second `/dev/videoY` node
+//! with the `V4L2_BUF_TYPE_META_CAPTURE` type. Its buffers hold a list of
+//! `uvc_meta_buf` blocks: the host's time and USB frame number, followed by
+//! the payload header the camera sent (with its PTS and SCR).
+//!
+//! The driver gives each metadata buffer the same sequence number as its
+//! video buffer, and finishes it just before the video buffer. So, by the
+//! time we've got a frame, its metadata is already waiting.
+//!
+//! See: https://docs.kernel.org/userspace-api/media/v4l/metafmt-uvc.html
+
+use core::time::Duration;
+use std::io;
+use std::path::Path;
+
+extern crate alloc;
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+use crate::frame::HardwareTimestamps;
+
+use super::device::V4LDevice;
+use super::kernel::Kernel;
+use super::raw::{
+    zeroed, MappedBuffer, V4l2Buffer, V4L2_BUF_FLAG_ERROR, V4L2_BUF_TYPE_META_CAPTURE,
+    V4L2_MEMORY_MMAP,
+};
+
+/// The metadata format that `uvcvideo` uses by default. (`V4L2_META_FMT_UVC`)
+const UVC_META_FORMAT: [u8; 4] = *b"UVCH";
+
+/// The header has a presentation timestamp. (`UVC_STREAM_PTS`)
+const UVC_STREAM_PTS: u8 = 0x04;
+/// The header has a source clock reference. (`UVC_STREAM_SCR`)
+const UVC_STREAM_SCR: u8 = 0x08;
+
+/// The host's timestamp and USB frame number, before each header.
+const HOST_INFO_LEN: usize = 10;
+/// `bHeaderLength` and `bmHeaderInfo`, which every header has.
+const HEADER_INFO_LEN: usize = 2;
+const PTS_LEN: usize = 4;
+const SCR_LEN: usize = 6;
+
+/// How many buffers we ask the driver for.
+const BUFFER_COUNT: u32 = 4;
+
+/// How many frames' timestamps we keep around while waiting for their
+/// frames.
+const RECENT_LEN: usize = 8;
+
+/// A stream from a UVC metadata node.
+pub(super) struct MetadataStream {
+    device: V4LDevice,
+    buffers: Vec<MappedBuffer>,
+    /// Timestamps we've read, by sequence number, oldest first.
+    recent: VecDeque<(u32, HardwareTimestamps)>,
+    streaming: bool,
+}
+
+impl MetadataStream {
+    /// Opens the metadata node, maps its buffers, and starts streaming.
+    ///
+    /// This fails if the node doesn't use the `UVCH` format.
+    pub(super) fn new(kernel: Arc<dyn Kernel>, path: &Path) -> io::Result<Self> {
+        let device = V4LDevice::open(kernel, path)?;
+        let kernel = Arc::clone(device.kernel());
+        let fd = device.fd();
+
+        let dataformat = device.format(V4L2_BUF_TYPE_META_CAPTURE)?.fourcc;
+        if dataformat != UVC_META_FORMAT {
+            return Err(io::Error::other(format!(
+                "metadata node uses `{}`, not `UVCH`",
+                String::from_utf8_lossy(&dataformat)
+            )));
+        }
+
+        let count = kernel.request_buffers(
+            fd,
+            V4L2_BUF_TYPE_META_CAPTURE,
+            V4L2_MEMORY_MMAP,
+            BUFFER_COUNT,
+        )?;
+
+        let mut stream = Self {
+            device,
+            buffers: Vec::with_capacity(count as usize),
+            recent: VecDeque::with_capacity(RECENT_LEN),
+            streaming: false,
+        };
+
+        for index in 0..count {
+            let mut buffer = raw_buffer(index);
+            kernel.query_buffer(fd, &mut buffer)?;
+
+            // SAFETY: the kernel set `offset`, since we asked for mmap.
+            let offset = unsafe { buffer.m.offset };
+            stream
+                .buffers
+                .push(kernel.map_buffer(fd, buffer.length, offset)?);
+            stream.queue(index)?;
+        }
+
+        kernel.stream_on(fd, V4L2_BUF_TYPE_META_CAPTURE)?;
+        stream.streaming = true;
+
+        Ok(stream)
+    }
+
+    /// Gives a buffer back to the driver.
+    fn queue(&self, index: u32) -> io::Result<()> {
+        let mut buffer = raw_buffer(index);
+        self.device
+            .kernel()
+            .queue_buffer(self.device.fd(), &mut buffer)
+    }
+
+    /// Reads every finished buffer without waiting, remembering the
+    /// timestamps in each.
+    fn drain(&mut self) -> io::Result<()> {
+        let kernel = Arc::clone(self.device.kernel());
+        let fd = self.device.fd();
+
+        while !kernel
+            .wait_readable(&[fd], Some(Duration::ZERO))?
+            .is_empty()
+        {
+            let mut buffer = raw_buffer(0);
+            match kernel.dequeue_buffer(fd, &mut buffer) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+
+            let parsed = self
+                .buffers
+                .get(buffer.index as usize)
+                .filter(|_| buffer.flags & V4L2_BUF_FLAG_ERROR == 0)
+                .and_then(|mapped| {
+                    // SAFETY: we just dequeued this buffer. the driver won't
+                    // write to it until we queue it again, just below.
+                    let bytes = unsafe { mapped.as_slice() };
+                    parse(bytes.get(..buffer.bytesused as usize).unwrap_or(bytes))
+                });
+
+            // we've copied out what we need, so the driver can have it back
+            self.queue(buffer.index)?;
+
+            if let Some(timestamps) = parsed {
+                if self.recent.len() == RECENT_LEN {
+                    self.recent.pop_front();
+                }
+                self.recent.push_back((buffer.sequence, timestamps));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finds the timestamps for the frame with the given sequence number.
+    ///
+    /// Metadata buffers that haven't been read yet are read first. If the
+    /// camera didn't send any timestamps for the frame, this is `None`.
+    pub(super) fn timestamps_for(&mut self, sequence: u32) -> Option<HardwareTimestamps> {
+        if let Err(e) = self.drain() {
+            tracing::warn!("Failed to read the UVC metadata node: {e}");
+        }
+
+        self.recent
+            .iter()
+            .find(|recent| recent.0 == sequence)
+            .map(|recent| recent.1)
+    }
+
+    /// Forgets every timestamp we've read so far.
+    ///
+    /// Sequence numbers start over when the video node starts streaming
+    /// again, so old timestamps would be matched to the wrong frames.
+    pub(super) fn forget(&mut self) {
+        if let Err(e) = self.drain() {
+            tracing::warn!("Failed to read the UVC metadata node: {e}");
+        }
+        self.recent.clear();
+    }
+
+    /// Asks the node to stop streaming.
+    pub(super) fn stop(&mut self) -> io::Result<()> {
+        self.recent.clear();
+        self.device
+            .kernel()
+            .stream_off(self.device.fd(), V4L2_BUF_TYPE_META_CAPTURE)?;
+        self.streaming = false;
+        Ok(())
+    }
+}
+
+impl Drop for MetadataStream {
+    fn drop(&mut self) {
+        if self.streaming {
+            if let Err(e) = self.stop() {
+                tracing::warn!("Failed to stop UVC metadata stream: {e}");
+            }
+        }
+
+        // the buffers have to be unmapped before they can be freed
+        self.buffers.clear();
+
+        // asking for zero buffers frees them
+        if let Err(e) = self.device.kernel().request_buffers(
+            self.device.fd(),
+            V4L2_BUF_TYPE_META_CAPTURE,
+            V4L2_MEMORY_MMAP,
+            0,
+        ) {
+            tracing::debug!("Failed to free UVC metadata buffers: {e}");
+        }
+    }
+}
+
+/// Makes a `v4l2_buffer` for one of the metadata buffers.
+const fn raw_buffer(index: u32) -> V4l2Buffer {
+    let mut buffer = zeroed::<V4l2Buffer>();
+    buffer.index = index;
+    buffer.r#type = V4L2_BUF_TYPE_META_CAPTURE;
+    buffer.memory = V4L2_MEMORY_MMAP;
+    buffer
+}
+
+/// Reads the timestamps out of a metadata buffer.
+///
+/// Every payload of a frame has the same PTS, so we take the first one. The
+/// SCR changes as the frame is sent, so we take the last one, along with the
+/// host's time for that block.
+///
+/// Returns `None` if no block has either timestamp.
+fn parse(mut bytes: &[u8]) -> Option<HardwareTimestamps> {
+    let mut timestamps = HardwareTimestamps::default();
+    let mut found = false;
+
+    while let Some((host, rest)) = bytes.split_first_chunk::<HOST_INFO_LEN>() {
+        let Some(&[_, flags]) = rest.first_chunk::<HEADER_INFO_LEN>() else {
+            break;
+        };
+        let has_pts = flags & UVC_STREAM_PTS != 0;
+        let has_scr = flags & UVC_STREAM_SCR != 0;
+
+        // `UVCH` blocks only keep the standard part of each header
+        let pts_len = if has_pts { PTS_LEN } else { 0 };
+        let scr_len = if has_scr { SCR_LEN } else { 0 };
+        let Some((header, remaining)) = rest.split_at_checked(HEADER_INFO_LEN + pts_len + scr_len)
+        else {
+            break;
+        };
+        bytes = remaining;
+
+        let fields = header.get(HEADER_INFO_LEN..).unwrap_or_default();
+        if has_pts && timestamps.presentation_time.is_none() {
+            timestamps.presentation_time = fields
+                .first_chunk::<PTS_LEN>()
+                .map(|pts| u32::from_le_bytes(*pts));
+        }
+        if has_scr {
+            let scr = fields
+                .get(pts_len..)
+                .and_then(<[u8]>::first_chunk::<SCR_LEN>);
+            if let Some(&[stc0, stc1, stc2, stc3, sof0, sof1]) = scr {
+                let (ns, sof) = host.split_at(8);
+                timestamps.source_clock = Some(u32::from_le_bytes([stc0, stc1, stc2, stc3]));
+                timestamps.source_sof = Some(u16::from_le_bytes([sof0, sof1]));
+                timestamps.host_time =
+                    Duration::from_nanos(u64::from_le_bytes(ns.try_into().unwrap_or_default()));
+                timestamps.host_sof = u16::from_le_bytes(sof.try_into().unwrap_or_default());
+            }
+        }
+
+        found |= has_pts || has_scr;
+    }
+
+    found.then_some(timestamps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::builder::V4LIoMethod;
+    use super::super::fake::uvc_camera;
+    use super::super::raw::V4L2_BUF_TYPE_VIDEO_CAPTURE;
+    use super::super::single::SingleStream;
+    use super::*;
+
+    /// Makes one `uvc_meta_buf` block.
+    fn block(ns: u64, sof: u16, flags: u8, fields: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&ns.to_le_bytes());
+        bytes.extend_from_slice(&sof.to_le_bytes());
+        bytes.push(u8::try_from(HEADER_INFO_LEN + fields.len()).unwrap());
+        bytes.push(flags);
+        bytes.extend_from_slice(fields);
+        bytes
+    }
+
+    #[test]
+    fn payload_headers_give_pts_and_scr() {
+        let pts = 1_000_u32.to_le_bytes();
+        let mut first = pts.to_vec();
+        first.extend_from_slice(&1_500_u32.to_le_bytes());
+        first.extend_from_slice(&7_u16.to_le_bytes());
+        let mut last = pts.to_vec();
+        last.extend_from_slice(&2_000_u32.to_le_bytes());
+        last.extend_from_slice(&9_u16.to_le_bytes());
+
+        let mut buffer = block(10, 1, UVC_STREAM_PTS | UVC_STREAM_SCR, &first);
+        buffer.extend(block(20, 2, UVC_STREAM_PTS | UVC_STREAM_SCR, &last));
+        // a trailing, cut-off block is ignored
+        buffer.extend_from_slice(&[0; 5]);
+
+        let timestamps = parse(&buffer).unwrap();
+        assert_eq!(timestamps.presentation_time, Some(1_000), "first pts");
+        assert_eq!(timestamps.source_clock, Some(2_000), "last scr");
+        assert_eq!(timestamps.source_sof, Some(9), "last scr's sof");
+        assert_eq!(
+            timestamps.host_time,
+            Duration::from_nanos(20),
+            "scr's host time"
+        );
+        assert_eq!(timestamps.host_sof, 2, "scr's host sof");
+
+        // 1000 ticks at 1 MHz is one millisecond before the host got the scr
+        let received = HardwareTimestamps {
+            host_time: Duration::from_millis(5),
+            ..timestamps
+        };
+        assert_eq!(
+            received.presentation_host_time(1_000_000),
+            Some(Duration::from_millis(4)),
+            "pts on the host's clock"
+        );
+
+        assert_eq!(parse(&block(10, 1, 0, &[])), None, "no timestamps");
+    }
+
+    #[test]
+    fn drains_and_matches_by_sequence() {
+        // each metadata buffer holds a different pts
+        let contents = (0..BUFFER_COUNT)
+            .map(|index| block(0, 0, UVC_STREAM_PTS, &(1_000 + index).to_le_bytes()))
+            .collect();
+        let kernel: Arc<dyn Kernel> = Arc::new(
+            uvc_camera()
+                .with_current_format(V4L2_BUF_TYPE_VIDEO_CAPTURE, *b"YUYV", (4, 2))
+                .with_current_format(V4L2_BUF_TYPE_META_CAPTURE, UVC_META_FORMAT, (16, 1))
+                .with_buffer_contents("/dev/video1", contents),
+        );
+
+        let mut metadata =
+            MetadataStream::new(Arc::clone(&kernel), Path::new("/dev/video1")).unwrap();
+        let video = V4LDevice::open(kernel, Path::new("/dev/video0")).unwrap();
+        let mut frames = SingleStream::new(&video, V4LIoMethod::Mmap, 2).unwrap();
+        let mut pts = |sequence| {
+            metadata
+                .timestamps_for(sequence)
+                .and_then(|timestamps| timestamps.presentation_time)
+        };
+
+        // a few frames go by before we look for their metadata
+        for _ in 0..3 {
+            assert!(frames.advance(None).unwrap(), "a frame arrived");
+        }
+        assert_eq!(pts(2), Some(1_002), "the newest frame");
+        assert_eq!(pts(0), Some(1_000), "older frames are still around");
+        assert_eq!(pts(3), None, "no metadata before its frame");
+
+        // read buffers went back to the driver, so they're used again
+        for _ in 0..2 {
+            assert!(frames.advance(None).unwrap(), "a frame arrived");
+        }
+        assert_eq!(pts(3), Some(1_003), "the last fresh buffer");
+        assert_eq!(pts(4), Some(1_000), "the first buffer, again");
+
+        metadata.forget();
+        assert_eq!(
+            metadata.timestamps_for(4),
+            None,
+            "forgotten timestamps stay forgotten"
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! The bounded queue behind [`crate::pool`]'s subscribers and
+//! [`crate::pipeline`]'s stages.
+//!
+//! [`Bounded`] only holds items and follows a [`DropPolicy`]. Waiting is up
+//! to its owner, which keeps it in a [`Signaled`] next to whatever else the
+//! waiters care about, like whether the other side is gone.
+
+extern crate alloc;
+
+use alloc::collections::VecDeque;
+use core::time::Duration;
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+
+/// What a full queue does when another item arrives.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Wait until the next stage takes an item. Nothing is lost, but slow
+    /// stages slow down everything before them.
+    #[default]
+    Block,
+    /// Throw away the item that just arrived.
+    DropNewest,
+    /// Throw away the oldest queued item, so the next stage always gets the
+    /// freshest ones.
+    DropOldest,
+}
+
+/// A queue of up to `capacity` items.
+#[derive(Debug)]
+pub(crate) struct Bounded<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+    policy: DropPolicy,
+    dropped: u64,
+}
+
+impl<T> Bounded<T> {
+    /// Makes an empty queue. A capacity of zero is treated as one.
+    pub(crate) fn new(capacity: usize, policy: DropPolicy) -> Self {
+        Self {
+            items: VecDeque::with_capacity(capacity.max(1)),
+            capacity: capacity.max(1),
+            policy,
+            dropped: 0,
+        }
+    }
+
+    /// Checks if a push would have to wait for room.
+    pub(crate) fn must_wait(&self) -> bool {
+        self.policy == DropPolicy::Block && self.items.len() >= self.capacity
+    }
+
+    /// Adds an item, following the drop policy if the queue is full.
+    ///
+    /// Gives the item back if the policy says to wait for room.
+    pub(crate) fn push(&mut self, item: T) -> Result<(), T> {
+        if self.items.len() >= self.capacity {
+            match self.policy {
+                DropPolicy::Block => return Err(item),
+                DropPolicy::DropNewest => {
+                    self.dropped = self.dropped.saturating_add(1);
+                    return Ok(());
+                }
+                DropPolicy::DropOldest => {
+                    self.items.pop_front();
+                    self.dropped = self.dropped.saturating_add(1);
+                }
+            }
+        }
+
+        self.items.push_back(item);
+        Ok(())
+    }
+
+    /// Takes the oldest item.
+    pub(crate) fn pop(&mut self) -> Option<T> {
+        self.items.pop_front()
+    }
+
+    /// Throws away every queued item, without counting them as dropped.
+    pub(crate) fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    /// How many items are waiting.
+    pub(crate) fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// How many items the policy threw away.
+    pub(crate) const fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+/// Some state, and a condvar that's signaled whenever it changes.
+#[derive(Debug, Default)]
+pub(crate) struct Signaled<S> {
+    state: Mutex<S>,
+    changed: Condvar,
+}
+
+impl<S> Signaled<S> {
+    pub(crate) const fn new(state: S) -> Self {
+        Self {
+            state: Mutex::new(state),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Locks the state, even if another thread panicked while holding it.
+    pub(crate) fn lock(&self) -> MutexGuard<'_, S> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Waits for the next change.
+    pub(crate) fn wait<'guard>(&self, guard: MutexGuard<'guard, S>) -> MutexGuard<'guard, S> {
+        self.changed
+            .wait(guard)
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Waits at most `timeout` for the next change.
+    pub(crate) fn wait_timeout<'guard>(
+        &self,
+        guard: MutexGuard<'guard, S>,
+        timeout: Duration,
+    ) -> MutexGuard<'guard, S> {
+        self.changed
+            .wait_timeout(guard, timeout)
+            .unwrap_or_else(PoisonError::into_inner)
+            .0
+    }
+
+    /// Wakes everyone who's waiting, so they can look again.
+    pub(crate) fn notify(&self) {
+        self.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocking_queues_give_items_back() {
+        let mut queue = Bounded::new(0, DropPolicy::Block);
+        assert_eq!(queue.push(1), Ok(()), "zero means one");
+        assert!(queue.must_wait());
+        assert_eq!(queue.push(2), Err(2), "there's no room");
+        assert_eq!(queue.dropped(), 0, "nothing was thrown away");
+
+        assert_eq!(queue.pop(), Some(1));
+        assert!(!queue.must_wait());
+    }
+}
@@ -0,0 +1,174 @@
+use core::cmp::Ordering;
+
+use super::{Format, Framerate, ResolutionSetting, VideoCaptureImageConfiguration};
+
+/// A description of the image configurations you'd be happy with, for when
+/// you don't know exactly what a device supports.
+///
+/// Use [`VideoCaptureImageConstraints::pick`] to choose one of a device's
+/// supported configurations.
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+pub struct VideoCaptureImageConstraints {
+    /// Formats to accept, from most to least preferred. When empty, any
+    /// format will do.
+    pub formats: Vec<Format>,
+    /// The resolution to aim for.
+    pub resolution: ResolutionSetting,
+    /// The slowest framerate to accept, if any.
+    pub min_framerate: Option<Framerate>,
+}
+
+impl Default for VideoCaptureImageConstraints {
+    /// Any format, at the highest resolution, at any framerate.
+    #[inline]
+    fn default() -> Self {
+        Self {
+            formats: Vec::new(),
+            resolution: ResolutionSetting::Highest,
+            min_framerate: None,
+        }
+    }
+}
+
+impl VideoCaptureImageConstraints {
+    /// Picks the best configuration that meets these constraints.
+    ///
+    /// Earlier formats win first, then the resolution setting decides, and
+    /// then faster framerates win. Returns `None` if nothing fits.
+    #[inline]
+    pub fn pick(
+        &self,
+        supported: &[VideoCaptureImageConfiguration],
+    ) -> Option<VideoCaptureImageConfiguration> {
+        supported
+            .iter()
+            .filter(|conf| self.accepts(conf))
+            .min_by(|a, b| {
+                self.format_rank(a.format)
+                    .cmp(&self.format_rank(b.format))
+                    .then_with(|| self.compare_resolutions(a, b))
+                    .then_with(|| {
+                        b.framerate
+                            .partial_cmp(&a.framerate)
+                            .unwrap_or(Ordering::Equal)
+                    })
+            })
+            .copied()
+    }
+
+    /// Checks if a configuration meets every constraint.
+    fn accepts(&self, conf: &VideoCaptureImageConfiguration) -> bool {
+        let format_ok = self.formats.is_empty() || self.formats.contains(&conf.format);
+        let framerate_ok = self.min_framerate.is_none_or(|min| conf.framerate >= min);
+        let resolution_ok = match self.resolution {
+            ResolutionSetting::Custom(exact) => conf.resolution == exact,
+            ResolutionSetting::Highest
+            | ResolutionSetting::Closest(_)
+            | ResolutionSetting::Lowest => true,
+        };
+
+        format_ok && framerate_ok && resolution_ok
+    }
+
+    /// Where a format sits in the preference list. Lower is better.
+    fn format_rank(&self, format: Format) -> usize {
+        self.formats
+            .iter()
+            .position(|&preferred| preferred == format)
+            .unwrap_or(0)
+    }
+
+    /// Orders two configurations by how well their resolutions fit. The
+    /// better one comes first.
+    fn compare_resolutions(
+        &self,
+        a: &VideoCaptureImageConfiguration,
+        b: &VideoCaptureImageConfiguration,
+    ) -> Ordering {
+        let area = |conf: &VideoCaptureImageConfiguration| {
+            u64::from(conf.resolution.width) * u64::from(conf.resolution.height)
+        };
+
+        match self.resolution {
+            ResolutionSetting::Highest => area(b).cmp(&area(a)),
+            ResolutionSetting::Lowest => area(a).cmp(&area(b)),
+            ResolutionSetting::Closest(target) => {
+                let distance = |conf: &VideoCaptureImageConfiguration| {
+                    let width = conf.resolution.width.abs_diff(target.width);
+                    width.saturating_add(conf.resolution.height.abs_diff(target.height))
+                };
+                distance(a).cmp(&distance(b))
+            }
+            ResolutionSetting::Custom(_) => Ordering::Equal,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{FramerateConsts as _, SpecificResolution};
+
+    use super::*;
+
+    fn supported() -> Vec<VideoCaptureImageConfiguration> {
+        [
+            "640x480@30 YUYV",
+            "1280x720@10 YUYV",
+            "640x480@30 MJPG",
+            "1280x720@30 MJPG",
+            "1280x720@60 MJPG",
+            "1920x1080@30 MJPG",
+        ]
+        .iter()
+        .map(|conf| conf.parse().unwrap())
+        .collect()
+    }
+
+    #[test]
+    fn constraints_pick_the_best_fit() {
+        let any = VideoCaptureImageConstraints::default();
+        assert_eq!(
+            any.pick(&supported()).unwrap().to_string(),
+            "1920x1080@30 MJPG",
+            "biggest by default"
+        );
+
+        let yuyv = VideoCaptureImageConstraints {
+            formats: vec![Format::YUYV, Format::MJPEG],
+            ..VideoCaptureImageConstraints::default()
+        };
+        assert_eq!(
+            yuyv.pick(&supported()).unwrap().to_string(),
+            "1280x720@10 YUYV",
+            "format order comes first"
+        );
+
+        let smooth = VideoCaptureImageConstraints {
+            formats: vec![Format::YUYV, Format::MJPEG],
+            resolution: ResolutionSetting::Closest(SpecificResolution::RES_16X9_720P),
+            min_framerate: Some(Framerate::FPS_30),
+        };
+        assert_eq!(
+            smooth.pick(&supported()).unwrap().to_string(),
+            "640x480@30 YUYV",
+            "slow configurations are skipped"
+        );
+
+        let exact = VideoCaptureImageConstraints {
+            formats: vec![Format::MJPEG],
+            resolution: ResolutionSetting::Custom(SpecificResolution::RES_16X9_720P),
+            min_framerate: None,
+        };
+        assert_eq!(
+            exact.pick(&supported()).unwrap().to_string(),
+            "1280x720@60 MJPG",
+            "faster framerates break ties"
+        );
+
+        let impossible = VideoCaptureImageConstraints {
+            min_framerate: Some(Framerate::FPS_60 * Framerate::from(2)),
+            ..VideoCaptureImageConstraints::default()
+        };
+        assert_eq!(impossible.pick(&supported()), None, "nothing fits");
+    }
+}
@@ -1,3 +1,10 @@
+use core::fmt::Display;
+use core::str::FromStr;
+
+use serumcv_image::PlanarLayout;
+
+use crate::error::VideoCaptureParseError as ParseError;
+
 /// A FourCC format.
 ///
 /// Consider using one of the pre-defined constants to get this type. They're
@@ -12,7 +19,13 @@ impl Format {
     /// will treat it like garbage.
     ///
     /// Consider using `MJPEG2000` instead if your uses support it.
-    pub const MJPEG: Self = Self(*b"MPEG");
+    pub const MJPEG: Self = Self(*b"MJPG");
+
+    /// Plain JPEG frames.
+    ///
+    /// Some drivers report this instead of `MJPEG`, but the frames are the
+    /// same.
+    pub const JPEG: Self = Self(*b"JPEG");
 
     /// A modern, efficient format that has low utilization.
     ///
@@ -39,6 +52,45 @@ impl Format {
     /// A modern, open format with high adoption and great efficiency.
     pub const AV1: Self = Self(*b"av10");
 
+    /// Packed YUV 4:2:2, ordered `Y0 U Y1 V`.
+    ///
+    /// This is the most common uncompressed format for webcams.
+    pub const YUYV: Self = Self(*b"YUYV");
+
+    /// Packed YUV 4:2:2, ordered `U Y0 V Y1`.
+    pub const UYVY: Self = Self(*b"UYVY");
+
+    /// 8-bit grayscale.
+    pub const GREY: Self = Self(*b"GREY");
+
+    /// 16-bit grayscale, in little-endian byte order.
+    pub const Y16: Self = Self(*b"Y16 ");
+
+    /// Packed 8-bit RGB.
+    pub const RGB24: Self = Self(*b"RGB3");
+
+    /// Packed 8-bit BGR.
+    pub const BGR24: Self = Self(*b"BGR3");
+
+    /// Planar YUV 4:2:0 with a Y plane, then an interleaved UV plane.
+    pub const NV12: Self = Self(*b"NV12");
+
+    /// Planar YUV 4:2:0 with a Y plane, then a U plane, then a V plane.
+    ///
+    /// Also known as I420.
+    pub const YUV420: Self = Self(*b"YU12");
+
+    /// Like `YUV420`, but the V plane comes before the U plane.
+    pub const YVU420: Self = Self(*b"YV12");
+
+    /// `NV12`, with each plane in its own buffer. Only multi-planar devices
+    /// use this.
+    pub const NV12M: Self = Self(*b"NM12");
+
+    /// `YUV420`, with each plane in its own buffer. Only multi-planar devices
+    /// use this.
+    pub const YUV420M: Self = Self(*b"YM12");
+
     /// Creates a new FourCC format identifier.
     ///
     /// Note that input isn't checked with any database. Consider using the
@@ -61,6 +113,119 @@ impl Format {
     pub const fn array(self) -> [u8; 4] {
         self.0
     }
+
+    /// The number of bytes each pixel takes up, for packed, uncompressed
+    /// formats.
+    ///
+    /// Returns `None` for compressed, planar, and unknown formats.
+    #[inline]
+    pub const fn bytes_per_pixel(self) -> Option<usize> {
+        match &self.0 {
+            b"GREY" => Some(1),
+            b"YUYV" | b"UYVY" | b"Y16 " => Some(2),
+            b"RGB3" | b"BGR3" => Some(3),
+            _ => None,
+        }
+    }
+
+    /// The plane layout of planar formats.
+    ///
+    /// Returns `None` for packed, compressed, and unknown formats.
+    #[inline]
+    pub const fn planar_layout(self) -> Option<PlanarLayout> {
+        match &self.0 {
+            b"NV12" | b"NM12" => Some(PlanarLayout::Nv12),
+            b"NV21" | b"NM21" => Some(PlanarLayout::Nv21),
+            b"YU12" | b"YM12" => Some(PlanarLayout::I420),
+            b"YV12" | b"YM21" => Some(PlanarLayout::Yv12),
+            b"422P" => Some(PlanarLayout::Yuv422p),
+            _ => None,
+        }
+    }
+}
+
+/// Formats display as their FourCC code, like `MJPG`. Trailing spaces are
+/// left off, so `Y16 ` displays as `Y16`.
+///
+/// Codes that can't be written that way, like ones with control bytes,
+/// display as their 32-bit V4L2 value in hex instead, like `0x0000000A`.
+/// Either form parses back into the same format.
+impl Display for Format {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let code = self.trimmed();
+        if code.is_empty() || !code.iter().all(u8::is_ascii_graphic) {
+            return write!(f, "0x{:08X}", u32::from_le_bytes(self.0));
+        }
+
+        for &byte in code {
+            f.write_fmt(format_args!("{}", char::from(byte)))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Format {
+    /// The FourCC code without its trailing spaces.
+    fn trimmed(&self) -> &[u8] {
+        let len = self
+            .0
+            .iter()
+            .rposition(|&byte| byte != b' ')
+            .map_or(0, |last| last + 1);
+
+        self.0.get(..len).unwrap_or(&self.0)
+    }
+}
+
+/// Parses a FourCC code, like `MJPG` or `YUYV`, or its 32-bit V4L2 value in
+/// hex, like `0x47504A4D`.
+///
+/// Codes shorter than four characters are padded with spaces, so `Y16`
+/// becomes `Y16 `.
+impl FromStr for Format {
+    type Err = ParseError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseError::InvalidFormat {
+            input: s.to_owned(),
+        };
+
+        if let Some(hex) = s.strip_prefix("0x").filter(|hex| hex.len() == 8) {
+            let value = u32::from_str_radix(hex, 16).map_err(|_parse| err())?;
+            return Ok(Self(value.to_le_bytes()));
+        }
+
+        if s.is_empty() || s.len() > 4 || !s.bytes().all(|byte| byte.is_ascii_graphic()) {
+            return Err(err());
+        }
+
+        let mut fourcc = [b' '; 4];
+        for (slot, byte) in fourcc.iter_mut().zip(s.bytes()) {
+            *slot = byte;
+        }
+
+        Ok(Self(fourcc))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Format {
+    #[inline]
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Format {
+    #[inline]
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let fourcc = <String as serde::Deserialize>::deserialize(deserializer)?;
+        fourcc.parse().map_err(serde::de::Error::custom)
+    }
 }
 
 #[cfg_attr(
@@ -73,3 +238,30 @@ impl From<v4l::Format> for Format {
         Self(value.fourcc.repr)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unprintable_codes_round_trip_as_hex() {
+        let odd = Format::new([b'A', 0x0A, 0xFF, b' ']);
+        assert_eq!(odd.to_string(), "0x20FF0A41", "little-endian, like V4L2");
+        assert_eq!("0x20FF0A41".parse(), Ok(odd), "and back");
+
+        let blank = Format::new(*b"    ");
+        assert_eq!(blank.to_string().parse(), Ok(blank), "all spaces");
+
+        assert_eq!(
+            Format::MJPEG.to_string(),
+            "MJPG",
+            "printable codes stay short"
+        );
+        assert_eq!(
+            "0x4D4A5047".parse(),
+            Ok(Format::new(*b"GPJM")),
+            "hex works for printable codes too"
+        );
+        assert!("0xZZZZZZZZ".parse::<Format>().is_err(), "not hex");
+    }
+}
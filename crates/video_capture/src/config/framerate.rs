@@ -1,5 +1,7 @@
 use fraction::Fraction;
 
+use crate::error::VideoCaptureParseError as ParseError;
+
 /// A framerate value.
 ///
 /// This uses the `fraction::Fraction` type underneath, so it is accurate enough
@@ -35,3 +37,64 @@ pub trait FramerateConsts {
     const FPS_7: Fraction = Fraction::new_raw(7, 1);
     const FPS_5: Fraction = Fraction::new_raw(5, 1);
 }
+
+/// Parses a framerate, like `30`, `29.97`, or `30000/1001`.
+///
+/// `Framerate` is an alias for a type from another crate, so it can't
+/// implement `FromStr` itself. Unlike `Fraction`'s own parser, this rejects
+/// zero denominators instead of panicking.
+///
+/// # Errors
+///
+/// This fails if the input isn't a positive number or fraction.
+#[inline]
+pub fn parse_framerate(input: &str) -> Result<Framerate, ParseError> {
+    let err = || ParseError::InvalidFramerate {
+        input: input.to_owned(),
+    };
+
+    let is_decimal = input
+        .bytes()
+        .all(|byte| byte.is_ascii_digit() || byte == b'.');
+    let rate = match input.split_once('/') {
+        Some((numer_str, denom_str)) => {
+            let numer: u64 = numer_str.parse().ok().ok_or_else(err)?;
+            let denom: u64 = denom_str.parse().ok().ok_or_else(err)?;
+            if denom == 0 {
+                return Err(err());
+            }
+            Fraction::new(numer, denom)
+        }
+        None if is_decimal => input.parse().ok().ok_or_else(err)?,
+        None => return Err(err()),
+    };
+
+    if rate <= Fraction::from(0) {
+        return Err(err());
+    }
+
+    Ok(rate)
+}
+
+/// Stores framerates as strings, like `"30000/1001"`.
+///
+/// Use this with `#[serde(with = "...")]`. Framerates are fractions, and
+/// fractions don't look very nice in config files otherwise.
+#[cfg(feature = "serde")]
+pub(super) mod framerate_as_string {
+    use super::{parse_framerate, Framerate};
+
+    pub(in crate::config) fn serialize<S: serde::Serializer>(
+        rate: &Framerate,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(rate)
+    }
+
+    pub(in crate::config) fn deserialize<'de, D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Framerate, D::Error> {
+        let rate = <String as serde::Deserialize>::deserialize(deserializer)?;
+        parse_framerate(&rate).map_err(serde::de::Error::custom)
+    }
+}
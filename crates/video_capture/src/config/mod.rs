@@ -1,14 +1,22 @@
+mod constraints;
 mod format;
 mod framerate;
+mod profile;
 mod properties;
 mod resolution;
 
 pub use format::Format;
 
-use crate::error::VideoCaptureConfigError as ConfigError;
+use core::fmt::Display;
+use core::str::FromStr;
+use core::time::Duration;
+
+use crate::error::{VideoCaptureConfigError as ConfigError, VideoCaptureParseError as ParseError};
 
 // re-exports
-pub use framerate::{Framerate, FramerateConsts};
+pub use constraints::VideoCaptureImageConstraints;
+pub use framerate::{parse_framerate, Framerate, FramerateConsts};
+pub use profile::{find_profile, Orientation, VideoCaptureProfile, VideoCaptureProfiles};
 pub use properties::{VideoCaptureProperties, VideoCaptureProperty};
 pub use resolution::{ResolutionSetting, SpecificResolution};
 
@@ -32,6 +40,9 @@ pub trait VideoCaptureConfiguration {
     /// Sets the device's image configuration given the input. It will then
     /// return the format the device is now using afterwards.
     ///
+    /// Most drivers can't change formats while buffers are allocated, so a
+    /// streaming device is stopped, reconfigured, and started again.
+    ///
     /// # Errors
     ///
     /// This can fail if the device isn't connected, is being used by another
@@ -39,7 +50,7 @@ pub trait VideoCaptureConfiguration {
     #[must_use = "The capture device may have used another image configuration
     that does not match the input. Consider checking the output config before continuing."]
     fn set_image_configuration(
-        &self,
+        &mut self,
         conf: &ImageConfiguration,
     ) -> Result<ImageConfiguration, ConfigError>;
 }
@@ -52,15 +63,155 @@ type ImageConfiguration = VideoCaptureImageConfiguration;
 /// The fields of this struct are all public. Create a new img. conf. using
 /// manual struct construction syntax.
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VideoCaptureImageConfiguration {
     pub format: Format,
     pub resolution: SpecificResolution,
+    #[cfg_attr(feature = "serde", serde(with = "framerate::framerate_as_string"))]
     pub framerate: Framerate,
 }
 
+/// Image configurations display as `RESOLUTION@FRAMERATE FORMAT`, like
+/// `1920x1080@30000/1001 MJPG`.
+impl Display for VideoCaptureImageConfiguration {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_fmt(format_args!(
+            "{}@{} {}",
+            self.resolution, self.framerate, self.format
+        ))
+    }
+}
+
+impl FromStr for VideoCaptureImageConfiguration {
+    type Err = ParseError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mode, format) =
+            s.trim()
+                .split_once(' ')
+                .ok_or_else(|| ParseError::InvalidImageConfiguration {
+                    input: s.to_owned(),
+                })?;
+        let (resolution, framerate) =
+            mode.split_once('@')
+                .ok_or_else(|| ParseError::InvalidImageConfiguration {
+                    input: s.to_owned(),
+                })?;
+
+        Ok(Self {
+            format: format.trim().parse()?,
+            resolution: resolution.parse()?,
+            framerate: parse_framerate(framerate)?,
+        })
+    }
+}
+
+/// What happened when a device changed its image configuration.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct VideoCaptureReconfiguration {
+    /// The image configuration that the device chose.
+    pub configuration: VideoCaptureImageConfiguration,
+    /// How long the switch took, from stopping the stream to starting it
+    /// again.
+    pub switch_time: Duration,
+    /// Whether the device was streaming, and had to be restarted.
+    pub restarted: bool,
+}
+
 // #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
 // pub struct VideoCaptureConfig<Props: Properties> {
 //     resolution: ResolutionSetting,
 //     format: Format,
 //     properties: Props,
 // }
+
+#[cfg(test)]
+mod tests {
+    use fraction::Fraction;
+
+    use super::*;
+
+    #[test]
+    fn image_configurations_round_trip_through_strings() {
+        let conf: ImageConfiguration = "1920x1080@30000/1001 MJPG".parse().unwrap();
+        assert_eq!(
+            conf,
+            ImageConfiguration {
+                format: Format::MJPEG,
+                resolution: SpecificResolution::RES_16X9_1080P,
+                framerate: Fraction::new(30000_u64, 1001_u64),
+            },
+            "parsed fields"
+        );
+        assert_eq!(conf.to_string(), "1920x1080@30000/1001 MJPG", "display");
+
+        let short: ImageConfiguration = "640x480@30 Y16".parse().unwrap();
+        assert_eq!(short.format, Format::Y16, "short fourccs get padded");
+        assert_eq!(short.to_string(), "640x480@30 Y16", "and trimmed again");
+
+        assert!(
+            "640x480@30/0 MJPG".parse::<ImageConfiguration>().is_err(),
+            "zero denominators aren't framerates"
+        );
+        assert!(
+            "640x480 MJPG".parse::<ImageConfiguration>().is_err(),
+            "framerate is required"
+        );
+    }
+
+    #[test]
+    fn settings_and_properties_parse() {
+        assert_eq!(
+            "~1280x720".parse(),
+            Ok(ResolutionSetting::Closest(
+                SpecificResolution::RES_16X9_720P
+            )),
+            "closest"
+        );
+        assert_eq!("lowest".parse(), Ok(ResolutionSetting::Lowest), "lowest");
+
+        let prop: VideoCaptureProperty = "exposure_auto = 1".parse().unwrap();
+        assert_eq!(
+            (prop.key(), prop.value()),
+            ("exposure_auto".into(), "1".into())
+        );
+        assert_eq!(prop.to_string(), "exposure_auto=1", "display");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn image_configurations_round_trip_through_json() {
+        let conf: ImageConfiguration = "1280x720@60 YUYV".parse().unwrap();
+        let json = serde_json::to_string(&conf).unwrap();
+        assert_eq!(
+            json, r#"{"format":"YUYV","resolution":{"width":1280,"height":720},"framerate":"60"}"#,
+            "compact json"
+        );
+        assert_eq!(
+            serde_json::from_str::<ImageConfiguration>(&json).unwrap(),
+            conf
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn image_configurations_round_trip_through_toml() {
+        let conf: ImageConfiguration = "640x480@30000/1001 Y16".parse().unwrap();
+        let toml = toml::to_string(&conf).unwrap();
+        assert_eq!(
+            toml, "format = \"Y16\"\nframerate = \"30000/1001\"\n\n[resolution]\nwidth = 640\nheight = 480\n",
+            "compact toml"
+        );
+        assert_eq!(toml::from_str::<ImageConfiguration>(&toml).unwrap(), conf);
+
+        // codes that aren't printable still come back exactly
+        let odd = ImageConfiguration {
+            format: Format::new([0x01, 0x02, 0x03, 0x04]),
+            ..conf
+        };
+        let toml = toml::to_string(&odd).unwrap();
+        assert_eq!(toml::from_str::<ImageConfiguration>(&toml).unwrap(), odd);
+    }
+}
@@ -0,0 +1,167 @@
+//! Per-device profiles that are applied when a device connects.
+
+use serumcv_geometry::calib::CameraCalibration;
+
+use crate::error::VideoCaptureConfigError as ConfigError;
+
+use super::{VideoCaptureImageConfiguration, VideoCaptureProperty};
+
+/// Everything we want a specific capture device to look like.
+///
+/// Hand a list of profiles to a backend's builder. Whichever one
+/// [`find_profile`] picks for the device is applied when it connects, and
+/// again whenever it reconnects.
+#[derive(Clone, Debug, Default, PartialEq, PartialOrd)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct VideoCaptureProfile {
+    /// The unique identifier (usually a serial number) of the device this
+    /// profile is for.
+    ///
+    /// When this is empty, the profile matches every device with the same
+    /// model.
+    pub device_identifier: String,
+    /// The model of the device this profile is for.
+    pub device_model: String,
+    /// The image configuration to use, if any.
+    pub image_configuration: Option<VideoCaptureImageConfiguration>,
+    /// Properties to set, in order.
+    ///
+    /// Order matters for some devices. For example, auto exposure usually
+    /// needs to be turned off before a manual exposure value sticks.
+    pub properties: Vec<VideoCaptureProperty>,
+    /// How the device is mounted.
+    pub orientation: Orientation,
+    /// The camera's calibration, if it's been calibrated.
+    ///
+    /// This is just stored alongside the rest of the profile. Applying a
+    /// profile doesn't use it.
+    pub calibration: Option<CameraCalibration>,
+    /// Anything else worth knowing about this device.
+    pub notes: String,
+}
+
+impl VideoCaptureProfile {
+    /// Creates an empty profile for the given device.
+    #[inline]
+    pub const fn new(device_identifier: String, device_model: String) -> Self {
+        Self {
+            device_identifier,
+            device_model,
+            image_configuration: None,
+            properties: Vec::new(),
+            orientation: Orientation::Normal,
+            calibration: None,
+            notes: String::new(),
+        }
+    }
+
+    /// Checks if this profile is meant for the given device.
+    #[inline]
+    pub fn matches(&self, device_identifier: &str, device_model: &str) -> bool {
+        self.device_model == device_model
+            && (self.device_identifier.is_empty() || self.device_identifier == device_identifier)
+    }
+}
+
+/// How a capture device is physically mounted.
+///
+/// This is just a note for whoever reads the frames. Devices don't rotate
+/// their frames on their own.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Orientation {
+    /// Upright.
+    #[default]
+    Normal,
+    /// Rotated 90 degrees clockwise.
+    Rotate90,
+    /// Upside down.
+    Rotate180,
+    /// Rotated 90 degrees counterclockwise.
+    Rotate270,
+    /// Mirrored left-to-right.
+    FlipHorizontal,
+    /// Mirrored top-to-bottom.
+    FlipVertical,
+}
+
+/// Methods to apply and export device profiles.
+pub trait VideoCaptureProfiles {
+    /// Applies a profile to this device.
+    ///
+    /// Devices usually can't change their image configuration while they're
+    /// streaming, so that part may fail on a running device.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the device refuses the image configuration or any of
+    /// the properties.
+    fn apply_profile(&mut self, profile: &VideoCaptureProfile) -> Result<(), ConfigError>;
+
+    /// Makes a profile from this device's current settings.
+    ///
+    /// The orientation and notes come from the last profile applied to the
+    /// device, if there was one.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the device doesn't respond.
+    fn export_profile(&self) -> Result<VideoCaptureProfile, ConfigError>;
+
+    /// The last profile applied to this device.
+    fn profile(&self) -> Option<&VideoCaptureProfile>;
+}
+
+/// Finds the profile for a device in a list of them.
+///
+/// Profiles for the exact device win over profiles for its whole model.
+#[inline]
+pub fn find_profile<'profiles>(
+    profiles: &'profiles [VideoCaptureProfile],
+    device_identifier: &str,
+    device_model: &str,
+) -> Option<&'profiles VideoCaptureProfile> {
+    profiles
+        .iter()
+        .filter(|profile| profile.matches(device_identifier, device_model))
+        .max_by_key(|profile| !profile.device_identifier.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_profiles_beat_model_profiles() {
+        let model = "profile-test-cam";
+        let mut for_model = VideoCaptureProfile::new(String::new(), model.into());
+        for_model.notes = "every unit".into();
+        let mut for_unit = VideoCaptureProfile::new("SN123".into(), model.into());
+        for_unit.notes = "just this one".into();
+
+        let profiles = [for_unit, for_model];
+
+        let notes = |serial: &str| {
+            find_profile(&profiles, serial, model).map(|profile| profile.notes.as_str())
+        };
+        assert_eq!(notes("SN123"), Some("just this one"), "exact");
+        assert_eq!(notes("SN999"), Some("every unit"), "model");
+        assert_eq!(
+            find_profile(&profiles, "SN123", "other-cam"),
+            None,
+            "wrong model"
+        );
+
+        let (_, rest) = profiles.split_at(1);
+        assert_eq!(
+            find_profile(rest, "SN123", model).map(|profile| profile.notes.as_str()),
+            Some("every unit"),
+            "falls back"
+        );
+    }
+}
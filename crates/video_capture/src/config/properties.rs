@@ -1,4 +1,7 @@
-use crate::error::VideoCaptureConfigError as ConfigError;
+use core::fmt::Display;
+use core::str::FromStr;
+
+use crate::error::{VideoCaptureConfigError as ConfigError, VideoCaptureParseError as ParseError};
 
 pub trait VideoCaptureProperties {
     /// Lists the properties available on this device.
@@ -14,12 +17,13 @@ pub trait VideoCaptureProperties {
     /// disconnected. (TODO: Result if the device isn't connected?)
     fn property(&self, key: PropertyKeyType) -> Option<Property>;
 
-    /// Attempts to set a property.
+    /// Attempts to set a property to the given value.
     ///
     /// # Errors
     ///
-    /// This can return an error
-    fn set_property(&mut self) -> Result<(), ConfigError>;
+    /// This can return an error if the device doesn't have the property, the
+    /// value doesn't make sense for it, or the device refuses to change it.
+    fn set_property(&mut self, property: &Property) -> Result<(), ConfigError>;
 }
 
 type PropertyKeyType = String;
@@ -29,6 +33,7 @@ type Property = VideoCaptureProperty;
 
 /// One of many adjustable properties that a video capture device has.
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VideoCaptureProperty {
     key: PropertyKeyType,
     value: PropertyValueType,
@@ -54,3 +59,28 @@ impl Property {
         self.value.clone()
     }
 }
+
+/// Properties display as `key=value`.
+impl Display for Property {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_fmt(format_args!("{}={}", self.key, self.value))
+    }
+}
+
+/// Parses a `key=value` pair. Everything after the first `=` is the value.
+impl FromStr for Property {
+    type Err = ParseError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                Ok(Self::new(key.trim().to_owned(), value.trim()))
+            }
+            _ => Err(ParseError::InvalidProperty {
+                input: s.to_owned(),
+            }),
+        }
+    }
+}
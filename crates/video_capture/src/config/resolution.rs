@@ -1,8 +1,14 @@
 use core::fmt::Display;
+use core::str::FromStr;
+
+use serumcv_geometry::Resolution;
+
+use crate::error::VideoCaptureParseError as ParseError;
 
 /// A video capture device's resolution setting.
-#[allow(clippy::exhaustive_enums)]
+#[expect(clippy::exhaustive_enums)]
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ResolutionSetting {
     /// Will use the highest possible resolution for the device.
     Highest,
@@ -14,18 +20,32 @@ pub enum ResolutionSetting {
     Lowest,
 }
 
+/// Resolution settings display as `highest`, `lowest`, `1920x1080` (custom),
+/// or `~1920x1080` (closest).
 impl Display for ResolutionSetting {
     #[inline]
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match *self {
-            Self::Highest => f.write_str("ResolutionSetting::Highest"),
-            Self::Custom(ref custom) => {
-                f.write_fmt(format_args!("ResolutionSetting::Custom({custom})"))
-            }
-            Self::Closest(ref custom) => {
-                f.write_fmt(format_args!("ResolutionSetting::Closest({custom})"))
-            }
-            Self::Lowest => f.write_str("ResolutionSetting::Lowest"),
+            Self::Highest => f.write_str("highest"),
+            Self::Custom(ref custom) => f.write_fmt(format_args!("{custom}")),
+            Self::Closest(ref custom) => f.write_fmt(format_args!("~{custom}")),
+            Self::Lowest => f.write_str("lowest"),
+        }
+    }
+}
+
+impl FromStr for ResolutionSetting {
+    type Err = ParseError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "highest" => Ok(Self::Highest),
+            "lowest" => Ok(Self::Lowest),
+            _ => s.strip_prefix('~').map_or_else(
+                || s.parse().map(Self::Custom),
+                |closest| closest.parse().map(Self::Closest),
+            ),
         }
     }
 }
@@ -38,6 +58,7 @@ impl Display for ResolutionSetting {
 /// Consider using one of the common constants instead of constructing a
 /// `SpecificResolution` manually.
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SpecificResolution {
     pub width: u32,
     pub height: u32,
@@ -65,9 +86,9 @@ impl SpecificResolution {
     }
 }
 
-#[allow(
+#[expect(
     clippy::from_over_into,
-    // reason = "You cannot convert a fraction into a resolution as the `fraction` crate automatically simplifies representations."
+    reason = "You cannot convert a fraction into a resolution as the `fraction` crate automatically simplifies representations."
 )]
 impl Into<fraction::Fraction> for SpecificResolution {
     #[inline]
@@ -76,13 +97,44 @@ impl Into<fraction::Fraction> for SpecificResolution {
     }
 }
 
+/// Camera models and calibrations use their own resolution type, so they
+/// don't depend on a capture device.
+impl From<SpecificResolution> for Resolution {
+    #[inline]
+    fn from(resolution: SpecificResolution) -> Self {
+        Self::new(resolution.width, resolution.height)
+    }
+}
+
+impl From<Resolution> for SpecificResolution {
+    #[inline]
+    fn from(resolution: Resolution) -> Self {
+        Self::new(resolution.width, resolution.height)
+    }
+}
+
+/// Resolutions display as `WIDTHxHEIGHT`, like `1920x1080`.
 impl Display for SpecificResolution {
     #[inline]
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.write_fmt(format_args!(
-            "SpecificResolution({} x {})",
-            self.width, self.height
-        ))
+        f.write_fmt(format_args!("{}x{}", self.width, self.height))
+    }
+}
+
+impl FromStr for SpecificResolution {
+    type Err = ParseError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseError::InvalidResolution {
+            input: s.to_owned(),
+        };
+
+        let (width, height) = s.split_once('x').ok_or_else(err)?;
+        Ok(Self {
+            width: width.parse().ok().ok_or_else(err)?,
+            height: height.parse().ok().ok_or_else(err)?,
+        })
     }
 }
 
@@ -1,9 +1,14 @@
 //! Errors for video capture devices and their surrounding operations.
 
+use core::error::Error;
 use pisserror::Error;
-use std::error::Error;
 
-use crate::config::{Format, ResolutionSetting, VideoCaptureImageConfiguration};
+use serumcv_image::error::ImageError;
+
+use crate::config::{
+    Format, ResolutionSetting, SpecificResolution, VideoCaptureImageConfiguration,
+};
+use crate::frame::FrameCorruption;
 
 /// An error that occurs when attempting to first access a system video capture
 /// device.
@@ -32,6 +37,14 @@ pub enum VideoCaptureConnectionError {
     #[error("The capture device with source `{source}` is busy. I/O error: `{err_msg}`")]
     CaptureDeviceBusy { source: String, err_msg: String },
 
+    /// The device is gone, usually because it was unplugged.
+    #[error("The capture device at `{source}` was disconnected. (errno `{errno}`)")]
+    Disconnected { source: String, errno: i32 },
+
+    /// The device didn't answer in time.
+    #[error("The capture device at `{source}` didn't respond in time. (errno `{errno}`)")]
+    Timeout { source: String, errno: i32 },
+
     /// Display this when we couldn't ask the device to stop.
     #[error("Failed to stop the device gracefully. Source: `{source}`, error: `{err_msg}`")]
     StopError { source: String, err_msg: String },
@@ -64,14 +77,176 @@ pub enum VideoCaptureConnectionError {
     /// This means that the device gave an error when we tried to first read from it.
     #[error("Failed to warm up device at `{source}`. See: `{err_msg}`")]
     WarmUpFailed { source: String, err_msg: String },
+
+    /// The device matched one of its profiles, but we couldn't apply it.
+    #[error("Failed to apply the profile for the device at `{source}`. See: `{err_msg}`")]
+    CouldntApplyProfile { source: String, err_msg: String },
+
+    /// The device doesn't have a metadata node to pair with its video node.
+    #[error("The capture device at `{source}` has no metadata node.")]
+    NoMetadataNode { source: String },
+
+    /// The device opened, but wouldn't take the configuration that it was
+    /// opened with.
+    #[error("Failed to configure the device at `{source}` before streaming. See: `{err_msg}`")]
+    CouldntConfigure { source: String, err_msg: String },
+
+    /// The device has a metadata node, but we couldn't stream from it.
+    #[error("Failed to open the metadata node at `{node}` for the device at `{source}`. See: `{err_msg}`")]
+    CouldntOpenMetadata {
+        source: String,
+        node: String,
+        err_msg: String,
+    },
+
+    /// The device exists, but it can't take frames from us.
+    #[error("The device at `{source}` isn't a video output device.")]
+    NotAnOutputDevice { source: String },
+}
+
+impl VideoCaptureConnectionError {
+    /// Checks if trying again later might work, without anyone touching the
+    /// device.
+    #[inline]
+    pub const fn is_transient(&self) -> bool {
+        match *self {
+            Self::CaptureDeviceBusy { .. } | Self::Timeout { .. } => true,
+            Self::OddIOError { err_kind, .. } => matches!(
+                err_kind,
+                std::io::ErrorKind::WouldBlock
+                    | std::io::ErrorKind::Interrupted
+                    | std::io::ErrorKind::TimedOut
+            ),
+            _ => false,
+        }
+    }
+
+    /// Checks if the device is gone, like after being unplugged.
+    #[inline]
+    pub const fn is_disconnect(&self) -> bool {
+        match *self {
+            Self::SourceDoesntExist { .. } | Self::Disconnected { .. } => true,
+            Self::OddIOError { err_kind, .. } => {
+                matches!(err_kind, std::io::ErrorKind::NotFound)
+            }
+            _ => false,
+        }
+    }
+
+    /// The OS's raw error number, if the error came from the OS.
+    #[inline]
+    pub const fn errno(&self) -> Option<i32> {
+        match *self {
+            Self::Disconnected { errno, .. } | Self::Timeout { errno, .. } => Some(errno),
+            _ => None,
+        }
+    }
 }
 
 /// An error that occurs when we fail to read from a capture device.
+///
+/// Variants that come from the OS keep its raw error number in `errno`.
 #[derive(Clone, Debug, Error, PartialEq, PartialOrd)]
 #[non_exhaustive]
 pub enum VideoCaptureUsageError {
+    /// Any other failure. Prefer the more specific variants below.
     #[error("IO error when attempting to access data from device at `{source}`: `{err_msg}`")]
     IoError { source: String, err_msg: String },
+
+    /// The device didn't have a frame ready in time.
+    #[error("The device at `{source}` didn't have a frame ready in time. (errno `{errno}`)")]
+    Timeout { source: String, errno: i32 },
+
+    /// The device is gone, usually because it was unplugged.
+    #[error("The device at `{source}` was disconnected. (errno `{errno}`)")]
+    Disconnected { source: String, errno: i32 },
+
+    /// The device sent a frame, but its data can't be trusted.
+    #[error("The device at `{source}` gave a corrupt frame: {corruption}.")]
+    CorruptFrame {
+        source: String,
+        corruption: FrameCorruption,
+    },
+
+    /// Every frame was corrupt for too long while skipping them. See
+    /// [`CorruptFramePolicy::MAX_SKIPS`](crate::frame::CorruptFramePolicy::MAX_SKIPS).
+    #[error(
+        "The device at `{source}` gave `{skipped}` corrupt frames in a row. The last one: {last}."
+    )]
+    TooManyCorruptFrames {
+        source: String,
+        skipped: u32,
+        last: FrameCorruption,
+    },
+
+    /// Frames were requested from a device that isn't streaming.
+    #[error("The device at `{source}` isn't streaming. (errno `{errno}`)")]
+    NotStreaming { source: String, errno: i32 },
+
+    /// The OS won't let us use the device.
+    #[error("Permission to use the device at `{source}` was denied. (errno `{errno}`)")]
+    PermissionDenied { source: String, errno: i32 },
+
+    /// Someone else is using the device, like another program streaming
+    /// from it.
+    #[error("The device at `{source}` is busy. (errno `{errno}`)")]
+    DeviceBusy { source: String, errno: i32 },
+
+    /// An output device was given a frame that doesn't match its image
+    /// configuration.
+    #[error("The output device at `{source}` takes `{expected_format}` frames at `{expected_resolution}`, but got a `{format}` frame at `{resolution}`.")]
+    FrameDoesntMatchOutput {
+        source: String,
+        expected_format: Format,
+        expected_resolution: SpecificResolution,
+        format: Format,
+        resolution: SpecificResolution,
+    },
+
+    /// An output device was given a frame that's too big for its buffers.
+    #[error("The output device at `{source}` has room for `{capacity}` bytes, but the frame needs `{size}`.")]
+    FrameTooLarge {
+        source: String,
+        size: usize,
+        capacity: usize,
+    },
+}
+
+impl VideoCaptureUsageError {
+    /// Checks if the next read might work, without anyone touching the
+    /// device.
+    ///
+    /// Timeouts and corrupt frames happen now and then, even on healthy
+    /// devices.
+    #[inline]
+    pub const fn is_transient(&self) -> bool {
+        matches!(*self, Self::Timeout { .. } | Self::CorruptFrame { .. })
+    }
+
+    /// Checks if the device is gone, like after being unplugged.
+    ///
+    /// Reconnecting is the only way to recover from these.
+    #[inline]
+    pub const fn is_disconnect(&self) -> bool {
+        matches!(*self, Self::Disconnected { .. })
+    }
+
+    /// The OS's raw error number, if the error came from the OS.
+    #[inline]
+    pub const fn errno(&self) -> Option<i32> {
+        match *self {
+            Self::Timeout { errno, .. }
+            | Self::Disconnected { errno, .. }
+            | Self::NotStreaming { errno, .. }
+            | Self::PermissionDenied { errno, .. }
+            | Self::DeviceBusy { errno, .. }
+            | Self::CorruptFrame {
+                corruption: FrameCorruption::DriverError { errno },
+                ..
+            } => Some(errno),
+            _ => None,
+        }
+    }
 }
 
 /// An error that occurs when configuring a video capture device.
@@ -88,6 +263,13 @@ pub enum VideoCaptureConfigError {
     #[error("Failed to write property to device with source `{source}`. See: `{err_msg}`")]
     PropertyWriteFailure { source: String, err_msg: String },
 
+    #[error("The capture device at `{source}` can't set its `{property_name}` property to `{value}`.")]
+    InvalidPropertyValue {
+        source: String,
+        property_name: String,
+        value: String,
+    },
+
     #[error("The capture device at `{source}` cannot use the given image configuration: {image_conf:?}")]
     UnsupportedImageConfiguration {
         source: String,
@@ -112,5 +294,211 @@ pub enum VideoCaptureConfigError {
     DeviceDoesntListConfigurations {
         source: String,
         err_msg: String,
+    },
+
+    /// None of the device's image configurations met the constraints.
+    #[error("The capture device at `{source}` has no image configuration that meets the constraints.")]
+    NoMatchingConfiguration { source: String },
+
+    /// The device can't be changed right now, usually because it's
+    /// streaming.
+    #[error("The capture device at `{source}` is busy. (errno `{errno}`)")]
+    DeviceBusy { source: String, errno: i32 },
+
+    /// The device is gone, usually because it was unplugged.
+    #[error("The capture device at `{source}` was disconnected. (errno `{errno}`)")]
+    Disconnected { source: String, errno: i32 },
+
+    /// The device didn't answer in time.
+    #[error("The capture device at `{source}` didn't respond in time. (errno `{errno}`)")]
+    Timeout { source: String, errno: i32 },
+
+    /// The device had to stop streaming before it could be reconfigured, but
+    /// it wouldn't.
+    #[error("Failed to stop the stream at `{source}` before reconfiguring it. See: `{err_msg}`")]
+    CouldntStopStream { source: String, err_msg: String },
+
+    /// The device stopped streaming to be reconfigured, but wouldn't start
+    /// again.
+    #[error("Failed to restart the stream at `{source}` after reconfiguring it. See: `{err_msg}`")]
+    CouldntRestartStream { source: String, err_msg: String },
+}
+
+impl VideoCaptureConfigError {
+    /// Checks if trying again later might work, without anyone touching the
+    /// device.
+    #[inline]
+    pub const fn is_transient(&self) -> bool {
+        matches!(*self, Self::DeviceBusy { .. } | Self::Timeout { .. })
+    }
+
+    /// Checks if the device is gone, like after being unplugged.
+    #[inline]
+    pub const fn is_disconnect(&self) -> bool {
+        matches!(*self, Self::Disconnected { .. })
+    }
+
+    /// The OS's raw error number, if the error came from the OS.
+    #[inline]
+    pub const fn errno(&self) -> Option<i32> {
+        match *self {
+            Self::DeviceBusy { errno, .. }
+            | Self::Disconnected { errno, .. }
+            | Self::Timeout { errno, .. } => Some(errno),
+            _ => None,
+        }
     }
 }
+
+/// An error that occurs when looking at a captured frame as an image.
+#[derive(Clone, Debug, Error, PartialEq, PartialOrd)]
+#[non_exhaustive]
+#[rustfmt::skip]
+pub enum VideoCaptureFrameError {
+    #[error("Frames in the `{format}` format aren't packed pixels, so they can't be viewed directly. Try decoding them first.")]
+    NotPacked { format: Format },
+
+    #[error("Frames in the `{format}` format aren't planar.")]
+    NotPlanar { format: Format },
+
+    #[error("Frames in the `{format}` format have `{format_bytes}` bytes per pixel, but the requested pixel type has `{pixel_bytes}`.")]
+    PixelSizeMismatch {
+        format: Format,
+        format_bytes: usize,
+        pixel_bytes: usize,
+    },
+
+    #[error("The frame's memory doesn't match its description. See: `{_0}`")]
+    Image(#[from] ImageError),
+}
+
+/// An error that occurs when decoding a compressed frame.
+#[derive(Clone, Debug, Error, PartialEq, PartialOrd)]
+#[non_exhaustive]
+#[rustfmt::skip]
+pub enum VideoCaptureDecodeError {
+    #[error("Expected an MJPEG frame, but the frame's format is `{format}`.")]
+    NotMjpeg { format: Format },
+
+    #[error("The frame doesn't start with a JPEG start-of-image marker.")]
+    MissingStartOfImage,
+
+    #[error("The frame ends before its end-of-image marker. It was probably cut off during transfer.")]
+    Truncated,
+
+    #[error("The frame has a malformed JPEG segment at byte `{offset}`.")]
+    MalformedSegment { offset: usize },
+
+    #[error("The JPEG decoder rejected the frame. See: `{err_msg}`")]
+    DecodeFailed { err_msg: String },
+}
+
+/// An error that occurs when parsing a configuration type from a string.
+#[derive(Clone, Debug, Error, PartialEq, PartialOrd)]
+#[non_exhaustive]
+#[rustfmt::skip]
+pub enum VideoCaptureParseError {
+    #[error("`{input}` isn't a FourCC format. Formats are one to four ASCII characters, like `MJPG`.")]
+    InvalidFormat { input: String },
+
+    #[error("`{input}` isn't a resolution. Try something like `1920x1080`.")]
+    InvalidResolution { input: String },
+
+    #[error("`{input}` isn't a framerate. Try something like `30` or `30000/1001`.")]
+    InvalidFramerate { input: String },
+
+    #[error("`{input}` isn't an image configuration. Try something like `1920x1080@30 MJPG`.")]
+    InvalidImageConfiguration { input: String },
+
+    #[error("`{input}` isn't a property. Try something like `brightness=128`.")]
+    InvalidProperty { input: String },
+}
+
+/// An error that occurs when serving live previews.
+#[cfg(feature = "preview")]
+#[derive(Clone, Debug, Error, PartialEq, PartialOrd)]
+#[non_exhaustive]
+#[rustfmt::skip]
+pub enum VideoCapturePreviewError {
+    #[error("Failed to start the preview server on `{address}`. See: `{err_msg}`")]
+    Bind { address: String, err_msg: String },
+
+    #[error("Frames in the `{format}` format can't be encoded for previews.")]
+    UnsupportedFormat { format: Format },
+
+    #[error("The frame is `{resolution}`, which is too large to encode as a JPEG.")]
+    TooLarge { resolution: SpecificResolution },
+
+    #[error("The JPEG encoder rejected the frame. See: `{err_msg}`")]
+    EncodeFailed { err_msg: String },
+
+    #[error("Couldn't look at the frame as an image. See: `{_0}`")]
+    Frame(#[from] VideoCaptureFrameError),
+
+    #[error("Couldn't read a frame from the device. See: `{_0}`")]
+    Capture(#[from] VideoCaptureUsageError),
+}
+
+/// An error that occurs when waiting for a frame from a broadcast.
+#[derive(Clone, Debug, Error, PartialEq, PartialOrd)]
+#[non_exhaustive]
+#[rustfmt::skip]
+pub enum VideoCaptureRecvError {
+    #[error("No frame is ready yet.")]
+    Empty,
+
+    #[error("No frame arrived in time.")]
+    Timeout,
+
+    /// The broadcast was dropped, and every frame it sent has been read.
+    #[error("The broadcast has stopped, and there are no frames left to read.")]
+    Closed,
+}
+
+/// An error that occurs when running a pipeline.
+#[derive(Clone, Debug, Error, PartialEq, PartialOrd)]
+#[non_exhaustive]
+#[rustfmt::skip]
+pub enum VideoCapturePipelineError {
+    #[error("Failed to start a thread for the pipeline stage `{stage}`. See: `{err_msg}`")]
+    CouldntSpawn { stage: String, err_msg: String },
+
+    /// The source gave up, so nothing new is coming down the pipeline.
+    #[error("The pipeline's source, `{stage}`, failed. See: `{err}`")]
+    SourceFailed {
+        stage: String,
+        err: VideoCaptureUsageError,
+    },
+
+    #[error("The pipeline stage `{stage}` panicked.")]
+    Panicked { stage: String },
+}
+
+/// An error that occurs when running a software auto-exposure or
+/// auto-white-balance loop.
+#[derive(Clone, Debug, Error, PartialEq, PartialOrd)]
+#[non_exhaustive]
+#[rustfmt::skip]
+pub enum VideoCaptureAutoError {
+    #[error("Frames in the `{format}` format can't be measured. Try decoding them first.")]
+    UnsupportedFormat { format: Format },
+
+    #[error("The frame is gray, so it doesn't have any color to balance.")]
+    NoColor,
+
+    /// The metering region didn't cover any pixels.
+    #[error("The metering region doesn't cover any of the frame's pixels.")]
+    EmptyRegion,
+
+    #[error("Couldn't look at the frame as an image. See: `{_0}`")]
+    Frame(#[from] VideoCaptureFrameError),
+
+    #[error("The device doesn't have the `{key}` property.")]
+    PropertyMissing { key: String },
+
+    #[error("The device's `{key}` property is `{value}`, which isn't a number.")]
+    PropertyNotNumeric { key: String, value: String },
+
+    #[error("The device wouldn't take a new setting. See: `{_0}`")]
+    Config(#[from] VideoCaptureConfigError),
+}
@@ -0,0 +1,648 @@
+//! Frames read from a capture device.
+
+use core::fmt::Display;
+use core::ops::Deref;
+use core::time::Duration;
+
+use serumcv_image::{Channel, ImageView, Pixel, PlanarView};
+
+use crate::config::{Format, SpecificResolution};
+use crate::error::VideoCaptureFrameError as FrameError;
+
+/// A single frame, borrowed from a capture device's stream.
+///
+/// The frame's bytes live inside the stream's buffers, so you'll need to copy
+/// or convert anything you want to keep before reading the next frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frame<'buf> {
+    /// The frame's bytes, trimmed to the amount the device actually wrote.
+    ///
+    /// For multi-planar devices, this is just the first memory plane. The
+    /// rest are in `planes`.
+    pub data: &'buf [u8],
+    /// The format that the bytes are in.
+    pub format: Format,
+    /// The size of the frame, in pixels.
+    pub resolution: SpecificResolution,
+    /// The distance between the start of each row, in bytes.
+    ///
+    /// For planar formats, this is the stride of the first plane. Compressed
+    /// formats don't have rows, so this is usually zero for them.
+    pub stride: usize,
+    /// Each of the frame's memory planes, with its own stride.
+    ///
+    /// Most devices put the whole frame in one buffer, so there's usually
+    /// just one plane here, even for planar formats like `NV12`.
+    /// Multi-planar devices give each plane its own buffer.
+    pub planes: FramePlanes<'buf>,
+    /// Extra info that the device gave us about this frame.
+    pub metadata: FrameMetadata,
+}
+
+/// The most memory planes that a frame can have.
+pub const MAX_FRAME_PLANES: usize = 4;
+
+/// One of a frame's memory planes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FramePlane<'buf> {
+    /// The plane's bytes, trimmed to the amount the device actually wrote.
+    pub data: &'buf [u8],
+    /// The distance between the start of each row, in bytes.
+    pub stride: usize,
+}
+
+/// A frame's memory planes. This derefs to a slice of [`FramePlane`]s.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FramePlanes<'buf> {
+    planes: [FramePlane<'buf>; MAX_FRAME_PLANES],
+    len: usize,
+}
+
+impl<'buf> FramePlanes<'buf> {
+    /// Makes a list with just one plane.
+    #[inline]
+    pub const fn single(data: &'buf [u8], stride: usize) -> Self {
+        let mut planes = [FramePlane {
+            data: &[],
+            stride: 0,
+        }; MAX_FRAME_PLANES];
+        planes[0] = FramePlane { data, stride };
+
+        Self { planes, len: 1 }
+    }
+
+    /// Makes a list from the given planes.
+    ///
+    /// Returns `None` if there are more than [`MAX_FRAME_PLANES`].
+    #[inline]
+    pub fn from_slice(from: &[FramePlane<'buf>]) -> Option<Self> {
+        let mut planes = [FramePlane::default(); MAX_FRAME_PLANES];
+        planes.get_mut(..from.len())?.copy_from_slice(from);
+
+        Some(Self {
+            planes,
+            len: from.len(),
+        })
+    }
+}
+
+impl<'buf> Deref for FramePlanes<'buf> {
+    type Target = [FramePlane<'buf>];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.planes.get(..self.len).unwrap_or_default()
+    }
+}
+
+/// Info about a captured frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct FrameMetadata {
+    /// The frame's sequence number. Gaps mean that frames were dropped.
+    pub sequence: u32,
+    /// When the frame was captured, according to the device's clock.
+    pub timestamp: Duration,
+    /// The number of bytes the device wrote into the buffer.
+    pub bytes_used: usize,
+    /// Timestamps from the device's own clock, if it sent any.
+    ///
+    /// On Video4Linux, these come from a UVC camera's metadata node, which
+    /// has to be opened first.
+    pub hardware: Option<HardwareTimestamps>,
+    /// What's wrong with the frame, if it looks damaged.
+    ///
+    /// Devices only fill this in when the driver flags a frame. Frames that
+    /// went through a [`CorruptFramePolicy`] also have their contents
+    /// checked. See [`Frame::find_corruption`].
+    pub corruption: Option<FrameCorruption>,
+}
+
+/// Why a frame looks damaged.
+///
+/// USB cameras lose packets now and then, especially on busy hubs. The
+/// driver usually notices and flags the frame, but some frames only show it
+/// by coming up short.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum FrameCorruption {
+    /// The driver marked the frame as damaged.
+    ///
+    /// On Video4Linux, this is `V4L2_BUF_FLAG_ERROR`.
+    Flagged,
+    /// The driver couldn't hand over a frame at all, and gave this error code
+    /// instead.
+    DriverError { errno: i32 },
+    /// One of the frame's memory planes has fewer bytes than its format and
+    /// resolution need. This is the first such plane.
+    ///
+    /// Drivers count bytes with 32 bits, so these do too.
+    Short {
+        plane: u32,
+        expected: u32,
+        actual: u32,
+    },
+    /// A JPEG frame doesn't start with a start-of-image marker.
+    MissingStartOfImage,
+    /// A JPEG frame was cut off before its end-of-image marker.
+    MissingEndOfImage,
+}
+
+impl Display for FrameCorruption {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::Flagged => f.write_str("the driver flagged it as damaged"),
+            Self::DriverError { errno } => {
+                write!(f, "the driver couldn't finish it (errno `{errno}`)")
+            }
+            Self::Short {
+                plane,
+                expected,
+                actual,
+            } => {
+                write!(
+                    f,
+                    "its plane `{plane}` has `{actual}` bytes, but needs `{expected}`"
+                )
+            }
+            Self::MissingStartOfImage => f.write_str("it's missing its JPEG start-of-image marker"),
+            Self::MissingEndOfImage => f.write_str("it's missing its JPEG end-of-image marker"),
+        }
+    }
+}
+
+/// What a capture device does with frames that look damaged.
+///
+/// Frames are checked with [`Frame::find_corruption`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum CorruptFramePolicy {
+    /// Hand them over anyway, with the problem in
+    /// [`FrameMetadata::corruption`].
+    #[default]
+    Deliver,
+    /// Drop them and wait for the next frame. Dropped frames are counted.
+    ///
+    /// A read gives up after [`CorruptFramePolicy::MAX_SKIPS`] corrupt frames
+    /// in a row, so a broken device can't keep it waiting forever.
+    Skip,
+    /// Fail the read with [`VideoCaptureUsageError::CorruptFrame`].
+    ///
+    /// [`VideoCaptureUsageError::CorruptFrame`]: crate::error::VideoCaptureUsageError::CorruptFrame
+    Error,
+}
+
+impl CorruptFramePolicy {
+    /// How many corrupt frames in a row a single read skips before failing
+    /// with [`VideoCaptureUsageError::TooManyCorruptFrames`].
+    ///
+    /// [`VideoCaptureUsageError::TooManyCorruptFrames`]: crate::error::VideoCaptureUsageError::TooManyCorruptFrames
+    pub const MAX_SKIPS: u32 = 32;
+}
+
+/// When a frame was captured, according to the device's own clock.
+///
+/// UVC cameras put these in the header of each USB payload. Each device has
+/// its own clock, so the raw ticks can't be compared across devices. Instead,
+/// use [`HardwareTimestamps::presentation_host_time`] to move them onto the
+/// host's clock.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HardwareTimestamps {
+    /// When the device started capturing the frame, in ticks of its clock.
+    ///
+    /// This is the UVC header's `dwPresentationTime` (PTS).
+    pub presentation_time: Option<u32>,
+    /// A reading of the device's clock, in ticks, taken while it was sending
+    /// the frame.
+    ///
+    /// This is the source time clock from the UVC header's
+    /// `scrSourceClock` (SCR).
+    pub source_clock: Option<u32>,
+    /// The USB start-of-frame number that the device saw when it read
+    /// `source_clock`. Only the low 11 bits are used.
+    pub source_sof: Option<u16>,
+    /// When the host got the header with `source_clock` in it, on the
+    /// `CLOCK_MONOTONIC` clock.
+    pub host_time: Duration,
+    /// The USB start-of-frame number that the host saw at `host_time`.
+    pub host_sof: u16,
+}
+
+impl HardwareTimestamps {
+    /// Estimates when the frame was captured, on the host's
+    /// `CLOCK_MONOTONIC` clock.
+    ///
+    /// This works backwards from `host_time` by however long the device's
+    /// clock says passed between the capture and the `source_clock` reading.
+    /// The device's clock frequency is its `dwClockFrequency`, in hertz.
+    ///
+    /// Returns `None` if the device didn't send both timestamps.
+    #[inline]
+    pub fn presentation_host_time(&self, clock_frequency: u32) -> Option<Duration> {
+        let (presentation, source) = self.presentation_time.zip(self.source_clock)?;
+        if clock_frequency == 0 {
+            return None;
+        }
+
+        // the device's clock wraps around, so this might too
+        let ticks = source.wrapping_sub(presentation);
+        let delay = Duration::from_secs_f64(f64::from(ticks) / f64::from(clock_frequency));
+        self.host_time.checked_sub(delay)
+    }
+}
+
+impl<'buf> Frame<'buf> {
+    /// Looks at this frame as an image of packed pixels, without copying.
+    ///
+    /// The pixel type must have the same size as one of the format's pixels.
+    /// For example, `GREY` frames work with `Luma<u8>`, `RGB3` frames work
+    /// with `Rgb<u8>`, and `YUYV` frames work with `[u8; 2]`.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the format is compressed or planar, the pixel type is
+    /// the wrong size, or the frame is shorter than its resolution says.
+    #[inline]
+    pub fn view<P: Pixel>(&self) -> Result<ImageView<'buf, P>, FrameError> {
+        let format_bytes = self.format.bytes_per_pixel().ok_or(FrameError::NotPacked {
+            format: self.format,
+        })?;
+
+        let pixel_bytes = size_of::<P>();
+        if format_bytes != pixel_bytes {
+            return Err(FrameError::PixelSizeMismatch {
+                format: self.format,
+                format_bytes,
+                pixel_bytes,
+            });
+        }
+
+        let stride = self.row_stride(format_bytes);
+        Ok(ImageView::from_bytes(
+            self.data,
+            self.resolution.width,
+            self.resolution.height,
+            stride,
+        )?)
+    }
+
+    /// Looks at this frame as a planar image, without copying.
+    ///
+    /// This works for frames with all their planes in one buffer, and for
+    /// multi-planar frames with one buffer per plane.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the format isn't planar or the frame is shorter than its
+    /// resolution says.
+    #[inline]
+    pub fn planar_view<C: Channel + Pixel<Channel = C>>(
+        &self,
+    ) -> Result<PlanarView<'buf, C>, FrameError> {
+        let layout = self.format.planar_layout().ok_or(FrameError::NotPlanar {
+            format: self.format,
+        })?;
+
+        // each plane has its own buffer (and stride)
+        if self.planes.len() > 1 {
+            let (width, height) = (self.resolution.width, self.resolution.height);
+            let planes = self
+                .planes
+                .iter()
+                .enumerate()
+                .map(|(index, plane)| {
+                    let (plane_width, plane_height) = layout
+                        .plane_dimensions(index, width, height)
+                        .unwrap_or_default();
+                    ImageView::from_bytes(plane.data, plane_width, plane_height, plane.stride)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            return Ok(PlanarView::from_planes(layout, width, height, planes)?);
+        }
+
+        let stride = self.row_stride(size_of::<C>());
+        Ok(PlanarView::from_bytes(
+            layout,
+            self.data,
+            self.resolution.width,
+            self.resolution.height,
+            stride,
+        )?)
+    }
+
+    /// Checks the frame for signs of damage.
+    ///
+    /// Anything already in [`FrameMetadata::corruption`] comes first. After
+    /// that, JPEG frames need both their start and end markers, while raw
+    /// frames need enough bytes for their format and resolution. Extra bytes
+    /// are fine, since drivers often pad their buffers. Other formats aren't
+    /// checked.
+    #[inline]
+    pub fn find_corruption(&self) -> Option<FrameCorruption> {
+        if let Some(corruption) = self.metadata.corruption {
+            return Some(corruption);
+        }
+
+        if self.format == Format::MJPEG || self.format == Format::JPEG {
+            return jpeg_corruption(self.data);
+        }
+
+        let needed = self.needed_lens()?;
+        let count = |len: usize| u32::try_from(len).unwrap_or(u32::MAX);
+        self.planes
+            .iter()
+            .zip(needed)
+            .enumerate()
+            .find(|&(_, (plane, needs))| plane.data.len() < needs)
+            .map(|(index, (plane, needs))| FrameCorruption::Short {
+                plane: count(index),
+                expected: count(needs),
+                actual: count(plane.data.len()),
+            })
+    }
+
+    /// The fewest bytes that each memory plane can have, for raw formats.
+    ///
+    /// The last row of a plane doesn't need its padding.
+    fn needed_lens(&self) -> Option<Vec<usize>> {
+        let (width, height) = (self.resolution.width, self.resolution.height);
+
+        if let Some(bytes_per_pixel) = self.format.bytes_per_pixel() {
+            let row_len = width as usize * bytes_per_pixel;
+            let stride = self.row_stride(bytes_per_pixel);
+            return Some(vec![region_len(stride, row_len, height)]);
+        }
+
+        let layout = self.format.planar_layout()?;
+        let plane_sizes = (0..layout.plane_count()).map(|index| {
+            layout
+                .plane_dimensions(index, width, height)
+                .unwrap_or_default()
+        });
+
+        // each plane has its own buffer (and stride)
+        if self.planes.len() > 1 {
+            return Some(
+                plane_sizes
+                    .zip(self.planes.iter())
+                    .map(|((plane_width, rows), plane)| {
+                        let row_len = plane_width as usize;
+                        region_len(plane.stride.max(row_len), row_len, rows)
+                    })
+                    .collect(),
+            );
+        }
+
+        // otherwise, the planes follow each other in one buffer
+        let first_stride = self.row_stride(1);
+        let mut needed = 0;
+        for (index, (plane_width, rows)) in plane_sizes.enumerate() {
+            let stride = layout.plane_stride(index, first_stride).unwrap_or_default();
+            needed = if index + 1 == layout.plane_count() {
+                needed + region_len(stride, plane_width as usize, rows)
+            } else {
+                needed + stride * rows as usize
+            };
+        }
+        Some(vec![needed])
+    }
+
+    /// Some drivers leave the stride empty. When they do, rows are packed.
+    const fn row_stride(&self, bytes_per_pixel: usize) -> usize {
+        match self.stride {
+            0 => self.resolution.width as usize * bytes_per_pixel,
+            stride => stride,
+        }
+    }
+
+    /// Makes a frame of packed pixels in one buffer, for tests.
+    #[cfg(test)]
+    pub(crate) fn packed_for_test(
+        data: &'buf [u8],
+        format: Format,
+        width: u32,
+        height: u32,
+        stride: usize,
+    ) -> Self {
+        Self {
+            data,
+            format,
+            resolution: SpecificResolution::new(width, height),
+            stride,
+            planes: FramePlanes::single(data, stride),
+            metadata: FrameMetadata::default(),
+        }
+    }
+}
+
+/// The bytes that `rows` rows take up, without the last row's padding.
+const fn region_len(stride: usize, row_len: usize, rows: u32) -> usize {
+    match rows {
+        0 => 0,
+        _ => stride * (rows as usize - 1) + row_len,
+    }
+}
+
+/// Checks that some JPEG data starts with a start-of-image marker and ends
+/// with an end-of-image marker. Zeroes after the end are ignored, since some
+/// devices pad their frames with them.
+pub(crate) fn jpeg_corruption(jpeg: &[u8]) -> Option<FrameCorruption> {
+    if !jpeg.starts_with(&[0xFF, 0xD8]) {
+        return Some(FrameCorruption::MissingStartOfImage);
+    }
+
+    let end = jpeg
+        .iter()
+        .rposition(|&byte| byte != 0x00)
+        .unwrap_or_default();
+
+    match jpeg.get(..=end) {
+        Some(trimmed) if trimmed.len() > 2 && trimmed.ends_with(&[0xFF, 0xD9]) => None,
+        _ => Some(FrameCorruption::MissingEndOfImage),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serumcv_image::{Luma, Rgb};
+
+    use super::*;
+
+    #[test]
+    fn packed_frames_view_without_copying() {
+        let data: Vec<u8> = (0..16).collect();
+        let grey = Frame::packed_for_test(&data, Format::GREY, 3, 4, 4);
+
+        let view = grey.view::<Luma<u8>>().unwrap();
+        assert_eq!(view.get(2, 3), Some(&Luma([14])), "stride skips padding");
+        assert!(
+            core::ptr::eq(view.as_raw().as_ptr(), data.as_ptr()),
+            "views should borrow the frame's bytes"
+        );
+
+        assert_eq!(
+            grey.view::<Rgb<u8>>().err(),
+            Some(FrameError::PixelSizeMismatch {
+                format: Format::GREY,
+                format_bytes: 1,
+                pixel_bytes: 3,
+            }),
+            "rgb pixels don't fit grey frames"
+        );
+    }
+
+    #[test]
+    fn planar_frames_split_into_planes() {
+        // 4x2 NV12: eight luma bytes, then four interleaved chroma bytes
+        let data: Vec<u8> = (0..12).collect();
+        let nv12 = Frame::packed_for_test(&data, Format::NV12, 4, 2, 0);
+
+        let view = nv12.planar_view::<u8>().unwrap();
+        let chroma = view.plane(1).unwrap();
+        assert_eq!(chroma.dimensions(), (4, 1), "one row of uv pairs");
+        assert_eq!(chroma.get(0, 0), Some(&8), "chroma starts after luma");
+
+        assert_eq!(
+            nv12.view::<Luma<u8>>().err(),
+            Some(FrameError::NotPacked {
+                format: Format::NV12
+            }),
+            "planar frames aren't packed"
+        );
+    }
+
+    #[test]
+    fn short_frames_are_corrupt() {
+        // 3x2 YUYV with 2 bytes of padding per row. the last row doesn't
+        // need its padding
+        let data = [0; 14];
+        let yuyv = Frame::packed_for_test(&data, Format::YUYV, 3, 2, 8);
+        assert_eq!(yuyv.find_corruption(), None, "the last row can be tight");
+
+        let cut = Frame::packed_for_test(data.get(..13).unwrap(), Format::YUYV, 3, 2, 8);
+        assert_eq!(
+            cut.find_corruption(),
+            Some(FrameCorruption::Short {
+                plane: 0,
+                expected: 14,
+                actual: 13,
+            })
+        );
+
+        let padded = [0; 32];
+        assert_eq!(
+            Frame::packed_for_test(&padded, Format::YUYV, 3, 2, 8).find_corruption(),
+            None,
+            "extra bytes are fine"
+        );
+
+        // 4x2 NV12 needs eight luma bytes and four chroma bytes
+        let nv12 = [0; 12];
+        assert_eq!(
+            Frame::packed_for_test(&nv12, Format::NV12, 4, 2, 0).find_corruption(),
+            None
+        );
+        assert_eq!(
+            Frame::packed_for_test(nv12.get(..10).unwrap(), Format::NV12, 4, 2, 0)
+                .find_corruption(),
+            Some(FrameCorruption::Short {
+                plane: 0,
+                expected: 12,
+                actual: 10,
+            }),
+            "missing chroma"
+        );
+
+        let mut flagged = Frame::packed_for_test(&padded, Format::YUYV, 3, 2, 8);
+        flagged.metadata.corruption = Some(FrameCorruption::Flagged);
+        assert_eq!(
+            flagged.find_corruption(),
+            Some(FrameCorruption::Flagged),
+            "driver flags come first"
+        );
+
+        assert_eq!(
+            Frame::packed_for_test(&[], Format::AVC, 3, 2, 0).find_corruption(),
+            None,
+            "other compressed formats aren't checked"
+        );
+    }
+
+    #[test]
+    fn jpeg_frames_need_both_markers() {
+        let jpeg = [0xFF, 0xD8, 0x12, 0x34, 0xFF, 0xD9, 0x00, 0x00];
+        assert_eq!(
+            Frame::packed_for_test(&jpeg, Format::MJPEG, 2, 2, 0).find_corruption(),
+            None
+        );
+
+        assert_eq!(
+            Frame::packed_for_test(jpeg.get(..4).unwrap(), Format::MJPEG, 2, 2, 0)
+                .find_corruption(),
+            Some(FrameCorruption::MissingEndOfImage),
+            "cut off"
+        );
+        assert_eq!(
+            Frame::packed_for_test(jpeg.get(2..).unwrap(), Format::JPEG, 2, 2, 0).find_corruption(),
+            Some(FrameCorruption::MissingStartOfImage),
+            "no start"
+        );
+    }
+
+    #[test]
+    fn multiplanar_frames_use_each_planes_stride() {
+        // 4x2 NV12M: a luma buffer with 2 bytes of padding per row, then a
+        // separate chroma buffer
+        let luma: Vec<u8> = (0..12).collect();
+        let chroma = [100, 101, 102, 103];
+        let mut nv12m = Frame::packed_for_test(&luma, Format::NV12M, 4, 2, 6);
+        nv12m.planes = FramePlanes::from_slice(&[
+            FramePlane {
+                data: &luma,
+                stride: 6,
+            },
+            FramePlane {
+                data: &chroma,
+                stride: 4,
+            },
+        ])
+        .unwrap();
+
+        assert_eq!(nv12m.find_corruption(), None, "each plane is long enough");
+
+        let view = nv12m.planar_view::<u8>().unwrap();
+        assert_eq!(view.plane(0).unwrap().get(0, 1), Some(&6), "luma stride");
+        assert_eq!(
+            view.plane(1).unwrap().get(1, 0),
+            Some(&101),
+            "chroma buffer"
+        );
+
+        let short_chroma = [100, 101];
+        nv12m.planes = FramePlanes::from_slice(&[
+            FramePlane {
+                data: &luma,
+                stride: 6,
+            },
+            FramePlane {
+                data: &short_chroma,
+                stride: 4,
+            },
+        ])
+        .unwrap();
+        assert_eq!(
+            nv12m.find_corruption(),
+            Some(FrameCorruption::Short {
+                plane: 1,
+                expected: 4,
+                actual: 2,
+            }),
+            "a long plane doesn't make up for a short one"
+        );
+        assert!(
+            FramePlanes::from_slice(&[FramePlane::default(); MAX_FRAME_PLANES + 1]).is_none(),
+            "too many planes"
+        );
+    }
+}
@@ -1,9 +1,22 @@
 use error::{VideoCaptureConnectionError as ConnectionError, VideoCaptureUsageError as UsageError};
 
+pub mod auto;
 pub mod backends;
+#[expect(
+    clippy::redundant_pub_crate,
+    reason = "both pool and pipeline use it, so pub(super) isn't enough"
+)]
+mod bounded;
 pub mod config;
 pub mod error;
+pub mod frame;
+#[cfg(feature = "mjpeg")]
+pub mod mjpeg;
+pub mod pipeline;
+pub mod pool;
 pub mod prelude;
+#[cfg(feature = "preview")]
+pub mod preview;
 
 // TODO: pub use config::(...);
 
@@ -91,23 +104,33 @@ where
     /// identifier to connect to a capture device.
     type SourceInput;
 
-    /// A backend's frame type.
-    type Metadata;
-
     /// Attempts to read a frame from the stream into the stream's internal
     /// buffer.
     ///
-    /// FIXME: uhh. how do we want users to access the buffer?
-    ///      | seems bad to make a pointer, and waiting on a RwLock seems
-    ///      | inefficient at best :p
+    /// The returned [`Frame`](frame::Frame) borrows that buffer, so it can be
+    /// viewed as an image without copying. It'll need to be dropped before
+    /// reading the next frame.
     ///
     /// # Errors
     ///
     /// This can return an error if the backend doesn't support the capture
     /// device, it is disconnected, or it is in use by another application.
-    fn read_frame<'func>(
+    fn read_frame<'func>(&'func mut self) -> Result<frame::Frame<'func>, crate::UsageError>
+    where
+        'path: 'func;
+
+    /// Like [`read_frame`](VideoCaptureStream::read_frame), but waits at most
+    /// `timeout` for a frame to arrive.
+    ///
+    /// # Errors
+    ///
+    /// This fails with [`UsageError::Timeout`](crate::UsageError::Timeout)
+    /// if no frame arrives in time. Otherwise, it fails like
+    /// [`read_frame`](VideoCaptureStream::read_frame).
+    fn read_frame_timeout<'func>(
         &'func mut self,
-    ) -> Result<(&'func [u8], &'func Self::Metadata), crate::UsageError>
+        timeout: core::time::Duration,
+    ) -> Result<frame::Frame<'func>, crate::UsageError>
     where
         'path: 'func;
 
@@ -128,6 +151,12 @@ pub struct VideoCapture<Desc: VideoCaptureDescriptor, Device, Source, Stream> {
     device: Device,
     source: Source,
     stream: Stream,
+    /// The profiles to pick from whenever the device connects.
+    profiles: Vec<config::VideoCaptureProfile>,
+    /// The profile that was picked, or applied since.
+    profile: Option<config::VideoCaptureProfile>,
+    corrupt_frames: frame::CorruptFramePolicy,
+    skipped_frames: u64,
 }
 
 // this is just here to help people find the ident/model values.
@@ -147,4 +176,39 @@ impl<Desc: VideoCaptureDescriptor, Device, Source, Stream>
     pub fn device_model(&self) -> Desc::ModelTy {
         self.descriptor.device_model()
     }
+
+    /// Returns the identifying information for a capture device.
+    #[inline]
+    pub const fn descriptor(&self) -> &Desc {
+        &self.descriptor
+    }
+
+    /// Returns where the capture device was found.
+    #[inline]
+    pub const fn source(&self) -> &Source {
+        &self.source
+    }
+
+    /// Returns what the device does with frames that look damaged.
+    #[inline]
+    pub const fn corrupt_frame_policy(&self) -> frame::CorruptFramePolicy {
+        self.corrupt_frames
+    }
+
+    /// Changes what the device does with frames that look damaged.
+    ///
+    /// By default, they're delivered like any other frame.
+    #[inline]
+    pub const fn set_corrupt_frame_policy(&mut self, policy: frame::CorruptFramePolicy) {
+        self.corrupt_frames = policy;
+    }
+
+    /// Returns how many damaged frames were skipped, since the device was
+    /// opened, because of [`CorruptFramePolicy::Skip`].
+    ///
+    /// [`CorruptFramePolicy::Skip`]: frame::CorruptFramePolicy::Skip
+    #[inline]
+    pub const fn skipped_frames(&self) -> u64 {
+        self.skipped_frames
+    }
 }
@@ -0,0 +1,397 @@
+//! Decoding for MJPEG frames.
+//!
+//! Most USB cameras can only hit high frame rates at high resolutions by
+//! sending MJPEG. Each frame is just a JPEG image, but UVC devices are allowed
+//! to leave out the Huffman tables (the `DHT` segment) and expect everyone to
+//! use the standard ones from the JPEG spec. Many decoders reject those
+//! frames, so we put the tables back in before decoding.
+
+extern crate alloc;
+
+use alloc::borrow::Cow;
+
+use serumcv_image::{Image, Rgb, Yuv};
+use zune_jpeg::zune_core::colorspace::ColorSpace;
+use zune_jpeg::zune_core::options::DecoderOptions;
+use zune_jpeg::JpegDecoder;
+
+use crate::config::Format;
+use crate::error::VideoCaptureDecodeError as DecodeError;
+use crate::frame::{jpeg_corruption, Frame, FrameCorruption};
+
+/// Start of image.
+const SOI: u8 = 0xD8;
+/// End of image.
+const EOI: u8 = 0xD9;
+/// Start of scan. Entropy-coded data follows this segment.
+const SOS: u8 = 0xDA;
+/// Define Huffman table(s).
+const DHT: u8 = 0xC4;
+
+/// Decodes MJPEG frames into images.
+///
+/// The decoder keeps a scratch buffer around for frames that need their
+/// Huffman tables put back, so reuse one decoder for a whole stream.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MjpegDecoder {
+    patched: Vec<u8>,
+}
+
+impl MjpegDecoder {
+    /// Creates a new decoder.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            patched: Vec::new(),
+        }
+    }
+
+    /// Decodes a frame into an RGB image.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the frame isn't MJPEG, was cut off, or is otherwise
+    /// corrupt.
+    #[inline]
+    pub fn decode_rgb(&mut self, frame: &Frame<'_>) -> Result<Image<Rgb<u8>>, DecodeError> {
+        let (data, width, height) = self.decode(frame, ColorSpace::RGB)?;
+        image_from_raw(data, width, height)
+    }
+
+    /// Decodes a frame into a YUV (YCbCr) image, with full-resolution chroma.
+    ///
+    /// This skips the decoder's color conversion, so it's a bit faster than
+    /// [`MjpegDecoder::decode_rgb`].
+    ///
+    /// # Errors
+    ///
+    /// This fails if the frame isn't MJPEG, was cut off, or is otherwise
+    /// corrupt.
+    #[inline]
+    pub fn decode_yuv(&mut self, frame: &Frame<'_>) -> Result<Image<Yuv<u8>>, DecodeError> {
+        let (data, width, height) = self.decode(frame, ColorSpace::YCbCr)?;
+        image_from_raw(data, width, height)
+    }
+
+    fn decode(
+        &mut self,
+        frame: &Frame<'_>,
+        colorspace: ColorSpace,
+    ) -> Result<(Vec<u8>, u32, u32), DecodeError> {
+        if frame.format != Format::MJPEG && frame.format != Format::JPEG {
+            return Err(DecodeError::NotMjpeg {
+                format: frame.format,
+            });
+        }
+
+        check_complete(frame.data)?;
+
+        // only copy the frame when it's missing its tables
+        let jpeg = match missing_tables_at(frame.data)? {
+            Some(sos) => {
+                self.patched.clear();
+                splice_huffman_tables(frame.data, sos, &mut self.patched);
+                self.patched.as_slice()
+            }
+            None => frame.data,
+        };
+
+        let options = DecoderOptions::default().jpeg_set_out_colorspace(colorspace);
+        let mut decoder = JpegDecoder::new_with_options(jpeg, options);
+        let data = decoder.decode().map_err(|e| DecodeError::DecodeFailed {
+            err_msg: e.to_string(),
+        })?;
+
+        let (width, height) = decoder
+            .info()
+            .map(|info| (u32::from(info.width), u32::from(info.height)))
+            .unwrap_or_default();
+
+        Ok((data, width, height))
+    }
+}
+
+/// Checks that some JPEG data starts with a start-of-image marker and ends
+/// with an end-of-image marker.
+///
+/// Frames that were cut off during transfer won't have the end marker, so
+/// this is a cheap way to find them before decoding. Some devices pad their
+/// frames with zeroes, which are ignored.
+///
+/// # Errors
+///
+/// This fails if either marker is missing.
+#[inline]
+pub fn check_complete(jpeg: &[u8]) -> Result<(), DecodeError> {
+    match jpeg_corruption(jpeg) {
+        Some(FrameCorruption::MissingStartOfImage) => Err(DecodeError::MissingStartOfImage),
+        Some(_) => Err(DecodeError::Truncated),
+        None => Ok(()),
+    }
+}
+
+/// Returns JPEG data that's guaranteed to have Huffman tables.
+///
+/// If the data already has them, it's returned as-is. Otherwise, the standard
+/// tables are copied in right before the scan starts. This is handy for
+/// saving MJPEG frames as normal `.jpg` files.
+///
+/// # Errors
+///
+/// This fails if the JPEG's segments are malformed or cut off.
+#[inline]
+pub fn with_huffman_tables(jpeg: &[u8]) -> Result<Cow<'_, [u8]>, DecodeError> {
+    Ok(missing_tables_at(jpeg)?.map_or(Cow::Borrowed(jpeg), |sos| {
+        let mut patched = Vec::with_capacity(jpeg.len() + DHT_SEGMENT_LEN);
+        splice_huffman_tables(jpeg, sos, &mut patched);
+        Cow::Owned(patched)
+    }))
+}
+
+/// Walks the segments before the scan, looking for Huffman tables.
+///
+/// Returns the offset of the start-of-scan marker if there weren't any.
+fn missing_tables_at(jpeg: &[u8]) -> Result<Option<usize>, DecodeError> {
+    if !jpeg.starts_with(&[0xFF, SOI]) {
+        return Err(DecodeError::MissingStartOfImage);
+    }
+
+    let mut offset = 2_usize;
+    let mut found_tables = false;
+
+    loop {
+        if jpeg.get(offset) != Some(&0xFF) {
+            return Err(match jpeg.get(offset) {
+                Some(_) => DecodeError::MalformedSegment { offset },
+                None => DecodeError::Truncated,
+            });
+        }
+
+        // markers can be padded with any number of `0xFF` fill bytes
+        let mut marker_at = offset;
+        while jpeg.get(marker_at + 1) == Some(&0xFF) {
+            marker_at += 1;
+        }
+
+        let marker = *jpeg.get(marker_at + 1).ok_or(DecodeError::Truncated)?;
+        match marker {
+            SOS => return Ok((!found_tables).then_some(offset)),
+            EOI => return Err(DecodeError::MalformedSegment { offset }),
+            DHT => found_tables = true,
+            _ => (),
+        }
+
+        // every other segment before the scan says how long it is
+        let len = match jpeg.get(marker_at + 2..marker_at + 4) {
+            Some(&[hi, lo]) => usize::from(u16::from_be_bytes([hi, lo])),
+            _ => return Err(DecodeError::Truncated),
+        };
+        if len < 2 {
+            return Err(DecodeError::MalformedSegment { offset });
+        }
+
+        offset = marker_at + 2 + len;
+    }
+}
+
+/// Copies the JPEG into `out`, putting the standard Huffman tables right
+/// before the start-of-scan marker at `sos`.
+fn splice_huffman_tables(jpeg: &[u8], sos: usize, out: &mut Vec<u8>) {
+    let (head, tail) = jpeg.split_at(sos.min(jpeg.len()));
+
+    out.reserve(jpeg.len() + DHT_SEGMENT_LEN);
+    out.extend_from_slice(head);
+    push_huffman_tables(out);
+    out.extend_from_slice(tail);
+}
+
+fn image_from_raw<P>(data: Vec<u8>, width: u32, height: u32) -> Result<Image<P>, DecodeError>
+where
+    P: serumcv_image::Pixel<Channel = u8>,
+{
+    Image::from_raw(data, width, height).map_err(|e| DecodeError::DecodeFailed {
+        err_msg: e.to_string(),
+    })
+}
+
+/// Writes a `DHT` segment holding the four standard tables.
+fn push_huffman_tables(out: &mut Vec<u8>) {
+    let tables = [
+        (0x00, &DC_LUMA_BITS, DC_LUMA_VALUES.as_slice()),
+        (0x10, &AC_LUMA_BITS, AC_LUMA_VALUES.as_slice()),
+        (0x01, &DC_CHROMA_BITS, DC_CHROMA_VALUES.as_slice()),
+        (0x11, &AC_CHROMA_BITS, AC_CHROMA_VALUES.as_slice()),
+    ];
+
+    out.extend_from_slice(&[0xFF, DHT]);
+    #[expect(
+        clippy::cast_possible_truncation,
+        reason = "the segment has a fixed length that fits in a `u16`"
+    )]
+    out.extend_from_slice(&((DHT_SEGMENT_LEN - 2) as u16).to_be_bytes());
+
+    for (class_and_id, bits, values) in tables {
+        out.push(class_and_id);
+        out.extend_from_slice(bits);
+        out.extend_from_slice(values);
+    }
+}
+
+/// The length of the `DHT` segment we insert, including its marker.
+const DHT_SEGMENT_LEN: usize = 2
+    + 2
+    + 4 * (1 + 16)
+    + DC_LUMA_VALUES.len()
+    + AC_LUMA_VALUES.len()
+    + DC_CHROMA_VALUES.len()
+    + AC_CHROMA_VALUES.len();
+
+// the standard tables, from section K.3 of the JPEG spec (ITU T.81).
+//
+// each `BITS` array holds the number of codes of each length (1 to 16 bits),
+// and each `VALUES` array holds the symbols for those codes, in order.
+
+const DC_LUMA_BITS: [u8; 16] = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+const DC_LUMA_VALUES: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+
+const DC_CHROMA_BITS: [u8; 16] = [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0];
+const DC_CHROMA_VALUES: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+
+const AC_LUMA_BITS: [u8; 16] = [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7D];
+#[rustfmt::skip]
+const AC_LUMA_VALUES: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xA1, 0x08, 0x23, 0x42, 0xB1, 0xC1, 0x15, 0x52, 0xD1, 0xF0,
+    0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0A, 0x16, 0x17, 0x18, 0x19, 0x1A, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2A, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4A, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5A, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6A, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7A, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8A, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9A, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7,
+    0xA8, 0xA9, 0xAA, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6, 0xB7, 0xB8, 0xB9, 0xBA, 0xC2, 0xC3, 0xC4, 0xC5,
+    0xC6, 0xC7, 0xC8, 0xC9, 0xCA, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA, 0xE1, 0xE2,
+    0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8, 0xE9, 0xEA, 0xF1, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8,
+    0xF9, 0xFA,
+];
+
+const AC_CHROMA_BITS: [u8; 16] = [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77];
+#[rustfmt::skip]
+const AC_CHROMA_VALUES: [u8; 162] = [
+    0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71,
+    0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xA1, 0xB1, 0xC1, 0x09, 0x23, 0x33, 0x52, 0xF0,
+    0x15, 0x62, 0x72, 0xD1, 0x0A, 0x16, 0x24, 0x34, 0xE1, 0x25, 0xF1, 0x17, 0x18, 0x19, 0x1A, 0x26,
+    0x27, 0x28, 0x29, 0x2A, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48,
+    0x49, 0x4A, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5A, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68,
+    0x69, 0x6A, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7A, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+    0x88, 0x89, 0x8A, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9A, 0xA2, 0xA3, 0xA4, 0xA5,
+    0xA6, 0xA7, 0xA8, 0xA9, 0xAA, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6, 0xB7, 0xB8, 0xB9, 0xBA, 0xC2, 0xC3,
+    0xC4, 0xC5, 0xC6, 0xC7, 0xC8, 0xC9, 0xCA, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA,
+    0xE2, 0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8, 0xE9, 0xEA, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8,
+    0xF9, 0xFA,
+];
+
+#[cfg(test)]
+mod tests {
+    use crate::config::SpecificResolution;
+    use crate::frame::{FrameMetadata, FramePlanes};
+
+    use super::*;
+
+    /// Encodes a small gradient, then strips its Huffman tables like a UVC
+    /// camera would.
+    fn uvc_style_jpeg() -> Vec<u8> {
+        let (width, height) = (16_u16, 8_u16);
+        let pixels: Vec<u8> = (0..width * height)
+            .flat_map(|i| {
+                let shade = u8::try_from(i % 256).unwrap_or(u8::MAX);
+                [shade, 128, 255 - shade]
+            })
+            .collect();
+
+        let mut encoded = Vec::new();
+        jpeg_encoder::Encoder::new(&mut encoded, 90)
+            .encode(&pixels, width, height, jpeg_encoder::ColorType::Rgb)
+            .unwrap();
+
+        strip_segments(&encoded, DHT)
+    }
+
+    /// Removes every segment with the given marker that comes before the scan.
+    fn strip_segments(jpeg: &[u8], marker: u8) -> Vec<u8> {
+        let mut out = jpeg.get(..2).unwrap().to_vec();
+        let mut offset = 2;
+        while let [0xFF, kind, hi, lo, ..] = *jpeg.get(offset..).unwrap() {
+            if kind == SOS {
+                break;
+            }
+            let end = offset + 2 + usize::from(u16::from_be_bytes([hi, lo]));
+            if kind != marker {
+                out.extend_from_slice(jpeg.get(offset..end).unwrap());
+            }
+            offset = end;
+        }
+        out.extend_from_slice(jpeg.get(offset..).unwrap());
+        out
+    }
+
+    fn mjpeg_frame(data: &[u8]) -> Frame<'_> {
+        Frame {
+            data,
+            format: Format::MJPEG,
+            resolution: SpecificResolution::new(16, 8),
+            stride: 0,
+            planes: FramePlanes::single(data, 0),
+            metadata: FrameMetadata::default(),
+        }
+    }
+
+    #[test]
+    fn frames_without_tables_decode() {
+        let jpeg = uvc_style_jpeg();
+        assert!(
+            missing_tables_at(&jpeg).unwrap().is_some(),
+            "the test frame shouldn't have any tables"
+        );
+
+        let patched = with_huffman_tables(&jpeg).unwrap();
+        assert_eq!(
+            patched.len(),
+            jpeg.len() + DHT_SEGMENT_LEN,
+            "one segment added"
+        );
+        assert_eq!(
+            missing_tables_at(&patched).unwrap(),
+            None,
+            "patched frames have tables"
+        );
+
+        let mut decoder = MjpegDecoder::new();
+        let rgb = decoder.decode_rgb(&mjpeg_frame(&jpeg)).unwrap();
+        assert_eq!(rgb.dimensions(), (16, 8), "rgb size");
+
+        let yuv = decoder.decode_yuv(&mjpeg_frame(&jpeg)).unwrap();
+        assert_eq!(yuv.dimensions(), (16, 8), "yuv size");
+    }
+
+    #[test]
+    fn truncated_frames_are_caught() {
+        let jpeg = uvc_style_jpeg();
+
+        let mut padded = jpeg.clone();
+        padded.extend_from_slice(&[0; 32]);
+        assert_eq!(check_complete(&padded), Ok(()), "zero padding is fine");
+
+        let cut = jpeg.get(..jpeg.len() - 40).unwrap();
+        assert_eq!(check_complete(cut), Err(DecodeError::Truncated), "no eoi");
+        assert_eq!(
+            MjpegDecoder::new().decode_rgb(&mjpeg_frame(cut)),
+            Err(DecodeError::Truncated),
+            "the decoder checks too"
+        );
+
+        assert_eq!(
+            check_complete(&[0x00, 0x01]),
+            Err(DecodeError::MissingStartOfImage),
+            "not a jpeg at all"
+        );
+    }
+}
@@ -0,0 +1,703 @@
+//! Pipelines that pass frames through a chain of stages.
+//!
+//! A pipeline starts with a [`Source`], like a capture device, then runs any
+//! number of [`Stage`]s (conversions, processing) before ending in a
+//! [`Sink`]. Each one runs on its own thread. They're joined by bounded
+//! queues, and each queue's [`DropPolicy`] says what happens when the next
+//! stage falls behind.
+//!
+//! ```no_run
+//! use serumcv_video_capture::backends::v4l::V4LVideoCaptureDevice;
+//! use serumcv_video_capture::pipeline::{DropPolicy, Pipeline, QueueOptions};
+//! use serumcv_video_capture::pool::{FramePool, SharedFrame};
+//! use serumcv_video_capture::VideoCaptureConnection as _;
+//!
+//! let camera = V4LVideoCaptureDevice::new("/dev/video0".into()).unwrap();
+//!
+//! let pipeline = Pipeline::capture("camera", camera, FramePool::new(8))
+//!     // always look at the newest frame, even if that means skipping some
+//!     .stage("brightness", QueueOptions::new(1, DropPolicy::DropOldest), |shared: SharedFrame| {
+//!         let frame = shared.frame();
+//!         let total: u64 = frame.data.iter().map(|&byte| u64::from(byte)).sum();
+//!         Some(total / frame.data.len().max(1) as u64)
+//!     })
+//!     .sink("log", QueueOptions::default(), |brightness: u64| {
+//!         println!("brightness: {brightness}");
+//!     })
+//!     .unwrap();
+//!
+//! std::thread::sleep(std::time::Duration::from_secs(5));
+//! for metrics in pipeline.metrics() {
+//!     println!("{metrics:?}");
+//! }
+//! pipeline.shutdown().unwrap();
+//! ```
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use crate::error::{
+    VideoCapturePipelineError as PipelineError, VideoCaptureUsageError as UsageError,
+};
+use crate::pool::{FramePool, SharedFrame};
+use crate::VideoCaptureStream;
+
+pub use queue::{DropPolicy, QueueOptions};
+
+use queue::{QueueReceiver, QueueSender, QueueStatus};
+
+mod queue;
+
+/// Where a pipeline's items come from.
+///
+/// This is implemented for closures that return the same thing as
+/// [`Source::produce`].
+pub trait Source: Send + 'static {
+    type Item: Send + 'static;
+
+    /// Makes the next item. `None` means there's nothing left, and the
+    /// pipeline winds down.
+    ///
+    /// # Errors
+    ///
+    /// Transient errors (see [`UsageError::is_transient`]) are logged and
+    /// skipped. Anything else stops the pipeline.
+    fn produce(&mut self) -> Result<Option<Self::Item>, UsageError>;
+}
+
+impl<Item, F> Source for F
+where
+    Item: Send + 'static,
+    F: FnMut() -> Result<Option<Item>, UsageError> + Send + 'static,
+{
+    type Item = Item;
+
+    #[inline]
+    fn produce(&mut self) -> Result<Option<Item>, UsageError> {
+        self()
+    }
+}
+
+/// A step in the middle of a pipeline, like a conversion or a detector.
+///
+/// This is implemented for closures that take an item and return an
+/// `Option`.
+pub trait Stage<In>: Send + 'static {
+    type Out: Send + 'static;
+
+    /// Turns one item into another. Returning `None` filters the item out.
+    fn process(&mut self, input: In) -> Option<Self::Out>;
+}
+
+impl<In, Out, F> Stage<In> for F
+where
+    Out: Send + 'static,
+    F: FnMut(In) -> Option<Out> + Send + 'static,
+{
+    type Out = Out;
+
+    #[inline]
+    fn process(&mut self, input: In) -> Option<Out> {
+        self(input)
+    }
+}
+
+/// The end of a pipeline, like a recorder or a display.
+///
+/// This is implemented for closures that take an item.
+pub trait Sink<In>: Send + 'static {
+    /// Does something with an item.
+    fn consume(&mut self, input: In);
+
+    /// Called once, after the last item, when the pipeline shuts down.
+    #[inline]
+    fn finish(&mut self) {}
+}
+
+impl<In, F> Sink<In> for F
+where
+    F: FnMut(In) + Send + 'static,
+{
+    #[inline]
+    fn consume(&mut self, input: In) {
+        self(input);
+    }
+}
+
+/// A source that reads frames from a capture device.
+///
+/// Each frame is copied once into a [`FramePool`] buffer, so the device can
+/// have its own buffer back right away.
+///
+/// Reads give up after [`CaptureSource::POLL_INTERVAL`], so a stalled device
+/// can't keep the pipeline from shutting down.
+#[derive(Debug)]
+pub struct CaptureSource<D, S> {
+    device: D,
+    pool: FramePool,
+    poll_interval: Duration,
+    source: PhantomData<fn() -> S>,
+}
+
+impl<D, S> CaptureSource<D, S> {
+    /// How long each read waits for a frame by default, before checking if
+    /// the pipeline is shutting down.
+    pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    /// Makes a source that reads from `device`, sharing frames through
+    /// `pool`.
+    #[inline]
+    pub const fn new(device: D, pool: FramePool) -> Self {
+        Self {
+            device,
+            pool,
+            poll_interval: Self::POLL_INTERVAL,
+            source: PhantomData,
+        }
+    }
+
+    /// Changes how long each read waits for a frame. Shorter intervals shut
+    /// down sooner, but wake up more often while the device is quiet.
+    #[inline]
+    #[must_use]
+    pub const fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+}
+
+impl<D, S> Source for CaptureSource<D, S>
+where
+    D: VideoCaptureStream<'static, 'static, S> + Send + 'static,
+    S: 'static,
+{
+    type Item = SharedFrame;
+
+    #[inline]
+    fn produce(&mut self) -> Result<Option<SharedFrame>, UsageError> {
+        // timeouts are transient, so the pipeline checks for a shutdown and
+        // tries again
+        let frame = self.device.read_frame_timeout(self.poll_interval)?;
+        Ok(Some(self.pool.share(&frame)))
+    }
+}
+
+/// How one of a pipeline's stages is doing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StageMetrics {
+    /// The stage's name.
+    pub name: String,
+    /// How many items the stage has handled.
+    pub processed: u64,
+    /// How many items the stage filtered out.
+    pub filtered: u64,
+    /// How many items were dropped from the stage's queue because it fell
+    /// behind. Sources don't have a queue.
+    pub dropped: u64,
+    /// How many items are waiting in the stage's queue right now.
+    pub queued: usize,
+    /// The total time spent handling items.
+    pub busy: Duration,
+    /// The longest time spent on one item.
+    pub slowest: Duration,
+}
+
+impl StageMetrics {
+    /// The average time spent on each item, if there were any.
+    #[inline]
+    pub fn mean_time(&self) -> Option<Duration> {
+        let count = u32::try_from(self.processed).unwrap_or(u32::MAX);
+        self.busy.checked_div(count)
+    }
+
+    /// Counts one handled item.
+    fn record(&mut self, took: Duration) {
+        self.processed = self.processed.saturating_add(1);
+        self.busy = self.busy.saturating_add(took);
+        self.slowest = self.slowest.max(took);
+    }
+}
+
+/// A running stage's thread, and what we know about it.
+struct Worker {
+    name: String,
+    thread: JoinHandle<Result<(), PipelineError>>,
+    metrics: Arc<Mutex<StageMetrics>>,
+    input: Option<Arc<dyn QueueStatus>>,
+}
+
+/// Starts a stage's thread once everything is linked up.
+type Spawner = Box<dyn FnOnce() -> Result<Worker, PipelineError>>;
+
+/// Links the last stage to whatever comes next.
+type Pending<T> = Box<dyn FnOnce(QueueSender<T>) -> Spawner>;
+
+/// Builds a pipeline, one stage at a time. Nothing runs until
+/// [`PipelineBuilder::sink`].
+///
+/// `T` is what the last stage makes.
+pub struct PipelineBuilder<T> {
+    spawners: Vec<Spawner>,
+    pending: Pending<T>,
+    stop: Arc<AtomicBool>,
+}
+
+/// A running pipeline.
+///
+/// Dropping it shuts it down, like [`Pipeline::shutdown`], but any errors are
+/// only logged.
+pub struct Pipeline {
+    workers: Vec<Worker>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Pipeline {
+    /// Starts building a pipeline that pulls items from `source`.
+    #[inline]
+    pub fn source<Src: Source>(name: &str, source: Src) -> PipelineBuilder<Src::Item> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (stage, flag) = (name.to_owned(), Arc::clone(&stop));
+
+        PipelineBuilder {
+            spawners: Vec::new(),
+            pending: Box::new(move |output| {
+                Box::new(move || spawn_source(stage, source, output, flag)) as Spawner
+            }),
+            stop,
+        }
+    }
+
+    /// Starts building a pipeline that reads frames from a capture device.
+    ///
+    /// Frames are shared through `pool`, so later stages get a
+    /// [`SharedFrame`]. See [`CaptureSource`] for more control over reads.
+    #[inline]
+    pub fn capture<D, S>(name: &str, device: D, pool: FramePool) -> PipelineBuilder<SharedFrame>
+    where
+        D: VideoCaptureStream<'static, 'static, S> + Send + 'static,
+        S: 'static,
+    {
+        Self::source(name, CaptureSource::new(device, pool))
+    }
+
+    /// Checks how each stage is doing, from the source to the sink.
+    #[inline]
+    pub fn metrics(&self) -> Vec<StageMetrics> {
+        self.workers.iter().map(Worker::metrics).collect()
+    }
+
+    /// Checks if every stage has stopped, like after the source ran out.
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        self.workers
+            .iter()
+            .all(|worker| worker.thread.is_finished())
+    }
+
+    /// Stops the source, lets the other stages finish what's queued, then
+    /// waits for all of them.
+    ///
+    /// # Errors
+    ///
+    /// This returns the first error from any stage, like a failed source or
+    /// a panic.
+    #[inline]
+    pub fn shutdown(mut self) -> Result<(), PipelineError> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.join()
+    }
+
+    /// Waits for every stage to finish on its own, like when the source runs
+    /// out.
+    ///
+    /// # Errors
+    ///
+    /// This fails like [`Pipeline::shutdown`].
+    #[inline]
+    pub fn wait(mut self) -> Result<(), PipelineError> {
+        self.join()
+    }
+
+    fn join(&mut self) -> Result<(), PipelineError> {
+        let mut first_err = None;
+        for worker in core::mem::take(&mut self.workers) {
+            let result = worker.thread.join().unwrap_or_else(|_panic| {
+                Err(PipelineError::Panicked {
+                    stage: worker.name.clone(),
+                })
+            });
+            if let Err(e) = result {
+                tracing::debug!("pipeline stage `{}` stopped. See: {e}", worker.name);
+                first_err.get_or_insert(e);
+            }
+        }
+
+        first_err.map_or(Ok(()), Err)
+    }
+}
+
+impl Drop for Pipeline {
+    #[inline]
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Err(e) = self.join() {
+            tracing::warn!("a pipeline stopped with an error. See: {e}");
+        }
+    }
+}
+
+impl core::fmt::Debug for Pipeline {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Pipeline")
+            .field("metrics", &self.metrics())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Send + 'static> PipelineBuilder<T> {
+    /// Adds a stage that takes what the last one made.
+    ///
+    /// `queue` sets up the queue in front of this stage.
+    #[inline]
+    #[must_use]
+    pub fn stage<St: Stage<T>>(
+        mut self,
+        name: &str,
+        queue: QueueOptions,
+        stage: St,
+    ) -> PipelineBuilder<St::Out> {
+        let (sender, receiver) = queue::bounded(queue);
+        self.spawners.push((self.pending)(sender));
+
+        let stage_name = name.to_owned();
+        PipelineBuilder {
+            spawners: self.spawners,
+            pending: Box::new(move |output| {
+                Box::new(move || spawn_stage(stage_name, stage, receiver, output)) as Spawner
+            }),
+            stop: self.stop,
+        }
+    }
+
+    /// Ends the pipeline with a sink, then starts every stage.
+    ///
+    /// `queue` sets up the queue in front of the sink.
+    ///
+    /// # Errors
+    ///
+    /// This fails if a stage's thread can't be started. Any stages that did
+    /// start are shut down again.
+    #[inline]
+    pub fn sink<Sk: Sink<T>>(
+        mut self,
+        name: &str,
+        queue: QueueOptions,
+        sink: Sk,
+    ) -> Result<Pipeline, PipelineError> {
+        let (sender, receiver) = queue::bounded(queue);
+        self.spawners.push((self.pending)(sender));
+
+        let sink_name = name.to_owned();
+        self.spawners
+            .push(Box::new(move || spawn_sink(sink_name, sink, receiver)));
+
+        let mut pipeline = Pipeline {
+            workers: Vec::with_capacity(self.spawners.len()),
+            stop: self.stop,
+        };
+        for spawner in self.spawners {
+            // dropping the pipeline stops whatever already started
+            pipeline.workers.push(spawner()?);
+        }
+
+        Ok(pipeline)
+    }
+}
+
+impl<T> core::fmt::Debug for PipelineBuilder<T> {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PipelineBuilder")
+            .field("stages", &self.spawners.len().saturating_add(1))
+            .finish_non_exhaustive()
+    }
+}
+
+impl Worker {
+    fn metrics(&self) -> StageMetrics {
+        let mut metrics = lock(&self.metrics).clone();
+        if let Some(ref input) = self.input {
+            metrics.dropped = input.dropped();
+            metrics.queued = input.len();
+        }
+        metrics
+    }
+}
+
+/// Starts a named thread for a stage.
+fn spawn_worker<F>(
+    name: String,
+    input: Option<Arc<dyn QueueStatus>>,
+    work: F,
+) -> Result<Worker, PipelineError>
+where
+    F: FnOnce(&Mutex<StageMetrics>) -> Result<(), PipelineError> + Send + 'static,
+{
+    let metrics = Arc::new(Mutex::new(StageMetrics {
+        name: name.clone(),
+        ..StageMetrics::default()
+    }));
+    let shared = Arc::clone(&metrics);
+
+    let thread = thread::Builder::new()
+        .name(format!("pipeline-{name}"))
+        .spawn(move || work(&shared))
+        .map_err(|e| PipelineError::CouldntSpawn {
+            stage: name.clone(),
+            err_msg: e.to_string(),
+        })?;
+
+    Ok(Worker {
+        name,
+        thread,
+        metrics,
+        input,
+    })
+}
+
+fn spawn_source<Src: Source>(
+    name: String,
+    mut source: Src,
+    output: QueueSender<Src::Item>,
+    stop: Arc<AtomicBool>,
+) -> Result<Worker, PipelineError> {
+    let stage = name.clone();
+    spawn_worker(name, None, move |metrics| {
+        while !stop.load(Ordering::Relaxed) {
+            let started = Instant::now();
+            let item = match source.produce() {
+                Ok(Some(item)) => item,
+                Ok(None) => break,
+                // a dropped frame shouldn't end the whole pipeline
+                Err(e) if e.is_transient() => {
+                    tracing::debug!("pipeline source `{stage}` skipped an item. See: {e}");
+                    continue;
+                }
+                Err(err) => return Err(PipelineError::SourceFailed { stage, err }),
+            };
+            lock(metrics).record(started.elapsed());
+
+            // nobody is listening anymore
+            if output.send(item).is_err() {
+                break;
+            }
+        }
+
+        Ok(())
+    })
+}
+
+fn spawn_stage<In, St>(
+    name: String,
+    mut stage: St,
+    input: QueueReceiver<In>,
+    output: QueueSender<St::Out>,
+) -> Result<Worker, PipelineError>
+where
+    In: Send + 'static,
+    St: Stage<In>,
+{
+    spawn_worker(name, Some(input.status()), move |metrics| {
+        while let Some(item) = input.recv() {
+            let started = Instant::now();
+            let processed = stage.process(item);
+            let mut stats = lock(metrics);
+            stats.record(started.elapsed());
+
+            match processed {
+                Some(out) => {
+                    drop(stats);
+                    if output.send(out).is_err() {
+                        break;
+                    }
+                }
+                None => stats.filtered = stats.filtered.saturating_add(1),
+            }
+        }
+
+        Ok(())
+    })
+}
+
+fn spawn_sink<In, Sk>(
+    name: String,
+    mut sink: Sk,
+    input: QueueReceiver<In>,
+) -> Result<Worker, PipelineError>
+where
+    In: Send + 'static,
+    Sk: Sink<In>,
+{
+    spawn_worker(name, Some(input.status()), move |metrics| {
+        while let Some(item) = input.recv() {
+            let started = Instant::now();
+            sink.consume(item);
+            lock(metrics).record(started.elapsed());
+        }
+
+        sink.finish();
+        Ok(())
+    })
+}
+
+/// Locks a mutex, even if another thread panicked while holding it.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::frame::Frame;
+
+    #[test]
+    fn items_flow_through_every_stage() {
+        let mut next = 0_u32;
+        let source = move || {
+            next = next.saturating_add(1);
+            Ok((next <= 10).then_some(next))
+        };
+
+        let (collected, results) = mpsc::channel();
+        let pipeline = Pipeline::source("count", source)
+            .stage("double", QueueOptions::default(), |n: u32| Some(n * 2))
+            .stage("evens", QueueOptions::default(), |n: u32| {
+                n.is_multiple_of(4).then_some(n)
+            })
+            .sink("collect", QueueOptions::default(), move |n: u32| {
+                collected.send(n).unwrap();
+            })
+            .unwrap();
+
+        let metrics = loop {
+            if pipeline.is_finished() {
+                break pipeline.metrics();
+            }
+            thread::yield_now();
+        };
+        pipeline.wait().unwrap();
+
+        assert_eq!(
+            results.iter().collect::<Vec<_>>(),
+            vec![4, 8, 12, 16, 20],
+            "every item went through in order"
+        );
+        let names: Vec<&str> = metrics.iter().map(|stage| stage.name.as_str()).collect();
+        assert_eq!(names, ["count", "double", "evens", "collect"]);
+        let filtered = metrics.get(2).unwrap();
+        assert_eq!((filtered.processed, filtered.filtered), (10, 5));
+        assert!(filtered.mean_time().is_some(), "stages are timed");
+        assert_eq!(metrics.get(3).unwrap().processed, 5);
+    }
+
+    #[test]
+    fn shutdown_stops_endless_sources() {
+        let source = || Ok(Some(0_u8));
+        let (seen, seen_rx) = mpsc::channel();
+        let pipeline = Pipeline::source("zeroes", source)
+            .sink(
+                "slow",
+                QueueOptions::new(1, DropPolicy::DropOldest),
+                move |_: u8| {
+                    seen.send(()).unwrap();
+                    thread::sleep(Duration::from_millis(1));
+                },
+            )
+            .unwrap();
+
+        seen_rx.recv().unwrap();
+        pipeline.shutdown().unwrap();
+    }
+
+    /// A camera that never sends a frame.
+    struct StalledCamera;
+
+    impl VideoCaptureStream<'static, 'static, ()> for StalledCamera {
+        type Buffer = ();
+        type Source = ();
+        type SourceInput = ();
+
+        fn read_frame<'func>(&'func mut self) -> Result<Frame<'func>, UsageError>
+        where
+            'static: 'func,
+        {
+            // long enough to hang the test if the source ever calls this
+            self.read_frame_timeout(Duration::MAX)
+        }
+
+        fn read_frame_timeout<'func>(
+            &'func mut self,
+            timeout: Duration,
+        ) -> Result<Frame<'func>, UsageError>
+        where
+            'static: 'func,
+        {
+            thread::sleep(timeout);
+            Err(UsageError::Timeout {
+                source: String::from("/dev/video0"),
+                errno: 110,
+            })
+        }
+    }
+
+    #[test]
+    fn shutdown_stops_stalled_cameras() {
+        let source = CaptureSource::new(StalledCamera, FramePool::new(1))
+            .poll_interval(Duration::from_millis(1));
+        let pipeline = Pipeline::source("stalled", source)
+            .sink("ignore", QueueOptions::default(), |_: SharedFrame| {})
+            .unwrap();
+
+        thread::sleep(Duration::from_millis(5));
+        assert!(!pipeline.is_finished(), "the camera is still waiting");
+        pipeline.shutdown().unwrap();
+    }
+
+    #[test]
+    fn source_errors_end_the_pipeline() {
+        let mut calls = 0_u8;
+        let source = move || {
+            calls = calls.saturating_add(1);
+            match calls {
+                1 => Err(UsageError::Timeout {
+                    source: String::from("/dev/video0"),
+                    errno: 110,
+                }),
+                2 => Ok(Some(calls)),
+                _ => Err(UsageError::Disconnected {
+                    source: String::from("/dev/video0"),
+                    errno: 19,
+                }),
+            }
+        };
+
+        let pipeline = Pipeline::source("flaky", source)
+            .sink("ignore", QueueOptions::default(), |_: u8| {})
+            .unwrap();
+        let err = pipeline.wait().unwrap_err();
+        assert!(
+            matches!(err, PipelineError::SourceFailed { err: ref source_err, .. } if source_err.is_disconnect()),
+            "timeouts are skipped, but disconnects aren't: {err}"
+        );
+    }
+}
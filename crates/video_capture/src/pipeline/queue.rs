@@ -0,0 +1,217 @@
+//! Bounded queues between pipeline stages.
+//!
+//! Each queue has one sender and one receiver. It closes when either side
+//! is dropped: a closed sender lets the receiver drain what's left, and a
+//! closed receiver makes every later send fail.
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+
+use crate::bounded::{Bounded, Signaled};
+
+pub use crate::bounded::DropPolicy;
+
+/// How big a queue is, and what it does when it fills up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueueOptions {
+    /// The most items the queue holds. Zero is treated as one.
+    pub capacity: usize,
+    /// What to do once it's full.
+    pub policy: DropPolicy,
+}
+
+impl QueueOptions {
+    /// Makes options for a queue of `capacity` items.
+    #[inline]
+    #[must_use]
+    pub const fn new(capacity: usize, policy: DropPolicy) -> Self {
+        Self { capacity, policy }
+    }
+}
+
+impl Default for QueueOptions {
+    /// A small queue that blocks when it's full.
+    #[inline]
+    fn default() -> Self {
+        Self::new(4, DropPolicy::Block)
+    }
+}
+
+/// What a pipeline can see about a queue, without knowing its item type.
+pub(super) trait QueueStatus: Send + Sync {
+    /// How many items are waiting.
+    fn len(&self) -> usize;
+    /// How many items the policy threw away.
+    fn dropped(&self) -> u64;
+}
+
+/// Signaled whenever an item goes in or out, or either side leaves.
+type Shared<T> = Signaled<State<T>>;
+
+struct State<T> {
+    items: Bounded<T>,
+    sender_gone: bool,
+    receiver_gone: bool,
+}
+
+impl<T: Send> QueueStatus for Shared<T> {
+    fn len(&self) -> usize {
+        self.lock().items.len()
+    }
+
+    fn dropped(&self) -> u64 {
+        self.lock().items.dropped()
+    }
+}
+
+/// Makes a queue with the given options.
+pub(super) fn bounded<T>(options: QueueOptions) -> (QueueSender<T>, QueueReceiver<T>) {
+    let shared = Arc::new(Signaled::new(State {
+        items: Bounded::new(options.capacity, options.policy),
+        sender_gone: false,
+        receiver_gone: false,
+    }));
+
+    (
+        QueueSender {
+            shared: Arc::clone(&shared),
+        },
+        QueueReceiver { shared },
+    )
+}
+
+/// The sending side of a queue.
+pub(super) struct QueueSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> QueueSender<T> {
+    /// Puts an item in the queue, following its drop policy if it's full.
+    ///
+    /// Gives the item back if the receiver is gone.
+    pub(super) fn send(&self, item: T) -> Result<(), T> {
+        let mut state = self.shared.lock();
+        let mut pending = item;
+        loop {
+            if state.receiver_gone {
+                return Err(pending);
+            }
+            match state.items.push(pending) {
+                Ok(()) => break,
+                Err(full) => {
+                    pending = full;
+                    state = self.shared.wait(state);
+                }
+            }
+        }
+
+        drop(state);
+        self.shared.notify();
+        Ok(())
+    }
+}
+
+impl<T> Drop for QueueSender<T> {
+    fn drop(&mut self) {
+        self.shared.lock().sender_gone = true;
+        self.shared.notify();
+    }
+}
+
+/// The receiving side of a queue.
+pub(super) struct QueueReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: Send + 'static> QueueReceiver<T> {
+    /// Waits for the next item. Returns `None` once the sender is gone and
+    /// the queue is empty.
+    pub(super) fn recv(&self) -> Option<T> {
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(item) = state.items.pop() {
+                drop(state);
+                // a blocked sender might be waiting for this room
+                self.shared.notify();
+                return Some(item);
+            }
+            if state.sender_gone {
+                return None;
+            }
+
+            state = self.shared.wait(state);
+        }
+    }
+
+    /// A view of the queue for metrics.
+    pub(super) fn status(&self) -> Arc<dyn QueueStatus> {
+        Arc::clone(&self.shared) as Arc<dyn QueueStatus>
+    }
+}
+
+impl<T> Drop for QueueReceiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.receiver_gone = true;
+        state.items.clear();
+        drop(state);
+        self.shared.notify();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn full_queues_follow_their_policy() {
+        let (newest_tx, newest_rx) = bounded(QueueOptions::new(2, DropPolicy::DropNewest));
+        let (oldest_tx, oldest_rx) = bounded(QueueOptions::new(2, DropPolicy::DropOldest));
+        for item in 0..5 {
+            newest_tx.send(item).unwrap();
+            oldest_tx.send(item).unwrap();
+        }
+        drop((newest_tx, oldest_tx));
+
+        assert_eq!(newest_rx.status().dropped(), 3);
+        assert_eq!(
+            core::iter::from_fn(|| newest_rx.recv()).collect::<Vec<_>>(),
+            vec![0, 1],
+            "new items were dropped"
+        );
+        assert_eq!(
+            core::iter::from_fn(|| oldest_rx.recv()).collect::<Vec<_>>(),
+            vec![3, 4],
+            "old items were dropped"
+        );
+    }
+
+    #[test]
+    fn blocking_queues_wait_for_room() {
+        let (tx, rx) = bounded(QueueOptions::new(1, DropPolicy::Block));
+        let sender = thread::spawn(move || {
+            for item in 0..10 {
+                tx.send(item).unwrap();
+            }
+        });
+
+        let received: Vec<i32> = core::iter::from_fn(|| rx.recv()).collect();
+        sender.join().unwrap();
+        assert_eq!(received, (0..10).collect::<Vec<_>>(), "nothing was lost");
+        assert_eq!(rx.status().dropped(), 0);
+    }
+
+    #[test]
+    fn sends_fail_once_the_receiver_is_gone() {
+        let (tx, rx) = bounded(QueueOptions::new(1, DropPolicy::Block));
+        tx.send(1).unwrap();
+
+        // the sender is blocked on a full queue until the receiver leaves
+        let sender = thread::spawn(move || tx.send(2));
+        drop(rx);
+        assert_eq!(sender.join().unwrap(), Err(2));
+    }
+}
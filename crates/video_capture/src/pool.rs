@@ -0,0 +1,609 @@
+//! Sharing frames between threads without copying them for each reader.
+//!
+//! A device's buffers go back to the driver on its next read, so a frame has
+//! to be copied out before anyone else can keep it. A [`FrameBroadcast`]
+//! does that once per frame, into a buffer from its [`FramePool`]. Each
+//! [`FrameSubscriber`] then gets a [`SharedFrame`] that points at those same
+//! bytes. When the last reader drops it, the buffer goes back to the pool
+//! for a later frame.
+//!
+//! ```no_run
+//! use std::thread;
+//!
+//! use serumcv_video_capture::backends::v4l::V4LVideoCaptureDevice;
+//! use serumcv_video_capture::pool::{FrameBroadcast, FramePool, LagPolicy};
+//! use serumcv_video_capture::VideoCaptureConnection as _;
+//!
+//! let mut camera = V4LVideoCaptureDevice::new("/dev/video0".into()).unwrap();
+//! let broadcast = FrameBroadcast::new(FramePool::new(8));
+//!
+//! // the recorder can't miss frames, but the preview can
+//! for (name, subscriber) in [
+//!     ("recorder", broadcast.subscribe(4, LagPolicy::Block)),
+//!     ("preview", broadcast.subscribe(1, LagPolicy::Skip)),
+//! ] {
+//!     thread::spawn(move || {
+//!         while let Ok(shared) = subscriber.recv() {
+//!             println!("{name} got frame {}", shared.metadata().sequence);
+//!         }
+//!     });
+//! }
+//!
+//! loop {
+//!     broadcast.publish_from(&mut camera).unwrap();
+//! }
+//! ```
+
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use core::fmt::Debug;
+use core::time::Duration;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Instant;
+
+use crate::bounded::{Bounded, DropPolicy, Signaled};
+use crate::config::{Format, SpecificResolution};
+use crate::error::{VideoCaptureRecvError as RecvError, VideoCaptureUsageError as UsageError};
+use crate::frame::{Frame, FrameMetadata, FramePlane, FramePlanes, MAX_FRAME_PLANES};
+use crate::VideoCaptureStream;
+
+/// Buffers for frames that are shared between readers.
+///
+/// Buffers come back when every reader is done with a frame, so a steady
+/// stream of frames stops allocating once the pool has warmed up. This is
+/// cheap to clone, and clones share the same buffers.
+#[derive(Clone, Debug)]
+pub struct FramePool {
+    inner: Arc<PoolInner>,
+}
+
+#[derive(Debug)]
+struct PoolInner {
+    idle: Mutex<Vec<Vec<u8>>>,
+    /// The most idle buffers to keep around. Extras are freed.
+    max_idle: usize,
+    /// How many buffers were ever made.
+    allocations: Mutex<u64>,
+}
+
+impl FramePool {
+    /// Makes an empty pool that keeps up to `max_idle` buffers around
+    /// between frames.
+    ///
+    /// Buffers are only made when a frame needs one, so a pool never runs
+    /// out. It just allocates more when readers hold onto lots of frames.
+    #[inline]
+    #[must_use]
+    pub fn new(max_idle: usize) -> Self {
+        Self {
+            inner: Arc::new(PoolInner {
+                idle: Mutex::new(Vec::with_capacity(max_idle)),
+                max_idle,
+                allocations: Mutex::new(0),
+            }),
+        }
+    }
+
+    /// Copies a frame into one of the pool's buffers, so it can be shared.
+    ///
+    /// Each of the frame's planes is copied, one after the other. The
+    /// shared frame's `data` is its first plane, like multi-planar frames.
+    #[inline]
+    pub fn share(&self, frame: &Frame<'_>) -> SharedFrame {
+        let single = [FramePlane {
+            data: frame.data,
+            stride: frame.stride,
+        }];
+        let planes: &[FramePlane<'_>] = if frame.planes.is_empty() {
+            &single
+        } else {
+            &frame.planes
+        };
+
+        let mut bytes = self.take(planes.iter().map(|plane| plane.data.len()).sum());
+        let mut ranges = [PlaneRange::default(); MAX_FRAME_PLANES];
+        for (range, plane) in ranges.iter_mut().zip(planes) {
+            let start = bytes.len();
+            bytes.extend_from_slice(plane.data);
+            *range = PlaneRange {
+                start,
+                end: bytes.len(),
+                stride: plane.stride,
+            };
+        }
+
+        SharedFrame {
+            inner: Arc::new(SharedBuffer {
+                bytes,
+                planes: ranges,
+                plane_count: planes.len().min(MAX_FRAME_PLANES),
+                format: frame.format,
+                resolution: frame.resolution,
+                metadata: frame.metadata,
+                pool: Arc::downgrade(&self.inner),
+            }),
+        }
+    }
+
+    /// Grabs an idle buffer, or makes one if there aren't any. It's empty,
+    /// with room for at least `len` bytes.
+    fn take(&self, len: usize) -> Vec<u8> {
+        let recycled = lock(&self.inner.idle).pop();
+        let mut bytes = recycled.unwrap_or_else(|| {
+            let mut allocations = lock(&self.inner.allocations);
+            *allocations = allocations.saturating_add(1);
+            Vec::new()
+        });
+
+        bytes.clear();
+        bytes.reserve(len);
+        bytes
+    }
+
+    /// How many buffers are waiting to be reused.
+    #[inline]
+    pub fn idle(&self) -> usize {
+        lock(&self.inner.idle).len()
+    }
+
+    /// How many buffers this pool has ever made.
+    ///
+    /// If this keeps going up, readers are holding onto frames for longer
+    /// than `max_idle` frames' worth of time.
+    #[inline]
+    pub fn allocations(&self) -> u64 {
+        *lock(&self.inner.allocations)
+    }
+}
+
+impl PoolInner {
+    /// Takes a buffer back, unless there are enough idle ones already.
+    fn recycle(&self, bytes: Vec<u8>) {
+        let mut idle = lock(&self.idle);
+        if idle.len() < self.max_idle {
+            idle.push(bytes);
+        }
+    }
+}
+
+/// Where one plane sits in a shared frame's buffer.
+#[derive(Clone, Copy, Debug, Default)]
+struct PlaneRange {
+    start: usize,
+    end: usize,
+    stride: usize,
+}
+
+/// A frame that any number of readers can hold at once.
+///
+/// Cloning this only bumps a reference count. The frame's buffer goes back
+/// to its pool once every clone is dropped.
+#[derive(Clone)]
+pub struct SharedFrame {
+    inner: Arc<SharedBuffer>,
+}
+
+struct SharedBuffer {
+    bytes: Vec<u8>,
+    planes: [PlaneRange; MAX_FRAME_PLANES],
+    plane_count: usize,
+    format: Format,
+    resolution: SpecificResolution,
+    metadata: FrameMetadata,
+    pool: Weak<PoolInner>,
+}
+
+impl SharedFrame {
+    /// Looks at the shared bytes as a regular frame.
+    #[inline]
+    pub fn frame(&self) -> Frame<'_> {
+        let inner = &*self.inner;
+        let plane_list: Vec<FramePlane<'_>> = inner
+            .planes
+            .iter()
+            .take(inner.plane_count)
+            .map(|range| FramePlane {
+                data: inner.bytes.get(range.start..range.end).unwrap_or_default(),
+                stride: range.stride,
+            })
+            .collect();
+        let first = plane_list.first().copied().unwrap_or_default();
+
+        Frame {
+            data: first.data,
+            format: inner.format,
+            resolution: inner.resolution,
+            stride: first.stride,
+            planes: FramePlanes::from_slice(&plane_list).unwrap_or_default(),
+            metadata: inner.metadata,
+        }
+    }
+
+    /// Info about the frame, from the device that captured it.
+    #[inline]
+    pub fn metadata(&self) -> &FrameMetadata {
+        &self.inner.metadata
+    }
+
+    /// How many handles to this frame exist right now.
+    #[inline]
+    pub fn readers(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+
+    /// Checks if two handles point at the same frame.
+    #[inline]
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        Arc::ptr_eq(&this.inner, &other.inner)
+    }
+}
+
+impl Debug for SharedFrame {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SharedFrame")
+            .field("format", &self.inner.format)
+            .field("resolution", &self.inner.resolution)
+            .field("metadata", &self.inner.metadata)
+            .field("readers", &self.readers())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for SharedBuffer {
+    fn drop(&mut self) {
+        // the pool might be gone already. then the buffer is just freed
+        if let Some(pool) = self.pool.upgrade() {
+            pool.recycle(core::mem::take(&mut self.bytes));
+        }
+    }
+}
+
+/// What a subscriber's queue does when it's full and another frame arrives.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LagPolicy {
+    /// Drop the subscriber's oldest frame to make room. The publisher never
+    /// waits, and [`FrameSubscriber::missed`] counts what was dropped.
+    #[default]
+    Skip,
+    /// Make the publisher wait until the subscriber reads a frame.
+    ///
+    /// This slows everyone down to the slowest blocking subscriber, and the
+    /// device itself might drop frames while the publisher waits.
+    Block,
+}
+
+/// Sends each frame to every subscriber, copying it only once.
+///
+/// Dropping the broadcast closes it. Subscribers can still read the frames
+/// they have queued, then get [`RecvError::Closed`].
+#[derive(Debug)]
+pub struct FrameBroadcast {
+    pool: FramePool,
+    channel: Arc<Channel>,
+}
+
+/// Signaled whenever a frame is sent or read, a subscriber leaves, or the
+/// broadcast closes.
+type Channel = Signaled<ChannelState>;
+
+#[derive(Debug, Default)]
+struct ChannelState {
+    queues: BTreeMap<u64, Bounded<SharedFrame>>,
+    next_id: u64,
+    closed: bool,
+}
+
+impl From<LagPolicy> for DropPolicy {
+    #[inline]
+    fn from(policy: LagPolicy) -> Self {
+        match policy {
+            LagPolicy::Skip => Self::DropOldest,
+            LagPolicy::Block => Self::Block,
+        }
+    }
+}
+
+impl FrameBroadcast {
+    /// Makes a broadcast that copies frames into buffers from `pool`.
+    #[inline]
+    #[must_use]
+    pub fn new(pool: FramePool) -> Self {
+        Self {
+            pool,
+            channel: Arc::default(),
+        }
+    }
+
+    /// Adds a subscriber that queues up to `depth` frames, and uses `policy`
+    /// once its queue is full.
+    ///
+    /// A depth of zero is treated as one. Subscribers only get frames that
+    /// are published after they subscribe.
+    #[inline]
+    pub fn subscribe(&self, depth: usize, policy: LagPolicy) -> FrameSubscriber {
+        let mut state = self.channel.lock();
+        let id = state.next_id;
+        state.next_id = state.next_id.wrapping_add(1);
+        state.queues.insert(id, Bounded::new(depth, policy.into()));
+
+        FrameSubscriber {
+            id,
+            channel: Arc::clone(&self.channel),
+        }
+    }
+
+    /// Copies a frame into the pool, then sends it to every subscriber.
+    ///
+    /// This waits while any [`LagPolicy::Block`] subscriber has a full
+    /// queue. Returns how many subscribers got the frame.
+    #[inline]
+    pub fn publish(&self, frame: &Frame<'_>) -> usize {
+        self.publish_shared(&self.pool.share(frame))
+    }
+
+    /// Sends a frame that's already shared to every subscriber, like
+    /// [`FrameBroadcast::publish`].
+    #[inline]
+    pub fn publish_shared(&self, frame: &SharedFrame) -> usize {
+        let mut state = self.channel.lock();
+        while state.queues.values().any(Bounded::must_wait) {
+            state = self.channel.wait(state);
+        }
+
+        // nobody has to wait now, so every push goes through
+        let delivered = state
+            .queues
+            .values_mut()
+            .filter_map(|queue| queue.push(frame.clone()).ok())
+            .count();
+        drop(state);
+
+        self.channel.notify();
+        delivered
+    }
+
+    /// Reads a frame from a device and publishes it.
+    ///
+    /// This is the one place that frames get copied, so the device can have
+    /// its buffer back right away.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the device can't read a frame.
+    #[inline]
+    pub fn publish_from<'path, 'conn, S, D>(
+        &self,
+        device: &'conn mut D,
+    ) -> Result<usize, UsageError>
+    where
+        D: VideoCaptureStream<'path, 'conn, S>,
+        'path: 'conn,
+        S: 'path,
+    {
+        let frame = device.read_frame()?;
+        Ok(self.publish(&frame))
+    }
+
+    /// How many subscribers are listening.
+    #[inline]
+    pub fn subscribers(&self) -> usize {
+        self.channel.lock().queues.len()
+    }
+
+    /// The pool that frames are copied into.
+    #[inline]
+    pub const fn pool(&self) -> &FramePool {
+        &self.pool
+    }
+}
+
+impl Drop for FrameBroadcast {
+    #[inline]
+    fn drop(&mut self) {
+        self.channel.lock().closed = true;
+        self.channel.notify();
+    }
+}
+
+/// Receives frames from a [`FrameBroadcast`].
+///
+/// Dropping a subscriber unsubscribes it, which also frees up a publisher
+/// that was waiting on it.
+#[derive(Debug)]
+pub struct FrameSubscriber {
+    id: u64,
+    channel: Arc<Channel>,
+}
+
+impl FrameSubscriber {
+    /// Waits for the next frame.
+    ///
+    /// # Errors
+    ///
+    /// This fails with [`RecvError::Closed`] once the broadcast is gone and
+    /// every queued frame has been read.
+    #[inline]
+    pub fn recv(&self) -> Result<SharedFrame, RecvError> {
+        self.recv_until(None)
+    }
+
+    /// Waits at most `timeout` for the next frame.
+    ///
+    /// # Errors
+    ///
+    /// This fails with [`RecvError::Timeout`] if no frame arrives in time,
+    /// or like [`FrameSubscriber::recv`].
+    #[inline]
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<SharedFrame, RecvError> {
+        // timeouts too long to add are as good as waiting forever
+        self.recv_until(Instant::now().checked_add(timeout))
+    }
+
+    /// Takes the next frame only if one is queued right now.
+    ///
+    /// # Errors
+    ///
+    /// This fails with [`RecvError::Empty`] if there's no frame yet, or like
+    /// [`FrameSubscriber::recv`].
+    #[inline]
+    pub fn try_recv(&self) -> Result<SharedFrame, RecvError> {
+        match self.recv_until(Some(Instant::now())) {
+            Err(RecvError::Timeout) => Err(RecvError::Empty),
+            other => other,
+        }
+    }
+
+    fn recv_until(&self, deadline: Option<Instant>) -> Result<SharedFrame, RecvError> {
+        let mut state = self.channel.lock();
+        loop {
+            let queue = state.queues.get_mut(&self.id).ok_or(RecvError::Closed)?;
+            if let Some(frame) = queue.pop() {
+                drop(state);
+                // a blocked publisher might be waiting for this room
+                self.channel.notify();
+                return Ok(frame);
+            }
+            if state.closed {
+                return Err(RecvError::Closed);
+            }
+
+            state = match deadline {
+                None => self.channel.wait(state),
+                Some(end) => {
+                    let remaining = end.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Err(RecvError::Timeout);
+                    }
+                    self.channel.wait_timeout(state, remaining)
+                }
+            };
+        }
+    }
+
+    /// How many frames were dropped from this subscriber's queue because
+    /// it fell behind.
+    #[inline]
+    pub fn missed(&self) -> u64 {
+        self.channel
+            .lock()
+            .queues
+            .get(&self.id)
+            .map_or(0, Bounded::dropped)
+    }
+
+    /// How many frames are waiting to be read.
+    #[inline]
+    pub fn pending(&self) -> usize {
+        self.channel
+            .lock()
+            .queues
+            .get(&self.id)
+            .map_or(0, Bounded::len)
+    }
+}
+
+impl Drop for FrameSubscriber {
+    #[inline]
+    fn drop(&mut self) {
+        self.channel.lock().queues.remove(&self.id);
+        self.channel.notify();
+    }
+}
+
+/// Locks a mutex, even if another thread panicked while holding it.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    fn frame(data: &[u8], sequence: u32) -> Frame<'_> {
+        Frame {
+            data,
+            format: Format::GREY,
+            resolution: SpecificResolution::new(4, 2),
+            stride: 4,
+            planes: FramePlanes::single(data, 4),
+            metadata: FrameMetadata {
+                sequence,
+                ..FrameMetadata::default()
+            },
+        }
+    }
+
+    #[test]
+    fn subscribers_share_one_copy() {
+        let broadcast = FrameBroadcast::new(FramePool::new(2));
+        let first = broadcast.subscribe(2, LagPolicy::Skip);
+        let second = broadcast.subscribe(2, LagPolicy::Skip);
+
+        let data: Vec<u8> = (0..8).collect();
+        assert_eq!(broadcast.publish(&frame(&data, 0)), 2, "both subscribed");
+
+        let (a, b) = (first.recv().unwrap(), second.recv().unwrap());
+        assert!(SharedFrame::ptr_eq(&a, &b), "no copy per subscriber");
+        assert_eq!(a.frame().data, data.as_slice());
+        assert_eq!(a.frame().planes.len(), 1);
+        assert_eq!(a.readers(), 2);
+
+        drop((a, b));
+        assert_eq!(broadcast.pool().idle(), 1, "the buffer came back");
+        broadcast.publish(&frame(&data, 1));
+        assert_eq!(
+            broadcast.pool().allocations(),
+            1,
+            "the next frame reused it"
+        );
+    }
+
+    #[test]
+    fn slow_subscribers_skip_old_frames() {
+        let broadcast = FrameBroadcast::new(FramePool::new(4));
+        let slow = broadcast.subscribe(2, LagPolicy::Skip);
+
+        for sequence in 0..5 {
+            broadcast.publish(&frame(&[0; 8], sequence));
+        }
+
+        assert_eq!(slow.missed(), 3, "three frames didn't fit");
+        assert_eq!(slow.recv().unwrap().metadata().sequence, 3);
+        assert_eq!(slow.recv().unwrap().metadata().sequence, 4);
+        assert_eq!(slow.try_recv().err(), Some(RecvError::Empty));
+        assert_eq!(
+            slow.recv_timeout(Duration::from_millis(1)).err(),
+            Some(RecvError::Timeout)
+        );
+
+        drop(broadcast);
+        assert_eq!(slow.recv().err(), Some(RecvError::Closed));
+    }
+
+    #[test]
+    fn blocking_subscribers_get_every_frame() {
+        let broadcast = FrameBroadcast::new(FramePool::new(1));
+        let careful = broadcast.subscribe(1, LagPolicy::Block);
+
+        let publisher = thread::spawn(move || {
+            for sequence in 0..10 {
+                broadcast.publish(&frame(&[0; 8], sequence));
+            }
+        });
+
+        let sequences: Vec<u32> = (0..10)
+            .map(|_| careful.recv().unwrap().metadata().sequence)
+            .collect();
+        publisher.join().unwrap();
+
+        assert_eq!(sequences, (0..10).collect::<Vec<_>>(), "nothing skipped");
+        assert_eq!(careful.missed(), 0);
+        assert_eq!(careful.recv().err(), Some(RecvError::Closed));
+    }
+}
@@ -1,11 +1,35 @@
 //! The useful traits and types from the `serumcv_video_capture` crate.
 
+pub use super::auto::{
+    AutoExposure, AutoExposureOptions, AutoStatus, AutoWhiteBalance, AutoWhiteBalanceOptions,
+    ConvergenceLimits, ExposureMetering, FrameStatistics, MeteringRegion, PropertyRange,
+    WhiteBalanceControls,
+};
 pub use super::backends::Backend;
 pub use super::config::{
-    Format, Framerate, FramerateConsts, ResolutionSetting, SpecificResolution,
-    VideoCaptureConfiguration, VideoCaptureImageConfiguration, VideoCaptureProperty,
+    parse_framerate, Format, Framerate, FramerateConsts, Orientation, ResolutionSetting,
+    SpecificResolution, VideoCaptureConfiguration, VideoCaptureImageConfiguration,
+    VideoCaptureImageConstraints, VideoCaptureProfile, VideoCaptureProfiles,
+    VideoCaptureProperties, VideoCaptureProperty, VideoCaptureReconfiguration,
 };
+#[cfg(feature = "preview")]
+pub use super::error::VideoCapturePreviewError;
 pub use super::error::{
-    VideoCaptureConfigError, VideoCaptureConnectionError, VideoCaptureUsageError,
+    VideoCaptureAutoError, VideoCaptureConfigError, VideoCaptureConnectionError,
+    VideoCaptureDecodeError, VideoCaptureFrameError, VideoCaptureParseError,
+    VideoCapturePipelineError, VideoCaptureRecvError, VideoCaptureUsageError,
+};
+pub use super::frame::{
+    CorruptFramePolicy, Frame, FrameCorruption, FrameMetadata, FramePlane, FramePlanes,
+    HardwareTimestamps,
+};
+#[cfg(feature = "mjpeg")]
+pub use super::mjpeg::MjpegDecoder;
+pub use super::pipeline::{
+    CaptureSource, DropPolicy, Pipeline, PipelineBuilder, QueueOptions, Sink, Source, Stage,
+    StageMetrics,
 };
+pub use super::pool::{FrameBroadcast, FramePool, FrameSubscriber, LagPolicy, SharedFrame};
+#[cfg(feature = "preview")]
+pub use super::preview::{PreviewFeed, PreviewServer};
 pub use super::{VideoCaptureConnection, VideoCaptureDescriptor, VideoCaptureStream};
@@ -0,0 +1,800 @@
+//! Live previews of capture devices, served over HTTP.
+//!
+//! Point a browser at the server to watch any camera while it captures.
+//! Each feed gets its own endpoints:
+//!
+//! - `/<name>` streams frames as `multipart/x-mixed-replace` MJPEG, which
+//!   browsers play like a video.
+//! - `/<name>/snapshot` returns the feed's image configuration as JSON.
+//!
+//! The index page at `/` shows every feed at once. At most [`MAX_CLIENTS`]
+//! streams are served at a time.
+//!
+//! MJPEG frames are passed through untouched. Raw formats are encoded into
+//! JPEG as they're published, so they cost some CPU time. This is meant for
+//! debugging in the field, not for serving video to real users!
+
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use core::net::SocketAddr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+use std::io::{self, Write};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+
+use jpeg_encoder::{ColorType, Encoder};
+use serumcv_image::{Pixel, PlanarLayout};
+use tiny_http::{Header, Request, Response, Server};
+
+use crate::config::{Format, VideoCaptureConfiguration, VideoCaptureImageConfiguration};
+use crate::error::VideoCapturePreviewError as PreviewError;
+use crate::error::VideoCaptureUsageError as UsageError;
+use crate::frame::Frame;
+use crate::VideoCaptureStream;
+
+/// How good encoded frames look, from 1 to 100.
+const QUALITY: u8 = 80;
+
+/// The chroma value for "no color".
+const NEUTRAL_CHROMA: u8 = 128;
+
+/// How long a device's thread waits for a frame before checking if the
+/// server has shut down.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How many streams the server sends at once, across every feed.
+///
+/// Each one gets its own thread, so later clients are turned away until
+/// someone leaves.
+pub const MAX_CLIENTS: usize = 16;
+
+/// Serves live previews of any number of feeds.
+///
+/// The server runs on its own thread until it's dropped. Dropping it also
+/// stops any devices started with [`PreviewServer::spawn_device`] within
+/// [`POLL_INTERVAL`].
+pub struct PreviewServer {
+    server: Arc<Server>,
+    address: SocketAddr,
+    feeds: Arc<Feeds>,
+    accept: Option<JoinHandle<()>>,
+}
+
+/// Every feed, by name.
+type Feeds = Mutex<BTreeMap<String, Arc<Feed>>>;
+
+impl PreviewServer {
+    /// Starts serving previews on the given address.
+    ///
+    /// Use port `0` to let the system pick one, then check
+    /// [`PreviewServer::address`].
+    ///
+    /// # Errors
+    ///
+    /// This fails if the address is already in use or can't be bound to.
+    #[inline]
+    pub fn bind(address: SocketAddr) -> Result<Self, PreviewError> {
+        let http = Server::http(address).map_err(|e| PreviewError::Bind {
+            address: address.to_string(),
+            err_msg: e.to_string(),
+        })?;
+        let bound = http.server_addr().to_ip().unwrap_or(address);
+        tracing::info!("serving previews on `{bound}`");
+
+        let server = Arc::new(http);
+        let feeds = Arc::new(Feeds::default());
+        let accept = {
+            let (listener, routes) = (Arc::clone(&server), Arc::clone(&feeds));
+            let clients = Arc::new(Clients::default());
+            thread::spawn(move || {
+                for request in listener.incoming_requests() {
+                    route(request, &routes, &clients);
+                }
+            })
+        };
+
+        Ok(Self {
+            server,
+            address: bound,
+            feeds,
+            accept: Some(accept),
+        })
+    }
+
+    /// The address that the server is listening on.
+    #[inline]
+    pub const fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Returns the feed with the given name, creating it if it doesn't
+    /// exist yet.
+    ///
+    /// The name is used in the feed's URLs as-is, so stick to characters
+    /// that are allowed in a URL path, like `video0` or `front-camera`.
+    #[inline]
+    pub fn feed(&self, name: &str) -> PreviewFeed {
+        let mut feeds = self.feeds.lock().unwrap_or_else(PoisonError::into_inner);
+        let feed = feeds.entry(name.to_owned()).or_default();
+
+        PreviewFeed {
+            feed: Arc::clone(feed),
+        }
+    }
+
+    /// Reads frames from a device on a new thread, publishing each one to
+    /// the feed with the given name.
+    ///
+    /// The device's image configuration is read again after each frame, so
+    /// the feed's snapshot endpoint stays up to date without ever waiting on
+    /// the device.
+    ///
+    /// The thread stops within [`POLL_INTERVAL`] of the server being
+    /// dropped, or when the device fails. Any error is logged and returned
+    /// from the thread.
+    #[inline]
+    pub fn spawn_device<D, S>(&self, name: &str, device: D) -> JoinHandle<Result<(), PreviewError>>
+    where
+        D: VideoCaptureStream<'static, 'static, S> + VideoCaptureConfiguration + Send + 'static,
+        S: 'static,
+    {
+        let feed = self.feed(name);
+        let feed_name = name.to_owned();
+
+        thread::spawn(move || {
+            let mut capturing = device;
+            let result = (|| {
+                refresh_configuration(&feed, &capturing, &feed_name);
+                while !feed.is_closed() {
+                    let frame = match capturing.read_frame_timeout(POLL_INTERVAL) {
+                        Ok(frame) => frame,
+                        // check for a shutdown, then keep waiting
+                        Err(UsageError::Timeout { .. }) => continue,
+                        // a dropped frame shouldn't end the whole preview
+                        Err(e) if e.is_transient() => {
+                            tracing::debug!("skipping a frame for feed `{feed_name}`. See: {e}");
+                            continue;
+                        }
+                        Err(e) => return Err(e.into()),
+                    };
+                    feed.publish(&frame)?;
+                    refresh_configuration(&feed, &capturing, &feed_name);
+                }
+                Ok(())
+            })();
+
+            if let Err(ref e) = result {
+                tracing::warn!("stopped the preview for feed `{feed_name}`. See: {e}");
+            }
+            result
+        })
+    }
+}
+
+/// Caches a device's image configuration in its feed, for the snapshot
+/// endpoint.
+fn refresh_configuration<D: VideoCaptureConfiguration>(feed: &PreviewFeed, device: &D, name: &str) {
+    match device.image_configuration() {
+        Ok(conf) => feed.set_image_configuration(conf),
+        Err(e) => tracing::debug!("feed `{name}` has no image configuration. See: {e}"),
+    }
+}
+
+impl core::fmt::Debug for PreviewServer {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PreviewServer")
+            .field("address", &self.address)
+            .field("feeds", &self.feeds)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for PreviewServer {
+    #[inline]
+    fn drop(&mut self) {
+        // wake up everyone waiting on a frame so they can leave
+        let feeds = self.feeds.lock().unwrap_or_else(PoisonError::into_inner);
+        for feed in feeds.values() {
+            feed.close();
+        }
+        drop(feeds);
+
+        self.server.unblock();
+        if let Some(accept) = self.accept.take() {
+            if accept.join().is_err() {
+                tracing::warn!("the preview server's thread panicked");
+            }
+        }
+    }
+}
+
+/// A handle for publishing frames to one of the server's feeds.
+///
+/// These are cheap to clone, so hand one to each thread that needs it.
+#[derive(Clone, Debug)]
+pub struct PreviewFeed {
+    feed: Arc<Feed>,
+}
+
+impl PreviewFeed {
+    /// Shows the given frame to everyone watching this feed.
+    ///
+    /// MJPEG frames are sent as-is. Other formats are encoded first.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the frame's format can't be encoded or the frame is
+    /// shorter than its resolution says.
+    #[inline]
+    pub fn publish(&self, frame: &Frame<'_>) -> Result<(), PreviewError> {
+        let jpeg = encode_jpeg(frame)?;
+
+        let mut latest = self.feed.lock();
+        latest.jpeg = Some(jpeg.into());
+        latest.generation = latest.generation.wrapping_add(1);
+        drop(latest);
+
+        self.feed.updated.notify_all();
+        Ok(())
+    }
+
+    /// Sets the image configuration that the feed's snapshot endpoint
+    /// returns.
+    ///
+    /// Feeds started with [`PreviewServer::spawn_device`] set this after
+    /// every frame.
+    #[inline]
+    pub fn set_image_configuration(&self, conf: VideoCaptureImageConfiguration) {
+        self.feed.lock().configuration = Some(conf);
+    }
+
+    /// Checks if the server has shut down. Once it has, nobody can see
+    /// published frames anymore.
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        self.feed.lock().closed
+    }
+}
+
+/// The shared state of a feed.
+#[derive(Debug, Default)]
+struct Feed {
+    latest: Mutex<Latest>,
+    updated: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct Latest {
+    /// The most recent frame, encoded.
+    jpeg: Option<Arc<[u8]>>,
+    /// Goes up by one for each published frame.
+    generation: u64,
+    configuration: Option<VideoCaptureImageConfiguration>,
+    closed: bool,
+}
+
+impl Feed {
+    fn lock(&self) -> MutexGuard<'_, Latest> {
+        self.latest.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.updated.notify_all();
+    }
+
+    /// The feed's image configuration, for its snapshot endpoint.
+    fn configuration(&self) -> Option<VideoCaptureImageConfiguration> {
+        self.lock().configuration
+    }
+
+    /// Waits for a frame that's newer than the `seen` generation.
+    ///
+    /// Returns `None` once the feed is closed.
+    fn next_after(&self, seen: u64) -> Option<(u64, Arc<[u8]>)> {
+        let mut latest = self.lock();
+        loop {
+            if latest.closed {
+                return None;
+            }
+
+            if latest.generation != seen {
+                if let Some(ref jpeg) = latest.jpeg {
+                    return Some((latest.generation, Arc::clone(jpeg)));
+                }
+            }
+
+            latest = self
+                .updated
+                .wait(latest)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+}
+
+/// Counts the streams that are being sent.
+#[derive(Debug, Default)]
+struct Clients(AtomicUsize);
+
+impl Clients {
+    /// Makes room for another stream, unless there are too many already.
+    fn join(self: &Arc<Self>) -> Option<Client> {
+        self.0
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                (count < MAX_CLIENTS).then_some(count.saturating_add(1))
+            })
+            .ok()?;
+        Some(Client(Arc::clone(self)))
+    }
+}
+
+/// Holds a stream's place until it's dropped.
+#[derive(Debug)]
+struct Client(Arc<Clients>);
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.0 .0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Answers a request from a browser.
+fn route(request: Request, feeds: &Feeds, clients: &Arc<Clients>) {
+    let path = request
+        .url()
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default()
+        .trim_matches('/')
+        .to_owned();
+    let find = |name: &str| {
+        feeds
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(name)
+            .map(Arc::clone)
+    };
+
+    let result = match path.split_once('/') {
+        None if path.is_empty() => respond(request, 200, "text/html; charset=utf-8", index(feeds)),
+        None => match find(&path) {
+            Some(feed) => match clients.join() {
+                Some(client) => {
+                    // streams last until the browser leaves, so they get their own thread
+                    thread::spawn(move || {
+                        if let Err(e) = stream(request.into_writer(), &feed) {
+                            tracing::debug!("a preview client for feed `{path}` left. See: {e}");
+                        }
+                        drop(client);
+                    });
+                    Ok(())
+                }
+                None => respond(
+                    request,
+                    503,
+                    "text/plain",
+                    b"Too many previews are being watched.".to_vec(),
+                ),
+            },
+            None => not_found(request),
+        },
+        Some((name, "snapshot")) => match find(name) {
+            Some(feed) => {
+                // a slow client shouldn't hold up everyone else's requests
+                thread::spawn(move || {
+                    if let Err(e) = snapshot(request, &feed) {
+                        tracing::debug!("failed to answer a preview request. See: {e}");
+                    }
+                });
+                Ok(())
+            }
+            None => not_found(request),
+        },
+        Some(_) => not_found(request),
+    };
+
+    if let Err(e) = result {
+        tracing::debug!("failed to answer a preview request. See: {e}");
+    }
+}
+
+/// Sends a feed's image configuration as JSON.
+fn snapshot(request: Request, feed: &Feed) -> io::Result<()> {
+    match feed.configuration() {
+        Some(conf) => match serde_json::to_vec(&conf) {
+            Ok(json) => respond(request, 200, "application/json", json),
+            Err(e) => respond(request, 500, "text/plain", e.to_string().into_bytes()),
+        },
+        None => not_found(request),
+    }
+}
+
+/// Makes a page that shows every feed.
+fn index(feeds: &Feeds) -> Vec<u8> {
+    let items = feeds
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .keys()
+        .map(String::as_str)
+        .map(escape_html)
+        .map(|name| {
+            format!(
+                "<figure><img src=\"/{name}\" alt=\"{name}\"><figcaption>\
+                 <a href=\"/{name}/snapshot\">{name}</a></figcaption></figure>"
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        "<!DOCTYPE html><html><head><title>SerumCV previews</title></head>\
+         <body>{items}</body></html>"
+    )
+    .into_bytes()
+}
+
+/// Escapes text so it can go in HTML, even inside an attribute.
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            other => escaped.push(other),
+        }
+    }
+    escaped
+}
+
+/// Writes a feed's frames as they're published, until the feed closes or
+/// the client leaves.
+fn stream(mut writer: Box<dyn Write + Send>, feed: &Feed) -> io::Result<()> {
+    writer.write_all(
+        b"HTTP/1.1 200 OK\r\n\
+          Content-Type: multipart/x-mixed-replace; boundary=frame\r\n\
+          Cache-Control: no-cache\r\n\
+          Connection: close\r\n\r\n",
+    )?;
+
+    let mut seen = 0;
+    while let Some((generation, jpeg)) = feed.next_after(seen) {
+        seen = generation;
+        write!(
+            writer,
+            "--frame\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+            jpeg.len()
+        )?;
+        writer.write_all(&jpeg)?;
+        writer.write_all(b"\r\n")?;
+        writer.flush()?;
+    }
+
+    Ok(())
+}
+
+fn respond(request: Request, status: u16, content_type: &str, body: Vec<u8>) -> io::Result<()> {
+    let mut response = Response::from_data(body).with_status_code(status);
+    if let Ok(header) = Header::from_bytes("Content-Type", content_type) {
+        response.add_header(header);
+    }
+
+    request.respond(response)
+}
+
+fn not_found(request: Request) -> io::Result<()> {
+    respond(request, 404, "text/plain", b"No such feed.".to_vec())
+}
+
+/// Turns a frame into a JPEG image.
+fn encode_jpeg(frame: &Frame<'_>) -> Result<Vec<u8>, PreviewError> {
+    if frame.format == Format::MJPEG || frame.format == Format::JPEG {
+        return Ok(frame.data.to_vec());
+    }
+
+    let resolution = frame.resolution;
+    let (Ok(width), Ok(height)) = (
+        u16::try_from(resolution.width),
+        u16::try_from(resolution.height),
+    ) else {
+        return Err(PreviewError::TooLarge { resolution });
+    };
+
+    let (pixels, color) = match &frame.format.array() {
+        b"GREY" => (packed_rows::<1>(frame)?, ColorType::Luma),
+        b"RGB3" => (packed_rows::<3>(frame)?, ColorType::Rgb),
+        b"BGR3" => (packed_rows::<3>(frame)?, ColorType::Bgr),
+        b"YUYV" => (
+            ycbcr_from_packed_422(frame, [0, 1, 2, 3])?,
+            ColorType::Ycbcr,
+        ),
+        b"UYVY" => (
+            ycbcr_from_packed_422(frame, [1, 0, 3, 2])?,
+            ColorType::Ycbcr,
+        ),
+        _ if frame.format.planar_layout().is_some() => {
+            (ycbcr_from_planar(frame)?, ColorType::Ycbcr)
+        }
+        _ => {
+            return Err(PreviewError::UnsupportedFormat {
+                format: frame.format,
+            })
+        }
+    };
+
+    let mut jpeg = Vec::new();
+    Encoder::new(&mut jpeg, QUALITY)
+        .encode(&pixels, width, height, color)
+        .map_err(|e| PreviewError::EncodeFailed {
+            err_msg: e.to_string(),
+        })?;
+
+    Ok(jpeg)
+}
+
+/// Copies a frame's packed pixels, leaving out any padding between rows.
+fn packed_rows<const N: usize>(frame: &Frame<'_>) -> Result<Vec<u8>, PreviewError>
+where
+    [u8; N]: Pixel,
+{
+    let view = frame.view::<[u8; N]>()?;
+    Ok(view
+        .rows()
+        .flat_map(|row| row.as_flattened().iter().copied())
+        .collect())
+}
+
+/// Expands packed 4:2:2 pixels into full-resolution YCbCr.
+///
+/// `order` gives where Y0, Cb, Y1, and Cr are in each pair of pixels.
+fn ycbcr_from_packed_422(frame: &Frame<'_>, order: [usize; 4]) -> Result<Vec<u8>, PreviewError> {
+    let view = frame.view::<[u8; 2]>()?;
+    let mut ycbcr = Vec::with_capacity(ycbcr_len(frame));
+
+    for row in view.rows() {
+        for pair in row.chunks(2) {
+            let bytes = pair.as_flattened();
+            let sample = |index: usize| order.get(index).and_then(|&at| bytes.get(at)).copied();
+            let (cb, cr) = (
+                sample(1).unwrap_or(NEUTRAL_CHROMA),
+                sample(3).unwrap_or(NEUTRAL_CHROMA),
+            );
+
+            // odd widths end with half a pair
+            for luma in [sample(0), sample(2)].into_iter().take(pair.len()) {
+                ycbcr.extend([luma.unwrap_or_default(), cb, cr]);
+            }
+        }
+    }
+
+    Ok(ycbcr)
+}
+
+/// The number of bytes a frame takes up as full-resolution YCbCr.
+const fn ycbcr_len(frame: &Frame<'_>) -> usize {
+    (frame.resolution.width as usize)
+        .saturating_mul(frame.resolution.height as usize)
+        .saturating_mul(3)
+}
+
+/// Expands a planar YUV frame into full-resolution YCbCr.
+fn ycbcr_from_planar(frame: &Frame<'_>) -> Result<Vec<u8>, PreviewError> {
+    let view = frame.planar_view::<u8>()?;
+    let layout = view.layout();
+    let unsupported = PreviewError::UnsupportedFormat {
+        format: frame.format,
+    };
+
+    // where to find each chroma sample: (interleaved, V before U)
+    let (interleaved, swapped) = match layout {
+        PlanarLayout::Nv12 => (true, false),
+        PlanarLayout::Nv21 => (true, true),
+        PlanarLayout::I420 | PlanarLayout::Yuv422p | PlanarLayout::Yuv444p => (false, false),
+        PlanarLayout::Yv12 => (false, true),
+        _ => return Err(unsupported),
+    };
+    let (Some(luma), Some(first)) = (view.plane(0), view.plane(1)) else {
+        return Err(unsupported);
+    };
+    let second = view.plane(2);
+    let (horizontal, vertical) = layout.subsampling();
+
+    let mut ycbcr = Vec::with_capacity(ycbcr_len(frame));
+    for (y, luma_row) in (0_u32..).zip(luma.rows()) {
+        let chroma_y = y.checked_div(vertical).unwrap_or_default();
+        let first_row = first.row(chroma_y).unwrap_or_default();
+        let second_row = second
+            .and_then(|plane| plane.row(chroma_y))
+            .unwrap_or_default();
+
+        for (x, &sample) in luma_row.iter().enumerate() {
+            let chroma_x = x.checked_div(horizontal as usize).unwrap_or_default();
+            let (u, v) = if interleaved {
+                let at = chroma_x.saturating_mul(2);
+                (first_row.get(at), first_row.get(at.saturating_add(1)))
+            } else {
+                (first_row.get(chroma_x), second_row.get(chroma_x))
+            };
+            let (cb, cr) = if swapped { (v, u) } else { (u, v) };
+
+            ycbcr.extend([
+                sample,
+                cb.copied().unwrap_or(NEUTRAL_CHROMA),
+                cr.copied().unwrap_or(NEUTRAL_CHROMA),
+            ]);
+        }
+    }
+
+    Ok(ycbcr)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read as _;
+    use std::net::TcpStream;
+
+    use crate::config::{Framerate, FramerateConsts as _, SpecificResolution};
+    use crate::error::VideoCaptureConfigError as ConfigError;
+
+    use super::*;
+
+    #[test]
+    fn raw_frames_expand_into_ycbcr() {
+        // two rows of two pixels, with two bytes of padding on each row
+        let yuyv = [10, 100, 20, 200, 0, 0, 30, 101, 40, 201, 0, 0];
+        let expanded = ycbcr_from_packed_422(
+            &Frame::packed_for_test(&yuyv, Format::YUYV, 2, 2, 6),
+            [0, 1, 2, 3],
+        )
+        .unwrap();
+        assert_eq!(
+            expanded,
+            [10, 100, 200, 20, 100, 200, 30, 101, 201, 40, 101, 201],
+            "each pixel gets its pair's chroma"
+        );
+
+        // 2x2 luma, then one sample each of U and V
+        let i420 = [1, 2, 3, 4, 50, 60];
+        let expanded =
+            ycbcr_from_planar(&Frame::packed_for_test(&i420, Format::YUV420, 2, 2, 2)).unwrap();
+        assert_eq!(
+            expanded,
+            [1, 50, 60, 2, 50, 60, 3, 50, 60, 4, 50, 60],
+            "chroma is shared by the whole block"
+        );
+
+        let jpeg = encode_jpeg(&Frame::packed_for_test(&yuyv, Format::YUYV, 2, 2, 6)).unwrap();
+        assert_eq!(
+            jpeg.get(..2),
+            Some([0xFF, 0xD8].as_slice()),
+            "raw frames become JPEGs"
+        );
+    }
+
+    #[test]
+    fn feed_names_are_escaped() {
+        let feeds = Feeds::default();
+        feeds
+            .lock()
+            .unwrap()
+            .insert(String::from("<b>\"cam\"</b>"), Arc::default());
+
+        let page = String::from_utf8(index(&feeds)).unwrap();
+        assert!(
+            page.contains("/&lt;b&gt;&quot;cam&quot;&lt;/b&gt;/snapshot"),
+            "escaped: {page}"
+        );
+        assert!(!page.contains("<b>"), "no markup gets through");
+    }
+
+    #[test]
+    fn streams_are_limited() {
+        let clients = Arc::new(Clients::default());
+        let mut watching: Vec<Client> = (0..MAX_CLIENTS).map_while(|_| clients.join()).collect();
+        assert_eq!(watching.len(), MAX_CLIENTS, "room for everyone");
+        assert!(clients.join().is_none(), "full");
+
+        watching.pop();
+        assert!(clients.join().is_some(), "someone left");
+    }
+
+    /// A camera that never sends a frame.
+    struct StalledCamera(VideoCaptureImageConfiguration);
+
+    impl VideoCaptureStream<'static, 'static, ()> for StalledCamera {
+        type Buffer = ();
+        type Source = ();
+        type SourceInput = ();
+
+        fn read_frame<'func>(&'func mut self) -> Result<Frame<'func>, UsageError>
+        where
+            'static: 'func,
+        {
+            // long enough to hang the test if the preview ever calls this
+            self.read_frame_timeout(Duration::MAX)
+        }
+
+        fn read_frame_timeout<'func>(
+            &'func mut self,
+            timeout: Duration,
+        ) -> Result<Frame<'func>, UsageError>
+        where
+            'static: 'func,
+        {
+            thread::sleep(timeout);
+            Err(UsageError::Timeout {
+                source: String::from("/dev/video0"),
+                errno: 110,
+            })
+        }
+    }
+
+    impl VideoCaptureConfiguration for StalledCamera {
+        fn supported_image_configurations(
+            &self,
+        ) -> Result<Vec<VideoCaptureImageConfiguration>, ConfigError> {
+            Ok(vec![self.0])
+        }
+
+        fn image_configuration(&self) -> Result<VideoCaptureImageConfiguration, ConfigError> {
+            Ok(self.0)
+        }
+
+        fn set_image_configuration(
+            &mut self,
+            conf: &VideoCaptureImageConfiguration,
+        ) -> Result<VideoCaptureImageConfiguration, ConfigError> {
+            self.0 = *conf;
+            Ok(self.0)
+        }
+    }
+
+    #[test]
+    fn stalled_cameras_dont_block_snapshots_or_shutdown() {
+        let server = PreviewServer::bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        let conf = VideoCaptureImageConfiguration {
+            format: Format::MJPEG,
+            resolution: SpecificResolution::new(640, 480),
+            framerate: Framerate::FPS_30,
+        };
+        let capturing = server.spawn_device("video0", StalledCamera(conf));
+
+        let mut client = TcpStream::connect(server.address()).unwrap();
+        client
+            .write_all(
+                b"GET /video0/snapshot HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            )
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "answered: {response}");
+        assert!(response.contains("640"), "cached configuration: {response}");
+
+        drop(server);
+        assert!(
+            capturing.join().unwrap().is_ok(),
+            "the camera stops cleanly"
+        );
+    }
+
+    #[test]
+    fn mjpeg_frames_pass_through() {
+        let data = [0xFF, 0xD8, 1, 2, 3, 0xFF, 0xD9];
+        let jpeg = encode_jpeg(&Frame::packed_for_test(&data, Format::MJPEG, 640, 480, 0)).unwrap();
+        assert_eq!(jpeg, data, "mjpeg is untouched");
+
+        let unsupported = encode_jpeg(&Frame::packed_for_test(&data, Format::AVC, 640, 480, 0));
+        assert_eq!(
+            unsupported,
+            Err(PreviewError::UnsupportedFormat {
+                format: Format::AVC
+            }),
+            "compressed video can't be previewed"
+        );
+    }
+}
@@ -1 +1,6 @@
+#[cfg(feature = "geometry")]
+pub use serumcv_geometry;
+#[cfg(feature = "image")]
+pub use serumcv_image;
+#[cfg(feature = "video_capture")]
 pub use serumcv_video_capture;